@@ -26,8 +26,8 @@ use super::AdifParseError;
 //
 // Special strings
 //
-const ADI_STR_EOH : &'static str = "eoh";   // end-of-header marker
-const ADI_STR_EOR : &'static str = "eor";   // end-of-record marker
+const ADI_STR_EOH : &str = "eoh";   // end-of-header marker
+const ADI_STR_EOR : &str = "eor";   // end-of-record marker
 
 //
 // We impose a maximum size for each value, primarily to ensure graceful failure
@@ -74,7 +74,6 @@ pub struct AdiRecord {
 pub struct AdiDataSpecifier {
     pub adif_name : String,         // name of the field
     pub adif_name_canon : String,   // canonicalized name (lowercase)
-    pub adif_length : usize,        // size in bytes of the field's value
     pub adif_bytes : Vec<u8>,       // contents of the field's value
     pub adif_type : Option<String>  // type specifier for the field, if provided
 }
@@ -107,7 +106,7 @@ pub fn adi_dump(adf : &AdiFile) -> String
         adi_dump_record(rec, &mut output);
     }
 
-    return output
+    output
 }
 
 #[cfg(test)]
@@ -115,15 +114,15 @@ fn adi_dump_record(rec : &AdiRecord, output: &mut String)
 {
     for field in &rec.adir_fields {
         output.push_str(format!("    <{}:{}", field.adif_name_canon.as_str(),
-            field.adif_length.to_string().as_str()).as_str());
+            field.adif_bytes.len().to_string().as_str()).as_str());
         if let Some(t) = &field.adif_type {
-            output.push_str(":");
+            output.push(':');
             output.push_str(t.as_str());
         }
-        output.push_str(">");
+        output.push('>');
         output.push_str(String::from_utf8(
             field.adif_bytes.clone()).unwrap().as_str());
-        output.push_str("\n");
+        output.push('\n');
     }
     output.push_str("<eor>\n");
 }
@@ -184,12 +183,12 @@ fn adi_token_text(token: &AdiToken) -> String
             match String::from_utf8(sample_bytes) {
                 Ok(s) => {
                     let mut sample_str = String::new();
-                    sample_str.push_str("\"");
+                    sample_str.push('"');
                     sample_str.push_str(&s);
                     if sample_len < buf.len() {
                         sample_str.push_str("...");
                     }
-                    sample_str.push_str("\"");
+                    sample_str.push('"');
                     sample_str
                 },
                 Err(_) => String::from("(non-UTF8 bytes)")
@@ -210,7 +209,6 @@ fn adi_token_string(token: &AdiToken, label : &str) ->
     Result<String, AdifParseError>
 {
     if let AdiToken::ADI_TOK_BYTES(buf) = token {
-        let mut i = 0;
         for &cb in buf.iter() {
             let c = cb as char;
 
@@ -228,7 +226,7 @@ fn adi_token_string(token: &AdiToken, label : &str) ->
                 // TODO add byte offset
                 return Err(AdifParseError::ADIF_EBADINPUT(format!(
                     "{}: expected ASCII character, but found byte 0x{:x}",
-                    label, buf[i])));
+                    label, cb)));
             }
         }
 
@@ -237,24 +235,24 @@ fn adi_token_string(token: &AdiToken, label : &str) ->
         // already validated that every character is ASCII.
         // TODO extra copy
         //
-        return Ok(String::from_utf8(buf.clone()).unwrap());
+        Ok(String::from_utf8(buf.clone()).unwrap())
     } else {
-        return Err(AdifParseError::ADIF_EBADINPUT(format!(
+        Err(AdifParseError::ADIF_EBADINPUT(format!(
             "{}: expected ASCII string, but found {}", label,
-            adi_token_text(token))));
+            adi_token_text(token))))
     }
 }
 
 //
 // Low-level function that reads the next token from the underlying stream.
 //
-fn adi_import_read_token(source : &mut BufRead) ->
+fn adi_import_read_token(source : &mut dyn BufRead) ->
     Result<AdiToken, AdifParseError>
 {
 
     let c = {
         let buf = source.fill_buf()?;
-        if buf.len() == 0 {
+        if buf.is_empty() {
             return Ok(AdiToken::ADI_TOK_EOF);
         }
 
@@ -293,7 +291,7 @@ fn adi_import_read_token(source : &mut BufRead) ->
             let chunk = source.fill_buf()?;
             let mut i = 0;
 
-            if chunk.len() == 0 {
+            if chunk.is_empty() {
                 done = true;
             }
 
@@ -358,7 +356,7 @@ fn adi_import_read_token(source : &mut BufRead) ->
 //
 //
 struct AdiParseState<'a> {
-    aps_source : Box<BufRead + 'a>,     // underlying source of ADI input
+    aps_source : Box<dyn BufRead + 'a>,     // underlying source of ADI input
     aps_tokens : Vec<AdiToken>,         // next unconsumed tokens
     aps_error : bool,                   // if true, we've encountered an error
     aps_done : bool,                    // if true, we've read EOF
@@ -443,7 +441,7 @@ fn adi_parse_consume_tokens(aps : &mut AdiParseState, howmany : u8)
  * Examine the Nth token from the start of unconsumed input.  If callers process
  * this token, they should call adi_parse_consume_tokens().
  */
-fn adi_parse_peek_token<'a>(aps : &'a mut AdiParseState, which : u8) ->
+fn adi_parse_peek_token(aps : &mut AdiParseState, which : u8) ->
     Result<AdiToken, AdifParseError>
 {
     adi_parse_advance_tokens(aps, which + 1)?;
@@ -458,15 +456,15 @@ fn adi_parse_peek_token<'a>(aps : &'a mut AdiParseState, which : u8) ->
     // the end-of-file token.
     //
     assert!(aps.aps_done);
-    assert!(aps.aps_tokens.len() > 0);
+    assert!(!aps.aps_tokens.is_empty());
     assert_eq!(aps.aps_tokens[aps.aps_tokens.len() - 1], AdiToken::ADI_TOK_EOF);
-    return Ok(aps.aps_tokens[aps.aps_tokens.len() - 1].clone());
+    Ok(aps.aps_tokens[aps.aps_tokens.len() - 1].clone())
 }
 
 //
 // General entry point for parsing an ADI file from an input source.
 //
-pub fn adi_parse(source: &mut io::Read) -> Result<AdiFile, AdifParseError>
+pub fn adi_parse(source: &mut dyn io::Read) -> Result<AdiFile, AdifParseError>
 {
     let mut aps = AdiParseState {
         aps_source: Box::new(BufReader::new(source)),
@@ -514,11 +512,11 @@ fn adi_parse_header(aps: &mut AdiParseState) -> Result<AdiHeader, AdifParseError
             // TODO record a warning?
             //
             AdiToken::ADI_TOK_COLON => {
-                header_content.push(':' as u8);
+                header_content.push(b':');
                 adi_parse_consume_tokens(aps, 1);
             },
             AdiToken::ADI_TOK_RAB => {
-                header_content.push('>' as u8);
+                header_content.push(b'>');
                 adi_parse_consume_tokens(aps, 1);
             },
 
@@ -581,8 +579,12 @@ fn adi_parse_header(aps: &mut AdiParseState) -> Result<AdiHeader, AdifParseError
 //                                     + 7 (LAB)
 //
 // ADI also allows an additional colon (COLON) and type specifier (STRING)
-// directly after the field length.  We do not yet support this, so we only
-// handle the sequence above.
+// directly after the field length:
+//
+//   <FIELDNAME:FIELDLEN:T>FIELDVALUE...
+//
+// In that case, tokens 4 (COLON), 5 (STRING) TYPE and 6 (RAB) precede the
+// value.
 //
 fn adi_parse_data_specifier(aps : &mut AdiParseState) ->
     Result<AdiDataSpecifier, AdifParseError>
@@ -632,13 +634,23 @@ fn adi_parse_data_specifier(aps : &mut AdiParseState) ->
         }
     };
 
-    match t_rab {
-        AdiToken::ADI_TOK_RAB => (),
+    let (fieldtype, ntokens) = match t_rab {
+        AdiToken::ADI_TOK_RAB => (None, 5),
         AdiToken::ADI_TOK_COLON => {
-            // TODO
-            return Err(AdifParseError::ADIF_ENOT_YET_IMPLEMENTED(format!(
-                "parsing data specifier (near byte {}): \
-                typed values are not supported", aps.aps_bytes_consumed)));
+            let t_type = adi_parse_peek_token(aps, 5)?;
+            let t_rab = adi_parse_peek_token(aps, 6)?;
+            let typestr = adi_token_string(&t_type,
+                &format!("parsing data specifier (near byte {}) type",
+                aps.aps_bytes_consumed))?;
+            if t_rab != AdiToken::ADI_TOK_RAB {
+                return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                    "parsing data specifier (near byte {}): \
+                    expected {}, but found {}", aps.aps_bytes_consumed,
+                    adi_token_text(&AdiToken::ADI_TOK_RAB),
+                    adi_token_text(&t_rab))));
+            }
+
+            (Some(typestr), 7)
         },
         _ => {
             return Err(AdifParseError::ADIF_EBADINPUT(format!(
@@ -653,20 +665,20 @@ fn adi_parse_data_specifier(aps : &mut AdiParseState) ->
     // TODO this could be more efficient in the common case that the token
     // contains at least the entire string that we care about.
     //
-    adi_parse_consume_tokens(aps, 5);
+    adi_parse_consume_tokens(aps, ntokens);
     let mut fieldvalue : Vec<u8> = Vec::with_capacity(fieldlength);
     while fieldlength > fieldvalue.len() {
         let t_value = adi_parse_peek_token(aps, 0)?;
         adi_parse_consume_tokens(aps, 1);
         match t_value {
             AdiToken::ADI_TOK_COLON => {
-                fieldvalue.push(':' as u8);
+                fieldvalue.push(b':');
             }
             AdiToken::ADI_TOK_RAB => {
-                fieldvalue.push('>' as u8);
+                fieldvalue.push(b'>');
             }
             AdiToken::ADI_TOK_LAB => {
-                fieldvalue.push('<' as u8);
+                fieldvalue.push(b'<');
             }
             AdiToken::ADI_TOK_BYTES(buf) => {
                 let nbytes = cmp::min(buf.len(), fieldlength - fieldvalue.len());
@@ -692,9 +704,8 @@ fn adi_parse_data_specifier(aps : &mut AdiParseState) ->
     Ok(AdiDataSpecifier {
         adif_name_canon: fieldname.to_lowercase(),
         adif_name: fieldname.to_string(), // TODO extra copy?
-        adif_length: fieldlength,
         adif_bytes: fieldvalue,
-        adif_type: None
+        adif_type: fieldtype
     })
 }
 
@@ -719,7 +730,7 @@ fn adi_parse_records(aps: &mut AdiParseState) ->
         }
     }
 
-    return Ok(records);
+    Ok(records)
 }
 
 //
@@ -752,7 +763,7 @@ fn adi_parse_record(aps: &mut AdiParseState) ->
         }
     }
 
-    return Ok(record);
+    Ok(record)
 }
 
 //
@@ -772,7 +783,57 @@ fn adi_parse_consume_until_lab(aps: &mut AdiParseState) ->
         }
     }
 
-    return Ok(());
+    Ok(())
+}
+
+//
+// ADI Export
+//
+// These functions write an AdiFile to an output stream.  The caller is
+// responsible for constructing an AdiFile that makes sense (e.g., with a header
+// whose content does not begin with "<").
+//
+
+//
+// Write a single data specifier.
+//
+fn adi_write_data_specifier(adf: &AdiDataSpecifier, out: &mut dyn io::Write) ->
+    Result<(), AdifParseError>
+{
+    write!(out, "<{}:{}", adf.adif_name, adf.adif_bytes.len())?;
+    if let Some(ref t) = adf.adif_type {
+        write!(out, ":{}", t)?;
+    }
+    write!(out, ">")?;
+    out.write_all(&adf.adif_bytes)?;
+    Ok(())
+}
+
+//
+// Write a complete ADI file.  Header fields are written one per line, and each
+// record is written on its own line.
+//
+pub fn adi_write(adf: &AdiFile, out: &mut dyn io::Write) ->
+    Result<(), AdifParseError>
+{
+    if let Some(ref adh) = adf.adi_header {
+        out.write_all(&adh.adih_content)?;
+        for field in &adh.adih_fields {
+            adi_write_data_specifier(field, out)?;
+            writeln!(out)?;
+        }
+        writeln!(out, "<EOH>")?;
+    }
+
+    for rec in &adf.adi_records {
+        for field in &rec.adir_fields {
+            adi_write_data_specifier(field, out)?;
+            write!(out, " ")?;
+        }
+        writeln!(out, "<EOR>")?;
+    }
+
+    Ok(())
 }
 
 //
@@ -788,7 +849,7 @@ mod test {
     fn make_file_basic() -> super::AdiFile {
         let header = None;
         let records = vec![];
-        return super::AdiFile {
+        super::AdiFile {
             adi_header: header,
             adi_records: records
         }
@@ -802,7 +863,7 @@ mod test {
             adih_fields: vec![]
         };
         let records = vec![];
-        return super::AdiFile {
+        super::AdiFile {
             adi_header: Some(header),
             adi_records: records
         }
@@ -817,7 +878,6 @@ mod test {
             adih_fields: vec![ super::AdiDataSpecifier {
                 adif_name: String::from("adif_VERSion"),
                 adif_name_canon: String::from("adif_version"),
-                adif_length: 3,
                 adif_bytes: String::from("1.0").as_bytes().to_vec(),
                 adif_type: None
             } ]
@@ -828,7 +888,6 @@ mod test {
                     super::AdiDataSpecifier {
                         adif_name: String::from("call"),
                         adif_name_canon: String::from("call"),
-                        adif_length: 6,
                         adif_bytes: String::from("KK6ZBI").as_bytes().to_vec(),
                         adif_type: None
                     },
@@ -836,7 +895,6 @@ mod test {
                     super::AdiDataSpecifier {
                         adif_name: String::from("QSO_date"),
                         adif_name_canon: String::from("qso_date"),
-                        adif_length: 8,
                         adif_bytes: String::from("20181129").as_bytes().to_vec(),
                         adif_type: None
                     }
@@ -847,7 +905,6 @@ mod test {
                     super::AdiDataSpecifier {
                         adif_name: String::from("call"),
                         adif_name_canon: String::from("call"),
-                        adif_length: 6,
                         adif_bytes: String::from("KB1HCN").as_bytes().to_vec(),
                         adif_type: Some(String::from("S"))
                     },
@@ -855,14 +912,13 @@ mod test {
                     super::AdiDataSpecifier {
                         adif_name: String::from("QSO_date"),
                         adif_name_canon: String::from("qso_date"),
-                        adif_length: 8,
                         adif_bytes: String::from("20181130").as_bytes().to_vec(),
                         adif_type: None
                     }
                 ]
             }
         ];
-        return super::AdiFile {
+        super::AdiFile {
             adi_header: Some(header),
             adi_records: records
         }
@@ -951,6 +1007,29 @@ mod test {
         // XXX test something
     }
 
+    #[test]
+    fn typed_data_specifiers() {
+        let adf = super::adi_parse_string(
+            "\n<userdef1:19:E>SweaterSize,{S,M,L}<eoh>\
+            <call:6:S>KK6ZBI<comment:0><eor>").unwrap();
+        let header = adf.adi_header.unwrap();
+        assert_eq!(header.adih_fields.len(), 1);
+        assert_eq!(header.adih_fields[0].adif_type, Some(String::from("E")));
+        assert_eq!(header.adih_fields[0].adif_bytes,
+            b"SweaterSize,{S,M,L}".to_vec());
+
+        assert_eq!(adf.adi_records.len(), 1);
+        let fields = &adf.adi_records[0].adir_fields;
+        assert_eq!(fields[0].adif_name_canon, "call");
+        assert_eq!(fields[0].adif_type, Some(String::from("S")));
+        assert_eq!(fields[0].adif_bytes, b"KK6ZBI".to_vec());
+        assert!(fields[1].adif_bytes.is_empty());
+        assert_eq!(fields[1].adif_type, None);
+
+        assert!(super::adi_parse_string(
+            "\n<eoh><call:6:S KK6ZBI<eor>").is_err());
+    }
+
     fn parse_test_string(s : &str) {
         println!("test input:\n{}\n", s);
         test_print(super::adi_parse_string(s));
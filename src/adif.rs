@@ -7,207 +7,628 @@
 
 use adi::AdiFile;
 use adi::AdiDataSpecifier;
+use adi::AdiHeader;
+use adi::AdiRecord;
+use adiftypes::AdifTimestamp;
+use adiftypes::AdifVersion;
+use adiftypes::adif_parse_timestamp;
+use adiftypes::adif_parse_version;
 use super::AdifParseError;
 use std::collections::BTreeMap;
 use std::fmt;
 
 // Well-known header fields
-const ADIF_HEADER_ADIF_VER : &'static str = "adif_ver";
-const ADIF_HEADER_CREATED_TIMESTAMP : &'static str = "created_timestamp";
-const ADIF_HEADER_PROGRAMID : &'static str = "programid";
-const ADIF_HEADER_PROGRAMVERSION : &'static str = "programversion";
-const ADIF_HEADER_USERDEF : &'static str = "userdef";
+const ADIF_HEADER_ADIF_VER : &str = "adif_ver";
+const ADIF_HEADER_CREATED_TIMESTAMP : &str = "created_timestamp";
+const ADIF_HEADER_PROGRAMID : &str = "programid";
+const ADIF_HEADER_PROGRAMVERSION : &str = "programversion";
+const ADIF_HEADER_USERDEF : &str = "userdef";
+
+//
+// When writing a file that has no preamble, we need to write something so that
+// the file does not begin with "<" (which would indicate no header at all).
+//
+const ADIF_DEFAULT_PREAMBLE : &str = "ADIF export\n";
 
 pub struct AdifFile {
-    // Well-known header fields
-    pub adif_adif_version : Option<String>,     // XXX semver type?
-    pub adif_program_id : Option<String>,
-    pub adif_program_version : Option<String>,
-    pub adif_created_timestamp : Option<String>,    // XXX date type
+    pub adif_header : AdifHeader,           // file header
 
     // Metadata
     pub adif_label : String,    // label for this file (e.g., filename)
-
-    // XXX map of application-defined field metadata and values?
-    // XXX map of user-defined field metadata and values?
+    pub adif_diagnostics : Vec<AdifDiagnostic>, // non-fatal parse problems
 
     // File contents
     pub adif_records : Vec<AdifRecord>,     // list of records in the file
 }
 
+//
+// AdifDuplicatePolicy: describes what to do when a record contains more than
+// one value for the same field.  The specification doesn't allow this, but some
+// exporters do it anyway (e.g., emitting an empty value and later a filled-in
+// one).
+//
 #[allow(non_camel_case_types)]
-pub enum AdifDumpWhichRecords {
-    ADR_NONE,
-    ADR_ONE,
-    ADR_ALL
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdifDuplicatePolicy {
+    ADP_ERROR,                  // fail to parse the file
+    ADP_KEEP_FIRST,             // use the first value
+    ADP_KEEP_LAST,              // use the last value
+    ADP_KEEP_FIRST_NONEMPTY,    // use the first non-empty value, if any
+    ADP_KEEP_ALL                // use the first value, but keep the rest too
+}
+
+//
+// AdifParseOptions: options that control how we interpret the logical contents
+// of a file.
+//
+#[derive(Clone, Debug)]
+pub struct AdifParseOptions {
+    pub apo_duplicates : AdifDuplicatePolicy    // handling of duplicate fields
+}
+
+impl Default for AdifParseOptions {
+    fn default() -> AdifParseOptions {
+        AdifParseOptions {
+            apo_duplicates: AdifDuplicatePolicy::ADP_ERROR
+        }
+    }
+}
+
+//
+// Parse the name of a duplicate-field policy, as a user would specify it on the
+// command line.
+//
+pub fn adif_parse_duplicate_policy(name: &str) -> Option<AdifDuplicatePolicy>
+{
+    match name {
+        "error" => Some(AdifDuplicatePolicy::ADP_ERROR),
+        "first" => Some(AdifDuplicatePolicy::ADP_KEEP_FIRST),
+        "last" => Some(AdifDuplicatePolicy::ADP_KEEP_LAST),
+        "first-nonempty" => Some(AdifDuplicatePolicy::ADP_KEEP_FIRST_NONEMPTY),
+        "all" => Some(AdifDuplicatePolicy::ADP_KEEP_ALL),
+        _ => None
+    }
+}
+
+//
+// AdifDiagnostic: describes a problem with the input that we were able to work
+// around (e.g., a duplicate field that was resolved by policy).  "adid_record"
+// is the 1-based index of the record involved, or None for the header (or for
+// input that didn't produce a record).  Readers for line-oriented formats also
+// report the 1-based line number of the problem, which is more useful to a
+// person than the record number.
+//
+#[derive(Clone, Debug)]
+pub struct AdifDiagnostic {
+    pub adid_record : Option<usize>,    // record number, if any
+    pub adid_line : Option<usize>,      // line number, if known
+    pub adid_message : String           // description of the problem
+}
+
+impl fmt::Display for AdifDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.adid_line, self.adid_record) {
+            (Some(line), _) =>
+                write!(f, "line {}: {}", line, self.adid_message),
+            (None, Some(which)) =>
+                write!(f, "record {}: {}", which, self.adid_message),
+            (None, None) => write!(f, "header: {}", self.adid_message)
+        }
+    }
+}
+
+//
+// AdifHeader: the logical contents of a file header.  Every field is kept, in
+// the order in which it appeared and with its value exactly as written, so that
+// consumers can write the header back out faithfully.  Well-known fields are
+// interpreted on demand by the adif_header_*() functions below.  If one can't
+// be parsed (e.g., a malformed timestamp), its function returns None and the
+// field is treated like any other unrecognized field.
+//
+#[derive(Clone, Debug, Default)]
+pub struct AdifHeader {
+    pub adh_preamble : Option<String>,      // free text before the first field
+    pub adh_fields : Vec<AdifHeaderField>   // all fields, in order
+}
+
+//
+// AdifHeaderField: one header field.  The name is preserved as it appeared in
+// the file (i.e., not canonicalized).
+//
+#[derive(Clone, Debug)]
+pub struct AdifHeaderField {
+    pub adhf_name : String,             // field name, as written
+    pub adhf_type : Option<String>,     // data type indicator, if any
+    pub adhf_value : String             // field value
+}
+
+//
+// AdifUserdef: the definition of a user-defined field, as described by a
+// "USERDEFn" header field.  These look like:
+//
+//     <USERDEF1:8:N>EPC
+//     <USERDEF2:19:E>SweaterSize,{S,M,L}
+//     <USERDEF3:15:N>ShoeSize,{5:20}
+//
+// Records then refer to the field by name (e.g., "<EPC:5>32123").
+//
+#[derive(Clone, Debug)]
+pub struct AdifUserdef {
+    pub adud_id : u32,                      // "n" in "USERDEFn"
+    pub adud_name : String,                 // name of the user-defined field
+    pub adud_type : Option<String>,         // data type indicator
+    pub adud_enum : Option<Vec<String>>,    // allowed values, if enumerated
+    pub adud_range : Option<(f64, f64)>     // allowed range, if numeric
+}
+
+//
+// Returns the value of the first header field with the given name (which is
+// case-insensitive), if there is one.
+//
+pub fn adif_header_value<'a>(header: &'a AdifHeader, name: &str) ->
+    Option<&'a str>
+{
+    header.adh_fields.iter().find(|f| f.adhf_name.eq_ignore_ascii_case(name))
+        .map(|f| f.adhf_value.as_str())
+}
+
+//
+// Sets the value of the first header field with the given name, or appends a
+// new field if there isn't one.
+//
+pub fn adif_header_set(header: &mut AdifHeader, name: &str, value: &str)
+{
+    match header.adh_fields.iter_mut().find(
+        |f| f.adhf_name.eq_ignore_ascii_case(name)) {
+        Some(field) => field.adhf_value = value.to_string(),
+        None => header.adh_fields.push(AdifHeaderField {
+            adhf_name: name.to_string(),
+            adhf_type: None,
+            adhf_value: value.to_string()
+        })
+    }
+}
+
+pub fn adif_header_version(header: &AdifHeader) -> Option<AdifVersion>
+{
+    adif_parse_version(adif_header_value(header, ADIF_HEADER_ADIF_VER)?)
+}
+
+pub fn adif_header_program_id(header: &AdifHeader) -> Option<&str>
+{
+    adif_header_value(header, ADIF_HEADER_PROGRAMID)
+}
+
+pub fn adif_header_program_version(header: &AdifHeader) -> Option<&str>
+{
+    adif_header_value(header, ADIF_HEADER_PROGRAMVERSION)
+}
+
+//
+// Returns the parsed CREATED_TIMESTAMP.  The original text (which may omit the
+// seconds) is available from adif_header_value().
+//
+pub fn adif_header_created_timestamp(header: &AdifHeader) ->
+    Option<AdifTimestamp>
+{
+    adif_parse_timestamp(adif_header_value(header,
+        ADIF_HEADER_CREATED_TIMESTAMP)?)
+}
+
+//
+// Returns the valid USERDEFn definitions, in order.
+//
+pub fn adif_header_userdefs(header: &AdifHeader) -> Vec<AdifUserdef>
+{
+    header.adh_fields.iter().filter_map(adif_parse_userdef).collect()
+}
+
+//
+// Returns whether "field" is a well-known field whose value we understand.
+//
+fn adif_header_known(field: &AdifHeaderField) -> bool
+{
+    match field.adhf_name.to_lowercase().as_str() {
+        ADIF_HEADER_ADIF_VER => adif_parse_version(&field.adhf_value).is_some(),
+        ADIF_HEADER_CREATED_TIMESTAMP =>
+            adif_parse_timestamp(&field.adhf_value).is_some(),
+        ADIF_HEADER_PROGRAMID | ADIF_HEADER_PROGRAMVERSION => true,
+        _ => adif_parse_userdef(field).is_some()
+    }
+}
+
+//
+// Returns the header's APP_* fields and unrecognized fields, in the order in
+// which they appeared in the file.  These are the fields that ADIF itself
+// doesn't assign meaning to.
+//
+pub fn adif_header_unknown_fields(header: &AdifHeader) -> Vec<&AdifHeaderField>
+{
+    header.adh_fields.iter().filter(|f| !adif_header_known(f)).collect()
 }
 
 impl fmt::Debug for AdifFile {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ADIF file:  {}\n", self.adif_label)?;
-        write!(f, "Created at: {}\n",
-            match &self.adif_created_timestamp {
-                Some(p) => p,
-                None => "unknown"
+        let header = &self.adif_header;
+        writeln!(f, "ADIF file:  {}", self.adif_label)?;
+        writeln!(f, "ADIF version: {}",
+            match adif_header_version(header) {
+                Some(v) => v.to_string(),
+                None => String::from("unknown")
             })?;
-        write!(f, "Created by: {} {}\n",
-            match &self.adif_program_id {
+        writeln!(f, "Created at: {}",
+            match adif_header_created_timestamp(header) {
+                Some(t) => t.to_string(),
+                None => String::from("unknown")
+            })?;
+        writeln!(f, "Created by: {} {}",
+            match adif_header_program_id(header) {
                 Some(p) => format!("program \"{}\"", p),
                 None => String::from("unknown program")
             },
-            match &self.adif_program_version {
+            match adif_header_program_version(header) {
                 Some(v) => format!("version \"{}\"", v),
                 None => String::from("unknown version")
             })?;
-        write!(f, "Total records: {}\n", self.adif_records.len())
+        for userdef in adif_header_userdefs(header) {
+            writeln!(f, "User-defined field: {}", userdef.adud_name)?;
+        }
+        writeln!(f, "Total records: {}", self.adif_records.len())
     }
 }
 
-pub fn adif_dump(adif: AdifFile, which: AdifDumpWhichRecords,
-    filterspec : &Option<Vec<(String, String)>>,
-    colspec : &Option<Vec<&String>>)
-{
-    print!("{:?}", adif);
+#[derive(Clone, Default)]
+pub struct AdifRecord {
+    pub adir_field_values : BTreeMap<String, String>, // XXX value type?
 
-    match which {
-        AdifDumpWhichRecords::ADR_NONE => (),
-        AdifDumpWhichRecords::ADR_ONE => {
-            print!("Example record:\n");
-            adif_dump_one(&adif.adif_records[0], &None, colspec);
-        },
-        AdifDumpWhichRecords::ADR_ALL => {
-            for rec in &adif.adif_records {
-                adif_dump_one(rec, filterspec, &colspec);
-            }
-        }
-    }
+    //
+    // When a record contains the same field more than once and we're using
+    // ADP_KEEP_ALL, "adir_field_values" contains the first value and this map
+    // contains the remaining values, in order.
+    //
+    pub adir_extra_values : BTreeMap<String, Vec<String>>
 }
 
-fn adif_dump_one(rec : &AdifRecord, filterspec: &Option<Vec<(String, String)>>,
-    colspec: &Option<Vec<&String>>)
-{
-    if let Some(filters) = filterspec {
-        for filter in filters {
-            let key = &filter.0;
-            let filterval = &filter.1;
-            let recordentry = rec.adir_field_values.get(key);
-            match recordentry {
-                None => {
-                    if filterval.len() > 0 {
-                        return;
-                    }
-                },
-                Some(recordval) => {
-                    if filterval != recordval {
-                        return;
-                    }
+impl fmt::Debug for AdifRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "RECORD:")?;
+
+        for (key, value) in self.adir_field_values.iter() {
+            writeln!(f, "    {:20}: {}", key, value)?;
+            if let Some(extras) = self.adir_extra_values.get(key) {
+                for extra in extras {
+                    writeln!(f, "    {:20}: {}", key, extra)?;
                 }
             }
         }
-    }
 
-    match colspec {
-        None => print!("{:?}\n\n", rec),
-        Some(colnames) => {
-            for colname in colnames {
-                let val = rec.adir_field_values.get(*colname);
-                print!("{}\t", match val {
-                    None => "-",
-                    Some(v) => v
-                });
-            }
-        }
+        Ok(())
     }
+}
 
-    print!("\n");
+//
+// AdifFieldState: describes whether and how a field appears in a record.  ADI
+// allows a field to be specified with an empty value (e.g., "<COMMENT:0>"),
+// which is used to indicate that a field was explicitly cleared.  That's
+// different from the field not being present at all.
+//
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdifFieldState<'a> {
+    ADFS_ABSENT,            // field does not appear in the record
+    ADFS_EMPTY,             // field appears with an empty value
+    ADFS_VALUE(&'a str)     // field appears with a non-empty value
 }
 
-pub struct AdifRecord {
-    pub adir_field_values : BTreeMap<String, String> // XXX value type?
+pub fn adif_record_field<'a>(rec: &'a AdifRecord, name: &str) ->
+    AdifFieldState<'a>
+{
+    match rec.adir_field_values.get(name) {
+        None => AdifFieldState::ADFS_ABSENT,
+        Some(v) if v.is_empty() => AdifFieldState::ADFS_EMPTY,
+        Some(v) => AdifFieldState::ADFS_VALUE(v)
+    }
 }
 
-impl fmt::Debug for AdifRecord {
+impl<'a> fmt::Display for AdifFieldState<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "RECORD:\n")?;
-
-        for (key, value) in self.adir_field_values.iter() {
-            write!(f, "    {:20}: {}\n", key, value)?;
+        match self {
+            AdifFieldState::ADFS_ABSENT => write!(f, "(absent)"),
+            AdifFieldState::ADFS_EMPTY => write!(f, "(empty)"),
+            AdifFieldState::ADFS_VALUE(v) => write!(f, "\"{}\"", v)
         }
-
-        Ok(())
     }
 }
 
 // TODO Would this be better off accepting an iterator?
-pub fn adif_parse_adi(label: &str, adi: &AdiFile) ->
+pub fn adif_parse_adi(label: &str, adi: &AdiFile, options: &AdifParseOptions) ->
     Result<AdifFile, AdifParseError>
 {
     let mut adif = AdifFile {
-        adif_adif_version: None,
-        adif_program_id: None,
-        adif_program_version: None,
-        adif_created_timestamp: None,
+        adif_header: AdifHeader::default(),
         adif_label: String::from(label), // XXX clone needed?
+        adif_diagnostics: Vec::new(),
         adif_records: Vec::with_capacity(adi.adi_records.len()),
     };
 
     if let Some(ref adih) = adi.adi_header {
-        // TODO can this be made table-based?
-        for adf in &adih.adih_fields {
-            if adf.adif_name_canon == ADIF_HEADER_ADIF_VER {
-                adif.adif_adif_version = Some(adif_string(&adf)?);
-            } else if adf.adif_name_canon == ADIF_HEADER_PROGRAMID {
-                adif.adif_program_id = Some(adif_string(&adf)?);
-            } else if adf.adif_name_canon == ADIF_HEADER_PROGRAMVERSION {
-                adif.adif_program_version = Some(adif_string(&adf)?);
-            } else if adf.adif_name_canon == ADIF_HEADER_CREATED_TIMESTAMP {
-                adif.adif_created_timestamp = Some(adif_string(&adf)?);
-            }
-        }
+        adif.adif_header = adif_parse_header(&adih.adih_content,
+            &adih.adih_fields, &mut adif.adif_diagnostics)?;
     }
 
-    let mut which = 1;
-    for adr in &adi.adi_records {
-        let mut record_values : BTreeMap<String, String> = BTreeMap::new();
+    for (i, adr) in adi.adi_records.iter().enumerate() {
+        let which = i + 1;
+        let mut record = AdifRecord::default();
 
         for adf in &adr.adir_fields {
-            // TODO presumably this is not legal ADIF?
-            if record_values.contains_key(&adf.adif_name_canon) {
-                return Err(AdifParseError::ADIF_EBADINPUT(format!(
-                    "record {}: duplicate value for field \"{}\"", which,
-                    adf.adif_name_canon)));
+            let value = adif_string(adf)?;
+            let name = &adf.adif_name_canon;
+
+            if !record.adir_field_values.contains_key(name) {
+                record.adir_field_values.insert(name.clone(), value);
+                continue;
             }
 
-            let value = adif_string(&adf)?;
-            record_values.insert(adf.adif_name_canon.clone(), value);
+            let action = match options.apo_duplicates {
+                AdifDuplicatePolicy::ADP_ERROR => {
+                    return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                        "record {}: duplicate value for field \"{}\"", which,
+                        name)));
+                },
+                AdifDuplicatePolicy::ADP_KEEP_FIRST => "kept first value",
+                AdifDuplicatePolicy::ADP_KEEP_LAST => {
+                    record.adir_field_values.insert(name.clone(), value);
+                    "kept last value"
+                },
+                AdifDuplicatePolicy::ADP_KEEP_FIRST_NONEMPTY => {
+                    if record.adir_field_values[name].is_empty() &&
+                       !value.is_empty() {
+                        record.adir_field_values.insert(name.clone(), value);
+                        "kept first non-empty value"
+                    } else {
+                        "kept first value"
+                    }
+                },
+                AdifDuplicatePolicy::ADP_KEEP_ALL => {
+                    record.adir_extra_values.entry(name.clone())
+                        .or_default().push(value);
+                    "kept all values"
+                }
+            };
+
+            adif.adif_diagnostics.push(AdifDiagnostic {
+                adid_record: Some(which),
+                adid_line: None,
+                adid_message: format!("duplicate value for field \"{}\": {}",
+                    name, action)
+            });
         }
 
-        which += 1;
-        adif.adif_records.push(AdifRecord {
-            adir_field_values : record_values
-        });
+        adif.adif_records.push(record);
     }
 
     Ok(adif)
 }
 
 //
-// Given a data specifier describing a string-valued field, return a new String
-// containing the field's contents.  This returns an error if the field is not
-// string-valued or the value cannot be processed as UTF-8.
+// Interpret the preamble and data specifiers of an ADI header.
 //
-fn adif_string(adf: &AdiDataSpecifier) ->
-    Result<String, AdifParseError>
+fn adif_parse_header(content: &[u8], fields: &[AdiDataSpecifier],
+    diagnostics: &mut Vec<AdifDiagnostic>) ->
+    Result<AdifHeader, AdifParseError>
 {
+    let mut header = AdifHeader::default();
+
     //
-    // XXX We need to check the type specified with data specifier, but this
-    // doesn't seem like the right way to do it.  Is it case-sensitive?  Are
-    // there other string types?
+    // The preamble is free text, so we don't insist that it be valid UTF-8.
+    // We also don't bother preserving a preamble that's entirely whitespace
+    // (as when the header contains only fields).
     //
+    let preamble = String::from_utf8_lossy(content);
+    if !preamble.trim().is_empty() {
+        header.adh_preamble = Some(preamble.into_owned());
+    }
+
+    for adf in fields {
+        let field = AdifHeaderField {
+            adhf_name: adf.adif_name.clone(),
+            adhf_type: adf.adif_type.clone(),
+            adhf_value: adif_header_string(adf)?
+        };
+        let name = adf.adif_name_canon.as_str();
+        let value = &field.adhf_value;
+
+        if name == ADIF_HEADER_ADIF_VER {
+            if adif_parse_version(value).is_none() {
+                diagnostics.push(AdifDiagnostic {
+                    adid_record: None,
+                    adid_line: None,
+                    adid_message: format!("unrecognized ADIF version \"{}\"",
+                        value)
+                });
+            }
+        } else if name == ADIF_HEADER_CREATED_TIMESTAMP {
+            if adif_parse_timestamp(value).is_none() {
+                diagnostics.push(AdifDiagnostic {
+                    adid_record: None,
+                    adid_line: None,
+                    adid_message: format!(
+                        "malformed creation timestamp \"{}\"", value)
+                });
+            }
+        } else if name.starts_with(ADIF_HEADER_USERDEF) &&
+            adif_parse_userdef(&field).is_none() {
+            diagnostics.push(AdifDiagnostic {
+                adid_record: None,
+                adid_line: None,
+                adid_message: format!("malformed field definition \"{}\"",
+                    adf.adif_name)
+            });
+        }
+
+        header.adh_fields.push(field);
+    }
+
+    Ok(header)
+}
+
+//
+// Parse a "USERDEFn" header field.  See AdifUserdef for the format.  Returns
+// None if the field doesn't look like a valid definition.
+//
+pub fn adif_parse_userdef(field: &AdifHeaderField) -> Option<AdifUserdef>
+{
+    let name = field.adhf_name.to_lowercase();
+    let id = name.strip_prefix(ADIF_HEADER_USERDEF)?.parse::<u32>().ok()?;
+    let value = field.adhf_value.as_str();
+
+    let (name, constraint) = match value.find(",{") {
+        None => (value.trim(), None),
+        Some(p) => {
+            let rest = &value[p + 2..];
+            if !rest.ends_with('}') {
+                return None;
+            }
+            (value[0..p].trim(), Some(&rest[0..rest.len() - 1]))
+        }
+    };
+
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut userdef = AdifUserdef {
+        adud_id: id,
+        adud_name: name.to_string(),
+        adud_type: field.adhf_type.clone(),
+        adud_enum: None,
+        adud_range: None
+    };
+
+    if let Some(c) = constraint {
+        //
+        // The specification says a range is given as "{LOW:HIGH}" and an
+        // enumeration as "{A,B,C}".
+        //
+        let bounds : Vec<&str> = c.split(':').collect();
+        if bounds.len() == 2 {
+            let low = bounds[0].trim().parse::<f64>().ok()?;
+            let high = bounds[1].trim().parse::<f64>().ok()?;
+            userdef.adud_range = Some((low, high));
+        } else {
+            userdef.adud_enum = Some(c.split(',').map(
+                |e| e.trim().to_string()).collect());
+        }
+    }
+
+    Some(userdef)
+}
+
+//
+// Return the value of a header field as a String.  Header fields may carry any
+// data type indicator (notably USERDEF fields), so unlike adif_string(), we do
+// not validate the type here.
+//
+fn adif_header_string(adf: &AdiDataSpecifier) ->
+    Result<String, AdifParseError>
+{
+    match String::from_utf8(adf.adif_bytes.clone()) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(AdifParseError::ADIF_EBADINPUT(format!(
+                "header field \"{}\": value contained invalid bytes for \
+                UTF-8 string", adf.adif_name)))
+    }
+}
+
+//
+// Construct a physical data specifier for the given field.
+//
+pub fn adif_data_specifier(name: &str, typestr: &Option<String>,
+    value: &str) -> AdiDataSpecifier
+{
+    AdiDataSpecifier {
+        adif_name: name.to_string(),
+        adif_name_canon: name.to_lowercase(),
+        adif_bytes: value.as_bytes().to_vec(),
+        adif_type: typestr.clone()
+    }
+}
+
+//
+// Returns true if "name" can be written as a field name in an ADI file.
+//
+pub fn adif_valid_field_name(name: &str) -> bool
+{
+    !name.is_empty() && !name.contains(|c: char| c.is_whitespace() ||
+        ",:<>{}".contains(c))
+}
+
+//
+// Convert the logical contents of a file back into the physical form that we'd
+// write to an ADI file.  This is the inverse of adif_parse_adi().  Header
+// fields are written in their original order with their original values.
+// Fields that are present with an empty value are written out (as
+// "<FIELD:0>"), while fields that are absent are not.
+//
+pub fn adif_to_adi(adif: &AdifFile) -> AdiFile
+{
+    let header = &adif.adif_header;
+    let mut fields = Vec::new();
+
+    for field in &header.adh_fields {
+        fields.push(adif_data_specifier(&field.adhf_name, &field.adhf_type,
+            &field.adhf_value));
+    }
+
+    let preamble = match header.adh_preamble {
+        Some(ref p) if !p.starts_with('<') => p.clone(),
+        Some(ref p) => format!("{}{}", ADIF_DEFAULT_PREAMBLE, p),
+        None => String::from(ADIF_DEFAULT_PREAMBLE)
+    };
+
+    let records = adif.adif_records.iter().map(|rec| {
+        let mut adir_fields = Vec::new();
+        for (name, value) in &rec.adir_field_values {
+            let upper = name.to_uppercase();
+            adir_fields.push(adif_data_specifier(&upper, &None, value));
+            if let Some(extras) = rec.adir_extra_values.get(name) {
+                for extra in extras {
+                    adir_fields.push(adif_data_specifier(&upper, &None, extra));
+                }
+            }
+        }
+        AdiRecord { adir_fields }
+    }).collect();
+
+    AdiFile {
+        adi_header: Some(AdiHeader {
+            adih_content: preamble.into_bytes(),
+            adih_fields: fields
+        }),
+        adi_records: records
+    }
+}
+
+//
+// Data type indicators defined by the specification.  ADIF represents all of
+// these as strings, so for now we accept any of them and return the value as a
+// String.
+//
+const ADIF_TYPE_INDICATORS : &str = "ABDEGILMNST";
+
+//
+// Given a data specifier, return a new String containing the field's contents.
+// This returns an error if the field has an unknown data type indicator or the
+// value cannot be processed as UTF-8.
+//
+fn adif_string(adf: &AdiDataSpecifier) ->
+    Result<String, AdifParseError>
+{
     if let Some(ref typestr) = adf.adif_type {
-        if typestr != "S" {
+        let typeupper = typestr.to_uppercase();
+        if typeupper.len() != 1 || !ADIF_TYPE_INDICATORS.contains(&typeupper) {
             return Err(AdifParseError::ADIF_EBADINPUT(format!(
-                "field \"{}\": expected string value, but found type \"{}\"",
+                "field \"{}\": unsupported data type indicator \"{}\"",
                 adf.adif_name, typestr)))
         }
     }
@@ -222,3 +643,174 @@ fn adif_string(adf: &AdiDataSpecifier) ->
                 adf.adif_name)))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use adi::adi_parse_string;
+    use adi::adi_write;
+    use adiftypes::adif_parse_version;
+    use super::*;
+
+    #[test]
+    fn header_model() {
+        let adi = adi_parse_string("Exported from a test\n\
+            <ADIF_VER:5>3.1.0\n\
+            <CREATED_TIMESTAMP:15>20181129 120130\n\
+            <PROGRAMID:4>test\n\
+            <USERDEF1:19:E>SweaterSize,{S,M,L}\n\
+            <USERDEF2:15:N>ShoeSize,{5:20}\n\
+            <APP_TEST_FOO:3>bar\n\
+            <Custom:1>x\n\
+            <EOH>\n\
+            <call:6>KK6ZBI<eor>").unwrap();
+        let adif = super::adif_parse_adi("test", &adi,
+            &super::AdifParseOptions::default()).unwrap();
+        let header = &adif.adif_header;
+
+        assert_eq!(header.adh_preamble.as_ref().unwrap().trim(),
+            "Exported from a test");
+        let version = adif_header_version(header).unwrap();
+        assert!(version > adif_parse_version("3.0.8").unwrap());
+        assert_eq!(adif_header_created_timestamp(header).unwrap().to_string(),
+            "20181129 120130");
+        assert_eq!(adif_header_program_id(header), Some("test"));
+        assert_eq!(adif_header_program_version(header), None);
+
+        let userdefs = adif_header_userdefs(header);
+        assert_eq!(userdefs.len(), 2);
+        assert_eq!(userdefs[0].adud_name, "SweaterSize");
+        assert_eq!(userdefs[0].adud_enum, Some(vec![
+            String::from("S"), String::from("M"), String::from("L")]));
+        assert_eq!(userdefs[1].adud_id, 2);
+        assert_eq!(userdefs[1].adud_range, Some((5.0, 20.0)));
+
+        let unknown = adif_header_unknown_fields(header);
+        assert_eq!(unknown.len(), 2);
+        assert_eq!(unknown[0].adhf_name, "APP_TEST_FOO");
+        assert_eq!(unknown[1].adhf_name, "Custom");
+        assert_eq!(unknown[1].adhf_value, "x");
+
+        let names : Vec<&str> = header.adh_fields.iter().map(
+            |f| f.adhf_name.as_str()).collect();
+        assert_eq!(names, vec![ "ADIF_VER", "CREATED_TIMESTAMP", "PROGRAMID",
+            "USERDEF1", "USERDEF2", "APP_TEST_FOO", "Custom" ]);
+    }
+
+    #[test]
+    fn header_original_text() {
+        let adi = adi_parse_string("\n\
+            <CREATED_TIMESTAMP:13>20240101 1200\
+            <APP_B:1>2<PROGRAMID:1>x<APP_A:1>1<eoh>").unwrap();
+        let mut adif = super::adif_parse_adi("test", &adi,
+            &AdifParseOptions::default()).unwrap();
+        let header = &mut adif.adif_header;
+        assert_eq!(adif_header_value(header, "created_timestamp"),
+            Some("20240101 1200"));
+        assert_eq!(adif_header_created_timestamp(header).unwrap().to_string(),
+            "20240101 120000");
+
+        adif_header_set(header, "programid", "y");
+        adif_header_set(header, "PROGRAMVERSION", "2");
+        let fields : Vec<(&str, &str)> = header.adh_fields.iter().map(
+            |f| (f.adhf_name.as_str(), f.adhf_value.as_str())).collect();
+        assert_eq!(fields, vec![ ("CREATED_TIMESTAMP", "20240101 1200"),
+            ("APP_B", "2"), ("PROGRAMID", "y"), ("APP_A", "1"),
+            ("PROGRAMVERSION", "2") ]);
+    }
+
+    #[test]
+    fn header_malformed_fields() {
+        let adi = adi_parse_string("\n<adif_ver:3>new\
+            <created_timestamp:9>yesterday<eoh>").unwrap();
+        let adif = super::adif_parse_adi("test", &adi,
+            &super::AdifParseOptions::default()).unwrap();
+        let header = &adif.adif_header;
+        assert!(header.adh_preamble.is_none());
+        assert!(adif_header_version(header).is_none());
+        assert!(adif_header_created_timestamp(header).is_none());
+        assert_eq!(adif_header_unknown_fields(header).len(), 2);
+        assert_eq!(adif.adif_diagnostics.len(), 2);
+    }
+
+    fn parse_duplicates(policy: super::AdifDuplicatePolicy) ->
+        Result<super::AdifFile, super::AdifParseError>
+    {
+        let adi = adi_parse_string("<call:6>KK6ZBI<comment:0><comment:3>abc\
+            <comment:3>def<eor>").unwrap();
+        let options = super::AdifParseOptions { apo_duplicates: policy };
+        super::adif_parse_adi("test", &adi, &options)
+    }
+
+    #[test]
+    fn empty_and_absent() {
+        let adi = adi_parse_string("<call:6>KK6ZBI<comment:0><eor>").unwrap();
+        let adif = super::adif_parse_adi("test", &adi,
+            &super::AdifParseOptions::default()).unwrap();
+        let record = &adif.adif_records[0];
+        assert_eq!(super::adif_record_field(record, "call"),
+            super::AdifFieldState::ADFS_VALUE("KK6ZBI"));
+        assert_eq!(super::adif_record_field(record, "comment"),
+            super::AdifFieldState::ADFS_EMPTY);
+        assert_eq!(super::adif_record_field(record, "name"),
+            super::AdifFieldState::ADFS_ABSENT);
+
+        let mut output = Vec::new();
+        adi_write(&super::adif_to_adi(&adif), &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.contains("<COMMENT:0>"));
+        assert!(!text.contains("<NAME"));
+    }
+
+    #[test]
+    fn round_trip() {
+        let adi = adi_parse_string("preamble text\n\
+            <ADIF_VER:5>3.1.0<App_Test_Foo:3:N>123\
+            <CREATED_TIMESTAMP:13>20240101 1200\
+            <USERDEF1:19:E>SweaterSize,{S,M,L}\
+            <USERDEF2:15:N>ShoeSize,{5:20}<EOH>\n\
+            <call:6>KK6ZBI<SweaterSize:1>M<eor>").unwrap();
+        let adif = super::adif_parse_adi("test", &adi,
+            &super::AdifParseOptions::default()).unwrap();
+
+        let mut output = Vec::new();
+        adi_write(&super::adif_to_adi(&adif), &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert_eq!(text, "preamble text\n\
+            <ADIF_VER:5>3.1.0\n\
+            <App_Test_Foo:3:N>123\n\
+            <CREATED_TIMESTAMP:13>20240101 1200\n\
+            <USERDEF1:19:E>SweaterSize,{S,M,L}\n\
+            <USERDEF2:15:N>ShoeSize,{5:20}\n\
+            <EOH>\n\
+            <CALL:6>KK6ZBI <SWEATERSIZE:1>M <EOR>\n");
+    }
+
+    #[test]
+    fn duplicate_policy() {
+        assert!(parse_duplicates(super::AdifDuplicatePolicy::ADP_ERROR)
+            .is_err());
+
+        let adif = parse_duplicates(
+            super::AdifDuplicatePolicy::ADP_KEEP_FIRST).unwrap();
+        assert_eq!(adif.adif_records[0].adir_field_values["comment"], "");
+        assert_eq!(adif.adif_diagnostics.len(), 2);
+        assert_eq!(adif.adif_diagnostics[0].to_string(),
+            "record 1: duplicate value for field \"comment\": \
+            kept first value");
+
+        let adif = parse_duplicates(
+            super::AdifDuplicatePolicy::ADP_KEEP_LAST).unwrap();
+        assert_eq!(adif.adif_records[0].adir_field_values["comment"], "def");
+
+        let adif = parse_duplicates(
+            super::AdifDuplicatePolicy::ADP_KEEP_FIRST_NONEMPTY).unwrap();
+        assert_eq!(adif.adif_records[0].adir_field_values["comment"], "abc");
+
+        let adif = parse_duplicates(
+            super::AdifDuplicatePolicy::ADP_KEEP_ALL).unwrap();
+        let record = &adif.adif_records[0];
+        assert_eq!(record.adir_field_values["comment"], "");
+        assert_eq!(record.adir_extra_values["comment"], vec![
+            String::from("abc"), String::from("def")]);
+    }
+}
@@ -0,0 +1,366 @@
+//
+// src/adiftypes.rs: typed representations of ADIF values
+//
+// ADIF transmits every value as a string, but many values have a well-defined
+// format (e.g., a Date is "YYYYMMDD" and a Time is "HHMM" or "HHMMSS").  The
+// types in this file represent those values in a form that can be compared and
+// manipulated, along with functions for converting to and from their string
+// forms.
+//
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+//
+// AdifVersion: an ADIF version number, as found in the "adif_ver" header field
+// (e.g., "3.0.8").  Versions compare numerically, component by component, with
+// missing trailing components treated as zero (so "3.1" == "3.1.0").  We keep
+// the original text so that the version can be written out exactly as it was
+// read.
+//
+#[derive(Clone, Debug)]
+pub struct AdifVersion {
+    pub adv_text : String,          // version as it appeared in the input
+    pub adv_components : Vec<u32>   // numeric components (e.g., [3, 0, 8])
+}
+
+impl PartialEq for AdifVersion {
+    fn eq(&self, other: &AdifVersion) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for AdifVersion {}
+
+impl PartialOrd for AdifVersion {
+    fn partial_cmp(&self, other: &AdifVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for AdifVersion {
+    fn cmp(&self, other: &AdifVersion) -> Ordering {
+        let n = std::cmp::max(self.adv_components.len(),
+            other.adv_components.len());
+        for i in 0..n {
+            let c1 = self.adv_components.get(i).cloned().unwrap_or(0);
+            let c2 = other.adv_components.get(i).cloned().unwrap_or(0);
+            match c1.cmp(&c2) {
+                Ordering::Equal => (),
+                o => return o
+            }
+        }
+
+        Ordering::Equal
+    }
+}
+
+impl fmt::Display for AdifVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.adv_text)
+    }
+}
+
+//
+// Parse a version string like "3.0.8" or "2.2".  Returns None if the string is
+// not a dot-separated list of non-negative integers.
+//
+pub fn adif_parse_version(text: &str) -> Option<AdifVersion>
+{
+    let trimmed = text.trim();
+    let mut components = Vec::new();
+
+    for part in trimmed.split('.') {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        match part.parse::<u32>() {
+            Ok(n) => components.push(n),
+            Err(_) => return None
+        }
+    }
+
+    Some(AdifVersion {
+        adv_text: trimmed.to_string(),
+        adv_components: components
+    })
+}
+
+//
+// AdifDate: an ADIF Date ("YYYYMMDD").  The field order makes the derived
+// comparison chronological.
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AdifDate {
+    pub add_year : u32,
+    pub add_month : u32,    // 1-12
+    pub add_day : u32       // 1-31
+}
+
+impl fmt::Display for AdifDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}{:02}{:02}", self.add_year, self.add_month,
+            self.add_day)
+    }
+}
+
+//
+// AdifTime: an ADIF Time ("HHMM" or "HHMMSS").  When seconds are not specified,
+// "adt_second" is zero.
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AdifTime {
+    pub adt_hour : u32,     // 0-23
+    pub adt_minute : u32,   // 0-59
+    pub adt_second : u32    // 0-59
+}
+
+impl fmt::Display for AdifTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}{:02}{:02}", self.adt_hour, self.adt_minute,
+            self.adt_second)
+    }
+}
+
+//
+// AdifTimestamp: a date and time, as in the "created_timestamp" header field
+// ("YYYYMMDD HHMMSS").  All ADIF times are UTC.
+//
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AdifTimestamp {
+    pub adts_date : AdifDate,
+    pub adts_time : AdifTime
+}
+
+impl fmt::Display for AdifTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.adts_date, self.adts_time)
+    }
+}
+
+//
+// Parse "nbytes" ASCII digits at the start of "text" as an integer.
+//
+fn adif_parse_digits(text: &[u8], nbytes: usize) -> Option<u32>
+{
+    if text.len() < nbytes {
+        return None;
+    }
+
+    let mut value = 0;
+    for &b in &text[0..nbytes] {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        value = value * 10 + (b - b'0') as u32;
+    }
+
+    Some(value)
+}
+
+//
+// Returns the number of days in the given month of the given year.
+//
+pub fn adif_days_in_month(year: u32, month: u32) -> u32
+{
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year.is_multiple_of(4) &&
+            (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        _ => 0
+    }
+}
+
+//
+// Parse an ADIF Date ("YYYYMMDD").  The specification requires that the year be
+// at least 1930; we accept any four-digit year so that we can report on old or
+// bogus data rather than failing to process it.
+//
+pub fn adif_parse_date(text: &str) -> Option<AdifDate>
+{
+    let bytes = text.trim().as_bytes();
+    if bytes.len() != 8 {
+        return None;
+    }
+
+    let year = adif_parse_digits(bytes, 4)?;
+    let month = adif_parse_digits(&bytes[4..], 2)?;
+    let day = adif_parse_digits(&bytes[6..], 2)?;
+    if day < 1 || day > adif_days_in_month(year, month) {
+        return None;
+    }
+
+    Some(AdifDate { add_year: year, add_month: month, add_day: day })
+}
+
+//
+// Parse an ADIF Time ("HHMM" or "HHMMSS").
+//
+pub fn adif_parse_time(text: &str) -> Option<AdifTime>
+{
+    let bytes = text.trim().as_bytes();
+    if bytes.len() != 4 && bytes.len() != 6 {
+        return None;
+    }
+
+    let hour = adif_parse_digits(bytes, 2)?;
+    let minute = adif_parse_digits(&bytes[2..], 2)?;
+    let second = if bytes.len() == 6 {
+        adif_parse_digits(&bytes[4..], 2)?
+    } else {
+        0
+    };
+
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    Some(AdifTime { adt_hour: hour, adt_minute: minute, adt_second: second })
+}
+
+//
+// Parse a timestamp of the form used by the "created_timestamp" header field
+// ("YYYYMMDD HHMMSS").
+//
+pub fn adif_parse_timestamp(text: &str) -> Option<AdifTimestamp>
+{
+    let mut parts = text.split_whitespace();
+    let date = adif_parse_date(parts.next()?)?;
+    let time = adif_parse_time(parts.next()?)?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(AdifTimestamp { adts_date: date, adts_time: time })
+}
+
+//
+// Returns the number of days between 1970-01-01 and the given date (negative
+// for earlier dates).  This is the usual "days from civil" algorithm for the
+// proleptic Gregorian calendar.
+//
+pub fn adif_date_to_days(date: &AdifDate) -> i64
+{
+    let month = date.add_month as i64;
+    let year = date.add_year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + date.add_day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+//
+// The inverse of adif_date_to_days().
+//
+pub fn adif_date_from_days(days: i64) -> AdifDate
+{
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    AdifDate {
+        add_year: year as u32,
+        add_month: month as u32,
+        add_day: day as u32
+    }
+}
+
+//
+// Returns the number of seconds between the Unix epoch and the given date and
+// time.  This makes it easy to compute differences between times that span
+// midnight (or months, or years).
+//
+pub fn adif_timestamp_to_seconds(ts: &AdifTimestamp) -> i64
+{
+    adif_date_to_days(&ts.adts_date) * 86400 +
+        (ts.adts_time.adt_hour * 3600 + ts.adts_time.adt_minute * 60 +
+        ts.adts_time.adt_second) as i64
+}
+
+//
+// The inverse of adif_timestamp_to_seconds().
+//
+pub fn adif_timestamp_from_seconds(seconds: i64) -> AdifTimestamp
+{
+    let days = seconds.div_euclid(86400);
+    let secs = seconds.rem_euclid(86400) as u32;
+    AdifTimestamp {
+        adts_date: adif_date_from_days(days),
+        adts_time: AdifTime {
+            adt_hour: secs / 3600,
+            adt_minute: (secs / 60) % 60,
+            adt_second: secs % 60
+        }
+    }
+}
+
+//
+// Returns the current time (UTC), e.g., for "created_timestamp" or the date a
+// QSL was sent.
+//
+pub fn adif_timestamp_now() -> AdifTimestamp
+{
+    let seconds = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64)
+    };
+    adif_timestamp_from_seconds(seconds)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn version_compare() {
+        let v308 = adif_parse_version("3.0.8").unwrap();
+        let v31 = adif_parse_version("3.1").unwrap();
+        let v310 = adif_parse_version("3.1.0").unwrap();
+        let v2 = adif_parse_version("2.2.7").unwrap();
+
+        assert!(v308 < v31);
+        assert!(v2 < v308);
+        assert_eq!(v31, v310);
+        assert_eq!(v31.to_string(), "3.1");
+        assert!(adif_parse_version("3.x").is_none());
+        assert!(adif_parse_version("").is_none());
+        assert!(adif_parse_version("3..1").is_none());
+    }
+
+    #[test]
+    fn timestamps() {
+        let ts = adif_parse_timestamp("20181129 235930").unwrap();
+        assert_eq!(ts.adts_date.add_year, 2018);
+        assert_eq!(ts.adts_time.adt_second, 30);
+        assert_eq!(ts.to_string(), "20181129 235930");
+
+        assert!(adif_parse_timestamp("20181129").is_none());
+        assert!(adif_parse_timestamp("20180229 000000").is_none());
+        assert!(adif_parse_timestamp("20160229 2400").is_none());
+        assert!(adif_parse_time("1234").is_some());
+        assert!(adif_parse_time("12345").is_none());
+
+        let secs = adif_timestamp_to_seconds(&ts);
+        assert_eq!(adif_timestamp_from_seconds(secs), ts);
+        let next = adif_timestamp_from_seconds(secs + 60);
+        assert_eq!(next.to_string(), "20181130 000030");
+
+        let epoch = adif_parse_date("19700101").unwrap();
+        assert_eq!(adif_date_to_days(&epoch), 0);
+        let leap = adif_parse_date("20000301").unwrap();
+        assert_eq!(adif_date_from_days(adif_date_to_days(&leap)), leap);
+    }
+}
@@ -23,7 +23,7 @@
 // short ASCII strings (like "eor") within arbitrarily large byte streams that
 // may contain non-ASCII characters.  That in turn seems strange -- blame ADI.
 //
-pub fn byteseq_equal_ci(bytes: &Vec<u8>, s: &str) -> bool
+pub fn byteseq_equal_ci(bytes: &[u8], s: &str) -> bool
 {
     //
     // Rather than bother checking the size, cloning "bytes", converting to a
@@ -45,7 +45,7 @@ pub fn byteseq_equal_ci(bytes: &Vec<u8>, s: &str) -> bool
         i += 1;
     }
 
-    return true;
+    true
 }
 
 // TODO add tests
@@ -0,0 +1,625 @@
+//
+// src/adx.rs: ADX (XML) physical format
+//
+// ADX represents the same logical content as ADI using XML.  A file looks like:
+//
+//     <?xml version="1.0" encoding="UTF-8"?>
+//     <ADX>
+//       <HEADER>
+//         <ADIF_VER>3.1.4</ADIF_VER>
+//         <USERDEF FIELDID="1" TYPE="N">EPC</USERDEF>
+//       </HEADER>
+//       <RECORDS>
+//         <RECORD>
+//           <CALL>KK6ZBI</CALL>
+//           <APP PROGRAMID="N1MM" FIELDNAME="EXCHANGE1" TYPE="S">5</APP>
+//           <USERDEF FIELDNAME="EPC">32123</USERDEF>
+//         </RECORD>
+//       </RECORDS>
+//     </ADX>
+//
+// Application-defined fields ("APP_PROGRAMID_FIELDNAME" in ADI) and references
+// to user-defined fields get their own elements, as shown.  The ADI preamble
+// has no equivalent in ADX, so it's not written.
+//
+// adx_parse() reads ADX by translating each element back into the data
+// specifier that would appear in an ADI file, then interpreting the result
+// exactly as we would an ADI file.  We only implement as much XML as ADX files
+// use: elements, attributes, character and entity references, comments,
+// CDATA sections, and (ignored) processing instructions and DOCTYPEs.
+//
+
+use std::io;
+
+use adi::AdiDataSpecifier;
+use adi::AdiFile;
+use adi::AdiHeader;
+use adi::AdiRecord;
+use adif::AdifFile;
+use adif::AdifParseOptions;
+use adif::adif_data_specifier;
+use adif::adif_header_userdefs;
+use adif::adif_parse_adi;
+use adif::adif_parse_userdef;
+use super::AdifParseError;
+
+//
+// Returns "s" with the characters that are special in XML replaced by
+// entity references.
+//
+pub fn adx_escape(s: &str) -> String
+{
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+//
+// Write one field as an element.  "name" is the field name as it would appear
+// in an ADI file, "typestr" is its data type indicator (if any), and "indent"
+// is the leading whitespace.
+//
+fn adx_write_field(out: &mut dyn io::Write, indent: &str, name: &str,
+    typestr: Option<&str>, userdef: bool, value: &str) ->
+    Result<(), AdifParseError>
+{
+    let upper = name.to_uppercase();
+    let typeattr = match typestr {
+        Some(t) => format!(" TYPE=\"{}\"", adx_escape(t)),
+        None => String::new()
+    };
+
+    if userdef {
+        writeln!(out, "{}<USERDEF FIELDNAME=\"{}\">{}</USERDEF>", indent,
+            adx_escape(name), adx_escape(value))?;
+        return Ok(());
+    }
+
+    if let Some(rest) = upper.strip_prefix("APP_") {
+        if let Some(p) = rest.find('_') {
+            let (program, field) = rest.split_at(p);
+            if !program.is_empty() && field.len() > 1 {
+                writeln!(out, "{}<APP PROGRAMID=\"{}\" FIELDNAME=\"{}\"{}>{}\
+                    </APP>", indent, adx_escape(program),
+                    adx_escape(&field[1..]), typeattr, adx_escape(value))?;
+                return Ok(());
+            }
+        }
+    }
+
+    writeln!(out, "{}<{}>{}</{}>", indent, upper, adx_escape(value), upper)?;
+    Ok(())
+}
+
+//
+// Write a complete ADX file.
+//
+pub fn adx_write(adif: &AdifFile, out: &mut dyn io::Write) ->
+    Result<(), AdifParseError>
+{
+    let header = &adif.adif_header;
+    let userdefs = adif_header_userdefs(header);
+    let indent = "    ";
+
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<ADX>")?;
+    writeln!(out, "  <HEADER>")?;
+    for field in &header.adh_fields {
+        let userdef = match adif_parse_userdef(field) {
+            Some(u) => u,
+            None => {
+                adx_write_field(out, indent, &field.adhf_name,
+                    field.adhf_type.as_deref(), false, &field.adhf_value)?;
+                continue;
+            }
+        };
+
+        let mut attrs = format!(" FIELDID=\"{}\"", userdef.adud_id);
+        if let Some(ref t) = userdef.adud_type {
+            attrs.push_str(&format!(" TYPE=\"{}\"", adx_escape(t)));
+        }
+        if let Some(ref values) = userdef.adud_enum {
+            attrs.push_str(&format!(" ENUM=\"{{{}}}\"",
+                adx_escape(&values.join(","))));
+        } else if let Some((low, high)) = userdef.adud_range {
+            attrs.push_str(&format!(" RANGE=\"{{{}:{}}}\"", low, high));
+        }
+        writeln!(out, "{}<USERDEF{}>{}</USERDEF>", indent, attrs,
+            adx_escape(&userdef.adud_name))?;
+    }
+    writeln!(out, "  </HEADER>")?;
+
+    let indent = "      ";
+    writeln!(out, "  <RECORDS>")?;
+    for rec in &adif.adif_records {
+        writeln!(out, "    <RECORD>")?;
+        for (key, value) in &rec.adir_field_values {
+            //
+            // References to user-defined fields use the name as it was
+            // defined in the header.
+            //
+            let userdef = userdefs.iter().find(
+                |u| u.adud_name.eq_ignore_ascii_case(key));
+            let name = match userdef {
+                Some(u) => &u.adud_name,
+                None => key
+            };
+            let values = std::iter::once(value).chain(
+                rec.adir_extra_values.get(key).into_iter().flatten());
+            for v in values {
+                adx_write_field(out, indent, name, None, userdef.is_some(),
+                    v)?;
+            }
+        }
+        writeln!(out, "    </RECORD>")?;
+    }
+    writeln!(out, "  </RECORDS>")?;
+    writeln!(out, "</ADX>")?;
+    Ok(())
+}
+
+//
+// AdxElement: an XML element.  Text content is only kept for elements that
+// have no child elements (which is all ADX uses it for).
+//
+struct AdxElement {
+    axe_name : String,
+    axe_attrs : Vec<(String, String)>,
+    axe_text : String,
+    axe_children : Vec<AdxElement>,
+    axe_line : usize                // line on which the element starts
+}
+
+impl AdxElement {
+    fn attr(&self, name: &str) -> Option<&str>
+    {
+        self.axe_attrs.iter().find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+//
+// Elements nested deeper than this are rejected rather than risk overflowing
+// the stack.  ADX itself only needs four levels.
+//
+const ADX_MAX_DEPTH : usize = 32;
+
+struct AdxParser<'a> {
+    axp_text : &'a str,
+    axp_pos : usize                 // byte offset of the next character
+}
+
+impl<'a> AdxParser<'a> {
+    fn line(&self) -> usize
+    {
+        self.axp_text[..self.axp_pos].matches('\n').count() + 1
+    }
+
+    fn error(&self, message: &str) -> AdifParseError
+    {
+        AdifParseError::ADIF_EBADINPUT(format!("line {}: {}", self.line(),
+            message))
+    }
+
+    fn rest(&self) -> &'a str
+    {
+        &self.axp_text[self.axp_pos..]
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        let rest = self.rest();
+        self.axp_pos += rest.len() - rest.trim_start().len();
+    }
+
+    //
+    // Skip past the next occurrence of "end".
+    //
+    fn skip_past(&mut self, end: &str, what: &str) -> Result<(), AdifParseError>
+    {
+        match self.rest().find(end) {
+            Some(p) => {
+                self.axp_pos += p + end.len();
+                Ok(())
+            },
+            None => Err(self.error(&format!("unterminated {}", what)))
+        }
+    }
+
+    //
+    // Skip comments, processing instructions, and DOCTYPE declarations, along
+    // with surrounding whitespace.
+    //
+    fn skip_misc(&mut self) -> Result<(), AdifParseError>
+    {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if self.rest().starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else if self.rest().starts_with("<!DOCTYPE") {
+                self.skip_past(">", "DOCTYPE")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, AdifParseError>
+    {
+        let rest = self.rest();
+        let len = rest.find(|c: char| c.is_whitespace() ||
+            "/>=".contains(c)).unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.axp_pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    //
+    // Parse an element, starting at its "<".
+    //
+    fn element(&mut self, depth: usize) -> Result<AdxElement, AdifParseError>
+    {
+        if depth > ADX_MAX_DEPTH {
+            return Err(self.error("elements nested too deeply"));
+        }
+
+        let line = self.line();
+        self.axp_pos += 1;
+        let mut element = AdxElement {
+            axe_name: self.name()?,
+            axe_attrs: Vec::new(),
+            axe_text: String::new(),
+            axe_children: Vec::new(),
+            axe_line: line
+        };
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.axp_pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.axp_pos += 1;
+                break;
+            }
+
+            let name = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error(&format!(
+                    "expected '=' after attribute \"{}\"", name)));
+            }
+            self.axp_pos += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(q) if q == '"' || q == '\'' => q,
+                _ => return Err(self.error(&format!(
+                    "expected quoted value for attribute \"{}\"", name)))
+            };
+            self.axp_pos += 1;
+            let len = match self.rest().find(quote) {
+                Some(len) => len,
+                None => return Err(self.error("unterminated attribute value"))
+            };
+            let value = adx_unescape(&self.rest()[..len]).map_err(
+                |message| self.error(&message))?;
+            self.axp_pos += len + 1;
+            element.axe_attrs.push((name, value));
+        }
+
+        //
+        // Parse the content, up to the matching end tag.
+        //
+        let mut text = String::new();
+        loop {
+            let rest = self.rest();
+            let len = rest.find('<').unwrap_or(rest.len());
+            text.push_str(&adx_unescape(&rest[..len]).map_err(
+                |message| self.error(&message))?);
+            self.axp_pos += len;
+
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(self.error(&format!("element \"{}\" (line {}) \
+                    is not closed", element.axe_name, line)));
+            } else if rest.starts_with("</") {
+                self.axp_pos += 2;
+                let name = self.name()?;
+                if name != element.axe_name {
+                    return Err(self.error(&format!("expected \"</{}>\", \
+                        found \"</{}>\"", element.axe_name, name)));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(self.error("expected '>'"));
+                }
+                self.axp_pos += 1;
+                break;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let len = match cdata.find("]]>") {
+                    Some(len) => len,
+                    None => return Err(self.error("unterminated CDATA section"))
+                };
+                text.push_str(&cdata[..len]);
+                self.axp_pos += "<![CDATA[".len() + len + "]]>".len();
+            } else {
+                let child = self.element(depth + 1)?;
+                element.axe_children.push(child);
+            }
+        }
+
+        if element.axe_children.is_empty() {
+            element.axe_text = text;
+        } else if !text.trim().is_empty() {
+            return Err(AdifParseError::ADIF_EBADINPUT(format!("line {}: \
+                element \"{}\" contains both text and elements", line,
+                element.axe_name)));
+        }
+
+        Ok(element)
+    }
+}
+
+//
+// Replace entity and character references in "s".
+//
+fn adx_unescape(s: &str) -> Result<String, String>
+{
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(p) = rest.find('&') {
+        unescaped.push_str(&rest[..p]);
+        rest = &rest[p..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => return Err(String::from("unterminated entity reference"))
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse::<u32>().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+            }
+        };
+        match c {
+            Some(c) => unescaped.push(c),
+            None => return Err(format!("unsupported entity reference \"&{};\"",
+                entity))
+        }
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+    Ok(unescaped)
+}
+
+//
+// Translate one field element (a child of HEADER or RECORD) into the data
+// specifier that ADI would use for it.
+//
+fn adx_field(element: &AdxElement, in_header: bool) ->
+    Result<AdiDataSpecifier, AdifParseError>
+{
+    let bad = |message: &str| AdifParseError::ADIF_EBADINPUT(format!(
+        "line {}: element \"{}\": {}", element.axe_line, element.axe_name,
+        message));
+    let required = |name: &str| element.attr(name).ok_or_else(
+        || bad(&format!("missing attribute \"{}\"", name)));
+
+    if !element.axe_children.is_empty() {
+        return Err(bad("unexpected child element"));
+    }
+
+    let typestr = element.attr("TYPE").map(|t| t.to_string());
+    let text = &element.axe_text;
+    let upper = element.axe_name.to_uppercase();
+    if upper == "APP" {
+        let name = format!("APP_{}_{}", required("PROGRAMID")?,
+            required("FIELDNAME")?);
+        return Ok(adif_data_specifier(&name, &typestr, text));
+    }
+
+    if upper != "USERDEF" {
+        return Ok(adif_data_specifier(&upper, &typestr, text));
+    }
+
+    if !in_header {
+        return Ok(adif_data_specifier(required("FIELDNAME")?, &None, text));
+    }
+
+    //
+    // A field definition.  ADI writes the constraint after the field name
+    // (e.g., "<USERDEF2:19:E>SweaterSize,{S,M,L}").
+    //
+    let id = required("FIELDID")?;
+    let mut value = text.trim().to_string();
+    if let Some(c) = element.attr("ENUM").or(element.attr("RANGE")) {
+        value.push(',');
+        value.push_str(c);
+    }
+    Ok(adif_data_specifier(&format!("USERDEF{}", id), &typestr, &value))
+}
+
+//
+// Parse an ADX file.
+//
+pub fn adx_parse(label: &str, source: &mut dyn io::Read,
+    options: &AdifParseOptions) -> Result<AdifFile, AdifParseError>
+{
+    let mut text = String::new();
+    if source.read_to_string(&mut text).is_err() {
+        return Err(AdifParseError::ADIF_EBADINPUT(String::from(
+            "file is not valid UTF-8")));
+    }
+
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+    let mut parser = AdxParser { axp_text: text, axp_pos: 0 };
+    parser.skip_misc()?;
+    if !parser.rest().starts_with('<') {
+        return Err(parser.error("expected \"<ADX>\""));
+    }
+    let root = parser.element(0)?;
+    parser.skip_misc()?;
+    if !parser.rest().is_empty() {
+        return Err(parser.error("unexpected content after \"</ADX>\""));
+    }
+    if !root.axe_name.eq_ignore_ascii_case("ADX") {
+        return Err(AdifParseError::ADIF_EBADINPUT(format!(
+            "expected \"<ADX>\", found \"<{}>\"", root.axe_name)));
+    }
+
+    let mut adi = AdiFile { adi_header: None, adi_records: Vec::new() };
+    for section in &root.axe_children {
+        let name = section.axe_name.to_uppercase();
+        if name == "HEADER" {
+            let mut fields = Vec::new();
+            for child in &section.axe_children {
+                fields.push(adx_field(child, true)?);
+            }
+            adi.adi_header = Some(AdiHeader {
+                adih_content: Vec::new(),
+                adih_fields: fields
+            });
+        } else if name == "RECORDS" {
+            for rec in &section.axe_children {
+                if !rec.axe_name.eq_ignore_ascii_case("RECORD") {
+                    return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                        "line {}: expected \"<RECORD>\", found \"<{}>\"",
+                        rec.axe_line, rec.axe_name)));
+                }
+                let mut fields = Vec::new();
+                for child in &rec.axe_children {
+                    fields.push(adx_field(child, false)?);
+                }
+                adi.adi_records.push(AdiRecord { adir_fields: fields });
+            }
+        } else {
+            return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                "line {}: unexpected element \"{}\"", section.axe_line,
+                section.axe_name)));
+        }
+    }
+
+    adif_parse_adi(label, &adi, options)
+}
+
+#[cfg(test)]
+mod test {
+    use adi::adi_parse_string;
+    use adif::AdifParseOptions;
+    use adif::adif_header_userdefs;
+    use adif::adif_header_value;
+    use adif::adif_parse_adi;
+    use super::adx_parse;
+    use super::adx_write;
+
+    #[test]
+    fn write() {
+        let adi = adi_parse_string("Test file\n<ADIF_VER:5>3.1.4\
+            <USERDEF1:10:E>Size,{S,M}<APP_N1MM_ID:3>abc<EOH>\n\
+            <CALL:6>KK6ZBI<COMMENT:8>R&D <ok><SIZE:1>M\
+            <APP_N1MM_EXCHANGE1:2>5A<EOR>\n").unwrap();
+        let adif = adif_parse_adi("test", &adi,
+            &AdifParseOptions::default()).unwrap();
+        let mut out = Vec::new();
+        adx_write(&adif, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <ADX>\n  <HEADER>\n\
+            \x20   <ADIF_VER>3.1.4</ADIF_VER>\n\
+            \x20   <USERDEF FIELDID=\"1\" TYPE=\"E\" ENUM=\"{S,M}\">Size\
+            </USERDEF>\n\
+            \x20   <APP PROGRAMID=\"N1MM\" FIELDNAME=\"ID\">abc</APP>\n\
+            \x20 </HEADER>\n  <RECORDS>\n    <RECORD>\n\
+            \x20     <APP PROGRAMID=\"N1MM\" FIELDNAME=\"EXCHANGE1\">5A</APP>\n\
+            \x20     <CALL>KK6ZBI</CALL>\n\
+            \x20     <COMMENT>R&amp;D &lt;ok&gt;</COMMENT>\n\
+            \x20     <USERDEF FIELDNAME=\"Size\">M</USERDEF>\n\
+            \x20   </RECORD>\n  </RECORDS>\n</ADX>\n");
+    }
+
+    #[test]
+    fn parse() {
+        let input = "\u{feff}<?xml version=\"1.0\"?>\n\
+            <!-- exported -->\n<ADX>\n  <HEADER>\n\
+            \x20   <ADIF_VER>3.1.4</ADIF_VER>\n\
+            \x20   <USERDEF FIELDID=\"1\" TYPE=\"E\" ENUM=\"{S,M}\">Size\
+            </USERDEF>\n\
+            \x20   <APP PROGRAMID=\"N1MM\" FIELDNAME=\"ID\">abc</APP>\n\
+            \x20 </HEADER>\n  <RECORDS>\n    <RECORD>\n\
+            \x20     <CALL>KK6ZBI</CALL>\n\
+            \x20     <COMMENT>R&amp;D &lt;ok&gt; &#233;<![CDATA[<x>]]>\
+            </COMMENT>\n\
+            \x20     <APP PROGRAMID='N1MM' FIELDNAME='EXCHANGE1'>5A</APP>\n\
+            \x20     <USERDEF FIELDNAME=\"Size\">M</USERDEF>\n\
+            \x20     <NAME/>\n\
+            \x20   </RECORD>\n  </RECORDS>\n</ADX>\n";
+        let adif = adx_parse("test", &mut input.as_bytes(),
+            &AdifParseOptions::default()).unwrap();
+        let header = &adif.adif_header;
+        assert_eq!(adif_header_value(header, "adif_ver"), Some("3.1.4"));
+        let userdefs = adif_header_userdefs(header);
+        assert_eq!(userdefs[0].adud_name, "Size");
+        assert_eq!(userdefs[0].adud_enum,
+            Some(vec![ String::from("S"), String::from("M") ]));
+        assert_eq!(header.adh_fields[2].adhf_name, "APP_N1MM_ID");
+        let fields = &adif.adif_records[0].adir_field_values;
+        assert_eq!(fields["call"], "KK6ZBI");
+        assert_eq!(fields["comment"], "R&D <ok> \u{e9}<x>");
+        assert_eq!(fields["app_n1mm_exchange1"], "5A");
+        assert_eq!(fields["size"], "M");
+        assert_eq!(fields["name"], "");
+
+        //
+        // Writing what we read should produce the same file.
+        //
+        let mut out = Vec::new();
+        adx_write(&adif, &mut out).unwrap();
+        let again = adx_parse("test", &mut out.as_slice(),
+            &AdifParseOptions::default()).unwrap();
+        assert_eq!(again.adif_records[0].adir_field_values, *fields);
+
+        let bad = [
+            "<ADX><HEADER></ADX>",
+            "<ADX><RECORDS><RECORD><CALL>W1AW</RECORD></RECORDS></ADX>",
+            "<ADX><RECORDS><QSO/></RECORDS></ADX>",
+            "<ADX><RECORDS><RECORD><APP>1</APP></RECORD></RECORDS></ADX>",
+            "<ADX><RECORDS><RECORD><CALL>&bogus;</CALL></RECORD></RECORDS>\
+                </ADX>",
+            "<ADIF></ADIF>",
+            "<ADX></ADX><ADX></ADX>"
+        ];
+        for input in &bad {
+            assert!(adx_parse("test", &mut input.as_bytes(),
+                &AdifParseOptions::default()).is_err(), "{}", input);
+        }
+    }
+}
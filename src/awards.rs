@@ -0,0 +1,464 @@
+//
+// src/awards.rs: tracking progress toward operating awards
+//
+// Each award counts distinct "entities" (DXCC entities, US states, CQ zones,
+// grid squares, or WPX prefixes) that have been worked and confirmed.  Most
+// awards are available for all QSOs ("Mixed"), for each band, and for each
+// mode group (CW, phone, and digital), and we compute progress for each of
+// these categories.  VUCC is only awarded per band (for 6m and higher) and for
+// satellite QSOs.
+//
+// A QSO counts as confirmed if a paper QSL ("qsl_rcvd") or LoTW confirmation
+// ("lotw_qsl_rcvd") has been received, or an eQSL ("eqsl_qsl_rcvd") if the
+// caller chooses to accept those.  Separately, the "credit_submitted" and
+// "credit_granted" fields record which award credits have been applied for and
+// granted for a QSO.  These use the ADIF "Credit" enumeration (e.g.,
+// "DXCC_BAND" or "CQWAZ_MIXED"), optionally followed by the QSL media used
+// (e.g., "DXCC:LOTW&CARD").  A granted or submitted credit counts as a
+// confirmation for that award category even if the QSL fields don't say so.
+//
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use adif::AdifRecord;
+use callsign::adif_wpx_prefix;
+use fields::adif_band_compare;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdifAward {
+    AA_DXCC,            // ARRL DX Century Club (DXCC entities)
+    AA_WAS,             // ARRL Worked All States
+    AA_WAZ,             // CQ Worked All Zones
+    AA_VUCC,            // ARRL VHF/UHF Century Club (grid squares)
+    AA_WPX              // CQ WPX (prefixes)
+}
+
+pub const ADIF_AWARDS : &[AdifAward] = &[
+    AdifAward::AA_DXCC, AdifAward::AA_WAS, AdifAward::AA_WAZ,
+    AdifAward::AA_VUCC, AdifAward::AA_WPX
+];
+
+pub fn adif_parse_award(name: &str) -> Option<AdifAward>
+{
+    match name.to_lowercase().as_str() {
+        "dxcc" => Some(AdifAward::AA_DXCC),
+        "was" => Some(AdifAward::AA_WAS),
+        "waz" => Some(AdifAward::AA_WAZ),
+        "vucc" => Some(AdifAward::AA_VUCC),
+        "wpx" => Some(AdifAward::AA_WPX),
+        _ => None
+    }
+}
+
+pub fn adif_award_name(award: AdifAward) -> &'static str
+{
+    match award {
+        AdifAward::AA_DXCC => "DXCC",
+        AdifAward::AA_WAS => "WAS",
+        AdifAward::AA_WAZ => "WAZ",
+        AdifAward::AA_VUCC => "VUCC",
+        AdifAward::AA_WPX => "WPX"
+    }
+}
+
+//
+// Status of one entity for one award category.  These are ordered so that a
+// QSO with a "better" status supersedes the others.
+//
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AdifAwardStatus {
+    AAS_WORKED,
+    AAS_CONFIRMED,
+    AAS_SUBMITTED,
+    AAS_GRANTED
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifAwardOptions {
+    pub aao_accept_eqsl : bool          // count eQSL confirmations
+}
+
+//
+// AdifAwardProgress: progress toward one category of one award (e.g., DXCC on
+// 20m).
+//
+#[derive(Clone, Debug)]
+pub struct AdifAwardProgress {
+    pub aap_award : AdifAward,
+    pub aap_category : String,          // "Mixed", a band, a mode group, "SAT"
+    pub aap_entities : BTreeMap<String, AdifAwardStatus>,
+    pub aap_worked : usize,
+    pub aap_confirmed : usize,          // includes submitted and granted
+    pub aap_target : Option<usize>,     // confirmations required for award
+    pub aap_needed : Vec<String>        // known entities not yet confirmed
+}
+
+//
+// The 50 states counted for WAS.
+//
+pub const ADIF_WAS_STATES : &[&str] = &[
+    "AK", "AL", "AR", "AZ", "CA", "CO", "CT", "DE", "FL", "GA",
+    "HI", "IA", "ID", "IL", "IN", "KS", "KY", "LA", "MA", "MD",
+    "ME", "MI", "MN", "MO", "MS", "MT", "NC", "ND", "NE", "NH",
+    "NJ", "NM", "NV", "NY", "OH", "OK", "OR", "PA", "RI", "SC",
+    "SD", "TN", "TX", "UT", "VA", "VT", "WA", "WI", "WV", "WY"
+];
+
+//
+// DXCC entity codes for the United States, Alaska, and Hawaii.  A QSO only
+// counts for WAS if it's with one of these (or if its entity is unknown).
+//
+const ADIF_WAS_DXCC : &[&str] = &[ "291", "6", "110" ];
+
+//
+// Bands that count for VUCC, with the number of grid squares required for the
+// award on each.  (The higher microwave bands require fewer; we use the lowest
+// requirement for all of them.)
+//
+const ADIF_VUCC_BANDS : &[(&str, usize)] = &[
+    ("6M", 100), ("2M", 100), ("1.25M", 50), ("70CM", 50), ("33CM", 25),
+    ("23CM", 25), ("13CM", 10), ("9CM", 5), ("6CM", 5), ("3CM", 5),
+    ("1.25CM", 5), ("6MM", 5), ("4MM", 5), ("2.5MM", 5), ("2MM", 5),
+    ("1MM", 5)
+];
+
+//
+// Returns the mode group ("CW", "PHONE", or "DIGITAL") that a mode counts
+// toward for mode-specific awards.
+//
+pub fn adif_mode_group(mode: &str) -> &'static str
+{
+    match mode.trim().to_uppercase().as_str() {
+        "CW" => "CW",
+        "SSB" | "AM" | "FM" | "DIGITALVOICE" | "USB" | "LSB" => "PHONE",
+        _ => "DIGITAL"
+    }
+}
+
+fn adif_field_upper(rec: &AdifRecord, field: &str) -> Option<String>
+{
+    let value = rec.adir_field_values.get(field)?.trim().to_uppercase();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+fn adif_valid_grid4(grid: &str) -> bool
+{
+    let bytes = grid.as_bytes();
+    bytes.len() >= 4 &&
+        (b'A'..=b'R').contains(&bytes[0]) &&
+        (b'A'..=b'R').contains(&bytes[1]) &&
+        bytes[2].is_ascii_digit() && bytes[3].is_ascii_digit()
+}
+
+//
+// Returns the entities (if any) that this QSO counts for toward "award".
+// Usually there's at most one, but a QSO with a station on a grid line or
+// corner counts for each of its grids (listed in "vucc_grids").
+//
+pub fn adif_award_entities(award: AdifAward, rec: &AdifRecord) -> Vec<String>
+{
+    match award {
+        AdifAward::AA_DXCC => {
+            rec.adir_field_values.get("dxcc")
+                .and_then(|d| d.trim().parse::<u32>().ok())
+                .filter(|&d| d != 0)
+                .map(|d| d.to_string()).into_iter().collect()
+        },
+        AdifAward::AA_WAS => {
+            if let Some(dxcc) = rec.adir_field_values.get("dxcc") {
+                if !ADIF_WAS_DXCC.contains(&dxcc.trim()) {
+                    return Vec::new();
+                }
+            }
+            adif_field_upper(rec, "state")
+                .filter(|s| ADIF_WAS_STATES.contains(&s.as_str()))
+                .into_iter().collect()
+        },
+        AdifAward::AA_WAZ => {
+            rec.adir_field_values.get("cqz")
+                .and_then(|z| z.trim().parse::<u32>().ok())
+                .filter(|z| (1..=40).contains(z))
+                .map(|z| z.to_string()).into_iter().collect()
+        },
+        AdifAward::AA_VUCC => {
+            let grids = match adif_field_upper(rec, "vucc_grids") {
+                Some(g) => g.split(',').map(|g| g.trim().to_string())
+                    .collect(),
+                None => adif_field_upper(rec, "gridsquare").into_iter()
+                    .collect::<Vec<String>>()
+            };
+            let mut result : Vec<String> = grids.iter()
+                .filter(|g| adif_valid_grid4(g))
+                .map(|g| g[0..4].to_string()).collect();
+            result.dedup();
+            result
+        },
+        AdifAward::AA_WPX => {
+            match adif_field_upper(rec, "pfx") {
+                Some(pfx) => vec![ pfx ],
+                None => adif_field_upper(rec, "call")
+                    .and_then(|c| adif_wpx_prefix(&c)).into_iter().collect()
+            }
+        }
+    }
+}
+
+//
+// Returns the ADIF credit names for the given award for the mixed, per-band,
+// and per-mode categories.
+//
+fn adif_award_credits(award: AdifAward) -> (&'static str, &'static str,
+    &'static str)
+{
+    match award {
+        AdifAward::AA_DXCC => ("DXCC", "DXCC_BAND", "DXCC_MODE"),
+        AdifAward::AA_WAS => ("WAS", "WAS_BAND", "WAS_MODE"),
+        AdifAward::AA_WAZ => ("CQWAZ_MIXED", "CQWAZ_BAND", "CQWAZ_MODE"),
+        AdifAward::AA_VUCC => ("VUCC_SAT", "VUCC_BAND", "VUCC_BAND"),
+        AdifAward::AA_WPX => ("CQWPX", "CQWPX_BAND", "CQWPX_MODE")
+    }
+}
+
+//
+// Returns true if the comma-separated credit list in "field" includes
+// "credit".
+//
+fn adif_has_credit(rec: &AdifRecord, field: &str, credit: &str) -> bool
+{
+    match rec.adir_field_values.get(field) {
+        None => false,
+        Some(list) => list.split(',').any(|c| {
+            let name = c.split(':').next().unwrap_or("");
+            name.trim().eq_ignore_ascii_case(credit)
+        })
+    }
+}
+
+fn adif_qsl_received(rec: &AdifRecord, field: &str) -> bool
+{
+    match rec.adir_field_values.get(field) {
+        Some(v) => {
+            let v = v.trim();
+            v.eq_ignore_ascii_case("y") || v.eq_ignore_ascii_case("v")
+        },
+        None => false
+    }
+}
+
+fn adif_award_status(rec: &AdifRecord, credit: &str,
+    options: &AdifAwardOptions) -> AdifAwardStatus
+{
+    if adif_has_credit(rec, "credit_granted", credit) {
+        AdifAwardStatus::AAS_GRANTED
+    } else if adif_has_credit(rec, "credit_submitted", credit) {
+        AdifAwardStatus::AAS_SUBMITTED
+    } else if adif_qsl_received(rec, "qsl_rcvd") ||
+        adif_qsl_received(rec, "lotw_qsl_rcvd") ||
+        (options.aao_accept_eqsl && adif_qsl_received(rec, "eqsl_qsl_rcvd")) {
+        AdifAwardStatus::AAS_CONFIRMED
+    } else {
+        AdifAwardStatus::AAS_WORKED
+    }
+}
+
+//
+// Returns the number of confirmed entities required for "award" in
+// "category", if we know it.
+//
+fn adif_award_target(award: AdifAward, category: &str) -> Option<usize>
+{
+    let mixed = category == "Mixed";
+    match award {
+        AdifAward::AA_DXCC => Some(100),
+        AdifAward::AA_WAS => Some(ADIF_WAS_STATES.len()),
+        AdifAward::AA_WAZ => Some(40),
+        AdifAward::AA_VUCC => {
+            if category == "SAT" {
+                Some(100)
+            } else {
+                ADIF_VUCC_BANDS.iter().find(|(b, _)| *b == category)
+                    .map(|&(_, n)| n)
+            }
+        },
+        AdifAward::AA_WPX => if mixed { Some(400) } else { Some(300) }
+    }
+}
+
+//
+// Compute progress toward "award" in all of its categories.  Categories are
+// returned with "Mixed" (or, for VUCC, "SAT") first, then bands, then mode
+// groups.
+//
+pub fn adif_award_progress<'a, I>(award: AdifAward, records: I,
+    options: &AdifAwardOptions) -> Vec<AdifAwardProgress>
+    where I: IntoIterator<Item = &'a AdifRecord>
+{
+    let (mixed_credit, band_credit, mode_credit) = adif_award_credits(award);
+    let mut mixed : BTreeMap<String, AdifAwardStatus> = BTreeMap::new();
+    let mut bands : BTreeMap<String, BTreeMap<String, AdifAwardStatus>> =
+        BTreeMap::new();
+    let mut modes : BTreeMap<String, BTreeMap<String, AdifAwardStatus>> =
+        BTreeMap::new();
+    let is_vucc = award == AdifAward::AA_VUCC;
+
+    let update = |map: &mut BTreeMap<String, AdifAwardStatus>,
+        entity: &str, status: AdifAwardStatus| {
+        let entry = map.entry(entity.to_string()).or_insert(status);
+        if status > *entry {
+            *entry = status;
+        }
+    };
+
+    for rec in records {
+        let entities = adif_award_entities(award, rec);
+        if entities.is_empty() {
+            continue;
+        }
+
+        let band = adif_field_upper(rec, "band");
+        let sat = adif_field_upper(rec, "prop_mode").as_deref() == Some("SAT");
+
+        let mixed_status = adif_award_status(rec, mixed_credit, options);
+        let band_status = adif_award_status(rec, band_credit, options);
+        let mode_status = adif_award_status(rec, mode_credit, options);
+        let mode = adif_field_upper(rec, "mode").map(
+            |m| adif_mode_group(&m).to_string());
+
+        for entity in &entities {
+            if !is_vucc || sat {
+                update(&mut mixed, entity, mixed_status);
+            }
+
+            if let Some(ref band) = band {
+                if !is_vucc || (!sat && ADIF_VUCC_BANDS.iter().any(
+                    |(b, _)| b == band)) {
+                    update(bands.entry(band.clone()).or_default(), entity,
+                        band_status);
+                }
+            }
+
+            if let Some(ref mode) = mode {
+                if !is_vucc {
+                    update(modes.entry(mode.clone()).or_default(), entity,
+                        mode_status);
+                }
+            }
+        }
+    }
+
+    //
+    // For awards with a fixed set of entities, everything not yet confirmed is
+    // needed.  Otherwise, we consider the entities worked in any category.
+    //
+    let universe : Vec<String> = match award {
+        AdifAward::AA_WAS => ADIF_WAS_STATES.iter().map(
+            |s| s.to_string()).collect(),
+        AdifAward::AA_WAZ => (1..=40).map(|z: u32| z.to_string()).collect(),
+        _ => {
+            let mut all : BTreeSet<&String> = mixed.keys().collect();
+            for map in bands.values().chain(modes.values()) {
+                all.extend(map.keys());
+            }
+            all.into_iter().cloned().collect()
+        }
+    };
+
+    let mut categories = Vec::new();
+    if !is_vucc || !mixed.is_empty() {
+        let name = if is_vucc { "SAT" } else { "Mixed" };
+        categories.push((name.to_string(), mixed));
+    }
+    let mut bands : Vec<(String, BTreeMap<String, AdifAwardStatus>)> =
+        bands.into_iter().collect();
+    bands.sort_by(|(b1, _), (b2, _)| adif_band_compare(b1, b2));
+    categories.extend(bands);
+    categories.extend(modes);
+
+    categories.into_iter().map(|(category, entities)| {
+        let confirmed = entities.values().filter(
+            |&&s| s >= AdifAwardStatus::AAS_CONFIRMED).count();
+        let needed = universe.iter().filter(|e| {
+            match entities.get(*e) {
+                Some(&s) => s < AdifAwardStatus::AAS_CONFIRMED,
+                None => true
+            }
+        }).cloned().collect();
+        AdifAwardProgress {
+            aap_award: award,
+            aap_target: adif_award_target(award, &category),
+            aap_category: category,
+            aap_worked: entities.len(),
+            aap_confirmed: confirmed,
+            aap_entities: entities,
+            aap_needed: needed
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifRecord;
+    use super::*;
+
+    fn qso(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    #[test]
+    fn progress() {
+        let records = vec![
+            qso(&[ ("call", "W1AW"), ("dxcc", "291"), ("state", "CT"),
+                ("cqz", "5"), ("band", "20m"), ("mode", "SSB"),
+                ("lotw_qsl_rcvd", "Y") ]),
+            qso(&[ ("call", "K6ZBI"), ("dxcc", "291"), ("state", "ca"),
+                ("cqz", "3"), ("band", "40m"), ("mode", "FT8"),
+                ("eqsl_qsl_rcvd", "Y") ]),
+            qso(&[ ("call", "VE3XYZ"), ("dxcc", "1"), ("state", "ON"),
+                ("cqz", "4"), ("band", "20m"), ("mode", "CW"),
+                ("credit_granted", "DXCC_BAND:CARD,DXCC") ]),
+            qso(&[ ("call", "KL7ABC"), ("dxcc", "6"), ("state", "AK"),
+                ("band", "2m"), ("mode", "FM"), ("gridsquare", "bp51ab"),
+                ("vucc_grids", "BP51,BP52") ]),
+        ];
+
+        let options = AdifAwardOptions::default();
+        let dxcc = adif_award_progress(AdifAward::AA_DXCC, &records,
+            &options);
+        let categories : Vec<&str> = dxcc.iter().map(
+            |p| p.aap_category.as_str()).collect();
+        assert_eq!(categories, vec![ "Mixed", "40M", "20M", "2M", "CW",
+            "DIGITAL", "PHONE" ]);
+        assert_eq!(dxcc[0].aap_worked, 3);
+        assert_eq!(dxcc[0].aap_confirmed, 2);
+        assert_eq!(dxcc[0].aap_entities["1"], AdifAwardStatus::AAS_GRANTED);
+        assert_eq!(dxcc[0].aap_needed, vec![ "6" ]);
+        assert_eq!(dxcc[2].aap_entities["1"], AdifAwardStatus::AAS_GRANTED);
+        assert_eq!(dxcc[2].aap_needed, vec![ "6" ]);
+
+        let was = adif_award_progress(AdifAward::AA_WAS, &records, &options);
+        assert_eq!(was[0].aap_worked, 3);
+        assert_eq!(was[0].aap_confirmed, 1);
+        assert_eq!(was[0].aap_needed.len(), 49);
+        let options = AdifAwardOptions { aao_accept_eqsl: true };
+        let was = adif_award_progress(AdifAward::AA_WAS, &records, &options);
+        assert_eq!(was[0].aap_confirmed, 2);
+
+        let vucc = adif_award_progress(AdifAward::AA_VUCC, &records,
+            &options);
+        assert_eq!(vucc.len(), 1);
+        assert_eq!(vucc[0].aap_category, "2M");
+        assert_eq!(vucc[0].aap_worked, 2);
+        assert_eq!(vucc[0].aap_target, Some(100));
+
+        let wpx = adif_award_progress(AdifAward::AA_WPX, &records, &options);
+        let prefixes : Vec<&String> = wpx[0].aap_entities.keys().collect();
+        assert_eq!(prefixes, vec![ "K6", "KL7", "VE3", "W1" ]);
+    }
+}
@@ -0,0 +1,162 @@
+//
+// src/bin/adif/awards.rs: "adif awards" reports progress toward awards
+// For each award (DXCC, WAS, WAZ, VUCC, and WPX by default) and each of its
+// categories, this prints the number of entities worked and confirmed.  See
+// src/awards.rs for what counts.  With -n, it also lists the entities still
+// needed, marking those that have been worked but not yet confirmed.
+//
+
+use std::io;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "awards",
+    cmd_summary: "report progress toward awards",
+    cmd_operands: "FILENAME...",
+    cmd_nargs: (1, None),
+    cmd_description: "",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('a'),
+            os_long: "awards",
+            os_arg: Some("AWARDS"),
+            os_help: "awards to report (default: dxcc,was,waz,vucc,wpx)"
+        },
+        OptSpec {
+            os_short: Some('e'),
+            os_long: "eqsl",
+            os_arg: None,
+            os_help: "count eQSL confirmations"
+        },
+        OptSpec {
+            os_short: Some('n'),
+            os_long: "needed",
+            os_arg: None,
+            os_help: "list entities still needed"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "format",
+            os_arg: Some("FORMAT"),
+            os_help: "output format: text (default) or json"
+        },
+        common::OPT_FILTER,
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let parse_options = common::parse_options(inv);
+    let filter = common::filter(inv);
+    let award_options = adif::AdifAwardOptions {
+        aao_accept_eqsl: inv.flag("eqsl")
+    };
+    let show_needed = inv.flag("needed");
+
+    let json = match inv.value("format") {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(name) => inv.usage_error(&format!("unsupported format: {}", name))
+    };
+
+    let mut awards : Vec<adif::AdifAward> = adif::ADIF_AWARDS.to_vec();
+    if let Some(names) = inv.value("awards") {
+        awards.clear();
+        for name in names.split(',') {
+            match adif::adif_parse_award(name.trim()) {
+                Some(a) => awards.push(a),
+                None => inv.usage_error(&format!("unsupported award: {}",
+                    name))
+            }
+        }
+    }
+
+    let mut records = Vec::new();
+    for filename in &inv.inv_operands {
+        let adf = common::read_file(filename, &parse_options)?;
+        records.extend(adf.adif_records);
+    }
+
+    if let Some(f) = &filter {
+        records.retain(|rec| adif::adif_filter_matches(f, rec));
+    }
+
+    let mut progress = Vec::new();
+    for award in awards {
+        progress.extend(adif::adif_award_progress(award, &records,
+            &award_options));
+    }
+
+    common::write_output(inv.value(common::OPT_OUTPUT.os_long), &|out| {
+        if json {
+            writeln!(out, "{}", adif::JsonValue::JSV_ARRAY(progress.iter()
+                .map(make_json).collect()))
+        } else {
+            write_text(out, &progress, show_needed)
+        }
+    })?;
+    Ok(0)
+}
+
+fn write_text(out: &mut dyn io::Write, progress: &[adif::AdifAwardProgress],
+    show_needed: bool) -> io::Result<()>
+{
+    for p in progress {
+        write!(out, "{} {}: {} worked, {} confirmed",
+            adif::adif_award_name(p.aap_award), p.aap_category, p.aap_worked,
+            p.aap_confirmed)?;
+        match p.aap_target {
+            Some(target) => writeln!(out, " (of {} required)", target)?,
+            None => writeln!(out)?
+        }
+
+        if show_needed && !p.aap_needed.is_empty() {
+            let needed : Vec<String> = p.aap_needed.iter().map(|e| {
+                if p.aap_entities.contains_key(e) {
+                    format!("{} (worked)", e)
+                } else {
+                    e.clone()
+                }
+            }).collect();
+            writeln!(out, "    needed: {}", needed.join(", "))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn make_json(p: &adif::AdifAwardProgress) -> adif::JsonValue
+{
+    let status = |s: &adif::AdifAwardStatus| {
+        adif::json_string(match s {
+            adif::AdifAwardStatus::AAS_WORKED => "worked",
+            adif::AdifAwardStatus::AAS_CONFIRMED => "confirmed",
+            adif::AdifAwardStatus::AAS_SUBMITTED => "submitted",
+            adif::AdifAwardStatus::AAS_GRANTED => "granted"
+        })
+    };
+
+    adif::json_object(vec![
+        ("award", adif::json_string(adif::adif_award_name(p.aap_award))),
+        ("category", adif::json_string(&p.aap_category)),
+        ("worked", adif::json_number(p.aap_worked as u32)),
+        ("confirmed", adif::json_number(p.aap_confirmed as u32)),
+        ("required", match p.aap_target {
+            Some(t) => adif::json_number(t as u32),
+            None => adif::JsonValue::JSV_NULL
+        }),
+        ("entities", adif::JsonValue::JSV_OBJECT(p.aap_entities.iter().map(
+            |(e, s)| (e.clone(), status(s))).collect())),
+        ("needed", adif::JsonValue::JSV_ARRAY(p.aap_needed.iter().map(
+            |e| adif::json_string(e)).collect()))
+    ])
+}
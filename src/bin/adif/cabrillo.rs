@@ -0,0 +1,161 @@
+//
+// src/bin/adif/cabrillo.rs: "adif cabrillo" writes a Cabrillo contest log
+// The log is written to stdout (or the file given with -o).  The header is
+// generated from the records, and the exchange is taken from fields named by
+// a template built in for the contest or given with --exchange.  With
+// --import, this instead reads a Cabrillo log and writes it as ADIF, using the
+// same template to split up the exchanges.  See src/cabrillo.rs for details.
+// Problems (like missing exchange fields or malformed QSO lines) are reported
+// on stderr.  This exits 0 if every selected record (or, with --import, every
+// QSO line) was converted, 1 if some had to be skipped, and 2 on other errors.
+//
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "cabrillo",
+    cmd_summary: "write (or import) a Cabrillo contest log",
+    cmd_operands: "FILENAME",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "\
+CONTEST, CALLSIGN, OPERATORS, and CATEGORY-OPERATOR, -BAND, and -MODE are
+generated from the records.  An exchange template lists the fields sent and
+received, with alternatives separated by \"|\":
+
+    rst_sent my_cq_zone|stx / rst_rcvd cqz|srx
+
+Templates are built in for common contests (e.g., CQ-WW-CW, CQ-WPX-SSB,
+ARRL-SS-CW, ARRL-DX-CW, ARRL-FD, IARU-HF, NAQP-CW).  Other contests use an RST
+and serial number (or string) in each direction.  With --import, FILENAME is a
+Cabrillo log, and the exchange words are stored in the first field named for
+each item.",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('i'),
+            os_long: "import",
+            os_arg: None,
+            os_help: "read a Cabrillo log and write it as ADIF"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "contest",
+            os_arg: Some("NAME"),
+            os_help: "contest name (default: from contest_id)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "callsign",
+            os_arg: Some("CALLSIGN"),
+            os_help: "callsign used (default: from station_callsign)"
+        },
+        OptSpec {
+            os_short: Some('x'),
+            os_long: "exchange",
+            os_arg: Some("TEMPLATE"),
+            os_help: "fields that make up the exchange (default:\n\
+                based on the contest)"
+        },
+        OptSpec {
+            os_short: Some('t'),
+            os_long: "tag",
+            os_arg: Some("TAG=VALUE"),
+            os_help: "add or replace a header tag, as in\n\
+                CATEGORY-POWER=LOW (may be repeated)"
+        },
+        common::OPT_FILTER,
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let mut options = adif::AdifCabrilloOptions {
+        acbo_contest: inv.value("contest").map(|c| c.to_uppercase()),
+        acbo_callsign: inv.value("callsign").map(|c| c.to_uppercase()),
+        ..Default::default()
+    };
+    if let Some(text) = inv.value("exchange") {
+        match adif::adif_parse_cabrillo_exchange(text) {
+            Ok(x) => options.acbo_exchange = Some(x),
+            Err(error) => inv.usage_error(&format!("--exchange: {}", error))
+        }
+    }
+    for spec in inv.values("tag") {
+        match spec.split_once('=') {
+            Some((tag, value)) if !tag.trim().is_empty() => options.acbo_header
+                .push((tag.to_string(), value.trim().to_string())),
+            _ => inv.usage_error(&format!("invalid header tag: {}", spec))
+        }
+    }
+
+    if inv.flag("import") {
+        return import(inv, &options);
+    }
+
+    let adf = common::read_file(&inv.inv_operands[0],
+        &common::parse_options(inv))?;
+    common::warn_diagnostics(inv, &adf);
+
+    //
+    // Keep track of where each selected record was in the file so that
+    // diagnostics can refer to it.
+    //
+    let filter = common::filter(inv);
+    let (which, records) : (Vec<usize>, Vec<adif::AdifRecord>) =
+        adf.adif_records.iter().enumerate().filter(|(_, r)| {
+            filter.as_ref().is_none_or(|f| adif::adif_filter_matches(f, r))
+        }).map(|(i, r)| (i, r.clone())).unzip();
+
+    let mut text = Vec::new();
+    let diagnostics = adif::adif_cabrillo_write(&records, &options, &mut text)
+        .map_err(|error| format!("write: {}", error))?;
+    common::write_output(inv.value(common::OPT_OUTPUT.os_long),
+        &|out| out.write_all(&text))?;
+
+    let mut nskipped = 0;
+    for mut diagnostic in diagnostics {
+        if diagnostic.adid_record.is_some() {
+            nskipped += 1;
+        }
+        diagnostic.adid_record = diagnostic.adid_record.map(
+            |r| which[r - 1] + 1);
+        inv.warn(&format!("warning: {}: {}", adf.adif_label, diagnostic));
+    }
+    if nskipped > 0 {
+        inv.warn(&format!("wrote {} of {} QSOs ({} skipped)",
+            records.len() - nskipped, records.len(), nskipped));
+    }
+    Ok(if nskipped == 0 { 0 } else { 1 })
+}
+
+fn import(inv: &Invocation, options: &adif::AdifCabrilloOptions) ->
+    Result<i32, String>
+{
+    let filename = &inv.inv_operands[0];
+    let label = common::input_label(filename);
+    let mut source = common::open_input(filename)?;
+    let import = adif::adif_cabrillo_parse(label, &mut source, options,
+        &common::parse_options(inv)).map_err(
+        |error| format!("{}: {}", label, error))?;
+    let mut adf = import.acbi_file;
+    common::warn_diagnostics(inv, &adf);
+
+    if let Some(f) = common::filter(inv) {
+        adf.adif_records.retain(|r| adif::adif_filter_matches(&f, r));
+    }
+    common::write_adif(inv.value(common::OPT_OUTPUT.os_long), &adf)?;
+
+    let nskipped = import.acbi_skipped.len();
+    if nskipped > 0 {
+        inv.warn(&format!("imported {} of {} QSOs ({} skipped)",
+            import.acbi_nqsos - nskipped, import.acbi_nqsos, nskipped));
+    }
+    Ok(if nskipped == 0 { 0 } else { 1 })
+}
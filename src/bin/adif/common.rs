@@ -0,0 +1,240 @@
+//
+// src/bin/adif/common.rs: facilities shared by subcommands
+//
+// Options that mean the same thing in several commands are defined here so
+// that they're spelled (and described) the same way everywhere.  Input and
+// output files named "-" refer to stdin and stdout.
+//
+
+use std::fs;
+use std::io;
+
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const OPT_DUPLICATES : OptSpec = OptSpec {
+    os_short: Some('d'),
+    os_long: "duplicates",
+    os_arg: Some("POLICY"),
+    os_help: "handling of duplicate fields: error (default),\n\
+        first, last, first-nonempty, or all"
+};
+
+pub const OPT_OUTPUT : OptSpec = OptSpec {
+    os_short: Some('o'),
+    os_long: "output",
+    os_arg: Some("FILE"),
+    os_help: "write output to FILE (default: stdout)"
+};
+
+pub const OPT_FILTER : OptSpec = OptSpec {
+    os_short: Some('f'),
+    os_long: "filter",
+    os_arg: Some("FILTER"),
+    os_help: "only use records matching FILTER, like\n\
+        \"band in (20m, 40m) and call ~ '^K'\"\n\
+        (may be repeated)"
+};
+
+pub const OPT_KEY : OptSpec = OptSpec {
+    os_short: Some('k'),
+    os_long: "key",
+    os_arg: Some("FIELDS"),
+    os_help: "fields that must match for records to match\n\
+        (default: call,band,mode)"
+};
+
+pub const OPT_TOLERANCE : OptSpec = OptSpec {
+    os_short: Some('t'),
+    os_long: "tolerance",
+    os_arg: Some("MINUTES"),
+    os_help: "max difference in QSO times for records to\n\
+        match (default: 5)"
+};
+
+pub const OPT_IGNORE_TIME : OptSpec = OptSpec {
+    os_short: Some('T'),
+    os_long: "ignore-time",
+    os_arg: None,
+    os_help: "ignore QSO times when matching records"
+};
+
+pub const OPT_COMPARE_MODE : OptSpec = OptSpec {
+    os_short: None,
+    os_long: "mode",
+    os_arg: Some("MODE"),
+    os_help: "how to compare fields: a comma-separated list\n\
+        of exact, typed (default), nocase, and nospace"
+};
+
+pub fn field_list(spec: &str) -> Vec<String>
+{
+    spec.split(',').map(|f| f.trim().to_lowercase())
+        .filter(|f| !f.is_empty()).collect()
+}
+
+//
+// Returns the parse options given by OPT_DUPLICATES.
+//
+pub fn parse_options(inv: &Invocation) -> adif::AdifParseOptions
+{
+    let mut options = adif::AdifParseOptions::default();
+    if let Some(policy) = inv.value(OPT_DUPLICATES.os_long) {
+        match adif::adif_parse_duplicate_policy(policy) {
+            Some(p) => options.apo_duplicates = p,
+            None => inv.usage_error(&format!(
+                "unsupported duplicate policy: {}", policy))
+        }
+    }
+    options
+}
+
+//
+// Returns the match options given by OPT_KEY, OPT_TOLERANCE, and
+// OPT_IGNORE_TIME.
+//
+pub fn match_options(inv: &Invocation) -> adif::AdifMatchOptions
+{
+    let mut options = adif::AdifMatchOptions::default();
+    if let Some(fields) = inv.value(OPT_KEY.os_long) {
+        options.amo_key_fields = field_list(fields);
+    }
+    if let Some(minutes) = inv.value(OPT_TOLERANCE.os_long) {
+        match minutes.parse::<u32>() {
+            Ok(m) => options.amo_time_tolerance = (m as i64) * 60,
+            Err(_) => inv.usage_error(&format!("invalid time tolerance: {}",
+                minutes))
+        }
+    }
+    if inv.flag(OPT_IGNORE_TIME.os_long) {
+        options.amo_use_time = false;
+    }
+    options
+}
+
+//
+// Returns the comparison mode given by OPT_COMPARE_MODE, if any.
+//
+pub fn compare_mode(inv: &Invocation) -> Option<adif::AdifCompareMode>
+{
+    inv.value(OPT_COMPARE_MODE.os_long).map(|spec| {
+        match adif::adif_parse_compare_mode(spec) {
+            Some(m) => m,
+            None => inv.usage_error(&format!("invalid comparison mode: {}",
+                spec))
+        }
+    })
+}
+
+//
+// Returns the filter given by OPT_FILTER.  Multiple filters must all match.
+//
+pub fn filter(inv: &Invocation) -> Option<adif::AdifFilter>
+{
+    let mut filter = None;
+    for text in inv.values(OPT_FILTER.os_long) {
+        let f = match adif::adif_parse_filter(text) {
+            Ok(f) => f,
+            Err(error) => inv.usage_error(&format!("{}", error))
+        };
+        filter = Some(match filter {
+            None => f,
+            Some(prev) => adif::AdifFilter::AFL_AND(Box::new(prev),
+                Box::new(f))
+        });
+    }
+    filter
+}
+
+//
+// Open a file for reading.  "-" means stdin.
+//
+pub fn open_input(filename: &str) -> Result<Box<dyn io::Read>, String>
+{
+    if filename == "-" {
+        return Ok(Box::new(io::stdin()));
+    }
+
+    match fs::File::open(filename) {
+        Ok(file) => Ok(Box::new(file)),
+        Err(error) => Err(format!("open \"{}\": {}", filename, error))
+    }
+}
+
+//
+// Returns the name used for a file in messages.
+//
+pub fn input_label(filename: &str) -> &str
+{
+    if filename == "-" { "(stdin)" } else { filename }
+}
+
+pub fn read_file(filename: &str, options: &adif::AdifParseOptions) ->
+    Result<adif::AdifFile, String>
+{
+    let mut source = open_input(filename)?;
+    let label = input_label(filename);
+    adif::adif_parse_options(label, &mut source, options).map_err(
+        |error| format!("{}: {}", label, error))
+}
+
+//
+// Print the diagnostics for a file that was parsed successfully.
+//
+pub fn warn_diagnostics(inv: &Invocation, adf: &adif::AdifFile)
+{
+    for diagnostic in &adf.adif_diagnostics {
+        inv.warn(&format!("warning: {}: {}", adf.adif_label, diagnostic));
+    }
+}
+
+//
+// Write output to the named file, or stdout if there's no name or the name is
+// "-".
+//
+pub fn write_output(filename: Option<&str>,
+    contents: &dyn Fn(&mut dyn io::Write) -> io::Result<()>) ->
+    Result<(), String>
+{
+    let written = match filename {
+        None | Some("-") => {
+            let stdout = io::stdout();
+            contents(&mut stdout.lock())
+        },
+        Some(name) => {
+            match fs::File::create(name) {
+                Ok(mut file) => contents(&mut file),
+                Err(error) => {
+                    return Err(format!("create \"{}\": {}", name, error))
+                }
+            }
+        }
+    };
+
+    written.map_err(|error| format!("write: {}", error))
+}
+
+//
+// Write an ADIF file as ADI to the named file (see write_output()).
+//
+pub fn write_adif(filename: Option<&str>, adf: &adif::AdifFile) ->
+    Result<(), String>
+{
+    write_output(filename, &|out| {
+        adif::adif_write(adf, out).map_err(
+            |error| io::Error::other(error.to_string()))
+    })
+}
+
+//
+// Describe a field's value in a message.  Note that an explicitly empty value
+// is different from one that's absent altogether.
+//
+pub fn field_state(value: &Option<String>) -> String
+{
+    match value {
+        None => String::from("(absent)"),
+        Some(v) if v.is_empty() => String::from("(empty)"),
+        Some(v) => format!("\"{}\"", v)
+    }
+}
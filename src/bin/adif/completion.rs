@@ -0,0 +1,222 @@
+//
+// src/bin/adif/completion.rs: "adif completion" prints shell completion code
+// The scripts are generated from the same command and option tables used for
+// parsing (see src/bin/adif/opts.rs), so they stay in sync with the program.
+// Commands, options, and (where a command takes them) file names are
+// completed.  For example, with bash:
+//
+//     adif completion bash > /etc/bash_completion.d/adif
+//
+
+use super::COMMANDS;
+use opts;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "completion",
+    cmd_summary: "print a shell completion script",
+    cmd_operands: "bash|zsh|fish",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "",
+    cmd_options: &[],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+const COMPLETION_SHELLS : &str = "bash zsh fish";
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let script = match inv.inv_operands[0].as_str() {
+        "bash" => bash(),
+        "zsh" => zsh(),
+        "fish" => fish(),
+        name => inv.usage_error(&format!("unsupported shell: {}", name))
+    };
+
+    print!("{}", script);
+    Ok(0)
+}
+
+//
+// Returns the words that name an option ("-o --output").
+//
+fn option_words(o: &OptSpec) -> Vec<String>
+{
+    let mut words = Vec::new();
+    if let Some(c) = o.os_short {
+        words.push(format!("-{}", c));
+    }
+    words.push(format!("--{}", o.os_long));
+    words
+}
+
+//
+// Returns an option's description on a single line.
+//
+fn option_help(o: &OptSpec) -> String
+{
+    o.os_help.lines().map(|l| l.trim()).collect::<Vec<&str>>().join(" ")
+}
+
+//
+// Returns true if an option's argument names a file.
+//
+fn takes_file(o: &OptSpec) -> bool
+{
+    o.os_arg.is_some_and(|a| a.ends_with("FILE"))
+}
+
+fn bash() -> String
+{
+    let names : Vec<&str> = COMMANDS.iter().map(|c| c.cmd_name).collect();
+    let mut script = format!("\
+_adif()
+{{
+    local cur=${{COMP_WORDS[COMP_CWORD]}}
+    local prev=${{COMP_WORDS[COMP_CWORD-1]}}
+    local opts= argopts= words=-f
+
+    if [ \"$COMP_CWORD\" -eq 1 ]; then
+        COMPREPLY=($(compgen -W \"{} help\" -- \"$cur\"))
+        return
+    fi
+
+    case \"${{COMP_WORDS[1]}}\" in
+", names.join(" "));
+
+    for cmd in COMMANDS {
+        let all = opts::opts_all(cmd);
+        let words : Vec<String> = all.iter().flat_map(
+            |o| option_words(o)).collect();
+        let argwords : Vec<String> = all.iter().filter(
+            |o| o.os_arg.is_some() && !takes_file(o)).flat_map(
+            |o| option_words(o)).collect();
+        let operands = if cmd.cmd_name == COMMAND.cmd_name {
+            format!("-W \\\"{}\\\"", COMPLETION_SHELLS)
+        } else {
+            String::from("-f")
+        };
+        script.push_str(&format!("    {})\n        opts=\"{}\"\n        \
+            argopts=\"{}\"\n        words=\"{}\"\n        ;;\n",
+            cmd.cmd_name, words.join(" "), argwords.join(" "), operands));
+    }
+
+    script.push_str(&format!("    help)\n        \
+        words=\"-W \\\"{}\\\"\"\n        ;;\n", names.join(" ")));
+    script.push_str("\
+    esac
+
+    case \" $argopts \" in
+    *\" $prev \"*)
+        COMPREPLY=()
+        return
+        ;;
+    esac
+
+    if [[ \"$cur\" == -* ]]; then
+        COMPREPLY=($(compgen -W \"$opts\" -- \"$cur\"))
+    else
+        eval \"COMPREPLY=(\\$(compgen $words -- \\\"\\$cur\\\"))\"
+    fi
+}
+complete -o filenames -F _adif adif
+");
+    script
+}
+
+fn zsh_quote(text: &str) -> String
+{
+    text.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh() -> String
+{
+    let mut script = String::from("#compdef adif\n\n_adif()\n{\n    \
+        local -a commands\n    commands=(\n");
+    for cmd in COMMANDS {
+        script.push_str(&format!("        '{}:{}'\n", cmd.cmd_name,
+            zsh_quote(cmd.cmd_summary)));
+    }
+    script.push_str("        'help:print help for a command'\n    )\n\n\
+        \x20   if (( CURRENT == 2 )); then\n        \
+        _describe 'command' commands\n        return\n    fi\n\n    \
+        case $words[2] in\n");
+
+    for cmd in COMMANDS {
+        script.push_str(&format!("    {})\n        _arguments -s",
+            cmd.cmd_name));
+        for o in opts::opts_all(cmd) {
+            let value = match o.os_arg {
+                None => String::new(),
+                Some(arg) if takes_file(o) => format!(":{}:_files", arg),
+                Some(arg) => format!(":{}: ", arg)
+            };
+            for word in option_words(o) {
+                script.push_str(&format!(" \\\n            '{}[{}]{}'", word,
+                    zsh_quote(&option_help(o)), value));
+            }
+        }
+        let operands = if cmd.cmd_name == COMMAND.cmd_name {
+            format!("'1:shell:({})'", COMPLETION_SHELLS)
+        } else {
+            String::from("'*:file:_files'")
+        };
+        script.push_str(&format!(" \\\n            {}\n        ;;\n",
+            operands));
+    }
+
+    script.push_str("    help)\n        _describe 'command' commands\n        \
+        ;;\n    esac\n}\n\n_adif \"$@\"\n");
+    script
+}
+
+fn fish_quote(text: &str) -> String
+{
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish() -> String
+{
+    let mut script = String::from("complete -c adif -f\n");
+    for cmd in COMMANDS {
+        script.push_str(&format!("complete -c adif -n __fish_use_subcommand \
+            -a {} -d {}\n", cmd.cmd_name, fish_quote(cmd.cmd_summary)));
+    }
+    script.push_str("complete -c adif -n __fish_use_subcommand -a help \
+        -d 'print help for a command'\n");
+
+    for cmd in COMMANDS {
+        let condition = format!("-n '__fish_seen_subcommand_from {}'",
+            cmd.cmd_name);
+        for o in opts::opts_all(cmd) {
+            let mut line = format!("complete -c adif {}", condition);
+            if let Some(c) = o.os_short {
+                line.push_str(&format!(" -s {}", c));
+            }
+            line.push_str(&format!(" -l {}", o.os_long));
+            if takes_file(o) {
+                line.push_str(" -r -F");
+            } else if o.os_arg.is_some() {
+                line.push_str(" -x");
+            }
+            line.push_str(&format!(" -d {}\n", fish_quote(&option_help(o))));
+            script.push_str(&line);
+        }
+
+        if cmd.cmd_name == COMMAND.cmd_name {
+            script.push_str(&format!("complete -c adif {} -a '{}'\n",
+                condition, COMPLETION_SHELLS));
+        } else {
+            script.push_str(&format!("complete -c adif {} -F\n", condition));
+        }
+    }
+
+    let names : Vec<&str> = COMMANDS.iter().map(|c| c.cmd_name).collect();
+    script.push_str(&format!("complete -c adif -n \
+        '__fish_seen_subcommand_from help' -a '{}'\n", names.join(" ")));
+    script
+}
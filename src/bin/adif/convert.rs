@@ -0,0 +1,125 @@
+//
+// src/bin/adif/convert.rs: "adif convert" converts a log between formats
+// The input format is given with --from or inferred from the extension of the
+// input file, and the output format is given with --to or inferred from the
+// extension of the output file.  See src/convert.rs for the formats.  Anything
+// that can't be represented in the output format is reported on stderr.
+//
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "convert",
+    cmd_summary: "convert a log between ADI, ADX, CSV, and JSON",
+    cmd_operands: "FILENAME",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "\
+Without --from or --to, formats are inferred from the extensions of the input
+and output files (e.g., \"log.adx\").  Standard input is assumed to be ADI.
+CSV and TSV input begin with a row of column names, each of which is taken as
+a field name unless it's mapped to another field with --map.",
+    cmd_options: &[
+        OptSpec {
+            os_short: None,
+            os_long: "from",
+            os_arg: Some("FORMAT"),
+            os_help: "input format: adi, adx, csv, tsv, json, or jsonl"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "to",
+            os_arg: Some("FORMAT"),
+            os_help: "output format: adi, adx, csv, tsv, json, or jsonl"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "map",
+            os_arg: Some("COLUMN=FIELD"),
+            os_help: "read CSV column COLUMN as FIELD, ignoring it if\n\
+                FIELD is empty (may be repeated; see \"adif\n\
+                import --help\" for conversions)"
+        },
+        common::OPT_FILTER,
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+const CONVERT_FORMATS : &[&str] = &[ "adi", "adx", "csv", "tsv", "json",
+    "jsonl" ];
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let input = &inv.inv_operands[0];
+    let output = inv.value(common::OPT_OUTPUT.os_long);
+
+    let from = match inv.value("from") {
+        Some(name) => parse_format(inv, name),
+        None if input == "-" => adif::AdifDumpFormat::ADDF_ADI,
+        None => infer_format(inv, input, "--from")
+    };
+    let to = match (inv.value("to"), output) {
+        (Some(name), _) => parse_format(inv, name),
+        (None, Some(filename)) if filename != "-" =>
+            infer_format(inv, filename, "--to"),
+        _ => inv.usage_error("an output format (--to) is required")
+    };
+
+    let mut read_options = adif::AdifReadOptions {
+        ardo_format: from,
+        ardo_parse: common::parse_options(inv),
+        ..Default::default()
+    };
+    for spec in inv.values("map") {
+        if let Err(error) = adif::adif_parse_csv_mapping_line(
+            &mut read_options.ardo_csv, spec) {
+            inv.usage_error(&format!("--map: {}", error));
+        }
+    }
+
+    let dump_options = adif::AdifDumpOptions {
+        admp_format: to,
+        admp_filter: common::filter(inv),
+        admp_summary: false,
+        ..Default::default()
+    };
+
+    let label = common::input_label(input);
+    let mut source = common::open_input(input)?;
+    let adf = adif::adif_read(label, &mut source, &read_options).map_err(
+        |error| format!("{}: {}", label, error))?;
+    common::warn_diagnostics(inv, &adf);
+    for loss in adif::adif_conversion_losses(&adf, to) {
+        inv.warn(&format!("warning: {}: {}", label, loss));
+    }
+
+    common::write_output(output, &|out| {
+        adif::adif_dump(&adf, &dump_options, out).map_err(
+            |error| std::io::Error::other(error.to_string()))
+    })?;
+    Ok(0)
+}
+
+fn parse_format(inv: &Invocation, name: &str) -> adif::AdifDumpFormat
+{
+    match adif::adif_parse_dump_format(name) {
+        Some(f) if CONVERT_FORMATS.contains(&name) => f,
+        _ => inv.usage_error(&format!("unsupported format: {}", name))
+    }
+}
+
+fn infer_format(inv: &Invocation, filename: &str, option: &str) ->
+    adif::AdifDumpFormat
+{
+    match adif::adif_format_for_filename(filename) {
+        Some(f) => f,
+        None => inv.usage_error(&format!("cannot infer format of \"{}\" \
+            (use {})", filename, option))
+    }
+}
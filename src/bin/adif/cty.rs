@@ -0,0 +1,110 @@
+//
+// src/bin/adif/cty.rs: "adif cty" resolves callsigns using a country file
+// Given a cty.dat (or bigcty) or cty.csv file from country-files.com, this
+// fills in missing "dxcc", "country", "cqz", "ituz", and "cont" fields in an
+// ADIF file, writing the result to stdout (or the file given with -o).  With
+// -l, it instead prints the entity for each callsign given on the command line.
+// Files whose names end in ".csv" are read as cty.csv.  See src/callsign.rs.
+//
+
+use std::io;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "cty",
+    cmd_summary: "fill in DXCC entities and zones using a country file",
+    cmd_operands: "FILENAME | -l CALLSIGN...",
+    cmd_nargs: (1, None),
+    cmd_description: "\
+The country file (cty.dat, bigcty, or cty.csv) comes from country-files.com.
+Files whose names end in \".csv\" are read as cty.csv.  Missing dxcc, country,
+cqz, ituz, and cont fields are filled in.",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('c'),
+            os_long: "cty",
+            os_arg: Some("CTYFILE"),
+            os_help: "country file to use (required)"
+        },
+        OptSpec {
+            os_short: Some('l'),
+            os_long: "lookup",
+            os_arg: None,
+            os_help: "print the entity for each callsign given"
+        },
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let ctyfile = match inv.value("cty") {
+        Some(f) => f,
+        None => inv.usage_error("a country file (-c) is required")
+    };
+
+    let lookup = inv.flag("lookup");
+    if !lookup && inv.inv_operands.len() != 1 {
+        inv.usage_error("expected one argument");
+    }
+
+    let parse_options = common::parse_options(inv);
+    let db = load_cty(ctyfile)?;
+    let output = inv.value(common::OPT_OUTPUT.os_long);
+
+    if lookup {
+        common::write_output(output,
+            &|out| write_lookups(out, &db, &inv.inv_operands))?;
+        return Ok(0);
+    }
+
+    let mut adf = common::read_file(&inv.inv_operands[0], &parse_options)?;
+    let nchanged = adif::adif_cty_fill(&mut adf, &db);
+    common::write_adif(output, &adf)?;
+    inv.warn(&format!("updated {} of {} records", nchanged,
+        adf.adif_records.len()));
+    Ok(0)
+}
+
+fn load_cty(filename: &str) -> Result<adif::AdifCtyDatabase, String>
+{
+    let mut source = common::open_input(filename)?;
+    let parsed = if filename.to_lowercase().ends_with(".csv") {
+        adif::adif_cty_parse_csv(&mut source)
+    } else {
+        adif::adif_cty_parse_dat(&mut source)
+    };
+
+    parsed.map_err(|error| format!("{}: {}", common::input_label(filename),
+        error))
+}
+
+fn write_lookups(out: &mut dyn io::Write, db: &adif::AdifCtyDatabase,
+    calls: &[String]) -> io::Result<()>
+{
+    for call in calls {
+        match adif::adif_cty_lookup(db, call) {
+            None => writeln!(out, "{}: unknown", call)?,
+            Some(l) => {
+                let entity = l.acl_entity;
+                write!(out, "{}: {} ({})", call, entity.ace_name,
+                    entity.ace_prefix)?;
+                if let Some(dxcc) = entity.ace_dxcc {
+                    write!(out, " dxcc {}", dxcc)?;
+                }
+                writeln!(out, " cqz {} ituz {} cont {}", l.acl_cqz,
+                    l.acl_ituz, l.acl_cont)?;
+            }
+        }
+    }
+
+    Ok(())
+}
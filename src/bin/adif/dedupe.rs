@@ -0,0 +1,119 @@
+//
+// src/bin/adif/dedupe.rs: "adif dedupe" removes duplicate QSOs from a file
+// Duplicates are found and merged as described in src/dedupe.rs.  The cleaned
+// file is written to stdout (or the file given with -o), and a report of what
+// was merged is written to stderr (or the file given with --report).
+//
+
+use std::io;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "dedupe",
+    cmd_summary: "find and merge duplicate QSOs",
+    cmd_operands: "FILENAME",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "",
+    cmd_options: &[
+        common::OPT_OUTPUT,
+        OptSpec {
+            os_short: None,
+            os_long: "report",
+            os_arg: Some("FILE"),
+            os_help: "write report to FILE (default: stderr)"
+        },
+        OptSpec {
+            os_short: Some('p'),
+            os_long: "precedence",
+            os_arg: Some("PRECEDENCE"),
+            os_help: "which duplicate's values to keep: confirmed\n\
+                (default), first, last, or most-fields"
+        },
+        OptSpec {
+            os_short: Some('S'),
+            os_long: "station-callsign",
+            os_arg: None,
+            os_help: "station_callsign must also match"
+        },
+        common::OPT_KEY,
+        common::OPT_TOLERANCE,
+        common::OPT_IGNORE_TIME,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let parse_options = common::parse_options(inv);
+    let mut dedupe_options = adif::AdifDedupeOptions {
+        adeo_match: common::match_options(inv),
+        adeo_station_callsign: inv.flag("station-callsign"),
+        ..Default::default()
+    };
+    if let Some(name) = inv.value("precedence") {
+        match adif::adif_parse_dedupe_precedence(name) {
+            Some(p) => dedupe_options.adeo_precedence = p,
+            None => inv.usage_error(&format!("unsupported precedence: {}",
+                name))
+        }
+    }
+
+    let mut adf = common::read_file(&inv.inv_operands[0], &parse_options)?;
+    let result = adif::adif_dedupe(&adf.adif_records, &dedupe_options);
+    let clusters = result.ader_clusters;
+    let original = std::mem::replace(&mut adf.adif_records,
+        result.ader_records);
+
+    common::write_adif(inv.value(common::OPT_OUTPUT.os_long), &adf)?;
+
+    let report = |out: &mut dyn io::Write| write_report(out, &original,
+        &adf.adif_records, &clusters);
+    match inv.value("report") {
+        None => {
+            let stderr = io::stderr();
+            report(&mut stderr.lock()).map_err(
+                |error| format!("write report: {}", error))?;
+        },
+        Some(reportname) => common::write_output(Some(reportname), &report)?
+    }
+
+    Ok(0)
+}
+
+//
+// Describe each set of merged records.  Records are identified by their 1-based
+// position in the input and output files.
+//
+fn write_report(out: &mut dyn io::Write, original: &[adif::AdifRecord],
+    cleaned: &[adif::AdifRecord], clusters: &[adif::AdifDedupeCluster]) ->
+    io::Result<()>
+{
+    for c in clusters {
+        let dropped : Vec<String> = c.adec_dropped.iter().map(
+            |d| (d + 1).to_string()).collect();
+        writeln!(out, "output record {}: {}", c.adec_output + 1,
+            adif::adif_qso_summary(&cleaned[c.adec_output]))?;
+        writeln!(out, "    kept record {}: {}", c.adec_kept + 1,
+            adif::adif_qso_summary(&original[c.adec_kept]))?;
+        writeln!(out, "    merged records: {}", dropped.join(", "))?;
+        if !c.adec_filled.is_empty() {
+            writeln!(out, "    fields filled in: {}",
+                c.adec_filled.join(", "))?;
+        }
+        if !c.adec_conflicts.is_empty() {
+            writeln!(out, "    conflicting fields (kept record's values \
+                used): {}", c.adec_conflicts.join(", "))?;
+        }
+    }
+
+    writeln!(out, "input records: {}", original.len())?;
+    writeln!(out, "output records: {}", cleaned.len())?;
+    writeln!(out, "records removed: {}", original.len() - cleaned.len())
+}
@@ -0,0 +1,237 @@
+//
+// src/bin/adif/diff.rs: "adif diff" reports differences between two ADIF files
+// Records are matched up using the library's matching facility (see
+// src/diff.rs), which by default requires that the callsign, band, and mode
+// match exactly and that the QSO times be within five minutes of each other.
+// For matched records, all fields are compared (by default, according to each
+// field's ADIF data type), and the differing fields are listed.
+//
+// Output can be human-readable text (the default), JSON, or a patch (an ADIF
+// file that "adif patch" can apply to the first file to make it agree with the
+// second).  Like diff(1), this command exits 0 if the files are equivalent, 1
+// if they differ, and 2 if there was a problem.
+//
+
+use std::io;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "diff",
+    cmd_summary: "report differences between two ADIF files",
+    cmd_operands: "FILENAME1 FILENAME2",
+    cmd_nargs: (2, Some(2)),
+    cmd_description: "\
+Exits 0 if the files are equivalent, 1 if they differ, and 2 on error.",
+    cmd_options: &[
+        OptSpec {
+            os_short: None,
+            os_long: "format",
+            os_arg: Some("FORMAT"),
+            os_help: "output format: text (default), json, or patch"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "fields",
+            os_arg: Some("FIELDS"),
+            os_help: "compare only these fields"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "ignore-fields",
+            os_arg: Some("FIELDS"),
+            os_help: "do not compare these fields"
+        },
+        common::OPT_COMPARE_MODE,
+        OptSpec {
+            os_short: None,
+            os_long: "compare",
+            os_arg: Some("FIELD=MODE"),
+            os_help: "how to compare one field (may be repeated)"
+        },
+        common::OPT_KEY,
+        common::OPT_TOLERANCE,
+        common::OPT_IGNORE_TIME,
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+//
+// Output formats
+//
+#[derive(Clone, Copy, PartialEq)]
+enum DiffFormat {
+    Text,
+    Json,
+    Patch
+}
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let parse_options = common::parse_options(inv);
+    let match_options = common::match_options(inv);
+    let mut diff_options = adif::AdifDiffOptions::default();
+
+    let format = match inv.value("format") {
+        None | Some("text") => DiffFormat::Text,
+        Some("json") => DiffFormat::Json,
+        Some("patch") => DiffFormat::Patch,
+        Some(name) => inv.usage_error(&format!("unsupported format: {}", name))
+    };
+
+    if let Some(fields) = inv.value("fields") {
+        diff_options.ado_fields = Some(common::field_list(fields));
+    }
+    for fields in inv.values("ignore-fields") {
+        diff_options.ado_ignore_fields.extend(common::field_list(fields));
+    }
+    if let Some(mode) = common::compare_mode(inv) {
+        diff_options.ado_mode = mode;
+    }
+    for spec in inv.values("compare") {
+        let parsed = spec.find('=').and_then(|p| {
+            let mode = adif::adif_parse_compare_mode(&spec[p + 1..])?;
+            Some((spec[0..p].trim().to_lowercase(), mode))
+        });
+        match parsed {
+            Some((field, mode)) => {
+                diff_options.ado_field_modes.insert(field, mode);
+            },
+            None => inv.usage_error(&format!("invalid comparison: {}", spec))
+        }
+    }
+
+    let adf1 = common::read_file(&inv.inv_operands[0], &parse_options)?;
+    let adf2 = common::read_file(&inv.inv_operands[1], &parse_options)?;
+
+    let records1 = &adf1.adif_records;
+    let records2 = &adf2.adif_records;
+    let result = adif::adif_match_records(records1, records2, &match_options);
+    let diffs : Vec<Vec<adif::AdifFieldDiff>> = result.amr_matched.iter()
+        .map(|&(i, j)| adif::adif_diff_records(&records1[i], &records2[j],
+        &diff_options)).collect();
+    let ndiff = diffs.iter().filter(|d| !d.is_empty()).count();
+    let identical = ndiff == 0 && result.amr_left_only.is_empty() &&
+        result.amr_right_only.is_empty();
+
+    let output = inv.value(common::OPT_OUTPUT.os_long);
+    match format {
+        DiffFormat::Text => {
+            common::write_output(output,
+                &|out| write_text(out, &adf1, &adf2, &result, &diffs))?;
+        },
+        DiffFormat::Json => {
+            let json = make_json(&adf1, &adf2, &result, &diffs);
+            common::write_output(output, &|out| writeln!(out, "{}", json))?;
+        },
+        DiffFormat::Patch => {
+            let patch = adif::adif_patch_create(&adf1, &adf2, &result,
+                &match_options, &diff_options);
+            common::write_adif(output, &patch)?;
+        }
+    }
+
+    Ok(if identical { 0 } else { 1 })
+}
+
+fn write_text(out: &mut dyn io::Write, adf1: &adif::AdifFile,
+    adf2: &adif::AdifFile, result: &adif::AdifMatchResult,
+    diffs: &[Vec<adif::AdifFieldDiff>]) -> io::Result<()>
+{
+    let label1 = &adf1.adif_label;
+    let label2 = &adf2.adif_label;
+
+    for &i in &result.amr_left_only {
+        writeln!(out, "only in {}: {}", label1,
+            adif::adif_qso_summary(&adf1.adif_records[i]))?;
+    }
+
+    for &j in &result.amr_right_only {
+        writeln!(out, "only in {}: {}", label2,
+            adif::adif_qso_summary(&adf2.adif_records[j]))?;
+    }
+
+    let mut ndiff = 0;
+    for (&(i, _), recdiffs) in result.amr_matched.iter().zip(diffs) {
+        if recdiffs.is_empty() {
+            continue;
+        }
+
+        ndiff += 1;
+        writeln!(out, "records differ: {}",
+            adif::adif_qso_summary(&adf1.adif_records[i]))?;
+        for d in recdiffs {
+            writeln!(out, "    {}: {} vs. {}", d.afd_field,
+                common::field_state(&d.afd_left),
+                common::field_state(&d.afd_right))?;
+        }
+    }
+
+    writeln!(out, "records only in {}: {}", label1,
+        result.amr_left_only.len())?;
+    writeln!(out, "records only in {}: {}", label2,
+        result.amr_right_only.len())?;
+    writeln!(out, "matched records: {}", result.amr_matched.len())?;
+    writeln!(out, "matched records with differences: {}", ndiff)
+}
+
+//
+// Build a JSON description of the results.  Records are identified by their
+// 1-based position in each file.  In field differences, null means the field
+// was absent.
+//
+fn make_json(adf1: &adif::AdifFile, adf2: &adif::AdifFile,
+    result: &adif::AdifMatchResult, diffs: &[Vec<adif::AdifFieldDiff>]) ->
+    adif::JsonValue
+{
+    let only = |adf: &adif::AdifFile, indices: &[usize]| {
+        adif::JsonValue::JSV_ARRAY(indices.iter().map(|&i| {
+            adif::json_object(vec![
+                ("record", adif::json_number((i + 1) as u32)),
+                ("summary", adif::json_string(
+                    &adif::adif_qso_summary(&adf.adif_records[i]))),
+                ("fields", adif::json_record(&adf.adif_records[i]))
+            ])
+        }).collect())
+    };
+
+    let matched = result.amr_matched.iter().zip(diffs).map(
+        |(&(i, j), recdiffs)| {
+        adif::json_object(vec![
+            ("left_record", adif::json_number((i + 1) as u32)),
+            ("right_record", adif::json_number((j + 1) as u32)),
+            ("summary", adif::json_string(
+                &adif::adif_qso_summary(&adf1.adif_records[i]))),
+            ("differences", adif::JsonValue::JSV_ARRAY(recdiffs.iter().map(
+                |d| adif::json_object(vec![
+                    ("field", adif::json_string(&d.afd_field)),
+                    ("left", adif::json_optional_string(&d.afd_left)),
+                    ("right", adif::json_optional_string(&d.afd_right))
+                ])).collect()))
+        ])
+    }).collect();
+
+    let ndiff = diffs.iter().filter(|d| !d.is_empty()).count();
+    adif::json_object(vec![
+        ("left", adif::json_string(&adf1.adif_label)),
+        ("right", adif::json_string(&adf2.adif_label)),
+        ("left_only", only(adf1, &result.amr_left_only)),
+        ("right_only", only(adf2, &result.amr_right_only)),
+        ("matched", adif::JsonValue::JSV_ARRAY(matched)),
+        ("summary", adif::json_object(vec![
+            ("left_only", adif::json_number(
+                result.amr_left_only.len() as u32)),
+            ("right_only", adif::json_number(
+                result.amr_right_only.len() as u32)),
+            ("matched", adif::json_number(result.amr_matched.len() as u32)),
+            ("different", adif::json_number(ndiff as u32))
+        ]))
+    ])
+}
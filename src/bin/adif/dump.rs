@@ -0,0 +1,123 @@
+//
+// src/bin/adif/dump.rs: "adif dump" prints the contents of an ADIF file
+// See src/dump.rs for the output formats.
+//
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "dump",
+    cmd_summary: "print the records of an ADIF file",
+    cmd_operands: "FILENAME",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "\
+By default, this prints a summary of the file followed by each record.  With
+--fields (and no --format), it prints a table of just those fields.",
+    cmd_options: &[
+        common::OPT_FILTER,
+        OptSpec {
+            os_short: Some('F'),
+            os_long: "fields",
+            os_arg: Some("FIELDS"),
+            os_help: "print only these fields (may be repeated)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "format",
+            os_arg: Some("FORMAT"),
+            os_help: "output format: text (default), table (default with\n\
+                --fields), csv, tsv, jsonl, json, adi, or adx"
+        },
+        OptSpec {
+            os_short: Some('s'),
+            os_long: "sort",
+            os_arg: Some("KEYS"),
+            os_help: "sort records by these fields, like \"band,-time\"\n\
+                (\"-\" sorts in descending order, and \"time\" is the\n\
+                QSO start time)"
+        },
+        OptSpec {
+            os_short: Some('g'),
+            os_long: "group-by",
+            os_arg: Some("FIELDS"),
+            os_help: "print records grouped by these fields"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "first",
+            os_arg: None,
+            os_help: "print only the first matching record"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "summary-only",
+            os_arg: None,
+            os_help: "print only the file summary"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "no-summary",
+            os_arg: None,
+            os_help: "do not print the file summary"
+        },
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let parse_options = common::parse_options(inv);
+    let fields : Vec<String> = inv.values("fields").iter().flat_map(
+        |f| common::field_list(f)).collect();
+
+    let format = match inv.value("format") {
+        Some(name) => match adif::adif_parse_dump_format(name) {
+            Some(f) => f,
+            None => inv.usage_error(&format!("unsupported format: {}", name))
+        },
+        None if !fields.is_empty() => adif::AdifDumpFormat::ADDF_TABLE,
+        None => adif::AdifDumpFormat::ADDF_TEXT
+    };
+
+    let mut sort = Vec::new();
+    for spec in inv.values("sort") {
+        match adif::adif_parse_sort_keys(spec) {
+            Ok(keys) => sort.extend(keys),
+            Err(error) => inv.usage_error(&format!("{}", error))
+        }
+    }
+
+    let which = if inv.flag("summary-only") {
+        adif::AdifDumpWhichRecords::ADR_NONE
+    } else if inv.flag("first") {
+        adif::AdifDumpWhichRecords::ADR_ONE
+    } else {
+        adif::AdifDumpWhichRecords::ADR_ALL
+    };
+
+    let dump_options = adif::AdifDumpOptions {
+        admp_format: format,
+        admp_which: which,
+        admp_filter: common::filter(inv),
+        admp_fields: if fields.is_empty() { None } else { Some(fields) },
+        admp_sort: sort,
+        admp_group_by: inv.values("group-by").iter().flat_map(
+            |f| common::field_list(f)).collect(),
+        admp_summary: !inv.flag("no-summary")
+    };
+
+    let adf = common::read_file(&inv.inv_operands[0], &parse_options)?;
+    common::warn_diagnostics(inv, &adf);
+    common::write_output(inv.value(common::OPT_OUTPUT.os_long), &|out| {
+        adif::adif_dump(&adf, &dump_options, out).map_err(
+            |error| std::io::Error::other(error.to_string()))
+    })?;
+    Ok(0)
+}
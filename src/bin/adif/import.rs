@@ -0,0 +1,150 @@
+//
+// src/bin/adif/import.rs: "adif import" imports QSOs from a CSV spreadsheet
+// Columns are mapped to ADIF fields (and their values converted) using a
+// mapping file given with -m, mapping lines given with --map, and defaults
+// given with --default.  See src/csvimport.rs for the mapping format.  Every
+// record is validated, and problems are reported on stderr by row.  Rows with
+// problems are left out of the output unless --keep-invalid is given.  This
+// exits 0 if every row was imported cleanly, 1 if any row had problems, and 2
+// on error.
+//
+
+use std::io::Read;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "import",
+    cmd_summary: "import QSOs from a CSV spreadsheet",
+    cmd_operands: "FILENAME",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "\
+The first row names the columns.  Each line of a mapping file (or --map) has
+the form \"COLUMN = FIELD [CONVERSION]\", where CONVERSION is one of:
+
+    date PATTERN    e.g., \"date %d/%m/%Y\" or \"date %d %b %y\"
+    time PATTERN    e.g., \"time %H%M\" or \"time %H:%M:%S\"
+    khz             frequency in kHz (written in MHz)
+
+An empty FIELD ignores the column.  A mapping file may also contain
+\"default FIELD = VALUE\" and \"ignore unmapped\" lines.  Unmapped columns hold
+the field of the same name.  Rows with problems are reported and left out.
+Exits 0 if every row was imported cleanly, 1 if not, and 2 on error.",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('m'),
+            os_long: "mapping",
+            os_arg: Some("MAPFILE"),
+            os_help: "read the column mapping from MAPFILE"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "map",
+            os_arg: Some("COLUMN=FIELD"),
+            os_help: "map a column, as in a mapping file (may be\n\
+                repeated)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "default",
+            os_arg: Some("FIELD=VALUE"),
+            os_help: "value for rows that lack FIELD (may be repeated)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "ignore-unmapped",
+            os_arg: None,
+            os_help: "ignore columns that aren't mapped"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "tsv",
+            os_arg: None,
+            os_help: "input is tab-separated (default if FILENAME ends\n\
+                in \".tsv\")"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "keep-invalid",
+            os_arg: None,
+            os_help: "keep rows that have problems"
+        },
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let mut mapping = match inv.value("mapping") {
+        None => adif::AdifCsvMapping::default(),
+        Some(filename) => load_mapping(filename)?
+    };
+    for spec in inv.values("map") {
+        if let Err(error) = adif::adif_parse_csv_mapping_line(&mut mapping,
+            spec) {
+            inv.usage_error(&format!("--map: {}", error));
+        }
+    }
+    for spec in inv.values("default") {
+        if let Err(error) = adif::adif_parse_csv_mapping_line(&mut mapping,
+            &format!("default {}", spec)) {
+            inv.usage_error(&format!("--default: {}", error));
+        }
+    }
+    if inv.flag("ignore-unmapped") {
+        mapping.acm_ignore_unmapped = true;
+    }
+
+    let filename = &inv.inv_operands[0];
+    let sep = if inv.flag("tsv") || filename.to_lowercase().ends_with(".tsv") {
+        '\t'
+    } else {
+        ','
+    };
+
+    let label = common::input_label(filename);
+    let mut source = common::open_input(filename)?;
+    let result = adif::adif_csv_import(label, &mut source, sep, &mapping,
+        &common::parse_options(inv)).map_err(
+        |error| format!("{}: {}", label, error))?;
+
+    let bad_rows = result.aci_bad_rows;
+    for bad in &bad_rows {
+        for problem in &bad.acb_problems {
+            inv.warn(&format!("{}: row {}: {}", label, bad.acb_row, problem));
+        }
+    }
+
+    let mut adf = result.aci_file;
+    let nrows = adf.adif_records.len();
+    let nbad = bad_rows.len();
+    if !inv.flag("keep-invalid") {
+        let mut which = 0;
+        adf.adif_records.retain(|_| {
+            which += 1;
+            !bad_rows.iter().any(|b| b.acb_record == which - 1)
+        });
+    }
+
+    common::write_adif(inv.value(common::OPT_OUTPUT.os_long), &adf)?;
+    inv.warn(&format!("imported {} of {} rows ({} with problems)",
+        adf.adif_records.len(), nrows, nbad));
+    Ok(if nbad == 0 { 0 } else { 1 })
+}
+
+fn load_mapping(filename: &str) -> Result<adif::AdifCsvMapping, String>
+{
+    let label = common::input_label(filename);
+    let mut text = String::new();
+    common::open_input(filename)?.read_to_string(&mut text).map_err(
+        |error| format!("read \"{}\": {}", label, error))?;
+    adif::adif_parse_csv_mapping(&text).map_err(
+        |error| format!("{}: {}", label, error))
+}
@@ -0,0 +1,144 @@
+//
+// src/bin/adif/main.rs: command-line interface to the adif library
+// This program provides several subcommands for working with ADIF files:
+//
+//     adif COMMAND [OPTIONS] [ARGUMENTS...]
+//
+// Each command lives in its own file in this directory and describes its
+// options with a table (see src/bin/adif/opts.rs), from which we generate
+// usage messages, "--help" output, and shell completion scripts.
+//
+
+use std::env;
+use std::path;
+use std::process;
+
+extern crate adif;
+
+mod awards;
+mod cabrillo;
+mod common;
+mod completion;
+mod convert;
+mod cty;
+mod dedupe;
+mod diff;
+mod dump;
+mod import;
+mod map;
+mod merge;
+mod opts;
+mod patch;
+mod qsl;
+mod reconcile;
+mod report;
+mod score;
+mod split;
+mod store;
+mod validate;
+
+use opts::Command;
+
+//
+// All commands, in the order in which they're listed in help output.
+//
+pub const COMMANDS : &[Command] = &[
+    dump::COMMAND,
+    convert::COMMAND,
+    import::COMMAND,
+    validate::COMMAND,
+    diff::COMMAND,
+    patch::COMMAND,
+    merge::COMMAND,
+    dedupe::COMMAND,
+    split::COMMAND,
+    report::COMMAND,
+    awards::COMMAND,
+    qsl::COMMAND,
+    reconcile::COMMAND,
+    cty::COMMAND,
+    cabrillo::COMMAND,
+    score::COMMAND,
+    store::COMMAND,
+    map::COMMAND,
+    completion::COMMAND
+];
+
+fn main()
+{
+    let argv : Vec<String> = env::args().collect();
+    let progname = argv.first().and_then(
+        |a| path::Path::new(a).file_name()).and_then(|f| f.to_str())
+        .unwrap_or("adif").to_string();
+
+    let name = match argv.get(1) {
+        None => usage(&progname, "expected a command"),
+        Some(name) => name.as_str()
+    };
+
+    match name {
+        "-h" | "--help" => {
+            print!("{}", help(&progname));
+            return;
+        },
+        "help" => {
+            match argv.get(2) {
+                None => print!("{}", help(&progname)),
+                Some(cmdname) => match find_command(cmdname) {
+                    Some(cmd) => print!("{}", opts::opts_help(cmd,
+                        &format!("{} {}", progname, cmd.cmd_name))),
+                    None => usage(&progname,
+                        &format!("unknown command: {}", cmdname))
+                }
+            }
+            return;
+        },
+        "--version" => {
+            println!("{} {}", progname, env!("CARGO_PKG_VERSION"));
+            return;
+        },
+        _ => ()
+    }
+
+    let cmd = match find_command(name) {
+        Some(cmd) => cmd,
+        None => usage(&progname, &format!("unknown command: {}", name))
+    };
+
+    let cmdprogname = format!("{} {}", progname, cmd.cmd_name);
+    let inv = opts::opts_parse(cmd, &cmdprogname, &argv[2..]);
+    match (cmd.cmd_run)(&inv) {
+        Ok(status) => process::exit(status),
+        Err(errmsg) => {
+            eprintln!("{}: {}", cmdprogname, errmsg);
+            process::exit(cmd.cmd_error_status);
+        }
+    }
+}
+
+fn find_command(name: &str) -> Option<&'static Command>
+{
+    COMMANDS.iter().find(|c| c.cmd_name == name)
+}
+
+fn help(progname: &str) -> String
+{
+    let mut help = format!("usage: {} COMMAND [OPTIONS] [ARGUMENTS...]\n\n\
+        Commands:\n", progname);
+    let width = COMMANDS.iter().map(|c| c.cmd_name.len()).max().unwrap_or(0);
+    for cmd in COMMANDS {
+        help.push_str(&format!("    {:w$}  {}\n", cmd.cmd_name,
+            cmd.cmd_summary, w = width));
+    }
+    help.push_str(&format!("\nRun \"{} COMMAND --help\" (or \"{} help \
+        COMMAND\") for details\nabout a command.  File arguments may be \"-\" \
+        for stdin.\n", progname, progname));
+    help
+}
+
+fn usage(progname: &str, message: &str) -> !
+{
+    eprintln!("{}: {}", progname, message);
+    eprint!("{}", help(progname));
+    process::exit(2);
+}
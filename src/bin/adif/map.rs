@@ -0,0 +1,122 @@
+//
+// src/bin/adif/map.rs: "adif map" draws QSOs on a map as GeoJSON or KML
+// Each QSO is located using "lat" and "lon" or "gridsquare", and paths are
+// drawn from the station's location ("my_lat" and "my_lon" or
+// "my_gridsquare", or the location given with --station).  See src/geo.rs for
+// details.  Records without a location are reported on stderr.
+//
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "map",
+    cmd_summary: "write a map of QSOs as GeoJSON or KML",
+    cmd_operands: "FILENAME",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "\
+Each QSO is drawn as a point with a great-circle path from the station.
+Features have properties for the selected fields, plus \"distance_km\" and
+\"bearing\" (in degrees) from the station.",
+    cmd_options: &[
+        common::OPT_FILTER,
+        OptSpec {
+            os_short: Some('F'),
+            os_long: "fields",
+            os_arg: Some("FIELDS"),
+            os_help: "fields to include as properties (default:\n\
+                call,qso_date,time_on,band,mode,gridsquare)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "format",
+            os_arg: Some("FORMAT"),
+            os_help: "output format: geojson (default) or kml (default\n\
+                if the output file ends in \".kml\")"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "station",
+            os_arg: Some("LOCATION"),
+            os_help: "station location, as a grid square or\n\
+                \"LATITUDE,LONGITUDE\" in degrees (default: from\n\
+                my_lat and my_lon or my_gridsquare)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "no-paths",
+            os_arg: None,
+            os_help: "draw only points, not paths"
+        },
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let output = inv.value(common::OPT_OUTPUT.os_long);
+    let format = match inv.value("format") {
+        Some(name) => match adif::adif_parse_map_format(name) {
+            Some(f) => f,
+            None => inv.usage_error(&format!("unsupported format: {}", name))
+        },
+        None if output.is_some_and(
+            |o| o.to_lowercase().ends_with(".kml")) =>
+            adif::AdifMapFormat::AMF_KML,
+        None => adif::AdifMapFormat::AMF_GEOJSON
+    };
+
+    let mut options = adif::AdifMapOptions {
+        amap_format: format,
+        amap_filter: common::filter(inv),
+        amap_paths: !inv.flag("no-paths"),
+        ..Default::default()
+    };
+    let fields : Vec<String> = inv.values("fields").iter().flat_map(
+        |f| common::field_list(f)).collect();
+    if !fields.is_empty() {
+        options.amap_fields = fields;
+    }
+    if let Some(location) = inv.value("station") {
+        match parse_station(location) {
+            Some(station) => options.amap_station = Some(station),
+            None => inv.usage_error(&format!("invalid station location: {}",
+                location))
+        }
+    }
+
+    let adf = common::read_file(&inv.inv_operands[0],
+        &common::parse_options(inv))?;
+    common::warn_diagnostics(inv, &adf);
+
+    let mut text = Vec::new();
+    let diagnostics = adif::adif_map_write(&adf.adif_records, &options,
+        &mut text).map_err(|error| format!("write: {}", error))?;
+    common::write_output(output, &|out| out.write_all(&text))?;
+    for diagnostic in diagnostics {
+        inv.warn(&format!("warning: {}: {}", adf.adif_label, diagnostic));
+    }
+    Ok(0)
+}
+
+fn parse_station(location: &str) -> Option<(f64, f64)>
+{
+    match location.split_once(',') {
+        None => adif::adif_parse_gridsquare(location.trim()),
+        Some((lat, lon)) => {
+            let lat = lat.trim().parse::<f64>().ok()?;
+            let lon = lon.trim().parse::<f64>().ok()?;
+            if lat.abs() <= 90.0 && lon.abs() <= 180.0 {
+                Some((lat, lon))
+            } else {
+                None
+            }
+        }
+    }
+}
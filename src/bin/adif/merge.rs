@@ -0,0 +1,171 @@
+//
+// src/bin/adif/merge.rs: "adif merge" does a three-way merge of ADIF files
+// Given a base log and two logs derived from it, this writes a merged log to
+// stdout (or the file given with -o).  See src/merge.rs for how records and
+// fields are merged.  Conflicts are summarized on stderr and can be written in
+// full as JSON with --conflicts.  Unresolved conflicts keep the left value and
+// are marked in the merged log with an APP_ADIFMERGE_CONFLICT field.
+// This exits 0 if there were no unresolved conflicts, 1 if there were, and 2
+// on error.
+//
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "merge",
+    cmd_summary: "merge two logs derived from a common base log",
+    cmd_operands: "BASE LEFT RIGHT",
+    cmd_nargs: (3, Some(3)),
+    cmd_description: "\
+Unresolved conflicts keep the left value and are marked in the merged log with
+an APP_ADIFMERGE_CONFLICT field.  Exits 0 if there were no unresolved
+conflicts, 1 if there were, and 2 on error.",
+    cmd_options: &[
+        common::OPT_OUTPUT,
+        OptSpec {
+            os_short: None,
+            os_long: "conflicts",
+            os_arg: Some("FILE"),
+            os_help: "write conflicts to FILE as JSON"
+        },
+        OptSpec {
+            os_short: Some('s'),
+            os_long: "strategy",
+            os_arg: Some("STRATEGY"),
+            os_help: "how to resolve conflicts: none (default),\n\
+                prefer-left, prefer-right, or\n\
+                prefer-newest-qsl"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "prefer",
+            os_arg: Some("FIELD=STRATEGY"),
+            os_help: "how to resolve conflicts in one field (may be\n\
+                repeated)"
+        },
+        common::OPT_COMPARE_MODE,
+        common::OPT_KEY,
+        common::OPT_TOLERANCE,
+        common::OPT_IGNORE_TIME,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let parse_options = common::parse_options(inv);
+    let mut merge_options = adif::AdifMergeOptions {
+        amgo_match: common::match_options(inv),
+        ..Default::default()
+    };
+
+    if let Some(name) = inv.value("strategy") {
+        match adif::adif_parse_conflict_strategy(name) {
+            Some(s) => merge_options.amgo_strategy = s,
+            None => inv.usage_error(&format!("unsupported strategy: {}",
+                name))
+        }
+    }
+    for spec in inv.values("prefer") {
+        let parsed = spec.find('=').and_then(|p| {
+            let s = adif::adif_parse_conflict_strategy(&spec[p + 1..])?;
+            Some((spec[0..p].trim().to_lowercase(), s))
+        });
+        match parsed {
+            Some((field, s)) => {
+                merge_options.amgo_field_strategies.insert(field, s);
+            },
+            None => inv.usage_error(&format!("invalid field strategy: {}",
+                spec))
+        }
+    }
+    if let Some(mode) = common::compare_mode(inv) {
+        merge_options.amgo_diff.ado_mode = mode;
+    }
+
+    let base = common::read_file(&inv.inv_operands[0], &parse_options)?;
+    let left = common::read_file(&inv.inv_operands[1], &parse_options)?;
+    let right = common::read_file(&inv.inv_operands[2], &parse_options)?;
+    let result = adif::adif_merge(&base, &left, &right, &merge_options);
+
+    common::write_adif(inv.value(common::OPT_OUTPUT.os_long),
+        &result.amgr_file)?;
+
+    if let Some(conflicts) = inv.value("conflicts") {
+        let json = make_json(&result.amgr_conflicts);
+        common::write_output(Some(conflicts),
+            &|out| writeln!(out, "{}", json))?;
+    }
+
+    let mut nunresolved = 0;
+    for c in &result.amgr_conflicts {
+        let what = match c.amc_kind {
+            adif::AdifConflictKind::ACK_FIELD => format!("{}: {} vs. {}",
+                c.amc_field, common::field_state(&c.amc_left),
+                common::field_state(&c.amc_right)),
+            adif::AdifConflictKind::ACK_DELETE_LEFT =>
+                String::from("deleted in left, modified in right"),
+            adif::AdifConflictKind::ACK_DELETE_RIGHT =>
+                String::from("modified in left, deleted in right")
+        };
+
+        if c.amc_resolution.is_none() {
+            nunresolved += 1;
+        }
+
+        inv.warn(&format!("{}conflict: {}: {}",
+            if c.amc_resolution.is_some() { "resolved " } else { "" },
+            c.amc_summary, what));
+    }
+
+    inv.warn(&format!("{} added, {} deleted, {} changed, {} conflicts \
+        ({} unresolved)", result.amgr_added, result.amgr_deleted,
+        result.amgr_changed, result.amgr_conflicts.len(), nunresolved));
+    Ok(if nunresolved == 0 { 0 } else { 1 })
+}
+
+//
+// Build a JSON description of the conflicts.  Records are identified by their
+// 1-based position in the merged log (null if the record was dropped).  Values
+// are null where the field was absent.
+//
+fn make_json(conflicts: &[adif::AdifMergeConflict]) -> adif::JsonValue
+{
+    adif::JsonValue::JSV_ARRAY(conflicts.iter().map(|c| {
+        let kind = match c.amc_kind {
+            adif::AdifConflictKind::ACK_FIELD => "field",
+            adif::AdifConflictKind::ACK_DELETE_LEFT => "deleted_left",
+            adif::AdifConflictKind::ACK_DELETE_RIGHT => "deleted_right"
+        };
+        let resolution = match c.amc_resolution {
+            None => adif::JsonValue::JSV_NULL,
+            Some(adif::AdifConflictStrategy::ACS_PREFER_LEFT) =>
+                adif::json_string("prefer-left"),
+            Some(adif::AdifConflictStrategy::ACS_PREFER_RIGHT) =>
+                adif::json_string("prefer-right"),
+            Some(adif::AdifConflictStrategy::ACS_PREFER_NEWEST_QSL) =>
+                adif::json_string("prefer-newest-qsl"),
+            Some(adif::AdifConflictStrategy::ACS_NONE) =>
+                adif::JsonValue::JSV_NULL
+        };
+        adif::json_object(vec![
+            ("kind", adif::json_string(kind)),
+            ("summary", adif::json_string(&c.amc_summary)),
+            ("record", match c.amc_record {
+                Some(r) => adif::json_number((r + 1) as u32),
+                None => adif::JsonValue::JSV_NULL
+            }),
+            ("field", adif::json_string(&c.amc_field)),
+            ("base", adif::json_optional_string(&c.amc_base)),
+            ("left", adif::json_optional_string(&c.amc_left)),
+            ("right", adif::json_optional_string(&c.amc_right)),
+            ("resolution", resolution)
+        ])
+    }).collect())
+}
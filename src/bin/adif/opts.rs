@@ -0,0 +1,265 @@
+//
+// src/bin/adif/opts.rs: command-line option parsing
+//
+// Each subcommand describes its options with a table of OptSpecs.  From that,
+// we parse arguments, generate usage and help messages, and generate shell
+// completion scripts (see src/bin/adif/completion.rs).  Options look like:
+//
+//     -T                  a flag
+//     -TS                 several flags
+//     -t 5, -t5           an option with an argument
+//     --ignore-time       a long flag
+//     --tolerance 5       a long option with an argument
+//     --tolerance=5
+//
+// Options and operands may appear in any order.  "--" ends the options, and
+// "-" by itself is an operand (which usually means stdin or stdout).  Every
+// command accepts "-h" and "--help".
+//
+
+use std::process;
+
+pub struct OptSpec {
+    pub os_short : Option<char>,            // short name, if any
+    pub os_long : &'static str,             // long name (without "--")
+    pub os_arg : Option<&'static str>,      // name of argument, if any
+    pub os_help : &'static str              // description (may have newlines)
+}
+
+pub struct Command {
+    pub cmd_name : &'static str,
+    pub cmd_summary : &'static str,         // one-line description
+    pub cmd_operands : &'static str,        // e.g., "FILENAME..."
+    pub cmd_nargs : (usize, Option<usize>), // minimum and maximum operands
+    pub cmd_description : &'static str,     // printed by --help
+    pub cmd_options : &'static [OptSpec],
+    pub cmd_error_status : i32,             // exit status on failure
+    pub cmd_run : fn(&Invocation) -> Result<i32, String>
+}
+
+const OPT_HELP : OptSpec = OptSpec {
+    os_short: Some('h'),
+    os_long: "help",
+    os_arg: None,
+    os_help: "print this help and exit"
+};
+
+//
+// The result of parsing a command's arguments.  Options are recorded by their
+// long names, in the order given.
+//
+pub struct Invocation<'a> {
+    pub inv_progname : String,              // e.g., "adif dump"
+    pub inv_command : &'a Command,
+    pub inv_options : Vec<(&'static str, Option<String>)>,
+    pub inv_operands : Vec<String>
+}
+
+impl<'a> Invocation<'a> {
+    pub fn flag(&self, long: &str) -> bool
+    {
+        self.inv_options.iter().any(|(name, _)| *name == long)
+    }
+
+    //
+    // Returns the last value given for the option, if any.
+    //
+    pub fn value(&self, long: &str) -> Option<&str>
+    {
+        self.values(long).pop()
+    }
+
+    pub fn values(&self, long: &str) -> Vec<&str>
+    {
+        self.inv_options.iter().filter(|(name, _)| *name == long).filter_map(
+            |(_, value)| value.as_deref()).collect()
+    }
+
+    pub fn usage_error(&self, message: &str) -> !
+    {
+        opts_usage_error(self.inv_command, &self.inv_progname, message)
+    }
+
+    pub fn warn(&self, message: &str)
+    {
+        eprintln!("{}: {}", self.inv_progname, message);
+    }
+}
+
+//
+// Returns the options that a command accepts, including --help.
+//
+pub fn opts_all(cmd: &Command) -> Vec<&OptSpec>
+{
+    cmd.cmd_options.iter().chain(std::iter::once(&OPT_HELP)).collect()
+}
+
+pub fn opts_usage_line(cmd: &Command, progname: &str) -> String
+{
+    format!("usage: {} [OPTIONS] {}", progname, cmd.cmd_operands)
+}
+
+pub fn opts_usage_error(cmd: &Command, progname: &str, message: &str) -> !
+{
+    eprintln!("{}: {}", progname, message);
+    eprintln!("{}", opts_usage_line(cmd, progname));
+    eprintln!("Try \"{} --help\" for more information.", progname);
+    process::exit(2);
+}
+
+//
+// Returns the full help message for a command.
+//
+pub fn opts_help(cmd: &Command, progname: &str) -> String
+{
+    let mut help = format!("{}\n\n{}\n", opts_usage_line(cmd, progname),
+        cmd.cmd_summary);
+    if !cmd.cmd_description.is_empty() {
+        help.push_str(&format!("\n{}\n", cmd.cmd_description));
+    }
+
+    let labels : Vec<(String, &OptSpec)> = opts_all(cmd).into_iter().map(
+        |o| {
+        let mut label = match o.os_short {
+            Some(c) => format!("-{}, --{}", c, o.os_long),
+            None => format!("    --{}", o.os_long)
+        };
+        if let Some(arg) = o.os_arg {
+            label.push(' ');
+            label.push_str(arg);
+        }
+        (label, o)
+    }).collect();
+    let width = labels.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+
+    help.push_str("\nOptions:\n");
+    for (label, o) in &labels {
+        for (i, line) in o.os_help.lines().enumerate() {
+            let prefix = if i == 0 { label.as_str() } else { "" };
+            help.push_str(&format!("    {:w$}  {}\n", prefix, line,
+                w = width));
+        }
+    }
+
+    help
+}
+
+fn opts_find<'a>(cmd: &'a Command, name: &str, short: bool) ->
+    Option<&'a OptSpec>
+{
+    opts_all(cmd).into_iter().find(|o| {
+        if short {
+            o.os_short.map(|c| c.to_string()).as_deref() == Some(name)
+        } else {
+            o.os_long == name
+        }
+    })
+}
+
+//
+// Parse "args" (which excludes the program and command names) for "cmd".
+// Usage errors are reported and cause the program to exit, and --help prints
+// the help message and exits.
+//
+pub fn opts_parse<'a>(cmd: &'a Command, progname: &str, args: &[String]) ->
+    Invocation<'a>
+{
+    let mut inv = Invocation {
+        inv_progname: progname.to_string(),
+        inv_command: cmd,
+        inv_options: Vec::new(),
+        inv_operands: Vec::new()
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+
+        if arg == "--" {
+            inv.inv_operands.extend(args[i..].iter().cloned());
+            break;
+        }
+
+        if arg == "-" || !arg.starts_with('-') {
+            inv.inv_operands.push(arg.clone());
+            continue;
+        }
+
+        //
+        // Split the argument into the options it names.  A long option may
+        // carry its value after "=".  A group of short options ends at the
+        // first one that takes an argument, and the rest of the group is that
+        // argument.
+        //
+        let mut named : Vec<(&OptSpec, Option<String>)> = Vec::new();
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.find('=') {
+                Some(p) => (&long[..p], Some(long[p + 1..].to_string())),
+                None => (long, None)
+            };
+            match opts_find(cmd, name, false) {
+                Some(o) => named.push((o, value)),
+                None => inv.usage_error(&format!("unrecognized option: --{}",
+                    name))
+            }
+        } else {
+            let chars : Vec<char> = arg[1..].chars().collect();
+            for (j, c) in chars.iter().enumerate() {
+                let o = match opts_find(cmd, &c.to_string(), true) {
+                    Some(o) => o,
+                    None => inv.usage_error(&format!("unrecognized option: \
+                        -{}", c))
+                };
+                if o.os_arg.is_some() && j + 1 < chars.len() {
+                    named.push((o, Some(chars[j + 1..].iter().collect())));
+                    break;
+                }
+                named.push((o, None));
+            }
+        }
+
+        for (o, mut value) in named {
+            if o.os_long == OPT_HELP.os_long {
+                print!("{}", opts_help(cmd, progname));
+                process::exit(0);
+            }
+
+            match (o.os_arg, &value) {
+                (Some(_), None) => {
+                    if i >= args.len() {
+                        inv.usage_error(&format!("option requires an \
+                            argument: --{}", o.os_long));
+                    }
+                    value = Some(args[i].clone());
+                    i += 1;
+                },
+                (None, Some(_)) => {
+                    inv.usage_error(&format!("option does not take an \
+                        argument: --{}", o.os_long));
+                },
+                _ => ()
+            }
+
+            inv.inv_options.push((o.os_long, value));
+        }
+    }
+
+    let (min, max) = cmd.cmd_nargs;
+    let n = inv.inv_operands.len();
+    if n < min || max.is_some_and(|m| n > m) {
+        let count = |n: usize| match n {
+            0 => String::from("no arguments"),
+            1 => String::from("one argument"),
+            _ => format!("{} arguments", n)
+        };
+        let expected = match max {
+            Some(m) if m == min => count(m),
+            Some(m) => format!("between {} and {}", min, count(m)),
+            None => format!("at least {}", count(min))
+        };
+        inv.usage_error(&format!("expected {}", expected));
+    }
+
+    inv
+}
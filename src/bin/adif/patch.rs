@@ -0,0 +1,53 @@
+//
+// src/bin/adif/patch.rs: "adif patch" applies a patch generated by
+// "adif diff --format patch" to an ADIF file, writing the result to stdout (or
+// the file given with -o).
+// This exits 0 if every change was applied, 1 if some changes could not be
+// applied (because the record to change was not found), and 2 on error.
+//
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "patch",
+    cmd_summary: "apply a patch created by \"diff --format patch\"",
+    cmd_operands: "FILENAME PATCHFILE",
+    cmd_nargs: (2, Some(2)),
+    cmd_description: "\
+Exits 0 if every change was applied, 1 if some changes could not be applied
+(because the record to change was not found), and 2 on error.",
+    cmd_options: &[
+        common::OPT_OUTPUT
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let options = adif::AdifParseOptions::default();
+    let patchfile = &inv.inv_operands[1];
+    let mut target = common::read_file(&inv.inv_operands[0], &options)?;
+    let patch = common::read_file(patchfile, &options)?;
+
+    let result = match adif::adif_patch_apply(&mut target, &patch) {
+        Ok(result) => result,
+        Err(error) => return Err(format!("{}: {}",
+            common::input_label(patchfile), error))
+    };
+
+    for &p in &result.apr_failed {
+        inv.warn(&format!("warning: patch record {}: no matching record: {}",
+            p + 1, adif::adif_qso_summary(&patch.adif_records[p])));
+    }
+
+    common::write_adif(inv.value(common::OPT_OUTPUT.os_long), &target)?;
+
+    inv.warn(&format!("{} added, {} deleted, {} modified, {} failed",
+        result.apr_added, result.apr_deleted, result.apr_modified,
+        result.apr_failed.len()));
+    Ok(if result.apr_failed.is_empty() { 0 } else { 1 })
+}
@@ -0,0 +1,215 @@
+//
+// src/bin/adif/qsl.rs: "adif qsl" reports and updates QSL status
+// By default, this prints how many QSOs are in each QSL state (not sent, to
+// send, sent, confirmed, or ignored) for cards, LoTW, and eQSL, or with -l,
+// lists the QSOs in one state.  With -m, it instead updates the status of the
+// QSOs matching the filter and writes the log to stdout (or the file given with
+// -o).  Updates that would undo progress are reported and skipped unless
+// --force is given.  See src/qsl.rs.
+//
+
+use std::io;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "qsl",
+    cmd_summary: "report or update the QSL status of QSOs",
+    cmd_operands: "FILENAME",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "\
+An update names a service (card, lotw, or eqsl), a direction (sent or rcvd),
+and optionally a status (default: Y), as in \"card-sent\" or \"card-sent=Q\".
+Setting a status of Y or V also sets the date sent or received.  For example,
+to mark cards for two calls as sent today via the bureau:
+
+    adif qsl -f \"call in (W1AW, K2XX)\" -m card-sent --via B log.adi",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('l'),
+            os_long: "list",
+            os_arg: Some("STATE"),
+            os_help: "list QSOs in STATE: not-sent, to-send, sent,\n\
+                confirmed, or ignored"
+        },
+        OptSpec {
+            os_short: Some('s'),
+            os_long: "service",
+            os_arg: Some("SERVICE"),
+            os_help: "with --list, only consider SERVICE: card, lotw,\n\
+                or eqsl"
+        },
+        OptSpec {
+            os_short: Some('m'),
+            os_long: "mark",
+            os_arg: Some("UPDATE"),
+            os_help: "update matching QSOs, as in \"card-sent\" or\n\
+                \"lotw-rcvd=V\" (may be repeated)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "date",
+            os_arg: Some("YYYYMMDD"),
+            os_help: "date sent or received (default: today, UTC)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "via",
+            os_arg: Some("ROUTE"),
+            os_help: "route for cards: B (bureau), D (direct),\n\
+                E (electronic), or M (manager)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "force",
+            os_arg: None,
+            os_help: "allow updates that undo progress (like Y to Q)"
+        },
+        common::OPT_FILTER,
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let updates = parse_updates(inv);
+    let list = inv.value("list").map(|name| {
+        match adif::adif_parse_qsl_state(name) {
+            Some(s) => s,
+            None => inv.usage_error(&format!("unknown QSL state: {}", name))
+        }
+    });
+    let services = match inv.value("service") {
+        None => adif::ADIF_QSL_SERVICES.to_vec(),
+        Some(name) => match adif::adif_parse_qsl_service(name) {
+            Some(s) => vec![s],
+            None => inv.usage_error(&format!("unknown QSL service: {}",
+                name))
+        }
+    };
+    if !updates.is_empty() && list.is_some() {
+        inv.usage_error("--mark and --list can't be used together");
+    }
+
+    let mut adf = common::read_file(&inv.inv_operands[0],
+        &common::parse_options(inv))?;
+    common::warn_diagnostics(inv, &adf);
+    let filter = common::filter(inv);
+    let output = inv.value(common::OPT_OUTPUT.os_long);
+
+    if updates.is_empty() {
+        common::write_output(output, &|out| match list {
+            Some(state) => write_list(out, &adf.adif_records, filter.as_ref(),
+                &services, state),
+            None => write_report(out, &adif::adif_qsl_report(
+                &adf.adif_records, filter.as_ref()))
+        })?;
+        return Ok(0);
+    }
+
+    let summary = adif::adif_qsl_update_records(&mut adf.adif_records,
+        filter.as_ref(), &updates);
+    common::write_adif(output, &adf)?;
+    for diagnostic in &summary.aqus_diagnostics {
+        inv.warn(&format!("{}: {}", adf.adif_label, diagnostic));
+    }
+    inv.warn(&format!("{}: {} QSOs matched, {} updated, {} updates refused",
+        adf.adif_label, summary.aqus_matched, summary.aqus_changed,
+        summary.aqus_diagnostics.len()));
+    Ok(if summary.aqus_diagnostics.is_empty() { 0 } else { 1 })
+}
+
+//
+// Returns the updates given with --mark, with the date, route, and force flag
+// filled in.
+//
+fn parse_updates(inv: &Invocation) -> Vec<adif::AdifQslUpdate>
+{
+    let date = match inv.value("date") {
+        None => adif::adif_timestamp_now().adts_date,
+        Some(text) => match adif::adif_parse_date(text) {
+            Some(d) => d,
+            None => inv.usage_error(&format!("invalid date: {}", text))
+        }
+    };
+
+    let via = inv.value("via");
+    let updates : Vec<adif::AdifQslUpdate> = inv.values("mark").iter().map(
+        |spec| match adif::adif_parse_qsl_update(spec) {
+        Ok(mut update) => {
+            update.aqu_date = Some(date);
+            update.aqu_force = inv.flag("force");
+            if update.aqu_service == adif::AdifQslService::AQS_CARD {
+                update.aqu_via = via.map(String::from);
+            }
+            update
+        },
+        Err(error) => inv.usage_error(&format!("--mark: {}", error))
+    }).collect();
+
+    if via.is_some() && !updates.iter().any(
+        |u| u.aqu_service == adif::AdifQslService::AQS_CARD) {
+        inv.usage_error("--via only applies to card updates");
+    }
+    updates
+}
+
+fn write_report(out: &mut dyn io::Write,
+    report: &[adif::AdifQslServiceReport]) -> io::Result<()>
+{
+    write!(out, "{:<8}", "Service")?;
+    for state in adif::ADIF_QSL_STATES {
+        write!(out, " {:>9}", adif::adif_qsl_state_name(*state))?;
+    }
+    writeln!(out)?;
+
+    for service in report {
+        write!(out, "{:<8}", adif::adif_qsl_service_name(
+            service.aqsr_service))?;
+        for count in service.aqsr_states.values() {
+            write!(out, " {:>9}", count)?;
+        }
+        writeln!(out)?;
+    }
+
+    for service in report {
+        if !service.aqsr_via.is_empty() {
+            let routes : Vec<String> = service.aqsr_via.iter().map(
+                |(via, n)| format!("{} {}", via, n)).collect();
+            writeln!(out, "{} routes: {}", adif::adif_qsl_service_name(
+                service.aqsr_service), routes.join(", "))?;
+        }
+    }
+    Ok(())
+}
+
+//
+// List the QSOs that are in the given state for any of the given services.
+// Records are identified by their 1-based position in the file.
+//
+fn write_list(out: &mut dyn io::Write, records: &[adif::AdifRecord],
+    filter: Option<&adif::AdifFilter>, services: &[adif::AdifQslService],
+    state: adif::AdifQslState) -> io::Result<()>
+{
+    for (i, rec) in records.iter().enumerate() {
+        if filter.is_some_and(|f| !adif::adif_filter_matches(f, rec)) {
+            continue;
+        }
+
+        let which : Vec<&str> = services.iter().filter(
+            |s| adif::adif_qsl_state(rec, **s) == state).map(
+            |s| adif::adif_qsl_service_name(*s)).collect();
+        if !which.is_empty() {
+            writeln!(out, "record {}: {} ({})", i + 1,
+                adif::adif_qso_summary(rec), which.join(", "))?;
+        }
+    }
+    Ok(())
+}
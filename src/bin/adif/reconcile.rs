@@ -0,0 +1,149 @@
+//
+// src/bin/adif/reconcile.rs: "adif reconcile" applies LoTW/eQSL confirmations
+// Each CONFIRMATIONS file (as downloaded from LoTW or eQSL) is matched against
+// the QSOs in FILENAME, and matching QSOs are marked as confirmed.  The updated
+// log is written to stdout (or the file given with -o), and a report of what
+// was confirmed, along with confirmations that match nothing in the log, is
+// written to stderr (or the file given with --report).  See src/reconcile.rs.
+//
+
+use std::io;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "reconcile",
+    cmd_summary: "apply LoTW or eQSL confirmations to a log",
+    cmd_operands: "FILENAME CONFIRMATIONS...",
+    cmd_nargs: (2, None),
+    cmd_description: "\
+QSOs match confirmations if the callsigns and bands match, the modes are in the
+same family (CW, phone, or digital), the station callsigns match (where both
+have one), and the times are close.  Matching QSOs
+get lotw_qsl_rcvd or eqsl_qsl_rcvd of Y with the date received, and any award
+credit in the confirmation's credit_granted.  Whether a file comes from LoTW or
+eQSL is recognized from its header unless --service is given.",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('s'),
+            os_long: "service",
+            os_arg: Some("SERVICE"),
+            os_help: "service the confirmations come from: lotw or\n\
+                eqsl (default: from the file's header)"
+        },
+        OptSpec {
+            os_short: Some('t'),
+            os_long: "tolerance",
+            os_arg: Some("MINUTES"),
+            os_help: "max difference in QSO times for a confirmation\n\
+                to match (default: 30)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "date",
+            os_arg: Some("YYYYMMDD"),
+            os_help: "date received, for confirmations that lack one\n\
+                (default: today, UTC)"
+        },
+        common::OPT_OUTPUT,
+        OptSpec {
+            os_short: None,
+            os_long: "report",
+            os_arg: Some("FILE"),
+            os_help: "write report to FILE (default: stderr)"
+        },
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let mut options = adif::AdifReconcileOptions::default();
+    if let Some(name) = inv.value("service") {
+        match adif::adif_parse_qsl_service(name) {
+            Some(s) if s != adif::AdifQslService::AQS_CARD =>
+                options.arco_service = Some(s),
+            _ => inv.usage_error(&format!("unsupported service: {}", name))
+        }
+    }
+    if let Some(minutes) = inv.value("tolerance") {
+        match minutes.parse::<u32>() {
+            Ok(m) => options.arco_time_tolerance = (m as i64) * 60,
+            Err(_) => inv.usage_error(&format!("invalid time tolerance: {}",
+                minutes))
+        }
+    }
+    options.arco_date = Some(match inv.value("date") {
+        None => adif::adif_timestamp_now().adts_date,
+        Some(text) => match adif::adif_parse_date(text) {
+            Some(d) => d,
+            None => inv.usage_error(&format!("invalid date: {}", text))
+        }
+    });
+
+    let parse_options = common::parse_options(inv);
+    let mut adf = common::read_file(&inv.inv_operands[0], &parse_options)?;
+    common::warn_diagnostics(inv, &adf);
+
+    let mut results = Vec::new();
+    for filename in &inv.inv_operands[1..] {
+        let confirmations = common::read_file(filename, &parse_options)?;
+        common::warn_diagnostics(inv, &confirmations);
+        let result = adif::adif_reconcile(&mut adf.adif_records,
+            &confirmations, &options).map_err(
+            |error| format!("{}: {}", confirmations.adif_label, error))?;
+        results.push((confirmations, result));
+    }
+
+    common::write_adif(inv.value(common::OPT_OUTPUT.os_long), &adf)?;
+
+    let report = |out: &mut dyn io::Write| write_report(out, &adf, &results);
+    match inv.value("report") {
+        None => {
+            let stderr = io::stderr();
+            report(&mut stderr.lock()).map_err(
+                |error| format!("write report: {}", error))?;
+        },
+        Some(reportname) => common::write_output(Some(reportname), &report)?
+    }
+
+    Ok(0)
+}
+
+//
+// Describe the confirmations applied from each file.  Records are identified by
+// their 1-based position in the log or confirmation file.
+//
+fn write_report(out: &mut dyn io::Write, adf: &adif::AdifFile,
+    results: &[(adif::AdifFile, adif::AdifReconcileResult)]) -> io::Result<()>
+{
+    for (confirmations, result) in results {
+        let label = &confirmations.adif_label;
+        let records = &confirmations.adif_records;
+        for &i in &result.arcr_updated {
+            writeln!(out, "{}: record {}: {}: confirmed", adf.adif_label,
+                i + 1, adif::adif_qso_summary(&adf.adif_records[i]))?;
+        }
+        for d in &result.arcr_diagnostics {
+            writeln!(out, "{}: {}", adf.adif_label, d)?;
+        }
+        for &j in &result.arcr_unmatched {
+            writeln!(out, "{}: record {}: {}: matches no QSO", label, j + 1,
+                adif::adif_qso_summary(&records[j]))?;
+        }
+
+        writeln!(out, "{}: {} confirmations ({}), {} matched, {} QSOs \
+            updated, {} unmatched, {} records not confirmed", label,
+            records.len() - result.arcr_unconfirmed.len(),
+            adif::adif_qsl_service_name(result.arcr_service),
+            result.arcr_matched.len(), result.arcr_updated.len(),
+            result.arcr_unmatched.len(), result.arcr_unconfirmed.len())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,353 @@
+//
+// src/bin/adif/report.rs: "adif report" summarizes QSOs in ADIF files
+// Each report counts QSOs worked and confirmed, grouped by one or more fields
+// (-b) or laid out as a table of one field against another (-m).  See
+// src/report.rs for details.  With no reports specified, this prints a table of
+// bands against modes.
+//
+
+use std::io;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "report",
+    cmd_summary: "count QSOs worked and confirmed",
+    cmd_operands: "FILENAME...",
+    cmd_nargs: (1, None),
+    cmd_description: "\
+With no reports specified, this prints a table of bands against modes.  FIELDS
+may include the derived fields year, month, day, and hour.",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('b'),
+            os_long: "by",
+            os_arg: Some("FIELDS"),
+            os_help: "count QSOs grouped by these fields (may be\n\
+                repeated)"
+        },
+        OptSpec {
+            os_short: Some('m'),
+            os_long: "matrix",
+            os_arg: Some("ROW,COLUMN"),
+            os_help: "count QSOs in a table of one field against another\n\
+                (may be repeated)"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "format",
+            os_arg: Some("FORMAT"),
+            os_help: "output format: text (default), csv, or json"
+        },
+        common::OPT_FILTER,
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+//
+// Output formats
+//
+#[derive(Clone, Copy, PartialEq)]
+enum ReportFormat {
+    Text,
+    Csv,
+    Json
+}
+
+//
+// Reports requested on the command line
+//
+enum ReportSpec {
+    Group(Vec<String>),
+    Matrix(String, String)
+}
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let parse_options = common::parse_options(inv);
+    let filter = common::filter(inv);
+
+    let format = match inv.value("format") {
+        None | Some("text") => ReportFormat::Text,
+        Some("csv") => ReportFormat::Csv,
+        Some("json") => ReportFormat::Json,
+        Some(name) => inv.usage_error(&format!("unsupported format: {}", name))
+    };
+
+    //
+    // Reports are printed in the order they were given, regardless of kind.
+    //
+    let mut specs : Vec<ReportSpec> = Vec::new();
+    for (name, value) in &inv.inv_options {
+        let value = match (*name, value) {
+            ("by", Some(v)) | ("matrix", Some(v)) => v,
+            _ => continue
+        };
+        let fields = common::field_list(value);
+        if *name == "by" {
+            if fields.is_empty() {
+                inv.usage_error(&format!("invalid field list: {}", value));
+            }
+            specs.push(ReportSpec::Group(fields));
+        } else {
+            if fields.len() != 2 {
+                inv.usage_error(&format!("expected two fields: {}", value));
+            }
+            specs.push(ReportSpec::Matrix(fields[0].clone(),
+                fields[1].clone()));
+        }
+    }
+
+    if specs.is_empty() {
+        specs.push(ReportSpec::Matrix(String::from("band"),
+            String::from("mode")));
+    }
+
+    let mut records = Vec::new();
+    for filename in &inv.inv_operands {
+        let adf = common::read_file(filename, &parse_options)?;
+        records.extend(adf.adif_records);
+    }
+
+    if let Some(f) = &filter {
+        records.retain(|rec| adif::adif_filter_matches(f, rec));
+    }
+
+    common::write_output(inv.value(common::OPT_OUTPUT.os_long),
+        &|out| write_reports(out, &specs, &records, format))?;
+    Ok(0)
+}
+
+fn write_reports(out: &mut dyn io::Write, specs: &[ReportSpec],
+    records: &[adif::AdifRecord], format: ReportFormat) -> io::Result<()>
+{
+    let mut json = Vec::new();
+    for (n, spec) in specs.iter().enumerate() {
+        if n > 0 && format != ReportFormat::Json {
+            writeln!(out)?;
+        }
+
+        match spec {
+            ReportSpec::Group(fields) => {
+                let report = adif::adif_report(records, fields);
+                match format {
+                    ReportFormat::Text => write_text(out, &report)?,
+                    ReportFormat::Csv => write_csv(out, &report)?,
+                    ReportFormat::Json => json.push(make_json(&report))
+                }
+            },
+            ReportSpec::Matrix(row, column) => {
+                let matrix = adif::adif_report_matrix(records, row, column);
+                match format {
+                    ReportFormat::Text => write_matrix_text(out, &matrix)?,
+                    ReportFormat::Csv => write_matrix_csv(out, &matrix)?,
+                    ReportFormat::Json => json.push(make_matrix_json(&matrix))
+                }
+            }
+        }
+    }
+
+    if format == ReportFormat::Json {
+        writeln!(out, "{}", adif::JsonValue::JSV_ARRAY(json))?;
+    }
+
+    Ok(())
+}
+
+fn display_value(value: &str) -> &str
+{
+    if value.is_empty() { "(none)" } else { value }
+}
+
+//
+// Print rows of cells with each column padded to the width of its widest cell.
+// The first "nleft" columns are left-aligned and the rest are right-aligned.
+//
+fn write_table(out: &mut dyn io::Write, rows: &[Vec<String>], nleft: usize) ->
+    io::Result<()>
+{
+    let ncols = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let widths : Vec<usize> = (0..ncols).map(|c| {
+        rows.iter().filter_map(|r| r.get(c)).map(|v| v.chars().count())
+            .max().unwrap_or(0)
+    }).collect();
+
+    for row in rows {
+        let cells : Vec<String> = row.iter().enumerate().map(|(c, v)| {
+            if c < nleft {
+                format!("{:<1$}", v, widths[c])
+            } else {
+                format!("{:>1$}", v, widths[c])
+            }
+        }).collect();
+        writeln!(out, "{}", cells.join("  ").trim_end())?;
+    }
+
+    Ok(())
+}
+
+fn write_text(out: &mut dyn io::Write, report: &adif::AdifReport) ->
+    io::Result<()>
+{
+    let mut rows = Vec::new();
+    let mut header : Vec<String> = report.arp_fields.iter().map(
+        |f| f.to_uppercase()).collect();
+    header.push(String::from("WORKED"));
+    header.push(String::from("CONFIRMED"));
+    rows.push(header);
+
+    for r in &report.arp_rows {
+        let mut row : Vec<String> = r.arpr_key.iter().map(
+            |v| display_value(v).to_string()).collect();
+        row.push(r.arpr_counts.arc_worked.to_string());
+        row.push(r.arpr_counts.arc_confirmed.to_string());
+        rows.push(row);
+    }
+
+    let mut total = vec![ String::new(); report.arp_fields.len() ];
+    total[0] = String::from("TOTAL");
+    total.push(report.arp_total.arc_worked.to_string());
+    total.push(report.arp_total.arc_confirmed.to_string());
+    rows.push(total);
+
+    write_table(out, &rows, report.arp_fields.len())
+}
+
+fn write_csv(out: &mut dyn io::Write, report: &adif::AdifReport) ->
+    io::Result<()>
+{
+    let mut header = report.arp_fields.clone();
+    header.push(String::from("worked"));
+    header.push(String::from("confirmed"));
+    writeln!(out, "{}", adif::csv_line(&header, ','))?;
+
+    for r in &report.arp_rows {
+        let mut row = r.arpr_key.clone();
+        row.push(r.arpr_counts.arc_worked.to_string());
+        row.push(r.arpr_counts.arc_confirmed.to_string());
+        writeln!(out, "{}", adif::csv_line(&row, ','))?;
+    }
+
+    Ok(())
+}
+
+//
+// In text form, each cell of a matrix shows "WORKED/CONFIRMED".
+//
+fn write_matrix_text(out: &mut dyn io::Write,
+    matrix: &adif::AdifReportMatrix) -> io::Result<()>
+{
+    let cell = |c: &adif::AdifReportCounts| {
+        format!("{}/{}", c.arc_worked, c.arc_confirmed)
+    };
+
+    let mut rows = Vec::new();
+    let mut header = vec![ format!("{}\\{}",
+        matrix.arm_row_field.to_uppercase(),
+        matrix.arm_column_field.to_uppercase()) ];
+    header.extend(matrix.arm_columns.iter().map(
+        |c| display_value(c).to_string()));
+    header.push(String::from("TOTAL"));
+    rows.push(header);
+
+    for (i, r) in matrix.arm_rows.iter().enumerate() {
+        let mut row = vec![ display_value(r).to_string() ];
+        row.extend(matrix.arm_cells[i].iter().map(&cell));
+        row.push(cell(&matrix.arm_row_totals[i]));
+        rows.push(row);
+    }
+
+    let mut total = vec![ String::from("TOTAL") ];
+    total.extend(matrix.arm_column_totals.iter().map(&cell));
+    total.push(cell(&matrix.arm_total));
+    rows.push(total);
+
+    writeln!(out, "QSOs worked/confirmed")?;
+    write_table(out, &rows, 1)
+}
+
+//
+// In CSV form, each column value of a matrix gets two columns: worked and
+// confirmed.
+//
+fn write_matrix_csv(out: &mut dyn io::Write,
+    matrix: &adif::AdifReportMatrix) -> io::Result<()>
+{
+    let mut header = vec![ matrix.arm_row_field.clone() ];
+    for c in &matrix.arm_columns {
+        header.push(format!("{} worked", c));
+        header.push(format!("{} confirmed", c));
+    }
+    writeln!(out, "{}", adif::csv_line(&header, ','))?;
+
+    for (i, r) in matrix.arm_rows.iter().enumerate() {
+        let mut row = vec![ r.clone() ];
+        for c in &matrix.arm_cells[i] {
+            row.push(c.arc_worked.to_string());
+            row.push(c.arc_confirmed.to_string());
+        }
+        writeln!(out, "{}", adif::csv_line(&row, ','))?;
+    }
+
+    Ok(())
+}
+
+fn counts_json(counts: &adif::AdifReportCounts) -> adif::JsonValue
+{
+    adif::json_object(vec![
+        ("worked", adif::json_number(counts.arc_worked as u32)),
+        ("confirmed", adif::json_number(counts.arc_confirmed as u32))
+    ])
+}
+
+fn make_json(report: &adif::AdifReport) -> adif::JsonValue
+{
+    let rows = report.arp_rows.iter().map(|r| {
+        let key = adif::JsonValue::JSV_OBJECT(report.arp_fields.iter()
+            .zip(&r.arpr_key).map(|(f, v)| (f.clone(), adif::json_string(v)))
+            .collect());
+        adif::json_object(vec![
+            ("key", key),
+            ("worked", adif::json_number(r.arpr_counts.arc_worked as u32)),
+            ("confirmed",
+                adif::json_number(r.arpr_counts.arc_confirmed as u32))
+        ])
+    }).collect();
+
+    adif::json_object(vec![
+        ("fields", adif::JsonValue::JSV_ARRAY(report.arp_fields.iter().map(
+            |f| adif::json_string(f)).collect())),
+        ("rows", adif::JsonValue::JSV_ARRAY(rows)),
+        ("total", counts_json(&report.arp_total))
+    ])
+}
+
+fn make_matrix_json(matrix: &adif::AdifReportMatrix) -> adif::JsonValue
+{
+    let strings = |values: &[String]| {
+        adif::JsonValue::JSV_ARRAY(values.iter().map(
+            |v| adif::json_string(v)).collect())
+    };
+    let counts = |values: &[adif::AdifReportCounts]| {
+        adif::JsonValue::JSV_ARRAY(values.iter().map(counts_json).collect())
+    };
+
+    adif::json_object(vec![
+        ("row_field", adif::json_string(&matrix.arm_row_field)),
+        ("column_field", adif::json_string(&matrix.arm_column_field)),
+        ("rows", strings(&matrix.arm_rows)),
+        ("columns", strings(&matrix.arm_columns)),
+        ("cells", adif::JsonValue::JSV_ARRAY(matrix.arm_cells.iter().map(
+            |r| counts(r)).collect())),
+        ("row_totals", counts(&matrix.arm_row_totals)),
+        ("column_totals", counts(&matrix.arm_column_totals)),
+        ("total", counts_json(&matrix.arm_total))
+    ])
+}
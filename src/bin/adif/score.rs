@@ -0,0 +1,330 @@
+//
+// src/bin/adif/score.rs: "adif score" checks dupes and scores a contest log
+// The rules come from a file given with --rules or are built in for the
+// contest given with --contest (or named by the records' "contest_id").  See
+// src/contest.rs for the rule format.  This prints the QSOs, dupes, points, and
+// multipliers for each band and the claimed score.  With -q, it also lists how
+// each QSO was scored.  Logs from several operators can be scored together by
+// naming several files.
+//
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "score",
+    cmd_summary: "check dupes and score a contest log",
+    cmd_operands: "FILENAME...",
+    cmd_nargs: (1, None),
+    cmd_description: "\
+Rules are built in for CQ-WW, CQ-WPX, ARRL-DX, ARRL-SS, ARRL-FD, and NAQP.
+These expect \"dxcc\", \"cont\", and \"cqz\" in each record (see \"adif cty\"),
+and \"my_dxcc\" and \"my_cont\", which may be given with --default, as in
+\"--default my_cont=NA\".  A rules file has lines like:
+
+    contest CQ-WW-CW
+    dupe band
+    points 0 if dxcc = $my_dxcc
+    points 3 if cont != $my_cont
+    points 1
+    multiplier zone cqz per band
+    multiplier country dxcc per band
+    default my_cont = NA",
+    cmd_options: &[
+        OptSpec {
+            os_short: None,
+            os_long: "contest",
+            os_arg: Some("NAME"),
+            os_help: "use the built-in rules for contest NAME (default:\n\
+                from contest_id)"
+        },
+        OptSpec {
+            os_short: Some('r'),
+            os_long: "rules",
+            os_arg: Some("RULESFILE"),
+            os_help: "read the contest rules from RULESFILE"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "default",
+            os_arg: Some("FIELD=VALUE"),
+            os_help: "value for records that lack FIELD (may be\n\
+                repeated)"
+        },
+        OptSpec {
+            os_short: Some('q'),
+            os_long: "qsos",
+            os_arg: None,
+            os_help: "list how each QSO was scored"
+        },
+        OptSpec {
+            os_short: None,
+            os_long: "format",
+            os_arg: Some("FORMAT"),
+            os_help: "output format: text (default) or json"
+        },
+        common::OPT_FILTER,
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let json = match inv.value("format") {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(name) => inv.usage_error(&format!("unsupported format: {}", name))
+    };
+    if inv.value("contest").is_some() && inv.value("rules").is_some() {
+        inv.usage_error("--contest and --rules can't be used together");
+    }
+
+    //
+    // Keep track of which file (and record) each record came from so that
+    // QSOs can be identified in the output.
+    //
+    let parse_options = common::parse_options(inv);
+    let filter = common::filter(inv);
+    let mut records = Vec::new();
+    let mut sources = Vec::new();
+    for filename in &inv.inv_operands {
+        let adf = common::read_file(filename, &parse_options)?;
+        common::warn_diagnostics(inv, &adf);
+        for (i, rec) in adf.adif_records.into_iter().enumerate() {
+            if filter.as_ref().is_none_or(|f| adif::adif_filter_matches(f,
+                &rec)) {
+                records.push(rec);
+                sources.push((adf.adif_label.clone(), i + 1));
+            }
+        }
+    }
+
+    let mut rules = match (inv.value("rules"), inv.value("contest")) {
+        (Some(filename), _) => load_rules(filename)?,
+        (None, Some(contest)) => builtin_rules(contest)?,
+        (None, None) => match most_common_contest(&records) {
+            Some(contest) => builtin_rules(&contest)?,
+            None => inv.usage_error("no contest_id in the records (use \
+                --contest or --rules)")
+        }
+    };
+    for spec in inv.values("default") {
+        if let Err(error) = adif::adif_parse_contest_rule(&mut rules,
+            &format!("default {}", spec)) {
+            inv.usage_error(&format!("--default: {}", error));
+        }
+    }
+
+    let score = adif::adif_contest_score(&records, &rules);
+    for diagnostic in &score.acs_diagnostics {
+        if let Some(r) = diagnostic.adid_record {
+            let (label, which) = &sources[r - 1];
+            inv.warn(&format!("warning: {}: record {}: {}", label, which,
+                diagnostic.adid_message));
+        }
+    }
+
+    let show_qsos = inv.flag("qsos");
+    let contest = rules.acr_names.first().cloned().unwrap_or_default();
+    let mut text = Vec::new();
+    if json {
+        let qsos = if show_qsos { Some(&records[..]) } else { None };
+        writeln!(text, "{}", make_json(&contest, &score, qsos, &sources))
+    } else if show_qsos {
+        write_qsos(&mut text, &score, &records, &sources).and_then(
+            |_| write_text(&mut text, &contest, &score))
+    } else {
+        write_text(&mut text, &contest, &score)
+    }.map_err(|error| format!("write: {}", error))?;
+    common::write_output(inv.value(common::OPT_OUTPUT.os_long),
+        &|out| out.write_all(&text))?;
+    Ok(0)
+}
+
+fn load_rules(filename: &str) -> Result<adif::AdifContestRules, String>
+{
+    let label = common::input_label(filename);
+    let mut text = String::new();
+    common::open_input(filename)?.read_to_string(&mut text).map_err(
+        |error| format!("read \"{}\": {}", label, error))?;
+    adif::adif_parse_contest_rules(&text).map_err(
+        |error| format!("{}: {}", label, error))
+}
+
+fn builtin_rules(contest: &str) -> Result<adif::AdifContestRules, String>
+{
+    adif::adif_contest_rules(contest).ok_or_else(|| format!("no built-in \
+        rules for contest {} (use --rules)", contest.to_uppercase()))
+}
+
+fn most_common_contest(records: &[adif::AdifRecord]) -> Option<String>
+{
+    let mut counts : BTreeMap<String, usize> = BTreeMap::new();
+    for rec in records {
+        if let Some(c) = rec.adir_field_values.get("contest_id") {
+            if !c.trim().is_empty() {
+                *counts.entry(c.trim().to_uppercase()).or_insert(0) += 1;
+            }
+        }
+    }
+    counts.into_iter().max_by_key(|(_, n)| *n).map(|(c, _)| c)
+}
+
+fn status_text(qso: &adif::AdifContestQso, sources: &[(String, usize)]) ->
+    String
+{
+    match qso.acq_status {
+        adif::AdifContestStatus::ACS_SCORED => {
+            let mut text = format!("{} points", qso.acq_points);
+            for (name, value) in &qso.acq_multipliers {
+                text.push_str(&format!(", new {} {}", name, value));
+            }
+            text
+        },
+        adif::AdifContestStatus::ACS_DUPE(first) => {
+            let (label, which) = &sources[first];
+            format!("dupe of {} record {}", label, which)
+        },
+        adif::AdifContestStatus::ACS_IGNORED => String::from("ignored"),
+        adif::AdifContestStatus::ACS_OTHER_CONTEST =>
+            String::from("other contest")
+    }
+}
+
+fn write_qsos(out: &mut dyn io::Write, score: &adif::AdifContestScore,
+    records: &[adif::AdifRecord], sources: &[(String, usize)]) ->
+    io::Result<()>
+{
+    for qso in &score.acs_qsos {
+        let (label, which) = &sources[qso.acq_record];
+        writeln!(out, "{}: record {}: {}: {}", label, which,
+            adif::adif_qso_summary(&records[qso.acq_record]),
+            status_text(qso, sources))?;
+    }
+    writeln!(out)
+}
+
+fn write_row(out: &mut dyn io::Write, band: &str,
+    totals: &adif::AdifContestTotals, widths: &[usize]) -> io::Result<()>
+{
+    write!(out, "{:<8} {:>6} {:>6} {:>7}", band, totals.acbt_qsos,
+        totals.acbt_dupes, totals.acbt_points)?;
+    for (n, width) in totals.acbt_multipliers.iter().zip(widths) {
+        write!(out, " {:>width$}", n, width = width)?;
+    }
+    writeln!(out)
+}
+
+fn write_text(out: &mut dyn io::Write, contest: &str,
+    score: &adif::AdifContestScore) -> io::Result<()>
+{
+    if !contest.is_empty() {
+        writeln!(out, "Contest: {}", contest)?;
+    }
+
+    let widths : Vec<usize> = score.acs_multiplier_names.iter().map(
+        |n| n.len().max(5)).collect();
+    write!(out, "{:<8} {:>6} {:>6} {:>7}", "Band", "QSOs", "Dupes",
+        "Points")?;
+    for (name, width) in score.acs_multiplier_names.iter().zip(&widths) {
+        write!(out, " {:>width$}", name, width = width)?;
+    }
+    writeln!(out)?;
+
+    for totals in &score.acs_bands {
+        let band = if totals.acbt_band.is_empty() {
+            "(none)"
+        } else {
+            &totals.acbt_band
+        };
+        write_row(out, band, totals, &widths)?;
+    }
+    write_row(out, "Total", &score.acs_total, &widths)?;
+
+    let ignored = score.acs_qsos.iter().filter(|q| q.acq_status ==
+        adif::AdifContestStatus::ACS_IGNORED).count();
+    let other = score.acs_qsos.iter().filter(|q| q.acq_status ==
+        adif::AdifContestStatus::ACS_OTHER_CONTEST).count();
+    if ignored > 0 || other > 0 {
+        writeln!(out, "({} QSOs ignored, {} for other contests)", ignored,
+            other)?;
+    }
+
+    writeln!(out)?;
+    if score.acs_multiplier_names.is_empty() {
+        writeln!(out, "Score: {}", score.acs_score)
+    } else {
+        let mults : usize = score.acs_total.acbt_multipliers.iter().sum();
+        writeln!(out, "Score: {} points x {} multipliers = {}",
+            score.acs_total.acbt_points, mults, score.acs_score)
+    }
+}
+
+fn make_totals_json(names: &[String], totals: &adif::AdifContestTotals) ->
+    adif::JsonValue
+{
+    adif::json_object(vec![
+        ("band", adif::json_string(&totals.acbt_band)),
+        ("qsos", adif::json_number(totals.acbt_qsos as u32)),
+        ("dupes", adif::json_number(totals.acbt_dupes as u32)),
+        ("points", adif::json_number(totals.acbt_points as f64)),
+        ("multipliers", adif::JsonValue::JSV_OBJECT(names.iter().zip(
+            &totals.acbt_multipliers).map(|(name, n)| (name.clone(),
+            adif::json_number(*n as u32))).collect()))
+    ])
+}
+
+fn make_json(contest: &str, score: &adif::AdifContestScore,
+    records: Option<&[adif::AdifRecord]>, sources: &[(String, usize)]) ->
+    adif::JsonValue
+{
+    let names = &score.acs_multiplier_names;
+    let mut properties = vec![
+        ("contest", adif::json_string(contest)),
+        ("score", adif::json_number(score.acs_score as f64)),
+        ("total", make_totals_json(names, &score.acs_total)),
+        ("bands", adif::JsonValue::JSV_ARRAY(score.acs_bands.iter().map(
+            |b| make_totals_json(names, b)).collect()))
+    ];
+
+    if let Some(records) = records {
+        properties.push(("qsos", adif::JsonValue::JSV_ARRAY(
+            score.acs_qsos.iter().map(|qso| {
+            let (label, which) = &sources[qso.acq_record];
+            let status = match qso.acq_status {
+                adif::AdifContestStatus::ACS_SCORED => "scored",
+                adif::AdifContestStatus::ACS_DUPE(_) => "dupe",
+                adif::AdifContestStatus::ACS_IGNORED => "ignored",
+                adif::AdifContestStatus::ACS_OTHER_CONTEST => "other contest"
+            };
+            adif::json_object(vec![
+                ("file", adif::json_string(label)),
+                ("record", adif::json_number(*which as u32)),
+                ("qso", adif::json_string(&adif::adif_qso_summary(
+                    &records[qso.acq_record]))),
+                ("status", adif::json_string(status)),
+                ("points", adif::json_number(qso.acq_points)),
+                ("multipliers", adif::JsonValue::JSV_ARRAY(
+                    qso.acq_multipliers.iter().map(|(name, value)| {
+                    adif::json_object(vec![
+                        ("name", adif::json_string(name)),
+                        ("value", adif::json_string(value))
+                    ])
+                }).collect()))
+            ])
+        }).collect())));
+    }
+
+    adif::json_object(properties)
+}
@@ -0,0 +1,101 @@
+//
+// src/bin/adif/split.rs: "adif split" splits a file into several files
+// Records are partitioned by the fields given with --by and/or split into
+// chunks of at most --max-records records.  Each part is written as an ADI file
+// named PREFIX-VALUES.adi (e.g., "log-K1AA-2024.adi"), with a copy of the
+// original header and a new created_timestamp.  See src/split.rs.
+//
+
+use std::path::Path;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "split",
+    cmd_summary: "split a file by field values or record count",
+    cmd_operands: "FILENAME",
+    cmd_nargs: (1, Some(1)),
+    cmd_description: "\
+Fields for --by can include \"year\", \"month\", \"day\", and \"hour\" (from the
+QSO's start time) and \"contest\" (for contest_id).  Characters in values that
+aren't safe in filenames are replaced with \"_\", and records without a value
+go in a part named \"none\".  Existing files are overwritten.  For example:
+
+    adif split --by station_callsign,year log.adi
+    adif split --max-records 1000 --prefix upload log.adi",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('b'),
+            os_long: "by",
+            os_arg: Some("FIELDS"),
+            os_help: "partition records by the values of FIELDS"
+        },
+        OptSpec {
+            os_short: Some('n'),
+            os_long: "max-records",
+            os_arg: Some("N"),
+            os_help: "write at most N records to each file"
+        },
+        OptSpec {
+            os_short: Some('p'),
+            os_long: "prefix",
+            os_arg: Some("PREFIX"),
+            os_help: "start each output filename with PREFIX (default:\n\
+                FILENAME without its extension)"
+        },
+        common::OPT_FILTER,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let mut options = adif::AdifSplitOptions {
+        aspo_timestamp: Some(adif::adif_timestamp_now()),
+        ..Default::default()
+    };
+    if let Some(fields) = inv.value("by") {
+        options.aspo_fields = common::field_list(fields).into_iter().map(
+            |f| if f == "contest" { String::from("contest_id") } else { f })
+            .collect();
+    }
+    if let Some(n) = inv.value("max-records") {
+        match n.parse::<usize>() {
+            Ok(n) if n > 0 => options.aspo_max_records = Some(n),
+            _ => inv.usage_error(&format!("invalid record count: {}", n))
+        }
+    }
+    if options.aspo_fields.is_empty() && options.aspo_max_records.is_none() {
+        inv.usage_error("expected --by or --max-records");
+    }
+
+    let filename = &inv.inv_operands[0];
+    let prefix = match inv.value("prefix") {
+        Some(p) => p.to_string(),
+        None if filename == "-" => String::from("split"),
+        None => Path::new(filename).with_extension("").to_string_lossy()
+            .into_owned()
+    };
+
+    let mut adf = common::read_file(filename, &common::parse_options(inv))?;
+    common::warn_diagnostics(inv, &adf);
+    if let Some(f) = common::filter(inv) {
+        adf.adif_records.retain(|r| adif::adif_filter_matches(&f, r));
+    }
+
+    let parts = adif::adif_split(&adf, &options);
+    let names = adif::adif_split_names(&parts, &options);
+    for (part, name) in parts.iter().zip(names) {
+        let output = format!("{}-{}.adi", prefix, name);
+        common::write_adif(Some(&output), &part.aspp_file)?;
+        inv.warn(&format!("wrote {} ({} records)", output,
+            part.aspp_file.adif_records.len()));
+    }
+    Ok(0)
+}
@@ -0,0 +1,101 @@
+//
+// src/bin/adif/store.rs: "adif store" keeps a log in an SQLite database
+// Each FILENAME is imported into DATABASE (which is created if needed), adding
+// new QSOs and replacing those already stored.  With --export, the stored log
+// is then written as ADIF.  See src/sqlite.rs for the schema and how QSOs are
+// identified.  This is only available when built with the "sqlite" feature.
+//
+
+#[cfg(feature = "sqlite")]
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "store",
+    cmd_summary: "import QSOs into (or export them from) an SQLite database",
+    cmd_operands: "DATABASE [FILENAME...]",
+    cmd_nargs: (1, None),
+    cmd_description: "\
+QSOs are identified by their key fields and start time (to the second), and
+an imported QSO replaces a stored QSO with the same identity (though records
+in one file never replace each other).  A database always uses the key fields
+it was first imported with.  The database
+has a \"qsos\" table with columns for common fields and a \"qso_fields\" table
+for all other fields, which can be queried directly (e.g., with sqlite3).
+Exporting reproduces the imported files exactly.  A filter (-f) applies both
+to the records imported and to those exported.",
+    cmd_options: &[
+        OptSpec {
+            os_short: Some('x'),
+            os_long: "export",
+            os_arg: None,
+            os_help: "write the stored log as ADIF"
+        },
+        OptSpec {
+            os_short: Some('k'),
+            os_long: "key",
+            os_arg: Some("FIELDS"),
+            os_help: "fields that identify a QSO, besides its time\n\
+                (default: call,band,mode,station_callsign)"
+        },
+        common::OPT_FILTER,
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 1,
+    cmd_run: run
+};
+
+#[cfg(feature = "sqlite")]
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let export = inv.flag("export");
+    if inv.inv_operands.len() == 1 && !export {
+        inv.usage_error("expected files to import or --export");
+    }
+
+    let mut options = adif::AdifStoreOptions::default();
+    if let Some(fields) = inv.value("key") {
+        options.aso_key_fields = common::field_list(fields);
+    }
+
+    let database = &inv.inv_operands[0];
+    let mut store = adif::adif_store_open(database).map_err(
+        |error| format!("{}: {}", database, error))?;
+
+    let parse_options = common::parse_options(inv);
+    let filter = common::filter(inv);
+    for filename in &inv.inv_operands[1..] {
+        let mut adf = common::read_file(filename, &parse_options)?;
+        common::warn_diagnostics(inv, &adf);
+        if let Some(f) = &filter {
+            adf.adif_records.retain(|r| adif::adif_filter_matches(f, r));
+        }
+
+        let result = adif::adif_store_upsert(&mut store, &adf, &options)
+            .map_err(|error| format!("{}: {}", database, error))?;
+        inv.warn(&format!("{}: {} QSOs added ({} without an identity), {} \
+            updated", adf.adif_label, result.asr_inserted, result.asr_unkeyed,
+            result.asr_updated));
+    }
+
+    if export {
+        let mut adf = adif::adif_store_export(&store, database).map_err(
+            |error| format!("{}: {}", database, error))?;
+        if let Some(f) = &filter {
+            adf.adif_records.retain(|r| adif::adif_filter_matches(f, r));
+        }
+        common::write_adif(inv.value(common::OPT_OUTPUT.os_long), &adf)?;
+    }
+    Ok(0)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn run(_inv: &Invocation) -> Result<i32, String>
+{
+    Err(String::from("this program was built without SQLite support (see \
+        the \"sqlite\" feature)"))
+}
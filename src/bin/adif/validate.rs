@@ -0,0 +1,111 @@
+//
+// src/bin/adif/validate.rs: "adif validate" checks ADIF files
+// This reports problems the parser worked around (like duplicate fields) along
+// with field values that don't conform to the specification and records that
+// lack required fields (see src/validate.rs).  It exits 0 if no problems were
+// found, 1 if there were problems, and 2 on error.
+//
+
+use std::io;
+
+use adif;
+use common;
+use opts::Command;
+use opts::Invocation;
+use opts::OptSpec;
+
+pub const COMMAND : Command = Command {
+    cmd_name: "validate",
+    cmd_summary: "check ADIF files against the specification",
+    cmd_operands: "FILENAME...",
+    cmd_nargs: (1, None),
+    cmd_description: "\
+Exits 0 if no problems were found, 1 if there were problems, and 2 on error.",
+    cmd_options: &[
+        OptSpec {
+            os_short: None,
+            os_long: "format",
+            os_arg: Some("FORMAT"),
+            os_help: "output format: text (default) or json"
+        },
+        OptSpec {
+            os_short: Some('q'),
+            os_long: "quiet",
+            os_arg: None,
+            os_help: "print nothing; just set the exit status"
+        },
+        common::OPT_OUTPUT,
+        common::OPT_DUPLICATES
+    ],
+    cmd_error_status: 2,
+    cmd_run: run
+};
+
+fn run(inv: &Invocation) -> Result<i32, String>
+{
+    let parse_options = common::parse_options(inv);
+    let json = match inv.value("format") {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(name) => inv.usage_error(&format!("unsupported format: {}", name))
+    };
+
+    let mut results = Vec::new();
+    for filename in &inv.inv_operands {
+        let adf = common::read_file(filename, &parse_options)?;
+        let mut diagnostics = adf.adif_diagnostics.clone();
+        diagnostics.extend(adif::adif_validate(&adf));
+        results.push((adf.adif_label, adf.adif_records.len(), diagnostics));
+    }
+
+    let nproblems : usize = results.iter().map(|(_, _, d)| d.len()).sum();
+    if !inv.flag("quiet") {
+        common::write_output(inv.value(common::OPT_OUTPUT.os_long), &|out| {
+            if json {
+                writeln!(out, "{}", make_json(&results))
+            } else {
+                write_text(out, &results)
+            }
+        })?;
+    }
+
+    Ok(if nproblems == 0 { 0 } else { 1 })
+}
+
+fn write_text(out: &mut dyn io::Write,
+    results: &[(String, usize, Vec<adif::AdifDiagnostic>)]) -> io::Result<()>
+{
+    for (label, nrecords, diagnostics) in results {
+        for d in diagnostics {
+            writeln!(out, "{}: {}", label, d)?;
+        }
+        writeln!(out, "{}: {} records, {} problems", label, nrecords,
+            diagnostics.len())?;
+    }
+
+    Ok(())
+}
+
+//
+// Records are identified by their 1-based position in the file, or null for
+// problems with the header.
+//
+fn make_json(results: &[(String, usize, Vec<adif::AdifDiagnostic>)]) ->
+    adif::JsonValue
+{
+    adif::JsonValue::JSV_ARRAY(results.iter().map(
+        |(label, nrecords, diagnostics)| {
+        adif::json_object(vec![
+            ("file", adif::json_string(label)),
+            ("records", adif::json_number(*nrecords as u32)),
+            ("problems", adif::JsonValue::JSV_ARRAY(diagnostics.iter().map(
+                |d| adif::json_object(vec![
+                    ("record", match d.adid_record {
+                        Some(r) => adif::json_number(r as u32),
+                        None => adif::JsonValue::JSV_NULL
+                    }),
+                    ("message", adif::json_string(&d.adid_message))
+                ])).collect()))
+        ])
+    }).collect())
+}
@@ -0,0 +1,1045 @@
+//
+// src/cabrillo.rs: Cabrillo contest logs
+//
+// Contest sponsors accept logs in Cabrillo format (version 3.0, described at
+// https://wwrof.org/cabrillo/).  A Cabrillo log is a series of "TAG: value"
+// lines: a header, one "QSO:" line per contact, and "END-OF-LOG:".  For
+// example:
+//
+//     START-OF-LOG: 3.0
+//     CONTEST: CQ-WW-CW
+//     CALLSIGN: W1AW
+//     CATEGORY-BAND: 20M
+//     CATEGORY-MODE: CW
+//     QSO: 14025 CW 2023-11-25 1201 W1AW      599 05   DL1ABC      599 14
+//     END-OF-LOG:
+//
+// Each QSO line gives the frequency (in kHz below 30 MHz, and otherwise a band
+// designator like "144" or "1.2G"), the mode (CW, PH, FM, RY, or DG), the UTC
+// date and time, and then the sent callsign and exchange followed by the
+// received callsign and exchange.  What makes up the exchange depends on the
+// contest.  Fields are separated by whitespace, but we pad them so that they
+// line up.
+//
+// An exchange template (AdifCabrilloExchange) says which ADIF fields hold each
+// item of the exchange.  Templates are written like this:
+//
+//     rst_sent my_cq_zone|stx / rst_rcvd cqz|srx
+//
+// The items before the "/" are sent and the rest are received.  Each item
+// names one or more fields, separated by "|", and the first one with a value
+// is used.  A missing RST is assumed to be 59 (or 599 for CW and digital
+// modes), since many loggers don't record it.  Templates for several common
+// contests are built in, and others use the default template, which is an RST
+// and serial number (or string) in each direction.
+//
+// The header is generated from the records: CONTEST comes from "contest_id",
+// CALLSIGN from "station_callsign", OPERATORS from "operator",
+// CATEGORY-OPERATOR from the number of operators, and CATEGORY-BAND and
+// CATEGORY-MODE from the bands and modes used.  In each case, the most common
+// value is used where records differ.  Any of these can be overridden, and
+// other tags (e.g., CATEGORY-POWER or NAME) added, by the caller.
+//
+// adif_cabrillo_parse() goes the other way.  The same exchange templates say
+// which fields receive the exchange items (the first field named for each
+// item is used), so that a CQ WW log produces "cqz" and a Sweepstakes log
+// produces "srx", "precedence", "check", and "arrl_sect".  Since an item like
+// "stx_string" may contain several words, the last item in either direction
+// takes any words left over when the number of words doesn't match the
+// template.  Band designators become a "band" field, and frequencies become
+// "freq" (in MHz) and "band".  There's no ADIF mode for "DG", so such QSOs get
+// no "mode" field, and the Cabrillo mode is kept in "app_cabrillo_mode".  The
+// header tags are kept in the ADIF header as APP_CABRILLO_* fields (with
+// characters other than letters and digits replaced by "_"), and CREATED-BY
+// becomes PROGRAMID.  X-QSO lines (QSOs that the entrant doesn't claim credit
+// for) are read like QSO lines and marked with "app_cabrillo_x_qso".
+// Malformed lines are skipped and reported as diagnostics.
+//
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io;
+
+use adi::AdiFile;
+use adi::AdiHeader;
+use adi::AdiRecord;
+use adif::AdifDiagnostic;
+use adif::AdifFile;
+use adif::AdifParseOptions;
+use adif::AdifRecord;
+use adif::adif_data_specifier;
+use adif::adif_parse_adi;
+use adif::adif_valid_field_name;
+use adiftypes::adif_parse_date;
+use adiftypes::adif_parse_time;
+use convert::adif_read_text;
+use csvimport::adif_khz_to_mhz;
+use diff::adif_record_timestamp;
+use fields::adif_band_range;
+use fields::adif_freq_band;
+use super::AdifParseError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifCabrilloExchange {
+    pub acx_sent : Vec<Vec<String>>,    // fields for each sent item, in
+                                        // order of preference
+    pub acx_rcvd : Vec<Vec<String>>     // same, for received items
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifCabrilloOptions {
+    pub acbo_contest : Option<String>,  // CONTEST (default: "contest_id")
+    pub acbo_callsign : Option<String>, // CALLSIGN (default:
+                                        // "station_callsign")
+    pub acbo_exchange : Option<AdifCabrilloExchange>,   // default: by contest
+    pub acbo_header : Vec<(String, String)>     // other header tags and
+                                                // values, overriding any we
+                                                // generate
+}
+
+pub struct AdifCabrilloImport {
+    pub acbi_file : AdifFile,           // one record for each QSO read
+    pub acbi_nqsos : usize,             // number of QSO and X-QSO lines
+    pub acbi_skipped : Vec<usize>       // lines of QSOs that were skipped
+}
+
+//
+// Exchange templates for common contests.
+//
+static ADIF_CABRILLO_EXCHANGES : &[(&str, &str)] = &[
+    ("ARRL-10", "rst_sent stx|stx_string / rst_rcvd srx|srx_string"),
+    ("ARRL-160", "rst_sent stx_string / rst_rcvd srx_string"),
+    ("ARRL-DX-CW", "rst_sent stx_string / rst_rcvd srx_string"),
+    ("ARRL-DX-SSB", "rst_sent stx_string / rst_rcvd srx_string"),
+    ("ARRL-FD", "stx_string / class arrl_sect"),
+    ("ARRL-SS-CW", "stx stx_string / srx precedence check arrl_sect"),
+    ("ARRL-SS-SSB", "stx stx_string / srx precedence check arrl_sect"),
+    ("CQ-WPX-CW", "rst_sent stx / rst_rcvd srx"),
+    ("CQ-WPX-RTTY", "rst_sent stx / rst_rcvd srx"),
+    ("CQ-WPX-SSB", "rst_sent stx / rst_rcvd srx"),
+    ("CQ-WW-CW", "rst_sent my_cq_zone|stx / rst_rcvd cqz|srx"),
+    ("CQ-WW-SSB", "rst_sent my_cq_zone|stx / rst_rcvd cqz|srx"),
+    ("IARU-HF", "rst_sent my_itu_zone|stx_string / rst_rcvd ituz|srx_string"),
+    ("NAQP-CW",
+        "my_name my_state|stx_string / name state|ve_prov|srx_string"),
+    ("NAQP-RTTY",
+        "my_name my_state|stx_string / name state|ve_prov|srx_string"),
+    ("NAQP-SSB",
+        "my_name my_state|stx_string / name state|ve_prov|srx_string")
+];
+
+static ADIF_CABRILLO_DEFAULT_EXCHANGE : &str =
+    "rst_sent stx|stx_string / rst_rcvd srx|srx_string";
+
+//
+// ADIF bands, the band designators used for them in QSO lines, and their names
+// in CATEGORY-BAND.  Other bands below 30 MHz are designated by their lower
+// edge in kHz.
+//
+static ADIF_CABRILLO_BANDS : &[(&str, &str, &str)] = &[
+    ("160M", "1800", "160M"),
+    ("80M", "3500", "80M"),
+    ("40M", "7000", "40M"),
+    ("20M", "14000", "20M"),
+    ("15M", "21000", "15M"),
+    ("10M", "28000", "10M"),
+    ("6M", "50", "6M"),
+    ("4M", "70", "4M"),
+    ("2M", "144", "2M"),
+    ("1.25M", "222", "222"),
+    ("70CM", "432", "432"),
+    ("33CM", "902", "902"),
+    ("23CM", "1.2G", "1.2G"),
+    ("13CM", "2.3G", "2.3G"),
+    ("9CM", "3.4G", "3.4G"),
+    ("6CM", "5.7G", "5.7G"),
+    ("3CM", "10G", "10G"),
+    ("1.25CM", "24G", "24G"),
+    ("6MM", "47G", "47G"),
+    ("4MM", "75G", "75G"),
+    ("2.5MM", "122G", "122G"),
+    ("2MM", "134G", "134G"),
+    ("1MM", "241G", "241G"),
+    ("SUBMM", "LIGHT", "LIGHT")
+];
+
+//
+// Parse an exchange template like "rst_sent stx / rst_rcvd srx".
+//
+pub fn adif_parse_cabrillo_exchange(text: &str) ->
+    Result<AdifCabrilloExchange, AdifParseError>
+{
+    if text.matches('/').count() != 1 {
+        return Err(AdifParseError::ADIF_EBADINPUT(format!("exchange \"{}\": \
+            expected \"SENT / RECEIVED\"", text)));
+    }
+
+    let exchange = adif_cabrillo_template(text);
+    match exchange.acx_sent.iter().chain(&exchange.acx_rcvd).flatten().find(
+        |f| !adif_valid_field_name(f)) {
+        Some(f) => Err(AdifParseError::ADIF_EBADINPUT(format!("exchange \
+            \"{}\": invalid field name \"{}\"", text, f))),
+        None => Ok(exchange)
+    }
+}
+
+//
+// Split up an exchange template without checking it.  This is used directly
+// only for the built-in templates, which are known to be valid.
+//
+fn adif_cabrillo_template(text: &str) -> AdifCabrilloExchange
+{
+    let (sent, rcvd) = text.split_once('/').unwrap_or((text, ""));
+    let items = |part: &str| part.split_whitespace().map(
+        |item| item.split('|').map(|f| f.to_lowercase()).collect()).collect();
+    AdifCabrilloExchange {
+        acx_sent: items(sent),
+        acx_rcvd: items(rcvd)
+    }
+}
+
+//
+// Returns the exchange template for the named contest (or the default template
+// if we don't know the contest).
+//
+pub fn adif_cabrillo_exchange(contest: &str) -> AdifCabrilloExchange
+{
+    let contest = contest.trim().to_uppercase();
+    let text = ADIF_CABRILLO_EXCHANGES.iter().find(|(c, _)| *c == contest)
+        .map(|(_, t)| *t).unwrap_or(ADIF_CABRILLO_DEFAULT_EXCHANGE);
+    adif_cabrillo_template(text)
+}
+
+fn adif_field<'a>(rec: &'a AdifRecord, field: &str) -> Option<&'a str>
+{
+    let value = rec.adir_field_values.get(field)?.trim();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+//
+// Returns the (upper-case) ADIF band of a record, from its "band" field or
+// else its frequency.
+//
+fn adif_cabrillo_band(rec: &AdifRecord) -> Option<String>
+{
+    if let Some(band) = adif_field(rec, "band") {
+        return Some(band.to_uppercase());
+    }
+    let freq : f64 = adif_field(rec, "freq")?.parse().ok()?;
+    adif_freq_band(freq).map(|b| b.to_string())
+}
+
+//
+// Returns the mode used in QSO lines (CW, PH, FM, RY, or DG).
+//
+pub fn adif_cabrillo_mode(mode: &str) -> &'static str
+{
+    match mode.trim().to_uppercase().as_str() {
+        "CW" => "CW",
+        "SSB" | "AM" | "USB" | "LSB" | "DIGITALVOICE" => "PH",
+        "FM" => "FM",
+        "RTTY" => "RY",
+        _ => "DG"
+    }
+}
+
+//
+// Returns the frequency or band designator for a QSO line.
+//
+fn adif_cabrillo_freq(rec: &AdifRecord) -> Option<String>
+{
+    let freq = adif_field(rec, "freq").and_then(|f| f.parse::<f64>().ok());
+    if let Some(f) = freq {
+        if f < 30.0 {
+            return Some(format!("{}", (f * 1000.0).round() as u64));
+        }
+    }
+
+    let band = adif_cabrillo_band(rec)?;
+    if let Some((_, designator, _)) = ADIF_CABRILLO_BANDS.iter().find(
+        |(b, _, _)| *b == band) {
+        return Some(designator.to_string());
+    }
+    match adif_band_range(&band) {
+        Some((lo, _)) if lo < 30.0 =>
+            Some(format!("{}", (lo * 1000.0).round() as u64)),
+        _ => None
+    }
+}
+
+//
+// Returns the values of an exchange, along with the names of any items for
+// which no value was found.
+//
+fn adif_cabrillo_items(rec: &AdifRecord, items: &[Vec<String>], mode: &str) ->
+    (Vec<String>, Vec<String>)
+{
+    let mut values = Vec::new();
+    let mut missing = Vec::new();
+    for fields in items {
+        let value = fields.iter().find_map(|f| adif_field(rec, f));
+        let width = if fields[0].starts_with("rst_") { 3 } else { 6 };
+        let value = match value {
+            Some(v) => v.to_string(),
+            None if fields[0].starts_with("rst_") => String::from(
+                if mode == "PH" || mode == "FM" { "59" } else { "599" }),
+            None => {
+                missing.push(fields.join("|"));
+                continue;
+            }
+        };
+        values.push(format!("{:<width$}", value, width = width));
+    }
+    (values, missing)
+}
+
+//
+// Returns the most common non-empty value of a field, if any.
+//
+fn adif_most_common<'a>(records: &[&'a AdifRecord], field: &str) ->
+    Option<&'a str>
+{
+    let mut counts : BTreeMap<&str, usize> = BTreeMap::new();
+    for rec in records {
+        if let Some(v) = adif_field(rec, field) {
+            *counts.entry(v).or_insert(0) += 1;
+        }
+    }
+    let max = counts.values().max()?;
+    counts.iter().find(|(_, n)| *n == max).map(|(v, _)| *v)
+}
+
+//
+// Generate the header tags (other than START-OF-LOG).
+//
+fn adif_cabrillo_header(records: &[&AdifRecord], options: &AdifCabrilloOptions,
+    contest: &Option<String>, callsign: &Option<String>) ->
+    Vec<(String, String)>
+{
+    let mut header = vec![ (String::from("CREATED-BY"),
+        format!("adif {}", env!("CARGO_PKG_VERSION"))) ];
+    if let Some(c) = contest {
+        header.push((String::from("CONTEST"), c.clone()));
+    }
+    if let Some(c) = callsign {
+        header.push((String::from("CALLSIGN"), c.clone()));
+    }
+
+    let operators : BTreeSet<String> = records.iter().filter_map(
+        |r| adif_field(r, "operator")).map(|o| o.to_uppercase()).collect();
+    header.push((String::from("CATEGORY-OPERATOR"), String::from(
+        if operators.len() > 1 { "MULTI-OP" } else { "SINGLE-OP" })));
+
+    let bands : BTreeSet<String> = records.iter().filter_map(
+        |r| adif_cabrillo_band(r)).collect();
+    let category_band = match bands.iter().next() {
+        Some(b) if bands.len() == 1 => ADIF_CABRILLO_BANDS.iter().find(
+            |(band, _, _)| band == b).map(|(_, _, c)| *c).unwrap_or("ALL"),
+        _ => "ALL"
+    };
+    header.push((String::from("CATEGORY-BAND"), category_band.to_string()));
+
+    let modes : BTreeSet<&str> = records.iter().filter_map(
+        |r| adif_field(r, "mode")).map(adif_cabrillo_mode).collect();
+    let category_mode = match modes.iter().next() {
+        Some(m) if modes.len() == 1 => match *m {
+            "CW" => "CW",
+            "PH" => "SSB",
+            "FM" => "FM",
+            "RY" => "RTTY",
+            _ => "DIGI"
+        },
+        _ => "MIXED"
+    };
+    header.push((String::from("CATEGORY-MODE"), category_mode.to_string()));
+
+    if !operators.is_empty() {
+        header.push((String::from("OPERATORS"), operators.into_iter()
+            .collect::<Vec<String>>().join(" ")));
+    }
+
+    for (tag, value) in &options.acbo_header {
+        let tag = tag.trim().to_uppercase();
+        match header.iter_mut().find(|(t, _)| *t == tag) {
+            Some(entry) => entry.1 = value.clone(),
+            None => header.push((tag, value.clone()))
+        }
+    }
+
+    header
+}
+
+//
+// Write a Cabrillo log for the given records.  QSOs are written in
+// chronological order.  Records that lack the date, time, callsign, mode,
+// frequency (or band), or any exchange item (other than an RST) can't be
+// written and are skipped.  Problems are returned as diagnostics, with records
+// identified by their 1-based position in "records".  Every diagnostic that
+// identifies a record means that record was skipped.
+//
+pub fn adif_cabrillo_write(records: &[AdifRecord],
+    options: &AdifCabrilloOptions, out: &mut dyn io::Write) ->
+    io::Result<Vec<AdifDiagnostic>>
+{
+    let mut diagnostics = Vec::new();
+    let mut sorted : Vec<(usize, &AdifRecord)> = records.iter().enumerate()
+        .collect();
+    sorted.sort_by_key(|(_, rec)| adif_record_timestamp(rec));
+    let all : Vec<&AdifRecord> = records.iter().collect();
+
+    let contest = options.acbo_contest.clone().or_else(
+        || adif_most_common(&all, "contest_id").map(|c| c.to_uppercase()));
+    let callsign = options.acbo_callsign.clone().or_else(
+        || adif_most_common(&all, "station_callsign").map(
+        |c| c.to_uppercase()));
+    let exchange = match (&options.acbo_exchange, &contest) {
+        (Some(x), _) => x.clone(),
+        (None, Some(c)) => adif_cabrillo_exchange(c),
+        (None, None) => adif_cabrillo_exchange("")
+    };
+
+    let header = adif_cabrillo_header(&all, options, &contest, &callsign);
+    for tag in &[ "CONTEST", "CALLSIGN" ] {
+        if !header.iter().any(|(t, _)| t == tag) {
+            diagnostics.push(AdifDiagnostic {
+                adid_record: None,
+                adid_line: None,
+                adid_message: format!("no value for {}", tag)
+            });
+        }
+    }
+
+    writeln!(out, "START-OF-LOG: 3.0")?;
+    for (tag, value) in &header {
+        writeln!(out, "{}: {}", tag, value)?;
+    }
+
+    for (i, rec) in sorted {
+        let mut problem = |message: String| diagnostics.push(AdifDiagnostic {
+            adid_record: Some(i + 1),
+            adid_line: None,
+            adid_message: message
+        });
+
+        let timestamp = adif_record_timestamp(rec);
+        let sentcall = adif_field(rec, "station_callsign").map(
+            |c| c.to_uppercase()).or_else(|| callsign.clone());
+        let line = (|| {
+            let ts = timestamp.as_ref()?;
+            Some((adif_cabrillo_freq(rec)?,
+                adif_cabrillo_mode(adif_field(rec, "mode")?),
+                format!("{:04}-{:02}-{:02}", ts.adts_date.add_year,
+                    ts.adts_date.add_month, ts.adts_date.add_day),
+                format!("{:02}{:02}", ts.adts_time.adt_hour,
+                    ts.adts_time.adt_minute),
+                sentcall.clone()?,
+                adif_field(rec, "call")?.to_uppercase()))
+        })();
+        let (freq, mode, date, time, sent, call) = match line {
+            Some(fields) => fields,
+            None => {
+                problem(String::from("skipped: missing date, time, \
+                    callsign, mode, or frequency"));
+                continue;
+            }
+        };
+
+        //
+        // Items are separated only by whitespace, so leaving one out would
+        // shift the rest into the wrong columns.
+        //
+        let (sentx, sentmissing) = adif_cabrillo_items(rec, &exchange.acx_sent,
+            mode);
+        let (rcvdx, rcvdmissing) = adif_cabrillo_items(rec, &exchange.acx_rcvd,
+            mode);
+        let missing : Vec<String> = sentmissing.into_iter().chain(rcvdmissing)
+            .collect();
+        if !missing.is_empty() {
+            problem(format!("skipped: no value for exchange item{} {}",
+                if missing.len() > 1 { "s" } else { "" }, missing.join(", ")));
+            continue;
+        }
+
+        let mut qso = format!("QSO: {:>5} {} {} {} {:<13}", freq, mode, date,
+            time, sent);
+        for item in &sentx {
+            qso.push(' ');
+            qso.push_str(item);
+        }
+        qso.push_str(&format!(" {:<13}", call));
+        for item in &rcvdx {
+            qso.push(' ');
+            qso.push_str(item);
+        }
+        writeln!(out, "{}", qso.trim_end())?;
+    }
+
+    writeln!(out, "END-OF-LOG:")?;
+    Ok(diagnostics)
+}
+
+//
+// Returns true if the exchange items' last item may contain several words.
+//
+fn adif_cabrillo_absorbs(items: &[Vec<String>]) -> bool
+{
+    items.last().is_some_and(|fields| fields[0].ends_with("_string"))
+}
+
+//
+// Given the words of a QSO line after the time (the callsigns, exchanges, and
+// an optional transmitter id), work out how many belong to the sent exchange
+// and whether the last word is a transmitter id.  We prefer an exact match
+// with the template, but in any case the received callsign must land on
+// something that looks like a callsign.  Returns None if the words can't be
+// matched to the template.
+//
+fn adif_cabrillo_split(words: &[&str], exchange: &AdifCabrilloExchange) ->
+    Option<(usize, bool)>
+{
+    let (nsent, nrcvd) = (exchange.acx_sent.len(), exchange.acx_rcvd.len());
+    let sent_absorbs = adif_cabrillo_absorbs(&exchange.acx_sent);
+    let rcvd_absorbs = adif_cabrillo_absorbs(&exchange.acx_rcvd);
+
+    for exact in &[ true, false ] {
+        for transmitter in &[ false, true ] {
+            let nexchange = match words.len().checked_sub(
+                2 + *transmitter as usize) {
+                Some(n) => n,
+                None => continue
+            };
+            if *transmitter && !words.last().is_some_and(
+                |w| w.len() == 1 && w.chars().all(|c| c.is_ascii_digit())) {
+                continue;
+            }
+
+            let split = if nexchange == nsent + nrcvd {
+                Some(nsent)
+            } else if *exact || nexchange < nsent + nrcvd {
+                None
+            } else if sent_absorbs && !rcvd_absorbs {
+                Some(nexchange - nrcvd)
+            } else if rcvd_absorbs && !sent_absorbs {
+                Some(nsent)
+            } else if sent_absorbs && nexchange.is_multiple_of(2) {
+                Some(nexchange / 2)
+            } else {
+                None
+            };
+
+            if let Some(n) = split {
+                if adif_cabrillo_callsign(words[n + 1]) {
+                    return Some((n, *transmitter));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+//
+// Assign exchange words to the fields named by "items".
+//
+fn adif_cabrillo_assign(items: &[Vec<String>], words: &[&str],
+    fields: &mut Vec<(String, String)>)
+{
+    for (i, item) in items.iter().enumerate() {
+        let value = if i + 1 == items.len() {
+            words[i..].join(" ")
+        } else {
+            words[i].to_string()
+        };
+        fields.push((item[0].clone(), value));
+    }
+}
+
+fn adif_cabrillo_callsign(word: &str) -> bool
+{
+    word.chars().all(|c| c.is_ascii_alphanumeric() || c == '/') &&
+        word.contains(|c: char| c.is_ascii_digit()) &&
+        word.contains(|c: char| c.is_ascii_alphabetic())
+}
+
+//
+// Parse the value of a QSO line into field names and values.
+//
+fn adif_cabrillo_qso(value: &str, exchange: &AdifCabrilloExchange) ->
+    Result<Vec<(String, String)>, String>
+{
+    let words : Vec<&str> = value.split_whitespace().collect();
+    if words.len() < 6 {
+        return Err(String::from("expected a frequency, mode, date, time, \
+            and callsigns"));
+    }
+
+    let mut fields = Vec::new();
+    let mut field = |name: &str, value: String| fields.push((name.to_string(),
+        value));
+
+    let designated = ADIF_CABRILLO_BANDS.iter().find(
+        |(_, d, _)| d.eq_ignore_ascii_case(words[0]));
+    if let Some((band, _, _)) = designated {
+        field("band", band.to_lowercase());
+    } else {
+        let band = adif_khz_to_mhz(words[0]).and_then(|mhz| {
+            let band = adif_freq_band(mhz.parse().ok()?)?;
+            Some((mhz, band))
+        });
+        match band {
+            Some((mhz, band)) => {
+                field("freq", mhz);
+                field("band", band.to_lowercase());
+            },
+            None => return Err(format!("unsupported frequency \"{}\"",
+                words[0]))
+        }
+    }
+
+    match words[1].to_uppercase().as_str() {
+        "CW" => field("mode", String::from("CW")),
+        "PH" => field("mode", String::from("SSB")),
+        "FM" => field("mode", String::from("FM")),
+        "RY" => field("mode", String::from("RTTY")),
+        "DG" => field("app_cabrillo_mode", String::from("DG")),
+        _ => return Err(format!("unsupported mode \"{}\"", words[1]))
+    }
+
+    let date = words[2].replace('-', "");
+    if words[2].len() != 10 || adif_parse_date(&date).is_none() {
+        return Err(format!("invalid date \"{}\"", words[2]));
+    }
+    field("qso_date", date);
+    if words[3].len() != 4 || adif_parse_time(words[3]).is_none() {
+        return Err(format!("invalid time \"{}\"", words[3]));
+    }
+    field("time_on", words[3].to_string());
+
+    let rest = &words[4..];
+    let (nsent, transmitter) = match adif_cabrillo_split(rest, exchange) {
+        Some(split) => split,
+        None => return Err(format!("exchange doesn't match the template \
+            ({} sent and {} received items)", exchange.acx_sent.len(),
+            exchange.acx_rcvd.len()))
+    };
+    let end = rest.len() - transmitter as usize;
+    let (sentcall, rcvdcall) = (rest[0], rest[nsent + 1]);
+    if !adif_cabrillo_callsign(sentcall) {
+        return Err(format!("invalid callsign \"{}\"", sentcall));
+    }
+
+    field("station_callsign", sentcall.to_uppercase());
+    field("call", rcvdcall.to_uppercase());
+    if transmitter {
+        field("app_cabrillo_transmitter", rest[end].to_string());
+    }
+    adif_cabrillo_assign(&exchange.acx_sent, &rest[1..nsent + 1], &mut fields);
+    adif_cabrillo_assign(&exchange.acx_rcvd, &rest[nsent + 2..end],
+        &mut fields);
+    Ok(fields)
+}
+
+//
+// Read a Cabrillo log.  Exchanges are split using "options.acbo_exchange", or
+// else the template for the contest (from "options.acbo_contest" or the
+// CONTEST tag).  Besides the records, this reports which QSO lines were
+// skipped, so that callers can tell whether the log was read completely.
+//
+pub fn adif_cabrillo_parse(label: &str, source: &mut dyn io::Read,
+    options: &AdifCabrilloOptions, parse_options: &AdifParseOptions) ->
+    Result<AdifCabrilloImport, AdifParseError>
+{
+    let text = adif_read_text(source)?;
+    let mut diagnostics = Vec::new();
+    let mut tags : Vec<(String, String)> = Vec::new();
+    let mut qsos : Vec<(usize, &str, bool)> = Vec::new();
+    let mut started = false;
+    let mut ended = false;
+
+    let note = |line: usize, message: String| AdifDiagnostic {
+        adid_record: None,
+        adid_line: Some(line),
+        adid_message: message
+    };
+
+    for (i, line) in text.lines().enumerate() {
+        let lineno = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if ended {
+            diagnostics.push(note(lineno, String::from("ignored text after \
+                END-OF-LOG")));
+            break;
+        }
+
+        let (tag, value) = match line.split_once(':') {
+            Some((tag, value)) => (tag.trim().to_uppercase(), value.trim()),
+            None => (String::new(), "")
+        };
+        if !started {
+            if tag != "START-OF-LOG" {
+                return Err(AdifParseError::ADIF_EBADINPUT(String::from(
+                    "not a Cabrillo log (expected START-OF-LOG)")));
+            }
+            if value != "3.0" && value != "2.0" {
+                diagnostics.push(note(lineno, format!("unsupported Cabrillo \
+                    version \"{}\"", value)));
+            }
+            started = true;
+            continue;
+        }
+
+        match tag.as_str() {
+            "" => diagnostics.push(note(lineno, String::from("expected \
+                \"TAG: value\""))),
+            "END-OF-LOG" => ended = true,
+            "QSO" => qsos.push((lineno, value, false)),
+            "X-QSO" => qsos.push((lineno, value, true)),
+            _ => match tags.iter_mut().find(|(t, _)| *t == tag) {
+                Some(entry) if !value.is_empty() => {
+                    if !entry.1.is_empty() {
+                        entry.1.push(' ');
+                    }
+                    entry.1.push_str(value);
+                },
+                Some(_) => (),
+                None => tags.push((tag, value.to_string()))
+            }
+        }
+    }
+
+    if !ended {
+        diagnostics.push(AdifDiagnostic {
+            adid_record: None,
+            adid_line: None,
+            adid_message: String::from("missing END-OF-LOG")
+        });
+    }
+
+    let tag = |name: &str| tags.iter().find(|(t, _)| t == name).map(
+        |(_, v)| v.as_str()).filter(|v| !v.is_empty());
+    let contest = options.acbo_contest.as_deref().or(tag("CONTEST"));
+    let exchange = match &options.acbo_exchange {
+        Some(x) => x.clone(),
+        None => adif_cabrillo_exchange(contest.unwrap_or(""))
+    };
+
+    let mut header = AdiHeader { adih_content: Vec::new(), adih_fields:
+        Vec::new() };
+    if let Some(program) = tag("CREATED-BY") {
+        header.adih_fields.push(adif_data_specifier("PROGRAMID", &None,
+            program));
+    }
+    for (name, value) in &tags {
+        let suffix : String = name.chars().map(|c|
+            if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+        let field = format!("APP_CABRILLO_{}", suffix);
+        if !adif_valid_field_name(&field) {
+            diagnostics.push(AdifDiagnostic {
+                adid_record: None,
+                adid_line: None,
+                adid_message: format!("ignored header tag \"{}\"", name)
+            });
+            continue;
+        }
+        header.adih_fields.push(adif_data_specifier(&field, &None, value));
+    }
+
+    let mut adi = AdiFile { adi_header: Some(header), adi_records: Vec::new() };
+    let mut lines = Vec::new();
+    let mut skipped = Vec::new();
+    let nqsos = qsos.len();
+    for (lineno, value, excluded) in qsos {
+        let mut fields = match adif_cabrillo_qso(value, &exchange) {
+            Ok(fields) => fields,
+            Err(message) => {
+                diagnostics.push(note(lineno, format!("skipped QSO: {}",
+                    message)));
+                skipped.push(lineno);
+                continue;
+            }
+        };
+        if let Some(c) = contest {
+            fields.push((String::from("contest_id"), c.to_uppercase()));
+        }
+        if excluded {
+            fields.push((String::from("app_cabrillo_x_qso"),
+                String::from("Y")));
+        }
+
+        adi.adi_records.push(AdiRecord {
+            adir_fields: fields.iter().map(
+                |(f, v)| adif_data_specifier(f, &None, v)).collect()
+        });
+        lines.push(lineno);
+    }
+
+    let mut adf = adif_parse_adi(label, &adi, parse_options)?;
+    for d in &mut adf.adif_diagnostics {
+        d.adid_line = d.adid_record.map(|r| lines[r - 1]);
+    }
+    adf.adif_diagnostics.extend(diagnostics);
+    adf.adif_diagnostics.sort_by_key(|d| d.adid_line);
+    Ok(AdifCabrilloImport {
+        acbi_file: adf,
+        acbi_nqsos: nqsos,
+        acbi_skipped: skipped
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use adif::AdifRecord;
+    use adif::adif_header_program_id;
+    use adif::adif_header_value;
+    use super::*;
+
+    fn record(fields: &[(&str, &str)]) -> AdifRecord
+    {
+        AdifRecord {
+            adir_field_values: fields.iter().map(
+                |(f, v)| (f.to_string(), v.to_string())).collect(),
+            adir_extra_values: BTreeMap::new()
+        }
+    }
+
+    fn write(records: &[AdifRecord], options: &AdifCabrilloOptions) ->
+        (String, Vec<String>)
+    {
+        let mut out = Vec::new();
+        let diagnostics = adif_cabrillo_write(records, options, &mut out)
+            .unwrap();
+        (String::from_utf8(out).unwrap(), diagnostics.iter().map(
+            |d| d.to_string()).collect())
+    }
+
+    #[test]
+    fn exchange() {
+        let x = adif_parse_cabrillo_exchange("rst_sent STX|stx_string / \
+            rst_rcvd srx").unwrap();
+        assert_eq!(x.acx_sent, vec![ vec![ "rst_sent" ],
+            vec![ "stx", "stx_string" ] ]);
+        assert_eq!(x.acx_rcvd, vec![ vec![ "rst_rcvd" ], vec![ "srx" ] ]);
+        assert!(adif_parse_cabrillo_exchange("rst_sent stx").is_err());
+        assert!(adif_parse_cabrillo_exchange("a<b / c").is_err());
+
+        assert!(adif_parse_cabrillo_exchange("a / b / c").is_err());
+        assert!(adif_parse_cabrillo_exchange("a // b").is_err());
+
+        for (contest, text) in ADIF_CABRILLO_EXCHANGES {
+            assert_eq!(adif_parse_cabrillo_exchange(text).unwrap(),
+                adif_cabrillo_exchange(contest), "{}", contest);
+        }
+        assert_eq!(adif_cabrillo_exchange("cq-ww-cw").acx_rcvd[1],
+            vec![ "cqz", "srx" ]);
+        assert_eq!(adif_cabrillo_exchange("NO-SUCH-CONTEST"),
+            adif_parse_cabrillo_exchange(ADIF_CABRILLO_DEFAULT_EXCHANGE)
+            .unwrap());
+    }
+
+    #[test]
+    fn cq_ww() {
+        let records = vec![
+            record(&[ ("call", "dl1abc"), ("qso_date", "20231125"),
+                ("time_on", "120130"), ("freq", "14.0253"), ("mode", "CW"),
+                ("station_callsign", "W1AW"), ("contest_id", "CQ-WW-CW"),
+                ("my_cq_zone", "5"), ("cqz", "14"), ("rst_rcvd", "579") ]),
+            record(&[ ("call", "JA1XYZ"), ("qso_date", "20231125"),
+                ("time_on", "0905"), ("band", "40m"), ("mode", "CW"),
+                ("station_callsign", "W1AW"), ("contest_id", "CQ-WW-CW"),
+                ("my_cq_zone", "5") ]),
+            record(&[ ("call", "K1JT"), ("qso_date", "20231125"),
+                ("mode", "CW") ])
+        ];
+        let options = AdifCabrilloOptions {
+            acbo_header: vec![
+                (String::from("category-power"), String::from("LOW")),
+                (String::from("CATEGORY-BAND"), String::from("ALL"))
+            ],
+            ..Default::default()
+        };
+        let (text, diagnostics) = write(&records, &options);
+        assert_eq!(text, format!("START-OF-LOG: 3.0\n\
+            CREATED-BY: adif {}\n\
+            CONTEST: CQ-WW-CW\n\
+            CALLSIGN: W1AW\n\
+            CATEGORY-OPERATOR: SINGLE-OP\n\
+            CATEGORY-BAND: ALL\n\
+            CATEGORY-MODE: CW\n\
+            CATEGORY-POWER: LOW\n\
+            QSO: 14025 CW 2023-11-25 1201 W1AW          599 5      \
+            DL1ABC        579 14\n\
+            END-OF-LOG:\n", env!("CARGO_PKG_VERSION")));
+        assert_eq!(diagnostics, vec![
+            "record 3: skipped: missing date, time, callsign, mode, or \
+            frequency",
+            "record 2: skipped: no value for exchange item cqz|srx"
+        ]);
+    }
+
+    #[test]
+    fn header() {
+        let records = vec![
+            record(&[ ("call", "W1AW"), ("qso_date", "20230610"),
+                ("time_on", "1800"), ("freq", "144.200"), ("mode", "USB"),
+                ("operator", "k1abc"), ("srx_string", "FN31"),
+                ("my_gridsquare", "FN42") ]),
+            record(&[ ("call", "W2XYZ"), ("qso_date", "20230610"),
+                ("time_on", "1805"), ("band", "2m"), ("mode", "SSB"),
+                ("operator", "K1DEF"), ("srx", "12"),
+                ("my_gridsquare", "FN42") ])
+        ];
+        let options = AdifCabrilloOptions {
+            acbo_contest: Some(String::from("ARRL-VHF-JUN")),
+            acbo_callsign: Some(String::from("K1ABC")),
+            acbo_exchange: Some(adif_parse_cabrillo_exchange(
+                "my_gridsquare / srx_string|srx").unwrap()),
+            ..Default::default()
+        };
+        let (text, diagnostics) = write(&records, &options);
+        let lines : Vec<&str> = text.lines().collect();
+        assert_eq!(&lines[2..], &[
+            "CONTEST: ARRL-VHF-JUN",
+            "CALLSIGN: K1ABC",
+            "CATEGORY-OPERATOR: MULTI-OP",
+            "CATEGORY-BAND: 2M",
+            "CATEGORY-MODE: SSB",
+            "OPERATORS: K1ABC K1DEF",
+            "QSO:   144 PH 2023-06-10 1800 K1ABC         FN42   W1AW          \
+            FN31",
+            "QSO:   144 PH 2023-06-10 1805 K1ABC         FN42   W2XYZ         \
+            12",
+            "END-OF-LOG:"
+        ]);
+        assert!(diagnostics.is_empty());
+
+        let (_, diagnostics) = write(&records[0..1],
+            &AdifCabrilloOptions::default());
+        assert_eq!(diagnostics[0..2], [ "header: no value for CONTEST",
+            "header: no value for CALLSIGN" ]);
+    }
+
+    fn parse(text: &str, options: &AdifCabrilloOptions) ->
+        Result<AdifCabrilloImport, AdifParseError>
+    {
+        adif_cabrillo_parse("test", &mut text.as_bytes(), options,
+            &AdifParseOptions::default())
+    }
+
+    #[test]
+    fn round_trip() {
+        let records = vec![
+            record(&[ ("call", "DL1ABC"), ("qso_date", "20231125"),
+                ("time_on", "1201"), ("freq", "14.0253"), ("mode", "CW"),
+                ("station_callsign", "W1AW"), ("contest_id", "CQ-WW-CW"),
+                ("my_cq_zone", "5"), ("cqz", "14"), ("rst_rcvd", "579") ]),
+            record(&[ ("call", "JA1XYZ"), ("qso_date", "20231125"),
+                ("time_on", "0905"), ("band", "2m"), ("mode", "SSB"),
+                ("station_callsign", "W1AW"), ("contest_id", "CQ-WW-CW"),
+                ("my_cq_zone", "5"), ("cqz", "25") ])
+        ];
+        let (text, _) = write(&records, &AdifCabrilloOptions::default());
+        let adf = parse(&text, &AdifCabrilloOptions::default()).unwrap()
+            .acbi_file;
+        assert!(adf.adif_diagnostics.is_empty());
+        assert_eq!(adif_header_program_id(&adf.adif_header), Some(format!(
+            "adif {}", env!("CARGO_PKG_VERSION")).as_str()));
+        assert_eq!(adif_header_value(&adf.adif_header,
+            "APP_CABRILLO_CALLSIGN"), Some("W1AW"));
+
+        let fields = |i: usize| -> Vec<(&str, &str)> {
+            adf.adif_records[i].adir_field_values.iter().map(
+                |(f, v)| (f.as_str(), v.as_str())).collect()
+        };
+        assert_eq!(fields(0), vec![ ("band", "2m"), ("call", "JA1XYZ"),
+            ("contest_id", "CQ-WW-CW"), ("cqz", "25"), ("mode", "SSB"),
+            ("my_cq_zone", "5"), ("qso_date", "20231125"),
+            ("rst_rcvd", "59"), ("rst_sent", "59"),
+            ("station_callsign", "W1AW"), ("time_on", "0905") ]);
+        assert_eq!(fields(1), vec![ ("band", "20m"), ("call", "DL1ABC"),
+            ("contest_id", "CQ-WW-CW"), ("cqz", "14"), ("freq", "14.025"),
+            ("mode", "CW"), ("my_cq_zone", "5"), ("qso_date", "20231125"),
+            ("rst_rcvd", "579"), ("rst_sent", "599"),
+            ("station_callsign", "W1AW"), ("time_on", "1201") ]);
+    }
+
+    #[test]
+    fn parsing() {
+        let text = "START-OF-LOG: 3.0\n\
+            CONTEST: ARRL-SS-CW\n\
+            ADDRESS: 225 Main St\n\
+            ADDRESS: Newington CT\n\
+            X-MY TAG: value\n\
+            QSO: 21042 CW 1997-11-01 2102 N5KO 3 B 74 STX K9ZO 2 A 69 IL\n\
+            QSO: 14000 DG 1997-11-01 2103 N5KO 4 B 74 STX K9ZO 3 A 69 IL 1\n\
+            X-QSO: 7030 CW 1997-11-01 2104 N5KO 5 B 74 STX W1AW 4 A 69 CT\n\
+            QSO: 7030 CW 1997-11-01 2105 N5KO 6 B 74 STX W1AW 5 A\n\
+            QSO: 7030 XX 1997-11-01 2106 N5KO 7 B 74 STX W1AW 6 A 69 CT\n\
+            QSO: 7030 CW 1997-11-31 2107 N5KO 8 B 74 STX W1AW 7 A 69 CT\n\
+            QSO: 9000 CW 1997-11-01 2108 N5KO 9 B 74 STX W1AW 8 A 69 CT\n\
+            garbage\n\
+            END-OF-LOG:\n\
+            more garbage\n";
+        let import = parse(text, &AdifCabrilloOptions::default()).unwrap();
+        assert_eq!(import.acbi_nqsos, 7);
+        assert_eq!(import.acbi_skipped, vec![ 9, 10, 11, 12 ]);
+        let adf = import.acbi_file;
+        assert_eq!(adif_header_value(&adf.adif_header,
+            "APP_CABRILLO_ADDRESS"), Some("225 Main St Newington CT"));
+        assert_eq!(adif_header_value(&adf.adif_header,
+            "APP_CABRILLO_X_MY_TAG"), Some("value"));
+        assert_eq!(adf.adif_records.len(), 3);
+
+        let rec = &adf.adif_records[0].adir_field_values;
+        assert_eq!(rec["stx"], "3");
+        assert_eq!(rec["stx_string"], "B 74 STX");
+        assert_eq!(rec["srx"], "2");
+        assert_eq!(rec["precedence"], "A");
+        assert_eq!(rec["check"], "69");
+        assert_eq!(rec["arrl_sect"], "IL");
+        assert_eq!(rec["freq"], "21.042");
+        assert_eq!(rec["band"], "15m");
+
+        let rec = &adf.adif_records[1].adir_field_values;
+        assert_eq!(rec["band"], "20m");
+        assert!(!rec.contains_key("freq"));
+        assert!(!rec.contains_key("mode"));
+        assert_eq!(rec["app_cabrillo_mode"], "DG");
+        assert_eq!(rec["app_cabrillo_transmitter"], "1");
+        assert_eq!(rec["arrl_sect"], "IL");
+        assert_eq!(adf.adif_records[2].adir_field_values["app_cabrillo_x_qso"],
+            "Y");
+
+        let messages : Vec<String> = adf.adif_diagnostics.iter().map(
+            |d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "line 9: skipped QSO: exchange doesn't match the template (2 \
+            sent and 4 received items)",
+            "line 10: skipped QSO: unsupported mode \"XX\"",
+            "line 11: skipped QSO: invalid date \"1997-11-31\"",
+            "line 12: skipped QSO: unsupported frequency \"9000\"",
+            "line 13: expected \"TAG: value\"",
+            "line 15: ignored text after END-OF-LOG"
+        ]);
+
+        //
+        // An explicit template overrides the contest's.
+        //
+        let options = AdifCabrilloOptions {
+            acbo_exchange: Some(adif_parse_cabrillo_exchange(
+                "stx_string / srx_string").unwrap()),
+            ..Default::default()
+        };
+        let adf = parse(text, &options).unwrap().acbi_file;
+        assert_eq!(adf.adif_records[0].adir_field_values["srx_string"],
+            "2 A 69 IL");
+
+        assert!(parse("QSO: 7030 CW 1997-11-01 2105 N5KO 599 W1AW 599\n",
+            &options).is_err());
+        let adf = parse("START-OF-LOG: 3.0\n", &options).unwrap().acbi_file;
+        assert_eq!(adf.adif_diagnostics[0].to_string(),
+            "header: missing END-OF-LOG");
+    }
+}
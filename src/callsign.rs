@@ -0,0 +1,607 @@
+//
+// src/callsign.rs: parsing callsigns and resolving them to DXCC entities
+//
+// Callsigns may carry extra designators separated by slashes: a prefix for
+// operation from another country or call area ("VE3/W1AW" or "W1AW/KH6"), a
+// digit for operation from another call area in the same country ("W1AW/4"),
+// or a suffix describing the operation ("/P" for portable, "/M" for mobile,
+// "/MM" for maritime mobile, "/AM" for aeronautical mobile, and "/QRP").
+//
+// To resolve a callsign to its DXCC entity (and CQ zone, ITU zone, and
+// continent), we use the country files published at country-files.com, which
+// the user supplies locally.  Two formats are supported:
+//
+//   o cty.dat (and its larger variant, bigcty): each entity is a line of
+//     colon-separated fields (name, CQ zone, ITU zone, continent, latitude,
+//     longitude (positive to the west), UTC offset, primary prefix), followed
+//     by a comma-separated list of prefixes and exact calls ending in ";".
+//     This format does not include ADIF DXCC entity codes.
+//
+//   o cty.csv: one line per entity with comma-separated fields (primary
+//     prefix, name, DXCC entity code, continent, CQ zone, ITU zone, latitude,
+//     longitude (positive to the east), UTC offset), followed by a
+//     space-separated list of prefixes and exact calls ending in ";".
+//
+// In both lists, an entry starting with "=" is an exact callsign rather than a
+// prefix, and an entry may be followed by overrides: "(n)" for the CQ zone,
+// "[n]" for the ITU zone, "{XX}" for the continent, "<lat/lon>" for the
+// location, and "~n~" for the UTC offset.  A primary prefix starting with "*"
+// denotes an entity that's not on the DXCC list (e.g., "*4U1V").
+//
+
+use std::collections::HashMap;
+use std::io;
+
+use adif::AdifFile;
+use super::AdifParseError;
+
+//
+// AdifCallsign: a callsign broken into its parts.  For example, "VE3/W1AW/P"
+// has base "W1AW", prefix "VE3", and is portable.
+//
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdifCallsign {
+    pub acn_call : String,              // full callsign, uppercased
+    pub acn_base : String,              // the station's home callsign
+    pub acn_prefix : Option<String>,    // prefix for operation elsewhere
+    pub acn_area : Option<char>,        // call area digit ("W1AW/4")
+    pub acn_portable : bool,            // "/P" (or "/A")
+    pub acn_mobile : bool,              // "/M"
+    pub acn_maritime : bool,            // "/MM"
+    pub acn_aeronautical : bool,        // "/AM"
+    pub acn_qrp : bool                  // "/QRP"
+}
+
+//
+// Parse a callsign into its parts.  Returns None if the callsign contains
+// characters other than letters, digits, and slashes, or if it has more than
+// one part (after removing suffixes) besides the base call.  When there are two
+// parts, the shorter is taken to be the prefix (or the first, if they're the
+// same length).
+//
+pub fn adif_parse_callsign(call: &str) -> Option<AdifCallsign>
+{
+    let call = call.trim().to_uppercase();
+    let mut result = AdifCallsign {
+        acn_call: call.clone(),
+        ..Default::default()
+    };
+
+    let mut parts : Vec<&str> = Vec::new();
+    for part in call.split('/') {
+        if part.is_empty() ||
+            !part.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        if parts.is_empty() {
+            parts.push(part);
+            continue;
+        }
+
+        match part {
+            "P" | "A" => result.acn_portable = true,
+            "M" => result.acn_mobile = true,
+            "MM" => result.acn_maritime = true,
+            "AM" => result.acn_aeronautical = true,
+            "QRP" => result.acn_qrp = true,
+            _ if part.len() == 1 && part.as_bytes()[0].is_ascii_digit() => {
+                result.acn_area = part.chars().next();
+            },
+            _ => parts.push(part)
+        }
+    }
+
+    match parts.len() {
+        1 => result.acn_base = parts[0].to_string(),
+        2 => {
+            let (prefix, base) = if parts[1].len() < parts[0].len() {
+                (parts[1], parts[0])
+            } else {
+                (parts[0], parts[1])
+            };
+            result.acn_base = base.to_string();
+            result.acn_prefix = Some(prefix.to_string());
+        },
+        _ => return None
+    }
+
+    Some(result)
+}
+
+//
+// Returns the WPX prefix of a callsign.  The prefix is the letter and number
+// combination up to and including the digits that separate the prefix from the
+// suffix (e.g., "WB2" for "WB2ABC" and "3DA0" for "3DA0RS").  A call with no
+// digit gets a "0" (e.g., "RA0" for "RAEM").  A portable prefix replaces the
+// callsign's own ("VE3" for "VE3/W1AW") and a portable digit replaces the
+// callsign's digit ("W4" for "W1AW/4").
+//
+pub fn adif_wpx_prefix(call: &str) -> Option<String>
+{
+    let parsed = adif_parse_callsign(call)?;
+    let base = &parsed.acn_base;
+    if base.len() < 2 {
+        return None;
+    }
+
+    if let Some(p) = parsed.acn_prefix {
+        if p.chars().any(|c| c.is_ascii_digit()) {
+            return Some(p);
+        }
+        return Some(format!("{}0", p));
+    }
+
+    //
+    // The prefix ends with the first run of digits that follows a letter.
+    // (Digits later in the call, as in "W1A2B", are unusual and don't count.)
+    //
+    let bytes = base.as_bytes();
+    let prefix = match (1..bytes.len()).find(|&i| bytes[i].is_ascii_digit() &&
+        bytes[i - 1].is_ascii_alphabetic()) {
+        Some(start) => {
+            let mut end = start;
+            while end + 1 < bytes.len() && bytes[end + 1].is_ascii_digit() {
+                end += 1;
+            }
+            base[0..=end].to_string()
+        },
+        None => format!("{}0", &base[0..2])
+    };
+
+    match parsed.acn_area {
+        None => Some(prefix),
+        Some(d) => Some(format!("{}{}", prefix.trim_end_matches(
+            |c: char| c.is_ascii_digit()), d))
+    }
+}
+
+//
+// AdifCtyEntity: one entity from a country file.
+//
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifCtyEntity {
+    pub ace_name : String,              // e.g., "United States"
+    pub ace_prefix : String,            // primary prefix (without "*")
+    pub ace_dxcc : Option<u32>,         // ADIF entity code (cty.csv only)
+    pub ace_is_dxcc : bool,             // false for non-DXCC entities
+    pub ace_cont : String,              // continent (e.g., "NA")
+    pub ace_cqz : u32,
+    pub ace_ituz : u32,
+    pub ace_lat : f64,                  // degrees north
+    pub ace_lon : f64,                  // degrees east
+    pub ace_utc_offset : f64            // hours
+}
+
+//
+// An entry in the prefix or exact-call index, with any overrides of the
+// entity's defaults.
+//
+#[derive(Clone, Debug, Default)]
+struct AdifCtyAlias {
+    aca_entity : usize,
+    aca_cqz : Option<u32>,
+    aca_ituz : Option<u32>,
+    aca_cont : Option<String>
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifCtyDatabase {
+    pub acd_entities : Vec<AdifCtyEntity>,
+    acd_prefixes : HashMap<String, AdifCtyAlias>,
+    acd_exact : HashMap<String, AdifCtyAlias>,
+    acd_max_prefix : usize              // length of longest prefix
+}
+
+//
+// AdifCtyLookup: the result of resolving a callsign.  Zones and continent
+// reflect any overrides for the matching prefix or call.
+//
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifCtyLookup<'a> {
+    pub acl_entity : &'a AdifCtyEntity,
+    pub acl_cqz : u32,
+    pub acl_ituz : u32,
+    pub acl_cont : String
+}
+
+fn adif_cty_error(what: &str, lineno: usize, message: &str) -> AdifParseError
+{
+    AdifParseError::ADIF_EBADINPUT(format!("{} line {}: {}", what, lineno,
+        message))
+}
+
+fn adif_cty_number<T: ::std::str::FromStr>(what: &str, lineno: usize,
+    field: &str, value: &str) -> Result<T, AdifParseError>
+{
+    value.trim().parse::<T>().map_err(|_| adif_cty_error(what, lineno,
+        &format!("invalid {}: \"{}\"", field, value.trim())))
+}
+
+//
+// Parse one alias (a prefix or exact call, with optional overrides) and add it
+// to the database.
+//
+fn adif_cty_add_alias(db: &mut AdifCtyDatabase, entity: usize, text: &str,
+    what: &str, lineno: usize) -> Result<(), AdifParseError>
+{
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    let mut alias = AdifCtyAlias { aca_entity: entity, ..Default::default() };
+    let end = text.find(|c| "([<{~".contains(c)).unwrap_or(text.len());
+    let (name, mut rest) = text.split_at(end);
+
+    while let Some(open) = rest.chars().next() {
+        let close = match open {
+            '(' => ')',
+            '[' => ']',
+            '<' => '>',
+            '{' => '}',
+            '~' => '~',
+            _ => return Err(adif_cty_error(what, lineno,
+                &format!("unexpected text in \"{}\"", text)))
+        };
+        let len = match rest[1..].find(close) {
+            Some(len) => len,
+            None => return Err(adif_cty_error(what, lineno,
+                &format!("unterminated override in \"{}\"", text)))
+        };
+        let value = &rest[1..1 + len];
+        match open {
+            '(' => alias.aca_cqz =
+                Some(adif_cty_number(what, lineno, "CQ zone", value)?),
+            '[' => alias.aca_ituz =
+                Some(adif_cty_number(what, lineno, "ITU zone", value)?),
+            '{' => alias.aca_cont = Some(value.to_string()),
+            _ => ()                     // location and UTC offset are unused
+        }
+        rest = &rest[len + 2..];
+    }
+
+    let name = name.to_uppercase();
+    if let Some(call) = name.strip_prefix('=') {
+        db.acd_exact.insert(call.to_string(), alias);
+    } else {
+        db.acd_max_prefix = db.acd_max_prefix.max(name.len());
+        db.acd_prefixes.insert(name, alias);
+    }
+
+    Ok(())
+}
+
+//
+// Strip the "*" that marks non-DXCC entities from a primary prefix.
+//
+fn adif_cty_primary(prefix: &str) -> (String, bool)
+{
+    let prefix = prefix.trim();
+    match prefix.strip_prefix('*') {
+        Some(p) => (p.to_string(), false),
+        None => (prefix.to_string(), true)
+    }
+}
+
+//
+// Parse a country file in cty.dat format.
+//
+pub fn adif_cty_parse_dat(source: &mut dyn io::Read) ->
+    Result<AdifCtyDatabase, AdifParseError>
+{
+    let what = "cty.dat";
+    let mut text = String::new();
+    source.read_to_string(&mut text)?;
+
+    let mut db = AdifCtyDatabase::default();
+    let mut current : Option<usize> = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let lineno = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if current.is_none() {
+            let fields : Vec<&str> = line.split(':').collect();
+            if fields.len() < 9 {
+                return Err(adif_cty_error(what, lineno,
+                    "expected 8 colon-terminated fields"));
+            }
+
+            let (prefix, is_dxcc) = adif_cty_primary(fields[7]);
+            let lon : f64 = adif_cty_number(what, lineno, "longitude",
+                fields[5])?;
+            db.acd_entities.push(AdifCtyEntity {
+                ace_name: fields[0].trim().to_string(),
+                ace_prefix: prefix,
+                ace_dxcc: None,
+                ace_is_dxcc: is_dxcc,
+                ace_cont: fields[3].trim().to_string(),
+                ace_cqz: adif_cty_number(what, lineno, "CQ zone", fields[1])?,
+                ace_ituz: adif_cty_number(what, lineno, "ITU zone",
+                    fields[2])?,
+                ace_lat: adif_cty_number(what, lineno, "latitude", fields[4])?,
+                ace_lon: -lon,
+                ace_utc_offset: adif_cty_number(what, lineno, "UTC offset",
+                    fields[6])?
+            });
+            current = Some(db.acd_entities.len() - 1);
+            continue;
+        }
+
+        let entity = current.unwrap();
+        let (aliases, done) = match line.find(';') {
+            Some(p) => (&line[0..p], true),
+            None => (line, false)
+        };
+        for alias in aliases.split(',') {
+            adif_cty_add_alias(&mut db, entity, alias, what, lineno)?;
+        }
+        if done {
+            current = None;
+        }
+    }
+
+    if current.is_some() {
+        return Err(AdifParseError::ADIF_EBADINPUT(String::from(
+            "cty.dat: unexpected end of file in prefix list")));
+    }
+
+    Ok(db)
+}
+
+//
+// Parse a country file in cty.csv format.
+//
+pub fn adif_cty_parse_csv(source: &mut dyn io::Read) ->
+    Result<AdifCtyDatabase, AdifParseError>
+{
+    let what = "cty.csv";
+    let mut text = String::new();
+    source.read_to_string(&mut text)?;
+
+    let mut db = AdifCtyDatabase::default();
+    for (i, line) in text.lines().enumerate() {
+        let lineno = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields : Vec<&str> = line.splitn(10, ',').collect();
+        if fields.len() < 10 {
+            return Err(adif_cty_error(what, lineno, "expected 10 fields"));
+        }
+
+        let (prefix, is_dxcc) = adif_cty_primary(fields[0]);
+        db.acd_entities.push(AdifCtyEntity {
+            ace_name: fields[1].trim().to_string(),
+            ace_prefix: prefix,
+            ace_dxcc: Some(adif_cty_number(what, lineno, "entity code",
+                fields[2])?),
+            ace_is_dxcc: is_dxcc,
+            ace_cont: fields[3].trim().to_string(),
+            ace_cqz: adif_cty_number(what, lineno, "CQ zone", fields[4])?,
+            ace_ituz: adif_cty_number(what, lineno, "ITU zone", fields[5])?,
+            ace_lat: adif_cty_number(what, lineno, "latitude", fields[6])?,
+            ace_lon: adif_cty_number(what, lineno, "longitude", fields[7])?,
+            ace_utc_offset: adif_cty_number(what, lineno, "UTC offset",
+                fields[8])?
+        });
+
+        let entity = db.acd_entities.len() - 1;
+        let aliases = fields[9].trim().trim_end_matches(';');
+        for alias in aliases.split_whitespace() {
+            adif_cty_add_alias(&mut db, entity, alias, what, lineno)?;
+        }
+    }
+
+    Ok(db)
+}
+
+fn adif_cty_result<'a>(db: &'a AdifCtyDatabase, alias: &AdifCtyAlias) ->
+    AdifCtyLookup<'a>
+{
+    let entity = &db.acd_entities[alias.aca_entity];
+    AdifCtyLookup {
+        acl_entity: entity,
+        acl_cqz: alias.aca_cqz.unwrap_or(entity.ace_cqz),
+        acl_ituz: alias.aca_ituz.unwrap_or(entity.ace_ituz),
+        acl_cont: alias.aca_cont.clone().unwrap_or_else(
+            || entity.ace_cont.clone())
+    }
+}
+
+//
+// Resolve a callsign to its entity.  Exact-call entries take precedence.
+// Otherwise, we use the longest matching prefix of the portable prefix (if
+// any) or else the base call.  Maritime and aeronautical mobile stations are
+// not in any entity, so these return None.
+//
+pub fn adif_cty_lookup<'a>(db: &'a AdifCtyDatabase, call: &str) ->
+    Option<AdifCtyLookup<'a>>
+{
+    let parsed = adif_parse_callsign(call)?;
+    if let Some(alias) = db.acd_exact.get(&parsed.acn_call) {
+        return Some(adif_cty_result(db, alias));
+    }
+
+    if parsed.acn_maritime || parsed.acn_aeronautical {
+        return None;
+    }
+
+    let target = match parsed.acn_prefix {
+        Some(ref p) => p,
+        None => {
+            if let Some(alias) = db.acd_exact.get(&parsed.acn_base) {
+                return Some(adif_cty_result(db, alias));
+            }
+            &parsed.acn_base
+        }
+    };
+
+    let maxlen = db.acd_max_prefix.min(target.len());
+    (1..=maxlen).rev().find_map(|len| db.acd_prefixes.get(&target[0..len]))
+        .map(|alias| adif_cty_result(db, alias))
+}
+
+//
+// Fill in missing "dxcc", "country", "cqz", "ituz", and "cont" fields in each
+// record of "adf" using the entity for the record's "call".  (We can only fill
+// in "dxcc" if the database came from cty.csv.)  Fields that are present, even
+// if empty, are left alone.  Returns the number of records that were changed.
+//
+pub fn adif_cty_fill(adf: &mut AdifFile, db: &AdifCtyDatabase) -> usize
+{
+    let mut nchanged = 0;
+
+    for rec in &mut adf.adif_records {
+        let lookup = match rec.adir_field_values.get("call")
+            .and_then(|c| adif_cty_lookup(db, c)) {
+            Some(lookup) => lookup,
+            None => continue
+        };
+
+        let entity = lookup.acl_entity;
+        let mut values = vec![
+            ("country", entity.ace_name.clone()),
+            ("cqz", lookup.acl_cqz.to_string()),
+            ("ituz", lookup.acl_ituz.to_string()),
+            ("cont", lookup.acl_cont.clone())
+        ];
+        if let Some(dxcc) = entity.ace_dxcc {
+            if entity.ace_is_dxcc {
+                values.push(("dxcc", dxcc.to_string()));
+            }
+        }
+
+        let mut changed = false;
+        for (field, value) in values {
+            if !rec.adir_field_values.contains_key(field) {
+                rec.adir_field_values.insert(field.to_string(), value);
+                changed = true;
+            }
+        }
+
+        if changed {
+            nchanged += 1;
+        }
+    }
+
+    nchanged
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static CTY_DAT : &str = "\
+United States:            05:  08:  NA:   37.53:    91.67:     5.0:  K:
+    AA,K,N,W,=W1AW/KH6,
+    =N6XYZ(3)[6];
+Alaska:                   01:  01:  NA:   61.40:   148.87:     9.0:  KL:
+    AL,KL,NL,WL,=W1AW/KL7;
+Hawaii:                   31:  61:  OC:   21.12:   157.48:    10.0:  KH6:
+    AH6,KH6,NH6,WH6;
+Canada:                   05:  09:  NA:   44.35:    78.75:     5.0:  VE:
+    CF,CG,CK,CY,VA,VE,VE3(4)[4],VO,VY;
+";
+
+    static CTY_CSV : &str = "\
+K,United States,291,NA,5,8,37.53,-91.67,-5.0,AA K N W =N6XYZ(3)[6];
+VE,Canada,1,NA,5,9,44.35,-78.75,-5.0,VA VE VE3(4)[4] VY;
+*4U1V,Vienna Intl Ctr,206,EU,15,28,48.20,16.30,1.0,=4U1VIC;
+";
+
+    #[test]
+    fn parse_callsigns() {
+        let c = adif_parse_callsign("ve3/w1aw/p").unwrap();
+        assert_eq!(c.acn_call, "VE3/W1AW/P");
+        assert_eq!(c.acn_base, "W1AW");
+        assert_eq!(c.acn_prefix.as_deref(), Some("VE3"));
+        assert!(c.acn_portable);
+
+        let c = adif_parse_callsign("W1AW/KH6").unwrap();
+        assert_eq!(c.acn_base, "W1AW");
+        assert_eq!(c.acn_prefix.as_deref(), Some("KH6"));
+
+        let c = adif_parse_callsign("W1AW/4").unwrap();
+        assert_eq!(c.acn_area, Some('4'));
+        assert!(adif_parse_callsign("K6ZBI/MM").unwrap().acn_maritime);
+        assert!(adif_parse_callsign("K6ZBI/AM").unwrap().acn_aeronautical);
+        assert!(adif_parse_callsign("K6ZBI/M").unwrap().acn_mobile);
+        assert_eq!(adif_parse_callsign("A/B/C"), None);
+        assert_eq!(adif_parse_callsign("W1 AW"), None);
+    }
+
+    #[test]
+    fn wpx_prefixes() {
+        let cases = [
+            ("W1AW", "W1"), ("WB2ABC", "WB2"), ("N8BJQ", "N8"),
+            ("3DA0RS", "3DA0"), ("4X4A", "4X4"), ("2E0ABC", "2E0"),
+            ("RAEM", "RA0"), ("W1AW/4", "W4"), ("VE3/W1AW", "VE3"),
+            ("W1AW/KH6", "KH6"), ("W1AW/P", "W1"), ("F/W1AW", "F0"),
+            ("k6zbi/mm", "K6")
+        ];
+        for (call, prefix) in cases.iter() {
+            assert_eq!(adif_wpx_prefix(call).as_deref(), Some(*prefix),
+                "call {}", call);
+        }
+        assert_eq!(adif_wpx_prefix("A/B/C"), None);
+    }
+
+    #[test]
+    fn cty_dat() {
+        let db = adif_cty_parse_dat(&mut CTY_DAT.as_bytes()).unwrap();
+        assert_eq!(db.acd_entities.len(), 4);
+        assert_eq!(db.acd_entities[0].ace_lon, -91.67);
+
+        let name = |call: &str| {
+            adif_cty_lookup(&db, call).map(|l| l.acl_entity.ace_name.clone())
+        };
+        assert_eq!(name("W1AW").as_deref(), Some("United States"));
+        assert_eq!(name("KL7ABC").as_deref(), Some("Alaska"));
+        assert_eq!(name("KH6/W1AW").as_deref(), Some("Hawaii"));
+        assert_eq!(name("W1AW/KL7").as_deref(), Some("Alaska"));
+        assert_eq!(name("VE3/W1AW/P").as_deref(), Some("Canada"));
+        assert_eq!(name("W1AW/MM"), None);
+        assert_eq!(name("ZZ9ZZ"), None);
+
+        let lookup = adif_cty_lookup(&db, "VE3ABC").unwrap();
+        assert_eq!((lookup.acl_cqz, lookup.acl_ituz), (4, 4));
+        let lookup = adif_cty_lookup(&db, "N6XYZ").unwrap();
+        assert_eq!((lookup.acl_cqz, lookup.acl_ituz), (3, 6));
+        let lookup = adif_cty_lookup(&db, "W1AW/KH6").unwrap();
+        assert_eq!(lookup.acl_entity.ace_name, "United States");
+        assert_eq!(lookup.acl_cont, "NA");
+
+        assert!(adif_cty_parse_dat(&mut "Bogus: 1: 2:\n".as_bytes())
+            .is_err());
+        assert!(adif_cty_parse_dat(&mut "United States: 05: 08: NA: 37.53: \
+            91.67: 5.0: K:\n    K,W\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn cty_csv_fill() {
+        let db = adif_cty_parse_csv(&mut CTY_CSV.as_bytes()).unwrap();
+        let lookup = adif_cty_lookup(&db, "4U1VIC").unwrap();
+        assert!(!lookup.acl_entity.ace_is_dxcc);
+
+        let input = "\n<eoh><call:4>W1AW<eor><call:6>VE3ABC<cqz:1>5<eor>\
+            <call:7>W1AW/MM<eor>";
+        let mut adf = ::adif_parse("test", &mut input.as_bytes()).unwrap();
+        assert_eq!(adif_cty_fill(&mut adf, &db), 2);
+        let rec = &adf.adif_records[0].adir_field_values;
+        assert_eq!(rec["dxcc"], "291");
+        assert_eq!(rec["country"], "United States");
+        assert_eq!(rec["cqz"], "5");
+        assert_eq!(rec["ituz"], "8");
+        assert_eq!(rec["cont"], "NA");
+        let rec = &adf.adif_records[1].adir_field_values;
+        assert_eq!(rec["dxcc"], "1");
+        assert_eq!(rec["cqz"], "5");
+        assert_eq!(rec["ituz"], "4");
+        assert!(!adf.adif_records[2].adir_field_values.contains_key("dxcc"));
+    }
+}
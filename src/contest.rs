@@ -0,0 +1,669 @@
+//
+// src/contest.rs: checking dupes and scoring contest logs
+//
+// Contest rules vary a lot, but most of them come down to the same few
+// questions: which QSOs are dupes, how many points each QSO is worth, and
+// which QSOs count as multipliers.  The rules for a contest (AdifContestRules)
+// answer these questions in terms of the fields of each record, and they can
+// be built directly or parsed from text like this:
+//
+//     # CQ World Wide DX Contest
+//     contest CQ-WW-CW CQ-WW-SSB
+//     dupe band
+//     ignore if app_cabrillo_x_qso = Y
+//     points 0 if dxcc = $my_dxcc
+//     points 3 if cont != $my_cont
+//     points 2 if cont = NA
+//     points 1
+//     multiplier zone cqz per band
+//     multiplier country dxcc per band
+//
+// The statements are:
+//
+//     contest NAME...         the contest IDs (as in "contest_id") the rules
+//                             are for.  Records for other contests are left
+//                             out, while records without a "contest_id" are
+//                             scored.
+//     dupe [FIELD...]         a QSO is a dupe if the same callsign was worked
+//                             earlier with the same values of these fields
+//                             (default: "band mode_group").  With no fields,
+//                             each station may be worked only once.
+//     ignore if EXPR          QSOs that match EXPR aren't scored at all
+//     points N [if EXPR]      QSOs that match EXPR are worth N points.  The
+//                             first matching statement applies, and a QSO
+//                             that matches none is worth nothing.
+//     multiplier NAME FIELD [per FIELD...] [if EXPR]
+//                             each distinct value of FIELD (among QSOs that
+//                             match EXPR) counts as a multiplier, once for
+//                             each distinct combination of the "per" fields.
+//                             Statements with the same NAME count together.
+//     default FIELD = VALUE   a value for records that have no value for
+//                             FIELD (e.g., "default my_cont = NA")
+//
+// Each EXPR is a filter expression (see src/filter.rs), which can compare one
+// field with another (as in "dxcc = $my_dxcc").  Besides the record's own
+// fields, rules can use "mode_group" (CW, PHONE, or DIGITAL), "wpx" (the WPX
+// prefix of the callsign), and "band" derived from "freq" when it's missing.
+// Values are compared ignoring case and leading zeros, so "05" and "5" are the
+// same zone.
+//
+// QSOs are scored in the order they were made.  The claimed score is the total
+// of the points times the total number of multipliers (or just the points if
+// the rules have no multipliers).  Rules for several common contests are built
+// in (see ADIF_CONTEST_RULES).
+//
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use adif::AdifDiagnostic;
+use adif::AdifRecord;
+use awards::adif_mode_group;
+use callsign::adif_wpx_prefix;
+use diff::adif_record_timestamp;
+use fields::adif_band_range;
+use fields::adif_freq_band;
+use filter::AdifFilter;
+use filter::adif_filter_matches;
+use filter::adif_parse_filter;
+use super::AdifParseError;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdifContestMultiplier {
+    pub acmu_name : String,
+    pub acmu_field : String,
+    pub acmu_per : Vec<String>,
+    pub acmu_filter : Option<AdifFilter>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifContestRules {
+    pub acr_names : Vec<String>,
+    pub acr_dupe : Vec<String>,
+    pub acr_ignore : Vec<AdifFilter>,
+    pub acr_points : Vec<(u32, Option<AdifFilter>)>,
+    pub acr_multipliers : Vec<AdifContestMultiplier>,
+    pub acr_defaults : Vec<(String, String)>
+}
+
+impl Default for AdifContestRules {
+    fn default() -> AdifContestRules {
+        AdifContestRules {
+            acr_names: Vec::new(),
+            acr_dupe: vec![ String::from("band"), String::from("mode_group") ],
+            acr_ignore: Vec::new(),
+            acr_points: Vec::new(),
+            acr_multipliers: Vec::new(),
+            acr_defaults: Vec::new()
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdifContestStatus {
+    ACS_SCORED,
+    ACS_DUPE(usize),            // record index of the earlier QSO
+    ACS_IGNORED,                // matched an "ignore" rule or has no callsign
+    ACS_OTHER_CONTEST
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifContestQso {
+    pub acq_record : usize,                         // index into the records
+    pub acq_status : AdifContestStatus,
+    pub acq_points : u32,
+    pub acq_multipliers : Vec<(String, String)>     // new (name, value) pairs
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdifContestTotals {
+    pub acbt_band : String,
+    pub acbt_qsos : usize,
+    pub acbt_dupes : usize,
+    pub acbt_points : u64,
+    pub acbt_multipliers : Vec<usize>   // one count for each multiplier name
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifContestScore {
+    pub acs_qsos : Vec<AdifContestQso>,             // in chronological order
+    pub acs_multiplier_names : Vec<String>,
+    pub acs_bands : Vec<AdifContestTotals>,         // sorted by frequency
+    pub acs_total : AdifContestTotals,
+    pub acs_score : u64,
+    pub acs_diagnostics : Vec<AdifDiagnostic>
+}
+
+//
+// Rules for common contests.  These assume that "dxcc", "cont", and "cqz" have
+// been filled in for each QSO (e.g., by "adif cty") and that "my_dxcc" and
+// "my_cont" are present or given as defaults.
+//
+pub const ADIF_CONTEST_RULES : &[&str] = &[
+    "# CQ World Wide DX Contest
+contest CQ-WW-CW CQ-WW-SSB CQ-WW-RTTY
+dupe band
+ignore if app_cabrillo_x_qso = Y
+points 0 if dxcc = $my_dxcc
+points 3 if cont != $my_cont
+points 2 if cont = NA and my_cont = NA
+points 1
+multiplier zone cqz per band
+multiplier country dxcc per band",
+
+    "# CQ WPX Contest
+contest CQ-WPX-CW CQ-WPX-SSB CQ-WPX-RTTY
+dupe band
+ignore if app_cabrillo_x_qso = Y
+points 1 if dxcc = $my_dxcc
+points 6 if cont != $my_cont and band in (160m, 80m, 40m)
+points 3 if cont != $my_cont
+points 4 if cont = NA and my_cont = NA and band in (160m, 80m, 40m)
+points 2 if cont = NA and my_cont = NA
+points 2 if band in (160m, 80m, 40m)
+points 1
+multiplier prefix wpx",
+
+    "# ARRL International DX Contest (W/VE and DX stations)
+contest ARRL-DX-CW ARRL-DX-SSB
+dupe band
+ignore if app_cabrillo_x_qso = Y
+points 0 if dxcc in (1, 291) and my_dxcc in (1, 291)
+points 0 if not dxcc in (1, 291) and not my_dxcc in (1, 291)
+points 3
+multiplier mult state per band if dxcc in (1, 291) and not my_dxcc in (1, 291)
+multiplier mult dxcc per band if not dxcc in (1, 291) and my_dxcc in (1, 291)",
+
+    "# ARRL November Sweepstakes
+contest ARRL-SS-CW ARRL-SS-SSB
+dupe
+ignore if app_cabrillo_x_qso = Y
+points 2
+multiplier section arrl_sect",
+
+    "# ARRL Field Day
+contest ARRL-FD
+dupe band mode_group
+points 2 if mode_group != PHONE
+points 1",
+
+    "# North American QSO Party
+contest NAQP-CW NAQP-SSB NAQP-RTTY
+dupe band
+ignore if app_cabrillo_x_qso = Y
+points 1
+multiplier mult state per band if cont = NA
+multiplier mult dxcc per band if cont = NA and not state"
+];
+
+//
+// Parse the text of contest rules.  See the comment at the top of this file.
+//
+pub fn adif_parse_contest_rules(text: &str) ->
+    Result<AdifContestRules, AdifParseError>
+{
+    let mut rules = AdifContestRules::default();
+    for (i, line) in text.lines().enumerate() {
+        adif_contest_rule(&mut rules, line).map_err(
+            |error| AdifParseError::ADIF_EBADINPUT(format!("line {}: {}",
+            i + 1, error)))?;
+    }
+    Ok(rules)
+}
+
+//
+// Splits "line" into its leading words and the filter expression following
+// the word "if" (if any).
+//
+fn adif_contest_condition(line: &str) ->
+    Result<(Vec<String>, Option<AdifFilter>), String>
+{
+    let words : Vec<&str> = line.split_whitespace().collect();
+    match words.iter().position(|w| w.eq_ignore_ascii_case("if")) {
+        None => Ok((words.iter().map(|w| w.to_string()).collect(), None)),
+        Some(n) => {
+            //
+            // Take the expression from the original text, which may have
+            // spacing that matters (in quoted strings).
+            //
+            let mut rest = line.trim_start();
+            for _ in 0..=n {
+                rest = rest[rest.find(char::is_whitespace)
+                    .unwrap_or(rest.len())..].trim_start();
+            }
+            let filter = adif_parse_filter(rest).map_err(
+                |error| error.to_string())?;
+            Ok((words[0..n].iter().map(|w| w.to_string()).collect(),
+                Some(filter)))
+        }
+    }
+}
+
+//
+// Parse one line of contest rules, adding it to "rules".
+//
+pub fn adif_parse_contest_rule(rules: &mut AdifContestRules, line: &str) ->
+    Result<(), AdifParseError>
+{
+    adif_contest_rule(rules, line).map_err(AdifParseError::ADIF_EBADINPUT)
+}
+
+fn adif_contest_rule(rules: &mut AdifContestRules, line: &str) ->
+    Result<(), String>
+{
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+
+    let (words, filter) = adif_contest_condition(line)?;
+    if words.is_empty() {
+        return Err(String::from("expected a statement before \"if\""));
+    }
+    let keyword = words[0].to_lowercase();
+    let args : Vec<String> = words[1..].to_vec();
+    let fields = || args.iter().map(|a| a.to_lowercase()).collect();
+    match keyword.as_str() {
+        "contest" if filter.is_none() && !args.is_empty() => {
+            rules.acr_names.extend(args.iter().map(|a| a.to_uppercase()));
+        },
+        "dupe" if filter.is_none() => rules.acr_dupe = fields(),
+        "ignore" if args.is_empty() => match filter {
+            Some(f) => rules.acr_ignore.push(f),
+            None => return Err(String::from("expected \"ignore if EXPR\""))
+        },
+        "points" if args.len() == 1 => match args[0].parse::<u32>() {
+            Ok(n) => rules.acr_points.push((n, filter)),
+            Err(_) => return Err(format!("invalid points: {}", args[0]))
+        },
+        "multiplier" => {
+            let per = args.iter().position(|a| a.eq_ignore_ascii_case("per"));
+            let nfields = per.unwrap_or(args.len());
+            if nfields != 2 || per.is_some_and(|p| p + 1 == args.len()) {
+                return Err(String::from("expected \"multiplier NAME FIELD \
+                    [per FIELD...] [if EXPR]\""));
+            }
+            rules.acr_multipliers.push(AdifContestMultiplier {
+                acmu_name: args[0].clone(),
+                acmu_field: args[1].to_lowercase(),
+                acmu_per: args[nfields..].iter().skip(1).map(
+                    |a| a.to_lowercase()).collect(),
+                acmu_filter: filter
+            });
+        },
+        "default" if filter.is_none() => {
+            let (field, value) = match line[keyword.len()..].split_once('=') {
+                Some((f, v)) if !f.trim().is_empty() =>
+                    (f.trim().to_lowercase(), v.trim().to_string()),
+                _ => return Err(String::from("expected \"default FIELD = \
+                    VALUE\""))
+            };
+            rules.acr_defaults.retain(|(f, _)| *f != field);
+            rules.acr_defaults.push((field, value));
+        },
+        "contest" | "dupe" | "ignore" | "points" | "default" =>
+            return Err(format!("invalid \"{}\" statement: {}", keyword, line)),
+        _ => return Err(format!("unknown statement: {}", words[0]))
+    }
+    Ok(())
+}
+
+//
+// Returns the built-in rules for the named contest, if there are any.
+//
+pub fn adif_contest_rules(contest: &str) -> Option<AdifContestRules>
+{
+    let contest = contest.trim().to_uppercase();
+    ADIF_CONTEST_RULES.iter().map(|text| adif_parse_contest_rules(text)
+        .expect("built-in contest rules")).find(
+        |rules| rules.acr_names.contains(&contest))
+}
+
+//
+// Returns a copy of "rec" with the defaults from "rules" and the derived
+// fields described at the top of this file filled in.
+//
+fn adif_contest_record(rec: &AdifRecord, rules: &AdifContestRules) ->
+    AdifRecord
+{
+    let mut rec = rec.clone();
+    let fields = &mut rec.adir_field_values;
+    for (field, value) in &rules.acr_defaults {
+        if fields.get(field).is_none_or(|v| v.trim().is_empty()) {
+            fields.insert(field.clone(), value.clone());
+        }
+    }
+
+    let band = fields.get("freq").and_then(|f| f.trim().parse::<f64>().ok())
+        .and_then(adif_freq_band);
+    if let (None, Some(band)) = (fields.get("band"), band) {
+        fields.insert(String::from("band"), band.to_string());
+    }
+    let group = fields.get("mode").map(|m| adif_mode_group(m));
+    if let (None, Some(group)) = (fields.get("mode_group"), group) {
+        fields.insert(String::from("mode_group"), group.to_string());
+    }
+    let prefix = fields.get("call").and_then(|c| adif_wpx_prefix(c));
+    if let (None, Some(prefix)) = (fields.get("wpx"), prefix) {
+        fields.insert(String::from("wpx"), prefix);
+    }
+    rec
+}
+
+//
+// Returns the value of "field" used for dupe checking and counting
+// multipliers.  See the comment at the top of this file.
+//
+fn adif_contest_value(rec: &AdifRecord, field: &str) -> String
+{
+    let value = rec.adir_field_values.get(field).map(
+        |v| v.trim().to_uppercase()).unwrap_or_default();
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        let trimmed = value.trim_start_matches('0');
+        return String::from(if trimmed.is_empty() { "0" } else { trimmed });
+    }
+    value
+}
+
+fn adif_band_totals(bands: &mut Vec<AdifContestTotals>, band: &str,
+    nmults: usize) -> usize
+{
+    match bands.iter().position(|b| b.acbt_band == band) {
+        Some(i) => i,
+        None => {
+            bands.push(AdifContestTotals {
+                acbt_band: band.to_string(),
+                acbt_multipliers: vec![ 0; nmults ],
+                ..Default::default()
+            });
+            bands.len() - 1
+        }
+    }
+}
+
+//
+// Score "records" according to "rules".
+//
+pub fn adif_contest_score(records: &[AdifRecord], rules: &AdifContestRules) ->
+    AdifContestScore
+{
+    let mut score = AdifContestScore::default();
+    for m in &rules.acr_multipliers {
+        if !score.acs_multiplier_names.contains(&m.acmu_name) {
+            score.acs_multiplier_names.push(m.acmu_name.clone());
+        }
+    }
+    let nmults = score.acs_multiplier_names.len();
+    score.acs_total.acbt_multipliers = vec![ 0; nmults ];
+
+    let mut sorted : Vec<(usize, &AdifRecord)> = records.iter().enumerate()
+        .collect();
+    sorted.sort_by_key(|(_, rec)| adif_record_timestamp(rec));
+
+    let mut worked : BTreeMap<Vec<String>, usize> = BTreeMap::new();
+    let mut mults : BTreeSet<(usize, String, Vec<String>)> = BTreeSet::new();
+    for (i, rec) in sorted {
+        let mut qso = AdifContestQso {
+            acq_record: i,
+            acq_status: AdifContestStatus::ACS_SCORED,
+            acq_points: 0,
+            acq_multipliers: Vec::new()
+        };
+
+        let contest = rec.adir_field_values.get("contest_id").map(
+            |c| c.trim().to_uppercase()).unwrap_or_default();
+        let rec = adif_contest_record(rec, rules);
+        let call = adif_contest_value(&rec, "call");
+        if !contest.is_empty() && !rules.acr_names.is_empty() &&
+            !rules.acr_names.contains(&contest) {
+            qso.acq_status = AdifContestStatus::ACS_OTHER_CONTEST;
+        } else if call.is_empty() {
+            qso.acq_status = AdifContestStatus::ACS_IGNORED;
+            score.acs_diagnostics.push(AdifDiagnostic {
+                adid_record: Some(i + 1),
+                adid_line: None,
+                adid_message: String::from("QSO has no callsign")
+            });
+        } else if rules.acr_ignore.iter().any(
+            |f| adif_filter_matches(f, &rec)) {
+            qso.acq_status = AdifContestStatus::ACS_IGNORED;
+        }
+        if qso.acq_status != AdifContestStatus::ACS_SCORED {
+            score.acs_qsos.push(qso);
+            continue;
+        }
+
+        let band = adif_contest_value(&rec, "band");
+        let which = adif_band_totals(&mut score.acs_bands, &band, nmults);
+        let totals = &mut score.acs_bands[which];
+        totals.acbt_qsos += 1;
+        score.acs_total.acbt_qsos += 1;
+
+        let mut key = vec![ call ];
+        key.extend(rules.acr_dupe.iter().map(
+            |f| adif_contest_value(&rec, f)));
+        if let Some(&first) = worked.get(&key) {
+            qso.acq_status = AdifContestStatus::ACS_DUPE(first);
+            totals.acbt_dupes += 1;
+            score.acs_total.acbt_dupes += 1;
+            score.acs_qsos.push(qso);
+            continue;
+        }
+        worked.insert(key, i);
+
+        qso.acq_points = rules.acr_points.iter().find(|(_, f)| {
+            f.as_ref().is_none_or(|f| adif_filter_matches(f, &rec))
+        }).map(|(n, _)| *n).unwrap_or(0);
+        totals.acbt_points += qso.acq_points as u64;
+        score.acs_total.acbt_points += qso.acq_points as u64;
+
+        for m in &rules.acr_multipliers {
+            let value = adif_contest_value(&rec, &m.acmu_field);
+            if value.is_empty() || m.acmu_filter.as_ref().is_some_and(
+                |f| !adif_filter_matches(f, &rec)) {
+                continue;
+            }
+            let n = score.acs_multiplier_names.iter().position(
+                |name| *name == m.acmu_name).unwrap();
+            let per = m.acmu_per.iter().map(
+                |f| adif_contest_value(&rec, f)).collect();
+            if mults.insert((n, value.clone(), per)) {
+                qso.acq_multipliers.push((m.acmu_name.clone(), value));
+                totals.acbt_multipliers[n] += 1;
+                score.acs_total.acbt_multipliers[n] += 1;
+            }
+        }
+        score.acs_qsos.push(qso);
+    }
+
+    score.acs_bands.sort_by(|b1, b2| {
+        let lo = |b: &AdifContestTotals| adif_band_range(&b.acbt_band).map(
+            |(lo, _)| lo).unwrap_or(f64::MAX);
+        lo(b1).partial_cmp(&lo(b2)).unwrap().then_with(
+            || b1.acbt_band.cmp(&b2.acbt_band))
+    });
+
+    let total_mults : usize = score.acs_total.acbt_multipliers.iter().sum();
+    score.acs_score = if nmults == 0 {
+        score.acs_total.acbt_points
+    } else {
+        score.acs_total.acbt_points * total_mults as u64
+    };
+    score
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifRecord;
+    use super::*;
+
+    fn qso(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    #[test]
+    fn rules() {
+        let rules = adif_parse_contest_rules("\
+            # test\n\
+            contest test-1 Test-2\n\
+            dupe Band\n\
+            ignore if comment ~ 'bad'\n\
+            points 2 if mode = CW and call ~ ' if '\n\
+            points 1\n\
+            multiplier Zone cqz per band mode\n\
+            multiplier state state if dxcc = 291\n\
+            default my_cont = NA\n").unwrap();
+        assert_eq!(rules.acr_names, vec![ "TEST-1", "TEST-2" ]);
+        assert_eq!(rules.acr_dupe, vec![ "band" ]);
+        assert_eq!(rules.acr_ignore.len(), 1);
+        assert_eq!(rules.acr_points.len(), 2);
+        assert_eq!(rules.acr_points[0].1.as_ref().unwrap().to_string(),
+            "((mode = 'CW') and (call ~ ' if '))");
+        assert_eq!(rules.acr_points[1], (1, None));
+        assert_eq!(rules.acr_multipliers[0].acmu_name, "Zone");
+        assert_eq!(rules.acr_multipliers[0].acmu_per,
+            vec![ "band", "mode" ]);
+        assert!(rules.acr_multipliers[1].acmu_filter.is_some());
+        assert_eq!(rules.acr_defaults, vec![ (String::from("my_cont"),
+            String::from("NA")) ]);
+        assert!(adif_parse_contest_rules("dupe").unwrap().acr_dupe.is_empty());
+
+        for bad in &[ "points", "points x", "points 1 2", "points 1 if",
+            "ignore", "multiplier zone", "multiplier zone cqz per",
+            "multiplier a b c", "default = 1", "contest", "score 3",
+            "if band = 20m", "IF" ] {
+            assert!(adif_parse_contest_rules(bad).is_err(), "rule {}", bad);
+        }
+        assert_eq!(adif_parse_contest_rules("dupe\nif cqz = 5").unwrap_err()
+            .to_string(), "input error: line 2: expected a statement before \
+            \"if\"");
+
+        for text in ADIF_CONTEST_RULES {
+            assert!(adif_parse_contest_rules(text).is_ok());
+        }
+        assert!(adif_contest_rules("cq-ww-cw").is_some());
+        assert!(adif_contest_rules("XX").is_none());
+    }
+
+    #[test]
+    fn cq_ww() {
+        let mine = [ ("my_dxcc", "291"), ("contest_id", "CQ-WW-CW"),
+            ("mode", "CW") ];
+        let log = [
+            [ ("call", "DL1ABC"), ("band", "20m"), ("time_on", "0002"),
+                ("dxcc", "230"), ("cont", "EU"), ("cqz", "14") ],
+            [ ("call", "K1ABC"), ("band", "20m"), ("time_on", "0001"),
+                ("dxcc", "291"), ("cont", "NA"), ("cqz", "05") ],
+            [ ("call", "dl1abc"), ("band", "20M"), ("time_on", "0003"),
+                ("dxcc", "230"), ("cont", "EU"), ("cqz", "14") ],
+            [ ("call", "VE3XX"), ("freq", "7.010"), ("time_on", "0004"),
+                ("dxcc", "1"), ("cont", "NA"), ("cqz", "4") ],
+            [ ("call", "DL1ABC"), ("band", "40m"), ("time_on", "0005"),
+                ("dxcc", "230"), ("cont", "EU"), ("cqz", "14") ],
+            [ ("call", "W6XX"), ("band", "40m"), ("time_on", "0006"),
+                ("dxcc", "291"), ("cont", "NA"), ("cqz", "5") ]
+        ];
+        let mut records : Vec<AdifRecord> = log.iter().map(|fields| {
+            let mut fields = fields.to_vec();
+            fields.extend(mine.iter().cloned());
+            fields.push(("qso_date", "20241026"));
+            qso(&fields)
+        }).collect();
+        records.push(qso(&[ ("call", "JA1XX"), ("contest_id", "NAQP-CW"),
+            ("band", "20m") ]));
+        records.push(qso(&[ ("band", "20m") ]));
+
+        let mut rules = adif_contest_rules("CQ-WW-CW").unwrap();
+        rules.acr_defaults.push((String::from("my_cont"),
+            String::from("NA")));
+        let score = adif_contest_score(&records, &rules);
+
+        let summary : Vec<(usize, AdifContestStatus, u32, usize)> =
+            score.acs_qsos.iter().map(|q| (q.acq_record, q.acq_status,
+            q.acq_points, q.acq_multipliers.len())).collect();
+        assert_eq!(summary, vec![
+            (6, AdifContestStatus::ACS_OTHER_CONTEST, 0, 0),
+            (7, AdifContestStatus::ACS_IGNORED, 0, 0),
+            (1, AdifContestStatus::ACS_SCORED, 0, 2),
+            (0, AdifContestStatus::ACS_SCORED, 3, 2),
+            (2, AdifContestStatus::ACS_DUPE(0), 0, 0),
+            (3, AdifContestStatus::ACS_SCORED, 2, 2),
+            (4, AdifContestStatus::ACS_SCORED, 3, 2),
+            (5, AdifContestStatus::ACS_SCORED, 0, 2)
+        ]);
+        assert_eq!(score.acs_qsos[2].acq_multipliers, vec![
+            (String::from("zone"), String::from("5")),
+            (String::from("country"), String::from("291")) ]);
+        assert_eq!(score.acs_diagnostics.len(), 1);
+        assert_eq!(score.acs_diagnostics[0].adid_record, Some(8));
+
+        assert_eq!(score.acs_multiplier_names, vec![ "zone", "country" ]);
+        let bands : Vec<(&str, usize, usize, u64, Vec<usize>)> =
+            score.acs_bands.iter().map(|b| (b.acbt_band.as_str(),
+            b.acbt_qsos, b.acbt_dupes, b.acbt_points,
+            b.acbt_multipliers.clone())).collect();
+        assert_eq!(bands, vec![
+            ("40M", 3, 0, 5, vec![ 3, 3 ]),
+            ("20M", 3, 1, 3, vec![ 2, 2 ])
+        ]);
+        assert_eq!(score.acs_total.acbt_qsos, 6);
+        assert_eq!(score.acs_total.acbt_points, 8);
+        assert_eq!(score.acs_score, 8 * 10);
+    }
+
+    #[test]
+    fn arrl_dx() {
+        let records = vec![
+            qso(&[ ("call", "K1ABC"), ("band", "20m"), ("dxcc", "291"),
+                ("state", "CT"), ("my_dxcc", "291") ]),
+            qso(&[ ("call", "DL1ABC"), ("band", "20m"), ("dxcc", "230"),
+                ("my_dxcc", "291") ]),
+            qso(&[ ("call", "W1AW"), ("band", "20m"), ("dxcc", "291"),
+                ("state", "CT"), ("my_dxcc", "230") ]),
+            qso(&[ ("call", "F5XX"), ("band", "20m"), ("dxcc", "227"),
+                ("my_dxcc", "230") ])
+        ];
+
+        let rules = adif_contest_rules("arrl-dx-cw").unwrap();
+        let score = adif_contest_score(&records, &rules);
+        let summary : Vec<(u32, &[(String, String)])> = score.acs_qsos.iter()
+            .map(|q| (q.acq_points, q.acq_multipliers.as_slice())).collect();
+        assert_eq!(summary, vec![
+            (0, &[][..]),
+            (3, &[ (String::from("mult"), String::from("230")) ][..]),
+            (3, &[ (String::from("mult"), String::from("CT")) ][..]),
+            (0, &[][..])
+        ]);
+    }
+
+    #[test]
+    fn wpx_and_field_day() {
+        let records = vec![
+            qso(&[ ("call", "K1ABC"), ("mode", "CW"), ("band", "20m") ]),
+            qso(&[ ("call", "K1ABC/4"), ("mode", "SSB"), ("band", "20m") ]),
+            qso(&[ ("call", "K1ABC/4"), ("mode", "USB"), ("band", "20m") ]),
+            qso(&[ ("call", "K1ABC"), ("mode", "FT8"), ("band", "20m") ])
+        ];
+
+        let rules = adif_contest_rules("arrl-fd").unwrap();
+        let score = adif_contest_score(&records, &rules);
+        let points : Vec<u32> = score.acs_qsos.iter().map(
+            |q| q.acq_points).collect();
+        assert_eq!(points, vec![ 2, 1, 0, 2 ]);
+        assert_eq!(score.acs_qsos[2].acq_status,
+            AdifContestStatus::ACS_DUPE(1));
+        assert_eq!(score.acs_score, 5);
+
+        let rules = adif_parse_contest_rules("dupe\npoints 1\n\
+            multiplier prefix wpx").unwrap();
+        let score = adif_contest_score(&records, &rules);
+        assert_eq!(score.acs_total.acbt_dupes, 2);
+        assert_eq!(score.acs_total.acbt_multipliers, vec![ 2 ]);
+        assert_eq!(score.acs_score, 4);
+    }
+}
@@ -0,0 +1,379 @@
+//
+// src/convert.rs: reading records from other formats
+//
+// adif_dump() (see src/dump.rs) writes a file in any of several formats.  This
+// file provides the other direction: adif_read() reads a file in any of the
+// machine-readable formats (ADI, ADX, CSV, TSV, JSON, and JSON Lines) into an
+// AdifFile.  Together, these allow conversion between any two formats.
+//
+// Each reader translates its input into the physical representation of an ADI
+// file and then interprets that just as we would an ADI file, so that field
+// names are canonicalized, header fields are recognized, and duplicate fields
+// are handled according to the same policy regardless of the input format.
+//
+// CSV and TSV files begin with a row of column names.  By default, each column
+// holds the field of the same name, but columns can be mapped to other fields
+// (or ignored) and their values converted as described in src/csvimport.rs.
+// Empty values are treated as absent.  JSON
+// input is an array of objects, one per record, like that written by
+// adif_dump().  JSON Lines input has one such object per line.  Property values
+// may be strings, numbers, booleans (which become "Y" or "N"), null (which is
+// ignored), or arrays of these (which represent a field with multiple values).
+//
+// Not every format can represent everything in an AdifFile.  Only ADI and ADX
+// have a header, only ADI has a preamble, and CSV cannot represent multiple
+// values for a field or distinguish empty values from absent ones.
+// adif_conversion_losses() describes what would be lost by writing a file in a
+// given format.
+//
+
+use std::io;
+
+use adi::AdiDataSpecifier;
+use adi::AdiFile;
+use adi::AdiRecord;
+use adif::AdifFile;
+use adif::AdifParseOptions;
+use adif::adif_data_specifier;
+use adif::adif_parse_adi;
+use adif::adif_valid_field_name;
+use adx::adx_parse;
+use csvimport::AdifCsvMapping;
+use csvimport::adif_csv_records;
+use dump::AdifDumpFormat;
+use json::JsonValue;
+use json::json_parse;
+use super::AdifParseError;
+use super::adif_parse_options;
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifReadOptions {
+    pub ardo_format : AdifDumpFormat,   // input format (text and table are
+                                        // not supported)
+    pub ardo_parse : AdifParseOptions,
+    pub ardo_csv : AdifCsvMapping,      // how to read CSV columns
+}
+
+//
+// Returns the format suggested by a file's extension, if any.
+//
+pub fn adif_format_for_filename(filename: &str) -> Option<AdifDumpFormat>
+{
+    let extension = filename.rsplit_once('.')?.1.to_lowercase();
+    match extension.as_str() {
+        "adi" | "adif" => Some(AdifDumpFormat::ADDF_ADI),
+        "adx" => Some(AdifDumpFormat::ADDF_ADX),
+        "csv" => Some(AdifDumpFormat::ADDF_CSV),
+        "tsv" => Some(AdifDumpFormat::ADDF_TSV),
+        "json" => Some(AdifDumpFormat::ADDF_JSON),
+        "jsonl" | "ndjson" => Some(AdifDumpFormat::ADDF_JSONL),
+        _ => None
+    }
+}
+
+fn adif_format_name(format: AdifDumpFormat) -> &'static str
+{
+    match format {
+        AdifDumpFormat::ADDF_TEXT => "text",
+        AdifDumpFormat::ADDF_TABLE => "table",
+        AdifDumpFormat::ADDF_CSV => "CSV",
+        AdifDumpFormat::ADDF_TSV => "TSV",
+        AdifDumpFormat::ADDF_JSONL => "JSON Lines",
+        AdifDumpFormat::ADDF_JSON => "JSON",
+        AdifDumpFormat::ADDF_ADI => "ADI",
+        AdifDumpFormat::ADDF_ADX => "ADX"
+    }
+}
+
+//
+// Read a file in the format given by "options".
+//
+pub fn adif_read(label: &str, source: &mut dyn io::Read,
+    options: &AdifReadOptions) -> Result<AdifFile, AdifParseError>
+{
+    let format = options.ardo_format;
+    let adi = match format {
+        AdifDumpFormat::ADDF_ADI => {
+            return adif_parse_options(label, source, &options.ardo_parse);
+        },
+        AdifDumpFormat::ADDF_ADX => {
+            return adx_parse(label, source, &options.ardo_parse);
+        },
+        AdifDumpFormat::ADDF_TEXT | AdifDumpFormat::ADDF_TABLE => {
+            return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                "cannot read {} format", adif_format_name(format))));
+        },
+        AdifDumpFormat::ADDF_CSV | AdifDumpFormat::ADDF_TSV => {
+            let sep = if format == AdifDumpFormat::ADDF_CSV { ',' } else {
+                '\t'
+            };
+            let (adi, rows) = adif_csv_records(&adif_read_text(source)?, sep,
+                &options.ardo_csv)?;
+            if let Some((row, problems)) = rows.iter().find(
+                |(_, problems)| !problems.is_empty()) {
+                return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                    "row {}: {}", row, problems[0])));
+            }
+            adi
+        },
+        AdifDumpFormat::ADDF_JSON | AdifDumpFormat::ADDF_JSONL => {
+            adif_read_json(&adif_read_text(source)?,
+                format == AdifDumpFormat::ADDF_JSONL)?
+        }
+    };
+
+    adif_parse_adi(label, &adi, &options.ardo_parse)
+}
+
+//
+// Read a text file, skipping any byte order mark.
+//
+pub fn adif_read_text(source: &mut dyn io::Read) ->
+    Result<String, AdifParseError>
+{
+    let mut bytes = Vec::new();
+    source.read_to_end(&mut bytes)?;
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text.strip_prefix('\u{feff}').map(
+            |t| t.to_string()).unwrap_or(text)),
+        Err(_) => Err(AdifParseError::ADIF_EBADINPUT(String::from(
+            "file is not valid UTF-8")))
+    }
+}
+
+fn adif_read_json(text: &str, lines: bool) -> Result<AdiFile, AdifParseError>
+{
+    let bad = |message: String| AdifParseError::ADIF_EBADINPUT(message);
+    let mut adi = AdiFile { adi_header: None, adi_records: Vec::new() };
+
+    if lines {
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value = json_parse(line).map_err(|error| match error {
+                AdifParseError::ADIF_EBADINPUT(message) => bad(format!(
+                    "line {}: {}", i + 1,
+                    message.trim_start_matches("line 1: "))),
+                error => error
+            })?;
+            adi.adi_records.push(adif_json_record(&value).map_err(
+                |error| bad(format!("line {}: {}", i + 1, error)))?);
+        }
+        return Ok(adi);
+    }
+
+    let values = match json_parse(text)? {
+        JsonValue::JSV_ARRAY(values) => values,
+        _ => return Err(bad(String::from("expected an array of records")))
+    };
+    for (i, value) in values.iter().enumerate() {
+        adi.adi_records.push(adif_json_record(value).map_err(
+            |error| bad(format!("record {}: {}", i + 1, error)))?);
+    }
+    Ok(adi)
+}
+
+fn adif_json_record(value: &JsonValue) -> Result<AdiRecord, String>
+{
+    let properties = match value {
+        JsonValue::JSV_OBJECT(properties) => properties,
+        _ => return Err(String::from("expected an object"))
+    };
+
+    let mut specifiers : Vec<AdiDataSpecifier> = Vec::new();
+    for (name, value) in properties {
+        if !adif_valid_field_name(name) {
+            return Err(format!("invalid field name \"{}\"", name));
+        }
+
+        let values = match value {
+            JsonValue::JSV_ARRAY(values) => values.as_slice(),
+            v => std::slice::from_ref(v)
+        };
+        for v in values {
+            let text = match v {
+                JsonValue::JSV_NULL => continue,
+                JsonValue::JSV_STRING(s) => s.clone(),
+                JsonValue::JSV_BOOL(b) => String::from(if *b { "Y" } else {
+                    "N" }),
+                JsonValue::JSV_NUMBER(n) => n.clone(),
+                _ => return Err(format!("field \"{}\": unsupported value",
+                    name))
+            };
+            specifiers.push(adif_data_specifier(name, &None, &text));
+        }
+    }
+
+    Ok(AdiRecord { adir_fields: specifiers })
+}
+
+//
+// Describe the information in "adf" that can't be represented when it's
+// written in "format".
+//
+pub fn adif_conversion_losses(adf: &AdifFile, format: AdifDumpFormat) ->
+    Vec<String>
+{
+    let mut losses = Vec::new();
+    let name = adif_format_name(format);
+    let header = &adf.adif_header;
+    let nextra = adf.adif_records.iter().filter(
+        |rec| !rec.adir_extra_values.is_empty()).count();
+
+    match format {
+        AdifDumpFormat::ADDF_ADI => (),
+        AdifDumpFormat::ADDF_ADX => {
+            if header.adh_preamble.is_some() {
+                losses.push(String::from("the header preamble cannot be \
+                    represented in ADX"));
+            }
+        },
+        _ => {
+            let nheader = header.adh_fields.len();
+            if header.adh_preamble.is_some() || nheader > 0 {
+                losses.push(format!("the header ({} fields{}) cannot be \
+                    represented in {}", nheader,
+                    if header.adh_preamble.is_some() { " and a preamble" }
+                    else { "" }, name));
+            }
+        }
+    }
+
+    if format == AdifDumpFormat::ADDF_CSV || format == AdifDumpFormat::ADDF_TSV
+    {
+        if nextra > 0 {
+            losses.push(format!("{} records have multiple values for a field, \
+                but only the first can be represented in {}", nextra, name));
+        }
+
+        let nempty = adf.adif_records.iter().filter(
+            |rec| rec.adir_field_values.values().any(|v| v.is_empty()))
+            .count();
+        if nempty > 0 {
+            losses.push(format!("{} records have empty fields, which cannot \
+                be distinguished from absent fields in {}", nempty, name));
+        }
+    }
+
+    losses
+}
+
+#[cfg(test)]
+mod test {
+    use adif_write;
+    use csvimport::adif_parse_csv_mapping;
+    use dump::AdifDumpOptions;
+    use dump::adif_dump;
+    use super::*;
+
+    fn read(format: AdifDumpFormat, input: &str) ->
+        Result<AdifFile, AdifParseError>
+    {
+        let options = AdifReadOptions {
+            ardo_format: format,
+            ..Default::default()
+        };
+        adif_read("test", &mut input.as_bytes(), &options)
+    }
+
+    fn write(adf: &AdifFile, format: AdifDumpFormat) -> String
+    {
+        let options = AdifDumpOptions {
+            admp_format: format,
+            admp_summary: false,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        adif_dump(adf, &options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let adf = read(AdifDumpFormat::ADDF_ADI, "Test\n<ADIF_VER:5>3.1.4\
+            <USERDEF1:10:E>Size,{S,M}<EOH>\n\
+            <CALL:6>KK6ZBI<COMMENT:10>a, \"b\"\nc<SIZE:1>M<EOR>\n\
+            <CALL:4>W1AW<BAND:3>20m<EOR>\n").unwrap();
+
+        for format in &[ AdifDumpFormat::ADDF_ADI, AdifDumpFormat::ADDF_ADX,
+            AdifDumpFormat::ADDF_CSV, AdifDumpFormat::ADDF_TSV,
+            AdifDumpFormat::ADDF_JSON, AdifDumpFormat::ADDF_JSONL ] {
+            let text = write(&adf, *format);
+            let again = read(*format, &text).unwrap();
+            assert_eq!(again.adif_records.len(), 2);
+            for (r1, r2) in adf.adif_records.iter().zip(&again.adif_records) {
+                assert_eq!(r1.adir_field_values, r2.adir_field_values,
+                    "{:?}", format);
+            }
+        }
+
+        assert!(adif_conversion_losses(&adf, AdifDumpFormat::ADDF_ADI)
+            .is_empty());
+        assert_eq!(adif_conversion_losses(&adf, AdifDumpFormat::ADDF_ADX),
+            vec![ "the header preamble cannot be represented in ADX" ]);
+        assert_eq!(adif_conversion_losses(&adf, AdifDumpFormat::ADDF_JSON),
+            vec![ "the header (2 fields and a preamble) cannot be \
+            represented in JSON" ]);
+    }
+
+    #[test]
+    fn csv_columns() {
+        let input = "Callsign,Band,Notes,QSO Date\n\
+            W1AW,20m,,20230102\n\
+            K1JT,40m,hello\n";
+        let options = AdifReadOptions {
+            ardo_format: AdifDumpFormat::ADDF_CSV,
+            ardo_csv: adif_parse_csv_mapping("callsign = call\n\
+                notes =\n\
+                QSO Date = qso_date\n").unwrap(),
+            ..Default::default()
+        };
+        let adf = adif_read("test", &mut input.as_bytes(), &options).unwrap();
+        let mut out = Vec::new();
+        adif_write(&adf, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "ADIF export\n<EOH>\n\
+            <BAND:3>20m <CALL:4>W1AW <QSO_DATE:8>20230102 <EOR>\n\
+            <BAND:3>40m <CALL:4>K1JT <EOR>\n");
+
+        assert!(read(AdifDumpFormat::ADDF_CSV, "QSO Date\n20230102\n")
+            .is_err());
+        assert!(read(AdifDumpFormat::ADDF_CSV, "call\nW1AW,20m\n").is_err());
+    }
+
+    #[test]
+    fn json_values() {
+        let adf = read(AdifDumpFormat::ADDF_JSONL,
+            "{\"call\":\"W1AW\",\"freq\":14.0740,\"swl\":false,\"name\":null}\n\
+            \n{\"CALL\":\"K1JT\",\"cqz\":5,\"srx\":12345678901234567890}\n")
+            .unwrap();
+        let fields = &adf.adif_records[0].adir_field_values;
+        assert_eq!(fields["freq"], "14.0740");
+        assert_eq!(fields["swl"], "N");
+        assert!(!fields.contains_key("name"));
+        assert_eq!(adf.adif_records[1].adir_field_values["cqz"], "5");
+        assert_eq!(adf.adif_records[1].adir_field_values["srx"],
+            "12345678901234567890");
+
+        //
+        // Multiple values are subject to the duplicate field policy.
+        //
+        let input = "[{\"call\":[\"W1AW\",\"W1AW/P\"]}]";
+        assert!(read(AdifDumpFormat::ADDF_JSON, input).is_err());
+        let options = AdifReadOptions {
+            ardo_format: AdifDumpFormat::ADDF_JSON,
+            ardo_parse: AdifParseOptions {
+                apo_duplicates: ::adif::AdifDuplicatePolicy::ADP_KEEP_ALL
+            },
+            ..Default::default()
+        };
+        let adf = adif_read("test", &mut input.as_bytes(), &options).unwrap();
+        assert_eq!(adf.adif_records[0].adir_extra_values["call"],
+            vec![ String::from("W1AW/P") ]);
+
+        assert!(read(AdifDumpFormat::ADDF_JSON, "{\"call\":\"W1AW\"}")
+            .is_err());
+        assert!(read(AdifDumpFormat::ADDF_JSON, "[{\"call\":{}}]").is_err());
+        assert!(read(AdifDumpFormat::ADDF_JSONL, "{\"a b\":\"c\"}").is_err());
+    }
+}
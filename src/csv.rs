@@ -0,0 +1,145 @@
+//
+// src/csv.rs: minimal CSV support
+//
+// Like src/json.rs, this provides just enough to emit and read
+// comma-separated (or tab-separated) values without taking on a dependency.
+// Values are quoted only when necessary.  When reading, quoted values may
+// contain separators, doubled quotes, and newlines, as in RFC 4180.
+//
+
+use super::AdifParseError;
+
+//
+// Returns "value" formatted as one field of a line separated by "sep".
+//
+pub fn csv_quote(value: &str, sep: char) -> String
+{
+    if !value.contains([ sep, '"', '\n', '\r' ]) {
+        return value.to_string();
+    }
+
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+//
+// Returns one line of output (with no trailing newline) for these values.
+//
+pub fn csv_line<S: AsRef<str>>(values: &[S], sep: char) -> String
+{
+    let quoted : Vec<String> = values.iter().map(
+        |v| csv_quote(v.as_ref(), sep)).collect();
+    quoted.join(&sep.to_string())
+}
+
+//
+// Split "text" into rows of values.  Lines may end with "\n" or "\r\n", and
+// blank lines are skipped.  Each row is returned with the 1-based line number
+// on which it starts.
+//
+pub fn csv_parse(text: &str, sep: char) ->
+    Result<Vec<(usize, Vec<String>)>, AdifParseError>
+{
+    let bad = |message: String| AdifParseError::ADIF_EBADINPUT(message);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut value = String::new();
+    let mut quoted = false;         // inside a quoted value
+    let mut was_quoted = false;     // current value was quoted
+    let mut lineno = 1;
+    let mut rowline = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    value.push('"');
+                } else {
+                    quoted = false;
+                }
+            } else {
+                if c == '\n' {
+                    lineno += 1;
+                }
+                value.push(c);
+            }
+            continue;
+        }
+
+        if c == '"' {
+            if !value.is_empty() || was_quoted {
+                return Err(bad(format!("line {}: unexpected quote", lineno)));
+            }
+            quoted = true;
+            was_quoted = true;
+        } else if c == sep {
+            row.push(std::mem::take(&mut value));
+            was_quoted = false;
+        } else if c == '\n' || (c == '\r' && chars.peek() == Some(&'\n')) {
+            if c == '\r' {
+                chars.next();
+            }
+            if !row.is_empty() || !value.is_empty() || was_quoted {
+                row.push(std::mem::take(&mut value));
+                rows.push((rowline, std::mem::take(&mut row)));
+            }
+            was_quoted = false;
+            lineno += 1;
+            rowline = lineno;
+        } else if was_quoted {
+            return Err(bad(format!(
+                "line {}: unexpected text after quoted value", lineno)));
+        } else {
+            value.push(c);
+        }
+    }
+
+    if quoted {
+        return Err(bad(format!("line {}: unterminated quoted value",
+            rowline)));
+    }
+    if !row.is_empty() || !value.is_empty() || was_quoted {
+        row.push(value);
+        rows.push((rowline, row));
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::csv_line;
+    use super::csv_parse;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(csv_line(&[ "W1AW", "20m" ], ','), "W1AW,20m");
+        assert_eq!(csv_line(&[ "a,b", "say \"hi\"", "" ], ','),
+            "\"a,b\",\"say \"\"hi\"\"\",");
+        assert_eq!(csv_line(&[ "a,b", "c\td" ], '\t'), "a,b\t\"c\td\"");
+    }
+
+    #[test]
+    fn parsing() {
+        let rows = csv_parse("call,comment\r\nW1AW,\"a,b\"\n\n\
+            K1JT,\"say \"\"hi\"\"\nthere\"\nN0CALL,\n", ',').unwrap();
+        assert_eq!(rows, vec![
+            (1, vec![ String::from("call"), String::from("comment") ]),
+            (2, vec![ String::from("W1AW"), String::from("a,b") ]),
+            (4, vec![ String::from("K1JT"),
+                String::from("say \"hi\"\nthere") ]),
+            (6, vec![ String::from("N0CALL"), String::new() ])
+        ]);
+
+        let line = csv_line(&[ "a\tb", "c\"d", "" ], '\t');
+        assert_eq!(csv_parse(&line, '\t').unwrap(),
+            vec![ (1, vec![ String::from("a\tb"), String::from("c\"d"),
+            String::new() ]) ]);
+
+        assert_eq!(csv_parse("a,\"b\n", ',').unwrap_err().to_string(),
+            "input error: line 1: unterminated quoted value");
+        assert!(csv_parse("a,b\"c\"\n", ',').is_err());
+        assert!(csv_parse("a,\"b\"c\n", ',').is_err());
+    }
+}
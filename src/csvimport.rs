@@ -0,0 +1,601 @@
+//
+// src/csvimport.rs: importing QSOs from CSV spreadsheets
+//
+// Paper logs are often transcribed into a spreadsheet and exported as CSV.  The
+// columns rarely match ADIF field names or formats, so the import is driven by
+// a mapping (AdifCsvMapping) that says which field each column holds and how
+// to convert its values.  A mapping can be built directly or parsed from text
+// like this:
+//
+//     # comments and blank lines are ignored
+//     Callsign = call
+//     Date = qso_date date %d/%m/%Y
+//     UTC = time_on time %H%M
+//     Freq (kHz) = freq khz
+//     Notes =
+//     default station_callsign = W1AW
+//     default band = 20m
+//     ignore unmapped
+//
+// Each "COLUMN = FIELD [CONVERSION]" line maps a column (named as in the header
+// row, ignoring case) to a field.  An empty FIELD means the column is ignored.
+// The conversions are:
+//
+//     date PATTERN    a date, written in ADIF form (YYYYMMDD)
+//     time PATTERN    a time, written in ADIF form (HHMM, or HHMMSS if the
+//                     pattern includes seconds)
+//     khz             a frequency in kHz, written in MHz
+//
+// Patterns match the value literally except for %Y (four-digit year), %y
+// (two-digit year, 1950-2049), %m (month number), %b (month name or
+// abbreviation), %d (day of month), %H (hour), %M (minute), %S (second), and
+// %% (a literal "%").  Numbers other than years may have one or two digits.
+// Spreadsheets often drop leading zeros, so a time value of fewer than four
+// digits is padded with zeros before it's matched.
+//
+// "default FIELD = VALUE" supplies a value for rows that have no value for
+// FIELD.  Columns that aren't mapped hold the field of the same name unless
+// "ignore unmapped" is given.
+//
+// adif_csv_import() applies a mapping to a CSV file and then validates every
+// record (see src/validate.rs), reporting problems by the row they came from.
+// Rows are identified by the line on which they start, which is the row number
+// in the spreadsheet unless values span lines.  Rows with no values are
+// skipped.
+//
+
+use std::io;
+
+use adi::AdiFile;
+use adi::AdiRecord;
+use adif::AdifFile;
+use adif::AdifParseOptions;
+use adif::adif_data_specifier;
+use adif::adif_header_userdefs;
+use adif::adif_parse_adi;
+use adif::adif_valid_field_name;
+use adiftypes::adif_days_in_month;
+use convert::adif_read_text;
+use csv::csv_parse;
+use validate::adif_validate_record;
+use super::AdifParseError;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdifCsvConversion {
+    ACV_NONE,                   // use the value as-is
+    ACV_DATE(String),           // date matching the pattern
+    ACV_TIME(String),           // time matching the pattern
+    ACV_KHZ                     // frequency in kHz
+}
+
+#[derive(Clone, Debug)]
+pub struct AdifCsvColumn {
+    pub acc_column : String,                // column name in the header row
+    pub acc_field : String,                 // field name ("" to ignore)
+    pub acc_conversion : AdifCsvConversion
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifCsvMapping {
+    pub acm_columns : Vec<AdifCsvColumn>,
+    pub acm_defaults : Vec<(String, String)>,   // field names and values
+    pub acm_ignore_unmapped : bool              // ignore unmapped columns
+}
+
+#[derive(Clone, Debug)]
+pub struct AdifCsvBadRow {
+    pub acb_row : usize,                // line on which the row starts
+    pub acb_record : usize,             // index of the record in the file
+    pub acb_problems : Vec<String>
+}
+
+pub struct AdifCsvImport {
+    pub aci_file : AdifFile,            // one record for each row
+    pub aci_rows : Vec<usize>,          // row from which each record came
+    pub aci_bad_rows : Vec<AdifCsvBadRow>
+}
+
+//
+// Row from which a record came, and problems converting its values.
+//
+type AdifCsvRowResult = (usize, Vec<String>);
+
+static ADIF_MONTHS : &[&str] = &[ "january", "february", "march", "april",
+    "may", "june", "july", "august", "september", "october", "november",
+    "december" ];
+
+//
+// Parse the text form of a mapping described above.
+//
+pub fn adif_parse_csv_mapping(text: &str) ->
+    Result<AdifCsvMapping, AdifParseError>
+{
+    let mut mapping = AdifCsvMapping::default();
+    for (i, line) in text.lines().enumerate() {
+        adif_csv_mapping_line(&mut mapping, line).map_err(
+            |error| AdifParseError::ADIF_EBADINPUT(format!("line {}: {}",
+            i + 1, error)))?;
+    }
+    Ok(mapping)
+}
+
+//
+// Parse one line of a mapping, adding it to "mapping".
+//
+pub fn adif_parse_csv_mapping_line(mapping: &mut AdifCsvMapping, line: &str) ->
+    Result<(), AdifParseError>
+{
+    adif_csv_mapping_line(mapping, line).map_err(AdifParseError::ADIF_EBADINPUT)
+}
+
+fn adif_csv_mapping_line(mapping: &mut AdifCsvMapping, line: &str) ->
+    Result<(), String>
+{
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+    if line.eq_ignore_ascii_case("ignore unmapped") {
+        mapping.acm_ignore_unmapped = true;
+        return Ok(());
+    }
+
+    let (left, right) = match line.split_once('=') {
+        Some((left, right)) => (left.trim(), right.trim()),
+        None => return Err(format!("expected \"COLUMN = FIELD\": {}", line))
+    };
+
+    if let Some(field) = left.strip_prefix("default ") {
+        let field = field.trim();
+        if !adif_valid_field_name(field) {
+            return Err(format!("invalid field name \"{}\"", field));
+        }
+        mapping.acm_defaults.push((field.to_lowercase(), right.to_string()));
+        return Ok(());
+    }
+
+    if left.is_empty() {
+        return Err(String::from("missing column name"));
+    }
+    let (field, conversion) = match right.split_once(char::is_whitespace) {
+        Some((field, conversion)) => (field, conversion.trim()),
+        None => (right, "")
+    };
+    if !field.is_empty() && !adif_valid_field_name(field) {
+        return Err(format!("invalid field name \"{}\"", field));
+    }
+
+    mapping.acm_columns.push(AdifCsvColumn {
+        acc_column: left.to_string(),
+        acc_field: field.to_lowercase(),
+        acc_conversion: adif_parse_csv_conversion(conversion)?
+    });
+    Ok(())
+}
+
+fn adif_parse_csv_conversion(text: &str) -> Result<AdifCsvConversion, String>
+{
+    let (name, pattern) = match text.split_once(char::is_whitespace) {
+        Some((name, pattern)) => (name, pattern.trim()),
+        None => (text, "")
+    };
+
+    match (name.to_lowercase().as_str(), pattern.is_empty()) {
+        ("", _) => Ok(AdifCsvConversion::ACV_NONE),
+        ("khz", true) => Ok(AdifCsvConversion::ACV_KHZ),
+        ("date", false) => {
+            adif_check_pattern(pattern, &[ "Yy", "mb", "d" ], "year, month, \
+                and day")?;
+            Ok(AdifCsvConversion::ACV_DATE(pattern.to_string()))
+        },
+        ("time", false) => {
+            adif_check_pattern(pattern, &[ "H", "M" ], "hour and minute")?;
+            Ok(AdifCsvConversion::ACV_TIME(pattern.to_string()))
+        },
+        _ => Err(format!("unsupported conversion: {}", text))
+    }
+}
+
+//
+// Check that "pattern" uses only known directives and includes one of each
+// group in "required" (described by "what").
+//
+fn adif_check_pattern(pattern: &str, required: &[&str], what: &str) ->
+    Result<(), String>
+{
+    let mut found = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        match chars.next() {
+            Some(d) if "YymbdHMS%".contains(d) => found.push(d),
+            _ => return Err(format!("pattern \"{}\": unsupported directive",
+                pattern))
+        }
+    }
+
+    if required.iter().all(|group| group.chars().any(|d| found.contains(d))) {
+        Ok(())
+    } else {
+        Err(format!("pattern \"{}\" must include the {}", pattern, what))
+    }
+}
+
+//
+// Consume between "min" and "max" leading digits from "text".
+//
+fn adif_take_digits(text: &mut &str, min: usize, max: usize) -> Option<u32>
+{
+    let len = text.chars().take(max).take_while(|c| c.is_ascii_digit())
+        .count();
+    if len < min {
+        return None;
+    }
+    let value = text[..len].parse().ok()?;
+    *text = &text[len..];
+    Some(value)
+}
+
+//
+// Match "value" against "pattern", returning the numbers found for each
+// directive.  Years are returned under 'Y' and months under 'm', however they
+// were written.
+//
+fn adif_match_pattern(pattern: &str, value: &str) -> Option<Vec<(char, u32)>>
+{
+    let mut found = Vec::new();
+    let mut rest = value;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            rest = rest.strip_prefix(c)?;
+            continue;
+        }
+
+        let d = chars.next()?;
+        match d {
+            '%' => rest = rest.strip_prefix('%')?,
+            'Y' => found.push(('Y', adif_take_digits(&mut rest, 4, 4)?)),
+            'y' => {
+                let year = adif_take_digits(&mut rest, 2, 2)?;
+                found.push(('Y', if year < 50 { 2000 + year } else {
+                    1900 + year }));
+            },
+            'b' => {
+                let len = rest.find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(rest.len());
+                let name = rest[..len].to_lowercase();
+                if name.len() < 3 {
+                    return None;
+                }
+                let month = ADIF_MONTHS.iter().position(
+                    |m| m.starts_with(&name))?;
+                found.push(('m', month as u32 + 1));
+                rest = &rest[len..];
+            },
+            _ => found.push((d, adif_take_digits(&mut rest, 1, 2)?))
+        }
+    }
+
+    if rest.is_empty() { Some(found) } else { None }
+}
+
+//
+// Convert a frequency in kHz to MHz.  This is done on the decimal text rather
+// than with floating point so that the digits are preserved exactly.
+//
+pub fn adif_khz_to_mhz(value: &str) -> Option<String>
+{
+    let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+    if (int.is_empty() && frac.is_empty()) ||
+        !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let int = format!("{:0>4}", int);
+    let split = int.len() - 3;
+    let mhz_frac = format!("{}{}", &int[split..], frac);
+    let mhz_frac = mhz_frac.trim_end_matches('0');
+    let mhz_int = match int[..split].trim_start_matches('0') {
+        "" => "0",
+        digits => digits
+    };
+
+    if mhz_frac.is_empty() {
+        Some(mhz_int.to_string())
+    } else {
+        Some(format!("{}.{}", mhz_int, mhz_frac))
+    }
+}
+
+//
+// Convert one value.  On failure, returns a description of the problem.
+//
+fn adif_csv_convert(conversion: &AdifCsvConversion, value: &str) ->
+    Result<String, String>
+{
+    let trimmed = value.trim();
+    let (pattern, fields) = match conversion {
+        AdifCsvConversion::ACV_NONE => return Ok(value.to_string()),
+        AdifCsvConversion::ACV_KHZ => {
+            return adif_khz_to_mhz(trimmed).ok_or_else(
+                || format!("\"{}\" is not a frequency", value));
+        },
+        AdifCsvConversion::ACV_DATE(pattern) => (pattern, "Ymd"),
+        AdifCsvConversion::ACV_TIME(pattern) => (pattern, "HMS")
+    };
+
+    let padded;
+    let mut text = trimmed;
+    if fields == "HMS" && !text.is_empty() && text.len() < 4 &&
+        text.chars().all(|c| c.is_ascii_digit()) {
+        padded = format!("{:0>4}", text);
+        text = &padded;
+    }
+
+    let found = match adif_match_pattern(pattern, text) {
+        Some(found) => found,
+        None => return Err(format!("\"{}\" does not match \"{}\"", value,
+            pattern))
+    };
+    let get = |d: char| found.iter().find(|(f, _)| *f == d).map(|(_, n)| *n);
+
+    //
+    // Mappings parsed from text are checked for the components they need, but
+    // one constructed directly might lack some, so check again here.
+    //
+    let need = |d: char| get(d).ok_or_else(|| format!("pattern \"{}\" has no \
+        \"%{}\" component", pattern, d));
+
+    if fields == "Ymd" {
+        let (year, month, day) = (need('Y')?, need('m')?, need('d')?);
+        if !(1..=12).contains(&month) || day < 1 ||
+            day > adif_days_in_month(year, month) {
+            return Err(format!("\"{}\" is not a valid date", value));
+        }
+        return Ok(format!("{:04}{:02}{:02}", year, month, day));
+    }
+
+    let (hour, minute) = (need('H')?, need('M')?);
+    let second = get('S');
+    if hour > 23 || minute > 59 || second.is_some_and(|s| s > 59) {
+        return Err(format!("\"{}\" is not a valid time", value));
+    }
+    Ok(match second {
+        Some(s) => format!("{:02}{:02}{:02}", hour, minute, s),
+        None => format!("{:02}{:02}", hour, minute)
+    })
+}
+
+//
+// Apply a mapping to the rows of a CSV file.  This produces a physical record
+// for each row that has any values, along with the row it came from and any
+// problems converting its values.  Values that can't be converted are kept
+// as they were.
+//
+pub fn adif_csv_records(text: &str, sep: char, mapping: &AdifCsvMapping) ->
+    Result<(AdiFile, Vec<AdifCsvRowResult>), AdifParseError>
+{
+    let bad = |message: String| AdifParseError::ADIF_EBADINPUT(message);
+    let mut rows = csv_parse(text, sep)?.into_iter();
+    let header = match rows.next() {
+        Some((_, header)) => header,
+        None => return Err(bad(String::from("missing header row")))
+    };
+
+    //
+    // Work out which field each column holds (or None to ignore it).
+    //
+    let mut columns : Vec<Option<(String, &AdifCsvConversion)>> = Vec::new();
+    for name in &header {
+        let name = name.trim();
+        let mapped = mapping.acm_columns.iter().find(
+            |c| c.acc_column.trim().eq_ignore_ascii_case(name));
+        columns.push(match mapped {
+            Some(c) if c.acc_field.is_empty() => None,
+            Some(c) => Some((c.acc_field.clone(), &c.acc_conversion)),
+            None if mapping.acm_ignore_unmapped => None,
+            None if adif_valid_field_name(name) => Some((name.to_string(),
+                &AdifCsvConversion::ACV_NONE)),
+            None => return Err(bad(format!("column \"{}\" is not a valid \
+                field name and must be mapped or ignored", name)))
+        });
+    }
+
+    for c in &mapping.acm_columns {
+        let column = c.acc_column.trim();
+        if !header.iter().any(|h| h.trim().eq_ignore_ascii_case(column)) {
+            return Err(bad(format!("no column named \"{}\"", column)));
+        }
+    }
+
+    let mut adi = AdiFile { adi_header: None, adi_records: Vec::new() };
+    let mut results = Vec::new();
+    for (row, values) in rows {
+        if values.iter().all(|v| v.trim().is_empty()) {
+            continue;
+        }
+
+        let mut problems = Vec::new();
+        if values.iter().skip(columns.len()).any(|v| !v.trim().is_empty()) {
+            problems.push(String::from("unexpected values beyond the last \
+                column"));
+        }
+
+        let mut specifiers = Vec::new();
+        for ((value, column), name) in values.iter().zip(&columns).zip(&header)
+        {
+            let (field, conversion) = match column {
+                Some(c) if !value.is_empty() => c,
+                _ => continue
+            };
+            let converted = adif_csv_convert(conversion, value).unwrap_or_else(
+                |error| {
+                    problems.push(format!("column \"{}\": {}", name.trim(),
+                        error));
+                    value.clone()
+                });
+            specifiers.push(adif_data_specifier(field, &None, &converted));
+        }
+
+        for (field, value) in &mapping.acm_defaults {
+            if !specifiers.iter().any(|s| s.adif_name_canon == *field) {
+                specifiers.push(adif_data_specifier(field, &None, value));
+            }
+        }
+
+        adi.adi_records.push(AdiRecord { adir_fields: specifiers });
+        results.push((row, problems));
+    }
+
+    Ok((adi, results))
+}
+
+//
+// Import a CSV file using "mapping" and validate the records produced.
+//
+pub fn adif_csv_import(label: &str, source: &mut dyn io::Read, sep: char,
+    mapping: &AdifCsvMapping, options: &AdifParseOptions) ->
+    Result<AdifCsvImport, AdifParseError>
+{
+    let text = adif_read_text(source)?;
+    let (adi, rows) = adif_csv_records(&text, sep, mapping)?;
+    let adf = adif_parse_adi(label, &adi, options)?;
+
+    let userdefs = adif_header_userdefs(&adf.adif_header);
+    let mut bad_rows = Vec::new();
+    for (i, rec) in adf.adif_records.iter().enumerate() {
+        let mut problems = rows[i].1.clone();
+        problems.extend(adf.adif_diagnostics.iter().filter(
+            |d| d.adid_record == Some(i + 1)).map(|d| d.adid_message.clone()));
+        problems.extend(adif_validate_record(rec, &userdefs));
+        if !problems.is_empty() {
+            bad_rows.push(AdifCsvBadRow {
+                acb_row: rows[i].0,
+                acb_record: i,
+                acb_problems: problems
+            });
+        }
+    }
+
+    Ok(AdifCsvImport {
+        aci_file: adf,
+        aci_rows: rows.iter().map(|(row, _)| *row).collect(),
+        aci_bad_rows: bad_rows
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let date = |p: &str, v: &str| adif_csv_convert(
+            &AdifCsvConversion::ACV_DATE(p.to_string()), v);
+        let time = |p: &str, v: &str| adif_csv_convert(
+            &AdifCsvConversion::ACV_TIME(p.to_string()), v);
+        let khz = |v: &str| adif_csv_convert(&AdifCsvConversion::ACV_KHZ, v);
+
+        assert_eq!(date("%d/%m/%Y", "1/3/2024").unwrap(), "20240301");
+        assert_eq!(date("%m/%d/%y", " 12/31/99 ").unwrap(), "19991231");
+        assert_eq!(date("%d %b %Y", "7 Sept 2023").unwrap(), "20230907");
+        assert_eq!(date("%Y%m%d", "20240229").unwrap(), "20240229");
+        assert!(date("%d/%m/%Y", "29/2/2023").is_err());
+        assert!(date("%d/%m/%Y", "2023-02-01").is_err());
+        assert!(date("%d %b %Y", "7 Se 2023").is_err());
+        assert_eq!(date("%d", "7").unwrap_err(),
+            "pattern \"%d\" has no \"%Y\" component");
+
+        assert_eq!(time("%H%M", "1430").unwrap(), "1430");
+        assert_eq!(time("%H%M", "930").unwrap(), "0930");
+        assert_eq!(time("%H:%M:%S", "9:05:07").unwrap(), "090507");
+        assert_eq!(time("%H%%%M", "12%34").unwrap(), "1234");
+        assert!(time("%H:%M", "24:00").is_err());
+        assert!(time("%H:%M", "12:00 UTC").is_err());
+        assert!(time("%H", "12").is_err());
+
+        assert_eq!(khz("14074").unwrap(), "14.074");
+        assert_eq!(khz("7074.5").unwrap(), "7.0745");
+        assert_eq!(khz("500").unwrap(), "0.5");
+        assert_eq!(khz("14000").unwrap(), "14");
+        assert_eq!(khz("144174.000").unwrap(), "144.174");
+        assert!(khz("14.074 MHz").is_err());
+        assert!(khz(".").is_err());
+    }
+
+    #[test]
+    fn mapping() {
+        let mapping = adif_parse_csv_mapping("# paper log\n\n\
+            Callsign = call\n\
+            QSO Date = QSO_DATE date %d/%m/%Y\n\
+            Freq = freq khz\n\
+            Notes =\n\
+            default band = 20m\n\
+            ignore unmapped\n").unwrap();
+        assert_eq!(mapping.acm_columns.len(), 4);
+        assert_eq!(mapping.acm_columns[1].acc_column, "QSO Date");
+        assert_eq!(mapping.acm_columns[1].acc_field, "qso_date");
+        assert_eq!(mapping.acm_columns[1].acc_conversion,
+            AdifCsvConversion::ACV_DATE(String::from("%d/%m/%Y")));
+        assert_eq!(mapping.acm_columns[3].acc_field, "");
+        assert_eq!(mapping.acm_defaults, vec![ (String::from("band"),
+            String::from("20m")) ]);
+        assert!(mapping.acm_ignore_unmapped);
+
+        for bad in &[ "call", "= call", "Call = c<a>ll", "Date = qso_date date",
+            "Date = qso_date date %d/%m", "Date = qso_date date %d/%m/%Q",
+            "Time = time_on time %H", "Freq = freq mhz", "default b d = 20m" ] {
+            assert!(adif_parse_csv_mapping(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(adif_parse_csv_mapping("a = b\nc").unwrap_err().to_string(),
+            "input error: line 2: expected \"COLUMN = FIELD\": c");
+    }
+
+    #[test]
+    fn import() {
+        let mapping = adif_parse_csv_mapping("\
+            Callsign = call\n\
+            Date = qso_date date %d/%m/%Y\n\
+            UTC = time_on time %H%M\n\
+            kHz = freq khz\n\
+            default mode = SSB\n\
+            default station_callsign = W1AW\n").unwrap();
+        let input = "Callsign,Date,UTC,kHz,Mode\n\
+            KK6ZBI,1/3/2024,930,14250,\n\
+            ,,,,\n\
+            K1JT,2/3/2024,1015,14074,FT8\n\
+            N0CALL,31/2/2024,1100,7100,\n\
+            W1AW,3/3/2024,1200,14074,CW,extra\n";
+        let result = adif_csv_import("test", &mut input.as_bytes(), ',',
+            &mapping, &AdifParseOptions::default()).unwrap();
+
+        let records = &result.aci_file.adif_records;
+        assert_eq!(records.len(), 4);
+        assert_eq!(result.aci_rows, vec![ 2, 4, 5, 6 ]);
+        assert_eq!(records[0].adir_field_values["qso_date"], "20240301");
+        assert_eq!(records[0].adir_field_values["time_on"], "0930");
+        assert_eq!(records[0].adir_field_values["freq"], "14.25");
+        assert_eq!(records[0].adir_field_values["mode"], "SSB");
+        assert_eq!(records[1].adir_field_values["mode"], "FT8");
+        assert_eq!(records[1].adir_field_values["station_callsign"], "W1AW");
+
+        let bad = &result.aci_bad_rows;
+        assert_eq!(bad.len(), 2);
+        assert_eq!(bad[0].acb_row, 5);
+        assert_eq!(bad[0].acb_record, 2);
+        assert_eq!(bad[0].acb_problems[0], "column \"Date\": \"31/2/2024\" \
+            is not a valid date");
+        assert_eq!(records[2].adir_field_values["qso_date"], "31/2/2024");
+        assert_eq!(bad[1].acb_row, 6);
+        assert_eq!(bad[1].acb_problems, vec![ "unexpected values beyond the \
+            last column" ]);
+
+        let bad_input = "Callsign,Frequency (kHz)\nW1AW,14074\n";
+        assert!(adif_csv_import("test", &mut bad_input.as_bytes(), ',',
+            &mapping, &AdifParseOptions::default()).is_err());
+    }
+}
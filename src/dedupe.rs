@@ -0,0 +1,327 @@
+//
+// src/dedupe.rs: finding and merging duplicate records
+//
+// Logs often accumulate duplicate copies of the same QSO (e.g., from importing
+// the same download twice).  We consider two records to be duplicates if they
+// have the same key (as with matching records across files, by default the
+// "call", "band", and "mode" fields, compared case-insensitively) and their QSO
+// times are within a configurable window.  Records with the same key are
+// clustered in time order: each cluster starts with its earliest record and
+// includes the later records within the window of that one.  (Measuring from
+// the start of the cluster keeps a long run of closely-spaced contacts from
+// being chained together into one cluster.)
+//
+// Each cluster is collapsed into one record.  The records in a cluster are
+// ranked according to an AdifDedupePrecedence, and the highest-ranked record
+// is kept as-is.  Fields that it lacks are filled in from the other records,
+// in rank order.  Fields whose values disagree are reported as conflicts, but
+// the value from the highest-ranked record always wins.
+//
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use adif::AdifRecord;
+use diff::AdifMatchOptions;
+use diff::adif_match_key;
+use fields::adif_typed_equal;
+
+//
+// Fields whose values indicate whether (and how) a QSO has been confirmed.
+// Per the ADIF specification, "Y" means a QSL was received and "V" means it was
+// received and verified (e.g., for an award).
+//
+const ADIF_CONFIRMATION_FIELDS : &[&str] = &[
+    "qsl_rcvd", "lotw_qsl_rcvd", "eqsl_qsl_rcvd"
+];
+
+//
+// Returns the number of ways in which this QSO has been confirmed (i.e., via
+// paper QSL, LoTW, or eQSL).
+//
+pub fn adif_record_confirmations(rec: &AdifRecord) -> usize
+{
+    ADIF_CONFIRMATION_FIELDS.iter().filter(|f| {
+        match rec.adir_field_values.get(**f) {
+            Some(v) => {
+                let v = v.trim();
+                v.eq_ignore_ascii_case("y") || v.eq_ignore_ascii_case("v")
+            },
+            None => false
+        }
+    }).count()
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AdifDedupePrecedence {
+    ADPR_FIRST,         // prefer the record that appears first in the file
+    ADPR_LAST,          // prefer the record that appears last in the file
+    #[default]
+    ADPR_CONFIRMED,     // prefer confirmed records, then the first one
+    ADPR_MOST_FIELDS    // prefer the record with the most fields
+}
+
+pub fn adif_parse_dedupe_precedence(name: &str) -> Option<AdifDedupePrecedence>
+{
+    match name {
+        "first" => Some(AdifDedupePrecedence::ADPR_FIRST),
+        "last" => Some(AdifDedupePrecedence::ADPR_LAST),
+        "confirmed" => Some(AdifDedupePrecedence::ADPR_CONFIRMED),
+        "most-fields" => Some(AdifDedupePrecedence::ADPR_MOST_FIELDS),
+        _ => None
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifDedupeOptions {
+    pub adeo_match : AdifMatchOptions,  // key fields and time window
+    pub adeo_station_callsign : bool,   // if true, station_callsign must match
+    pub adeo_precedence : AdifDedupePrecedence
+}
+
+//
+// AdifDedupeCluster: describes a set of records that were merged into one.
+// Records are identified by their index in the original list.
+//
+#[derive(Clone, Debug)]
+pub struct AdifDedupeCluster {
+    pub adec_output : usize,            // index of the merged record
+    pub adec_kept : usize,              // record whose values took precedence
+    pub adec_dropped : Vec<usize>,      // other records, in precedence order
+    pub adec_filled : Vec<String>,      // fields filled in from other records
+    pub adec_conflicts : Vec<String>    // fields whose values disagreed
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifDedupeResult {
+    pub ader_records : Vec<AdifRecord>,         // deduplicated records
+    pub ader_clusters : Vec<AdifDedupeCluster>  // only clusters of 2 or more
+}
+
+//
+// Rank the records in a cluster, best first.
+//
+fn adif_dedupe_rank(records: &[AdifRecord], cluster: &mut [usize],
+    precedence: AdifDedupePrecedence)
+{
+    cluster.sort_by(|&a, &b| {
+        let ord = match precedence {
+            AdifDedupePrecedence::ADPR_FIRST => Ordering::Equal,
+            AdifDedupePrecedence::ADPR_LAST => b.cmp(&a),
+            AdifDedupePrecedence::ADPR_CONFIRMED =>
+                adif_record_confirmations(&records[b]).cmp(
+                    &adif_record_confirmations(&records[a])),
+            AdifDedupePrecedence::ADPR_MOST_FIELDS =>
+                records[b].adir_field_values.len().cmp(
+                    &records[a].adir_field_values.len())
+        };
+        ord.then(a.cmp(&b))
+    });
+}
+
+//
+// Collapse the (ranked) records of a cluster into one record.
+//
+fn adif_dedupe_merge(records: &[AdifRecord], cluster: &[usize],
+    output: usize) -> (AdifRecord, AdifDedupeCluster)
+{
+    let mut merged = records[cluster[0]].clone();
+    let mut filled = Vec::new();
+    let mut conflicts = BTreeSet::new();
+
+    for &other in &cluster[1..] {
+        let rec = &records[other];
+        for (name, value) in &rec.adir_field_values {
+            match merged.adir_field_values.get(name) {
+                None => {
+                    merged.adir_field_values.insert(name.clone(),
+                        value.clone());
+                    if let Some(extras) = rec.adir_extra_values.get(name) {
+                        merged.adir_extra_values.insert(name.clone(),
+                            extras.clone());
+                    }
+                    filled.push(name.clone());
+                },
+                Some(v) => {
+                    if !adif_typed_equal(name, v, value) {
+                        conflicts.insert(name.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    (merged, AdifDedupeCluster {
+        adec_output: output,
+        adec_kept: cluster[0],
+        adec_dropped: cluster[1..].to_vec(),
+        adec_filled: filled,
+        adec_conflicts: conflicts.into_iter().collect()
+    })
+}
+
+fn adif_dedupe_assign(cluster_of: &mut [usize], members: &[usize])
+{
+    if let Some(&first) = members.iter().min() {
+        for &m in members {
+            cluster_of[m] = first;
+        }
+    }
+}
+
+//
+// Find and merge duplicate records.  See the comment at the top of this file
+// for details.  Merged records appear in the output in the position of the
+// earliest record of their cluster.
+//
+pub fn adif_dedupe(records: &[AdifRecord], options: &AdifDedupeOptions) ->
+    AdifDedupeResult
+{
+    let mut match_options = options.adeo_match.clone();
+    if options.adeo_station_callsign {
+        match_options.amo_key_fields.push(String::from("station_callsign"));
+    }
+
+    let mut groups : HashMap<String, Vec<(usize, Option<i64>)>> =
+        HashMap::new();
+    for (i, rec) in records.iter().enumerate() {
+        let (key, time) = adif_match_key(rec, &match_options);
+        groups.entry(key).or_default().push((i, time));
+    }
+
+    //
+    // Assign each record to a cluster, identified by the index of its earliest
+    // record in the file.
+    //
+    let mut cluster_of = vec![ 0; records.len() ];
+    for entries in groups.values_mut() {
+        entries.sort_by_key(|&(i, time)| (time, i));
+        let mut start : Option<Option<i64>> = None;
+        let mut members : Vec<usize> = Vec::new();
+        for &(i, time) in entries.iter() {
+            let same = match (start, time) {
+                (Some(Some(t0)), Some(t)) =>
+                    t - t0 <= match_options.amo_time_tolerance,
+                (Some(None), None) => true,
+                _ => false
+            };
+
+            if !same {
+                adif_dedupe_assign(&mut cluster_of, &members);
+                members.clear();
+                start = Some(time);
+            }
+            members.push(i);
+        }
+        adif_dedupe_assign(&mut cluster_of, &members);
+    }
+
+    let mut clusters : HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, &c) in cluster_of.iter().enumerate() {
+        clusters.entry(c).or_default().push(i);
+    }
+
+    let mut result = AdifDedupeResult::default();
+    for i in 0..records.len() {
+        let cluster = match clusters.get_mut(&i) {
+            Some(cluster) => cluster,
+            None => continue
+        };
+
+        let output = result.ader_records.len();
+        if cluster.len() == 1 {
+            result.ader_records.push(records[i].clone());
+            continue;
+        }
+
+        adif_dedupe_rank(records, cluster, options.adeo_precedence);
+        let (merged, info) = adif_dedupe_merge(records, cluster, output);
+        result.ader_records.push(merged);
+        result.ader_clusters.push(info);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifRecord;
+
+    fn make_record(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    fn qso(call: &str, time: &str, extra: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = make_record(&[ ("call", call), ("qso_date", "20180623"),
+            ("time_on", time), ("band", "20m"), ("mode", "FT8") ]);
+        for (name, value) in extra {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    #[test]
+    fn dedupe_clusters() {
+        let records = vec![
+            qso("W1AW", "1800", &[ ("name", "Hiram") ]),
+            qso("K1ABC", "1801", &[]),
+            qso("w1aw", "180130", &[ ("lotw_qsl_rcvd", "Y"),
+                ("name", "H") ]),
+            qso("W1AW", "1900", &[]),
+            qso("W1AW", "1804", &[ ("qth", "CT") ]),
+            qso("W1AW", "1806", &[]),
+        ];
+
+        let options = super::AdifDedupeOptions::default();
+        let result = super::adif_dedupe(&records, &options);
+        assert_eq!(result.ader_records.len(), 4);
+        assert_eq!(result.ader_clusters.len(), 1);
+
+        let cluster = &result.ader_clusters[0];
+        assert_eq!(cluster.adec_output, 0);
+        assert_eq!(cluster.adec_kept, 2);
+        assert_eq!(cluster.adec_dropped, vec![ 0, 4 ]);
+        assert_eq!(cluster.adec_filled, vec![ "qth" ]);
+        assert_eq!(cluster.adec_conflicts, vec![ "call", "name", "time_on" ]);
+
+        let merged = &result.ader_records[0];
+        assert_eq!(merged.adir_field_values["name"], "H");
+        assert_eq!(merged.adir_field_values["qth"], "CT");
+        assert_eq!(result.ader_records[1].adir_field_values["call"], "K1ABC");
+        assert_eq!(result.ader_records[2].adir_field_values["time_on"],
+            "1900");
+        assert_eq!(result.ader_records[3].adir_field_values["time_on"],
+            "1806");
+
+        let options = super::AdifDedupeOptions {
+            adeo_precedence: super::AdifDedupePrecedence::ADPR_FIRST,
+            ..Default::default()
+        };
+        let result = super::adif_dedupe(&records, &options);
+        assert_eq!(result.ader_records[0].adir_field_values["name"], "Hiram");
+    }
+
+    #[test]
+    fn dedupe_station_callsign() {
+        let records = vec![
+            qso("W1AW", "1800", &[ ("station_callsign", "KK6ZBI") ]),
+            qso("W1AW", "1800", &[ ("station_callsign", "N0CALL") ]),
+        ];
+
+        let options = super::AdifDedupeOptions::default();
+        assert_eq!(super::adif_dedupe(&records, &options).ader_records.len(),
+            1);
+        let options = super::AdifDedupeOptions {
+            adeo_station_callsign: true,
+            ..Default::default()
+        };
+        assert_eq!(super::adif_dedupe(&records, &options).ader_records.len(),
+            2);
+    }
+}
@@ -0,0 +1,500 @@
+//
+// src/diff.rs: matching up and comparing records in two ADIF files
+//
+// Two logs of the same contacts rarely agree exactly.  One program may record
+// the time the QSO started and another the time it was logged, one may fill in
+// a band where the other only has a frequency, and so on.  The facilities here
+// identify which records in one file correspond to which records in another,
+// and then which fields of corresponding records differ.
+//
+// A record's "key" is built from a configurable set of fields (by default,
+// "call", "band", and "mode"), compared case-insensitively.  Two records can
+// only match if their keys are identical.  Among records with the same key, we
+// additionally require that the QSO times (from "qso_date" and "time_on") are
+// within a configurable tolerance of each other.  Matching is one-to-one: each
+// record is matched with at most one record in the other file, and we prefer
+// the closest pairs in time.
+//
+// Once records are matched, adif_diff_records() compares their fields.  By
+// default, all fields are compared according to their ADIF data type (see
+// src/fields.rs), but callers can restrict the set of fields and choose how
+// each field is compared.
+//
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use adif::AdifRecord;
+use fields::adif_typed_equal;
+use adiftypes::AdifTimestamp;
+use adiftypes::adif_parse_date;
+use adiftypes::adif_parse_time;
+use adiftypes::adif_timestamp_to_seconds;
+
+//
+// AdifMatchOptions: describes how to decide whether two records represent the
+// same QSO.
+//
+#[derive(Clone, Debug)]
+pub struct AdifMatchOptions {
+    pub amo_key_fields : Vec<String>,   // fields that must be equal
+    pub amo_use_time : bool,            // if true, QSO times must be close
+    pub amo_time_tolerance : i64        // max difference in times (seconds)
+}
+
+impl Default for AdifMatchOptions {
+    fn default() -> AdifMatchOptions {
+        AdifMatchOptions {
+            amo_key_fields: vec![ String::from("call"), String::from("band"),
+                String::from("mode") ],
+            amo_use_time: true,
+            amo_time_tolerance: 5 * 60
+        }
+    }
+}
+
+//
+// AdifMatchResult: describes how the records in two lists correspond.  Each
+// record appears in exactly one of these lists.  Records are identified by
+// their index in the original list.
+//
+#[derive(Clone, Debug, Default)]
+pub struct AdifMatchResult {
+    pub amr_matched : Vec<(usize, usize)>,  // (left index, right index)
+    pub amr_left_only : Vec<usize>,         // left records with no match
+    pub amr_right_only : Vec<usize>         // right records with no match
+}
+
+//
+// Returns the start time of the QSO described by this record, if the record
+// has a valid "qso_date" and "time_on".
+//
+pub fn adif_record_timestamp(rec: &AdifRecord) -> Option<AdifTimestamp>
+{
+    let date = adif_parse_date(rec.adir_field_values.get("qso_date")?)?;
+    let time = adif_parse_time(rec.adir_field_values.get("time_on")?)?;
+    Some(AdifTimestamp { adts_date: date, adts_time: time })
+}
+
+//
+// Returns a short, human-readable summary of a QSO, suitable for identifying
+// the record in a report (e.g., "20181129 2359 KK6ZBI 20M SSB").
+//
+pub fn adif_qso_summary(rec: &AdifRecord) -> String
+{
+    let fields = [ "qso_date", "time_on", "call", "band", "mode" ];
+    let values : Vec<&str> = fields.iter().filter_map(
+        |f| rec.adir_field_values.get(*f)).map(|v| v.as_str())
+        .filter(|v| !v.is_empty()).collect();
+    if values.is_empty() {
+        String::from("(empty record)")
+    } else {
+        values.join(" ")
+    }
+}
+
+//
+// Compute the key used to decide which records may match.  Records whose times
+// can't be parsed still need to be matched somehow, so in that case we add the
+// raw date and time to the key (so that they must match exactly) and return no
+// time.
+//
+pub fn adif_match_key(rec: &AdifRecord, options: &AdifMatchOptions) ->
+    (String, Option<i64>)
+{
+    let mut parts : Vec<String> = options.amo_key_fields.iter().map(|f| {
+        match rec.adir_field_values.get(f) {
+            Some(v) => v.trim().to_uppercase(),
+            None => String::new()
+        }
+    }).collect();
+
+    if !options.amo_use_time {
+        return (parts.join("\u{1f}"), None);
+    }
+
+    match adif_record_timestamp(rec) {
+        Some(ts) => {
+            (parts.join("\u{1f}"), Some(adif_timestamp_to_seconds(&ts)))
+        },
+        None => {
+            for f in &[ "qso_date", "time_on" ] {
+                parts.push(rec.adir_field_values.get(*f).cloned()
+                    .unwrap_or_default());
+            }
+            (parts.join("\u{1f}"), None)
+        }
+    }
+}
+
+//
+// An entry in the match index: a record's index and QSO time (in seconds).
+//
+type AdifMatchEntry = (usize, Option<i64>);
+
+//
+// Match up the records in "left" with those in "right".  See the comment at the
+// top of this file for details.
+//
+pub fn adif_match_records(left: &[AdifRecord], right: &[AdifRecord],
+    options: &AdifMatchOptions) -> AdifMatchResult
+{
+    //
+    // Group records from both lists by key.  Only records in the same group
+    // can possibly match, so this avoids comparing every pair of records.
+    //
+    let mut groups : HashMap<String, (Vec<AdifMatchEntry>,
+        Vec<AdifMatchEntry>)> = HashMap::new();
+    for (i, rec) in left.iter().enumerate() {
+        let (key, time) = adif_match_key(rec, options);
+        groups.entry(key).or_default().0.push((i, time));
+    }
+    for (j, rec) in right.iter().enumerate() {
+        let (key, time) = adif_match_key(rec, options);
+        groups.entry(key).or_default().1.push((j, time));
+    }
+
+    let mut left_matched = vec![ None; left.len() ];
+    let mut right_matched = vec![ false; right.len() ];
+
+    for (lefts, rights) in groups.values() {
+        if lefts.is_empty() || rights.is_empty() {
+            continue;
+        }
+
+        //
+        // Records without a usable time (or all records, if we're not using
+        // times) can't be compared by time.  Their keys are otherwise
+        // identical, so we simply pair them up in file order.
+        //
+        let untimed = |entries: &Vec<AdifMatchEntry>| -> Vec<usize> {
+            entries.iter().filter(|e| e.1.is_none()).map(|e| e.0).collect()
+        };
+        for (i, j) in untimed(lefts).into_iter().zip(untimed(rights)) {
+            left_matched[i] = Some(j);
+            right_matched[j] = true;
+        }
+
+        //
+        // For the rest, sort both sides by time and sweep through the left
+        // records, keeping a window of right records that are close enough to
+        // the current one.  Only pairs within the window are candidates, and we
+        // assign matches greedily, closest first.  Ties are broken by file
+        // order so that results are deterministic.
+        //
+        let timed = |entries: &Vec<AdifMatchEntry>| -> Vec<(i64, usize)> {
+            let mut rv : Vec<(i64, usize)> = entries.iter().filter_map(
+                |&(i, t)| t.map(|t| (t, i))).collect();
+            rv.sort();
+            rv
+        };
+        let lefts = timed(lefts);
+        let rights = timed(rights);
+        let tolerance = options.amo_time_tolerance;
+
+        let mut candidates = Vec::new();
+        let mut start = 0;
+        for &(t1, i) in &lefts {
+            while start < rights.len() && rights[start].0 < t1 - tolerance {
+                start += 1;
+            }
+            for &(t2, j) in rights[start..].iter().take_while(
+                |&&(t2, _)| t2 <= t1 + tolerance) {
+                candidates.push(((t1 - t2).abs(), i, j));
+            }
+        }
+
+        candidates.sort();
+        for (_, i, j) in candidates {
+            if left_matched[i].is_none() && !right_matched[j] {
+                left_matched[i] = Some(j);
+                right_matched[j] = true;
+            }
+        }
+    }
+
+    let mut result = AdifMatchResult::default();
+    for (i, m) in left_matched.iter().enumerate() {
+        match m {
+            Some(j) => result.amr_matched.push((i, *j)),
+            None => result.amr_left_only.push(i)
+        }
+    }
+    for (j, m) in right_matched.iter().enumerate() {
+        if !m {
+            result.amr_right_only.push(j);
+        }
+    }
+
+    result
+}
+
+//
+// AdifCompareMode: describes how to decide whether two values of a field are
+// the same.  When "acm_typed" is set, values are compared according to the
+// field's ADIF data type (e.g., numerically for "freq") after applying the
+// other options.
+//
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdifCompareMode {
+    pub acm_ignore_case : bool,     // compare case-insensitively
+    pub acm_ignore_space : bool,    // ignore leading, trailing, and repeated
+                                    // whitespace
+    pub acm_typed : bool            // compare according to the field's type
+}
+
+impl Default for AdifCompareMode {
+    fn default() -> AdifCompareMode {
+        AdifCompareMode {
+            acm_ignore_case: false,
+            acm_ignore_space: false,
+            acm_typed: true
+        }
+    }
+}
+
+//
+// Parse a comparison mode specified as a comma-separated list of keywords (as
+// a user would specify it on the command line):
+//
+//     exact       compare values exactly
+//     typed       compare according to the field's data type
+//     nocase      compare case-insensitively
+//     nospace     ignore differences in whitespace
+//
+pub fn adif_parse_compare_mode(spec: &str) -> Option<AdifCompareMode>
+{
+    let mut mode = AdifCompareMode {
+        acm_ignore_case: false,
+        acm_ignore_space: false,
+        acm_typed: false
+    };
+
+    for word in spec.split(',') {
+        match word.trim() {
+            "exact" => (),
+            "typed" => mode.acm_typed = true,
+            "nocase" => mode.acm_ignore_case = true,
+            "nospace" => mode.acm_ignore_space = true,
+            _ => return None
+        }
+    }
+
+    Some(mode)
+}
+
+//
+// AdifDiffOptions: describes which fields of matched records to compare and how
+// to compare them.
+//
+#[derive(Clone, Debug, Default)]
+pub struct AdifDiffOptions {
+    pub ado_fields : Option<Vec<String>>,   // if set, compare only these
+    pub ado_ignore_fields : Vec<String>,    // never compare these
+    pub ado_mode : AdifCompareMode,         // default comparison mode
+    pub ado_field_modes : BTreeMap<String, AdifCompareMode>  // per-field modes
+}
+
+//
+// AdifFieldDiff: describes a field whose value differs between two records.
+// A value of None means that the field is absent from that record.
+//
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifFieldDiff {
+    pub afd_field : String,             // name of the field
+    pub afd_left : Option<String>,      // value in the left record
+    pub afd_right : Option<String>      // value in the right record
+}
+
+//
+// Returns true if the two values of the named field should be considered the
+// same under the given comparison mode.
+//
+pub fn adif_values_equal(field: &str, v1: &str, v2: &str,
+    mode: &AdifCompareMode) -> bool
+{
+    let mut s1 = v1.to_string();
+    let mut s2 = v2.to_string();
+
+    if mode.acm_ignore_space {
+        s1 = s1.split_whitespace().collect::<Vec<&str>>().join(" ");
+        s2 = s2.split_whitespace().collect::<Vec<&str>>().join(" ");
+    }
+
+    if mode.acm_ignore_case {
+        s1 = s1.to_lowercase();
+        s2 = s2.to_lowercase();
+    }
+
+    if mode.acm_typed {
+        adif_typed_equal(field, &s1, &s2)
+    } else {
+        s1 == s2
+    }
+}
+
+//
+// Compare the fields of two records.  Returns the list of fields that differ,
+// sorted by field name.  A field that's explicitly empty in one record and
+// absent from the other is considered different.
+//
+pub fn adif_diff_records(left: &AdifRecord, right: &AdifRecord,
+    options: &AdifDiffOptions) -> Vec<AdifFieldDiff>
+{
+    let names : BTreeSet<&String> = match options.ado_fields {
+        Some(ref fields) => fields.iter().collect(),
+        None => left.adir_field_values.keys().chain(
+            right.adir_field_values.keys()).collect()
+    };
+
+    let mut diffs = Vec::new();
+    for name in names {
+        if options.ado_ignore_fields.contains(name) {
+            continue;
+        }
+
+        let v1 = left.adir_field_values.get(name);
+        let v2 = right.adir_field_values.get(name);
+        let mode = options.ado_field_modes.get(name).unwrap_or(
+            &options.ado_mode);
+        let same = match (v1, v2) {
+            (None, None) => true,
+            (Some(a), Some(b)) => adif_values_equal(name, a, b, mode),
+            _ => false
+        };
+
+        if !same {
+            diffs.push(AdifFieldDiff {
+                afd_field: name.clone(),
+                afd_left: v1.cloned(),
+                afd_right: v2.cloned()
+            });
+        }
+    }
+
+    diffs
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifRecord;
+
+    fn make_record(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    fn make_qso(call: &str, date: &str, time: &str) -> AdifRecord {
+        make_record(&[ ("call", call), ("qso_date", date), ("time_on", time),
+            ("band", "20m"), ("mode", "SSB") ])
+    }
+
+    #[test]
+    fn match_with_tolerance() {
+        let left = vec![
+            make_qso("KK6ZBI", "20181129", "2358"),
+            make_qso("W1AW", "20181130", "0100"),
+            make_qso("W1AW", "20181130", "0200"),
+            make_qso("N0CALL", "20181130", "0300"),
+        ];
+        let right = vec![
+            make_qso("w1aw", "20181130", "020130"),
+            make_qso("kk6zbi", "20181130", "0001"),
+            make_qso("W1AW", "20181130", "0130"),
+            make_qso("K1ABC", "20181130", "0300"),
+        ];
+
+        let options = super::AdifMatchOptions::default();
+        let result = super::adif_match_records(&left, &right, &options);
+        assert_eq!(result.amr_matched, vec![ (0, 1), (2, 0) ]);
+        assert_eq!(result.amr_left_only, vec![ 1, 3 ]);
+        assert_eq!(result.amr_right_only, vec![ 2, 3 ]);
+    }
+
+    #[test]
+    fn match_one_to_one() {
+        let left = vec![
+            make_qso("W1AW", "20181130", "0100"),
+            make_qso("W1AW", "20181130", "0102"),
+        ];
+        let right = vec![
+            make_qso("W1AW", "20181130", "0101"),
+            make_qso("W1AW", "20181130", "0103"),
+            make_qso("W1AW", "20181130", "0104"),
+        ];
+
+        let options = super::AdifMatchOptions::default();
+        let result = super::adif_match_records(&left, &right, &options);
+        assert_eq!(result.amr_matched.len(), 2);
+        assert_eq!(result.amr_left_only.len(), 0);
+        assert_eq!(result.amr_right_only.len(), 1);
+
+        let mut notime = left[0].clone();
+        notime.adir_field_values.remove("time_on");
+        let result = super::adif_match_records(&[ notime.clone() ], &right,
+            &options);
+        assert_eq!(result.amr_matched.len(), 0);
+
+        let result = super::adif_match_records(&[ notime.clone() ],
+            &[ right[0].clone(), notime.clone(), notime ], &options);
+        assert_eq!(result.amr_matched, vec![ (0, 1) ]);
+        assert_eq!(result.amr_right_only, vec![ 0, 2 ]);
+    }
+
+    #[test]
+    fn match_large_group() {
+        //
+        // Many records with the same key, spread out in time, should only be
+        // compared with their neighbors.
+        //
+        let make = |offset: usize| -> Vec<AdifRecord> {
+            (0..20000).map(|n| {
+                let minutes = n * 10 + offset;
+                let days = minutes / (60 * 24);
+                let date = format!("2018{:02}{:02}", 1 + days / 28,
+                    1 + days % 28);
+                let time = format!("{:02}{:02}", (minutes / 60) % 24,
+                    minutes % 60);
+                make_qso("W1AW", &date, &time)
+            }).collect()
+        };
+        let left = make(0);
+        let right = make(2);
+
+        let options = super::AdifMatchOptions::default();
+        let result = super::adif_match_records(&left, &right, &options);
+        assert_eq!(result.amr_matched.len(), 20000);
+        assert!(result.amr_matched.iter().all(|(i, j)| i == j));
+    }
+
+    #[test]
+    fn diff_fields() {
+        let left = make_record(&[ ("call", "W1AW"), ("freq", "14.074"),
+            ("name", "Hiram  Maxim"), ("comment", ""), ("qth", "CT") ]);
+        let right = make_record(&[ ("call", "W1AW"), ("freq", "14.07400"),
+            ("name", "hiram maxim"), ("rst_rcvd", "59") ]);
+
+        let mut options = super::AdifDiffOptions::default();
+        let diffs = super::adif_diff_records(&left, &right, &options);
+        let names : Vec<&str> = diffs.iter().map(|d| d.afd_field.as_str())
+            .collect();
+        assert_eq!(names, vec![ "comment", "name", "qth", "rst_rcvd" ]);
+        assert_eq!(diffs[0].afd_left, Some(String::from("")));
+        assert_eq!(diffs[0].afd_right, None);
+
+        options.ado_field_modes.insert(String::from("name"),
+            super::adif_parse_compare_mode("nocase,nospace").unwrap());
+        options.ado_ignore_fields.push(String::from("qth"));
+        let diffs = super::adif_diff_records(&left, &right, &options);
+        assert_eq!(diffs.len(), 2);
+
+        options.ado_fields = Some(vec![ String::from("freq") ]);
+        assert!(super::adif_diff_records(&left, &right, &options).is_empty());
+        options.ado_mode = super::adif_parse_compare_mode("exact").unwrap();
+        assert_eq!(super::adif_diff_records(&left, &right, &options).len(), 1);
+    }
+}
@@ -0,0 +1,364 @@
+//
+// src/dump.rs: printing the contents of ADIF files
+//
+// adif_dump() prints the records of a file in one of several formats:
+//
+//     text    a summary of the file followed by each record, one field per line
+//     table   the selected fields, one record per line, in aligned columns
+//     csv     the selected fields as comma-separated values
+//     tsv     the selected fields as tab-separated values
+//     jsonl   one JSON object per record, one per line
+//     json    a JSON array of records
+//     adi     an ADI file (including the original header)
+//     adx     an ADX file (see src/adx.rs)
+//
+// The columnar formats (table, csv, and tsv) begin with a row of field names.
+// If no fields are given, they use every field that appears in any printed
+// record.  For the other formats, a list of fields restricts which fields are
+// printed.  The file summary is only printed for the human-readable formats
+// (text and table).
+//
+// Records are printed in the order given by the sort keys (see src/sort.rs),
+// or in file order if there are none.  When grouping by one or more fields,
+// the records for each group are printed together, and the human-readable
+// formats print a heading for each group.
+//
+
+use std::io;
+
+use adif::AdifFile;
+use adif::AdifRecord;
+use adx::adx_write;
+use csv::csv_line;
+use filter::AdifFilter;
+use filter::adif_filter_matches;
+use json::JsonValue;
+use json::json_record;
+use sort::AdifRecordGroup;
+use sort::AdifSortKey;
+use sort::adif_group_records;
+use sort::adif_record_compare;
+use super::AdifParseError;
+use super::adif_write;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AdifDumpWhichRecords {
+    ADR_NONE,
+    ADR_ONE,
+    #[default]
+    ADR_ALL
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AdifDumpFormat {
+    #[default]
+    ADDF_TEXT,
+    ADDF_TABLE,
+    ADDF_CSV,
+    ADDF_TSV,
+    ADDF_JSONL,
+    ADDF_JSON,
+    ADDF_ADI,
+    ADDF_ADX
+}
+
+pub fn adif_parse_dump_format(name: &str) -> Option<AdifDumpFormat>
+{
+    match name {
+        "text" => Some(AdifDumpFormat::ADDF_TEXT),
+        "table" => Some(AdifDumpFormat::ADDF_TABLE),
+        "csv" => Some(AdifDumpFormat::ADDF_CSV),
+        "tsv" => Some(AdifDumpFormat::ADDF_TSV),
+        "jsonl" => Some(AdifDumpFormat::ADDF_JSONL),
+        "json" => Some(AdifDumpFormat::ADDF_JSON),
+        "adi" => Some(AdifDumpFormat::ADDF_ADI),
+        "adx" => Some(AdifDumpFormat::ADDF_ADX),
+        _ => None
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AdifDumpOptions {
+    pub admp_format : AdifDumpFormat,
+    pub admp_which : AdifDumpWhichRecords,
+    pub admp_filter : Option<AdifFilter>,   // only print matching records
+    pub admp_fields : Option<Vec<String>>,  // fields to print
+    pub admp_sort : Vec<AdifSortKey>,       // order of records
+    pub admp_group_by : Vec<String>,        // fields to group records by
+    pub admp_summary : bool                 // print the file summary
+}
+
+impl Default for AdifDumpOptions {
+    fn default() -> AdifDumpOptions {
+        AdifDumpOptions {
+            admp_format: AdifDumpFormat::default(),
+            admp_which: AdifDumpWhichRecords::default(),
+            admp_filter: None,
+            admp_fields: None,
+            admp_sort: Vec::new(),
+            admp_group_by: Vec::new(),
+            admp_summary: true
+        }
+    }
+}
+
+//
+// Returns a copy of "rec" containing only the given fields.
+//
+fn adif_dump_select(rec: &AdifRecord, fields: &Option<Vec<String>>) ->
+    AdifRecord
+{
+    let fields = match fields {
+        None => return rec.clone(),
+        Some(f) => f
+    };
+
+    let mut selected = AdifRecord::default();
+    for field in fields {
+        if let Some(value) = rec.adir_field_values.get(field) {
+            selected.adir_field_values.insert(field.clone(), value.clone());
+        }
+        if let Some(extras) = rec.adir_extra_values.get(field) {
+            selected.adir_extra_values.insert(field.clone(), extras.clone());
+        }
+    }
+    selected
+}
+
+//
+// Returns the heading printed before a group of records.
+//
+fn adif_dump_heading(fields: &[String], group: &AdifRecordGroup) -> String
+{
+    let values : Vec<String> = fields.iter().zip(group.argp_key.iter()).map(
+        |(f, v)| format!("{} {}", f, if v.is_empty() { "(none)" } else { v }))
+        .collect();
+    format!("{} ({} record{})", values.join(", "), group.argp_records.len(),
+        if group.argp_records.len() == 1 { "" } else { "s" })
+}
+
+//
+// Print the records of "adif" to "out" as described by "options".
+//
+pub fn adif_dump(adif: &AdifFile, options: &AdifDumpOptions,
+    out: &mut dyn io::Write) -> Result<(), AdifParseError>
+{
+    let limit = match options.admp_which {
+        AdifDumpWhichRecords::ADR_NONE => 0,
+        AdifDumpWhichRecords::ADR_ONE => 1,
+        AdifDumpWhichRecords::ADR_ALL => adif.adif_records.len()
+    };
+    let mut matched : Vec<&AdifRecord> = adif.adif_records.iter().filter(
+        |rec| match &options.admp_filter {
+            None => true,
+            Some(f) => adif_filter_matches(f, rec)
+        }).collect();
+    matched.sort_by(|r1, r2| adif_record_compare(r1, r2, &options.admp_sort));
+    matched.truncate(limit);
+
+    //
+    // Without any grouping fields, all records form a single group.  Groups
+    // only get headings in the human-readable formats.  Otherwise, grouping
+    // just puts each group's records together.
+    //
+    let group_by = &options.admp_group_by;
+    let groups : Vec<(Option<String>, Vec<AdifRecord>)> =
+        adif_group_records(matched, group_by).iter().map(|g| {
+        let heading = if group_by.is_empty() { None } else {
+            Some(adif_dump_heading(group_by, g))
+        };
+        (heading, g.argp_records.iter().map(
+            |rec| adif_dump_select(rec, &options.admp_fields)).collect())
+    }).collect();
+    let records : Vec<&AdifRecord> = groups.iter().flat_map(
+        |(_, recs)| recs.iter()).collect();
+
+    //
+    // The columnar formats need a fixed set of columns.
+    //
+    let columns : Vec<String> = match &options.admp_fields {
+        Some(fields) => fields.clone(),
+        None => {
+            let mut all : Vec<String> = records.iter().flat_map(
+                |rec| rec.adir_field_values.keys().cloned()).collect();
+            all.sort();
+            all.dedup();
+            all
+        }
+    };
+    let row = |rec: &AdifRecord, absent: &str| -> Vec<String> {
+        columns.iter().map(|c| match rec.adir_field_values.get(c) {
+            Some(v) => v.clone(),
+            None => absent.to_string()
+        }).collect()
+    };
+
+    let format = options.admp_format;
+    if options.admp_summary && (format == AdifDumpFormat::ADDF_TEXT ||
+        format == AdifDumpFormat::ADDF_TABLE) {
+        write!(out, "{:?}", adif)?;
+        if format == AdifDumpFormat::ADDF_TABLE {
+            writeln!(out)?;
+        }
+    }
+
+    match format {
+        AdifDumpFormat::ADDF_TEXT => {
+            if options.admp_which == AdifDumpWhichRecords::ADR_ONE {
+                writeln!(out, "Example record:")?;
+            }
+            for (heading, recs) in &groups {
+                if let Some(h) = heading {
+                    write!(out, "{}\n\n", h)?;
+                }
+                for rec in recs {
+                    write!(out, "{:?}\n\n", rec)?;
+                }
+            }
+        },
+        AdifDumpFormat::ADDF_TABLE => {
+            //
+            // Use the same column widths for every group so that the groups
+            // line up with each other.
+            //
+            let header : Vec<String> = columns.iter().map(
+                |c| c.to_uppercase()).collect();
+            let rows : Vec<Vec<Vec<String>>> = groups.iter().map(
+                |(_, recs)| recs.iter().map(|rec| row(rec, "-")).collect())
+                .collect();
+            let widths : Vec<usize> = (0..columns.len()).map(|i| {
+                rows.iter().flatten().chain(std::iter::once(&header)).map(
+                    |r| r[i].chars().count()).max().unwrap_or(0)
+            }).collect();
+            let line = |r: &Vec<String>| {
+                let cells : Vec<String> = r.iter().zip(widths.iter()).map(
+                    |(v, w)| format!("{:w$}", v, w = w)).collect();
+                cells.join("  ").trim_end().to_string()
+            };
+            for (i, ((heading, _), group_rows)) in
+                groups.iter().zip(rows.iter()).enumerate() {
+                if let Some(h) = heading {
+                    if i > 0 {
+                        writeln!(out)?;
+                    }
+                    writeln!(out, "{}", h)?;
+                }
+                writeln!(out, "{}", line(&header))?;
+                for r in group_rows {
+                    writeln!(out, "{}", line(r))?;
+                }
+            }
+        },
+        AdifDumpFormat::ADDF_CSV | AdifDumpFormat::ADDF_TSV => {
+            let sep = if format == AdifDumpFormat::ADDF_CSV { ',' } else {
+                '\t'
+            };
+            writeln!(out, "{}", csv_line(&columns, sep))?;
+            for rec in &records {
+                writeln!(out, "{}", csv_line(&row(rec, ""), sep))?;
+            }
+        },
+        AdifDumpFormat::ADDF_JSONL => {
+            for rec in &records {
+                writeln!(out, "{}", json_record(rec))?;
+            }
+        },
+        AdifDumpFormat::ADDF_JSON => {
+            writeln!(out, "{}", JsonValue::JSV_ARRAY(
+                records.iter().map(|rec| json_record(rec)).collect()))?;
+        },
+        AdifDumpFormat::ADDF_ADI | AdifDumpFormat::ADDF_ADX => {
+            let selected = AdifFile {
+                adif_header: adif.adif_header.clone(),
+                adif_label: adif.adif_label.clone(),
+                adif_diagnostics: Vec::new(),
+                adif_records: records.into_iter().cloned().collect()
+            };
+            if format == AdifDumpFormat::ADDF_ADI {
+                adif_write(&selected, out)?;
+            } else {
+                adx_write(&selected, out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use adi::adi_parse_string;
+    use adif::AdifParseOptions;
+    use adif::adif_parse_adi;
+    use filter::adif_parse_filter;
+    use sort::adif_parse_sort_keys;
+    use super::*;
+
+    fn dump(options: &AdifDumpOptions) -> String {
+        let adi = adi_parse_string("\n<EOH>\n\
+            <CALL:6>KK6ZBI<BAND:3>20m<COMMENT:8>hi, \"me\"<EOR>\n\
+            <CALL:4>W1AW<BAND:3>40m<EOR>\n\
+            <CALL:6>N0CALL<BAND:3>20m<GRIDSQUARE:4>CM87<EOR>\n").unwrap();
+        let adif = adif_parse_adi("test", &adi,
+            &AdifParseOptions::default()).unwrap();
+        let mut out = Vec::new();
+        adif_dump(&adif, options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        let mut options = AdifDumpOptions {
+            admp_format: AdifDumpFormat::ADDF_CSV,
+            ..Default::default()
+        };
+        assert_eq!(dump(&options), "band,call,comment,gridsquare\n\
+            20m,KK6ZBI,\"hi, \"\"me\"\"\",\n40m,W1AW,,\n20m,N0CALL,,CM87\n");
+
+        options.admp_fields = Some(vec![ "call".to_string(),
+            "gridsquare".to_string() ]);
+        options.admp_format = AdifDumpFormat::ADDF_TSV;
+        assert_eq!(dump(&options), "call\tgridsquare\nKK6ZBI\t\nW1AW\t\n\
+            N0CALL\tCM87\n");
+
+        options.admp_format = AdifDumpFormat::ADDF_TABLE;
+        options.admp_summary = false;
+        assert_eq!(dump(&options), "CALL    GRIDSQUARE\nKK6ZBI  -\n\
+            W1AW    -\nN0CALL  CM87\n");
+
+        options.admp_format = AdifDumpFormat::ADDF_JSONL;
+        options.admp_filter = Some(adif_parse_filter("band = 20m").unwrap());
+        assert_eq!(dump(&options), "{\"call\":\"KK6ZBI\"}\n\
+            {\"call\":\"N0CALL\",\"gridsquare\":\"CM87\"}\n");
+
+        options.admp_format = AdifDumpFormat::ADDF_JSON;
+        options.admp_which = AdifDumpWhichRecords::ADR_ONE;
+        assert_eq!(dump(&options), "[{\"call\":\"KK6ZBI\"}]\n");
+
+        options.admp_format = AdifDumpFormat::ADDF_ADI;
+        options.admp_which = AdifDumpWhichRecords::ADR_ALL;
+        options.admp_fields = None;
+        assert_eq!(dump(&options), "ADIF export\n<EOH>\n<BAND:3>20m \
+            <CALL:6>KK6ZBI <COMMENT:8>hi, \"me\" <EOR>\n<BAND:3>20m \
+            <CALL:6>N0CALL <GRIDSQUARE:4>CM87 <EOR>\n");
+    }
+
+    #[test]
+    fn groups() {
+        let mut options = AdifDumpOptions {
+            admp_format: AdifDumpFormat::ADDF_TABLE,
+            admp_fields: Some(vec![ "call".to_string() ]),
+            admp_sort: adif_parse_sort_keys("-call").unwrap(),
+            admp_group_by: vec![ "band".to_string() ],
+            admp_summary: false,
+            ..Default::default()
+        };
+        assert_eq!(dump(&options), "band 40M (1 record)\nCALL\nW1AW\n\n\
+            band 20M (2 records)\nCALL\nN0CALL\nKK6ZBI\n");
+
+        options.admp_format = AdifDumpFormat::ADDF_CSV;
+        assert_eq!(dump(&options), "call\nW1AW\nN0CALL\nKK6ZBI\n");
+    }
+}
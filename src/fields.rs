@@ -0,0 +1,323 @@
+//
+// src/fields.rs: definitions of well-known ADIF fields
+//
+// ADIF defines a data type for each field (e.g., "freq" is a Number and
+// "qso_date" is a Date).  Values are always transmitted as strings, but knowing
+// the type lets us compare values more usefully: "14.074" and "14.07400" are
+// the same frequency, "1200" and "120000" are the same time, and "20M" and
+// "20m" are the same band.
+//
+// This table is not exhaustive.  Fields that don't appear here (including
+// application-defined and user-defined fields) are treated as Strings.
+//
+
+use std::cmp::Ordering;
+
+use adiftypes::adif_parse_date;
+use adiftypes::adif_parse_time;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdifDataType {
+    ADT_STRING,         // String, IntlString, MultilineString, etc.
+    ADT_NUMBER,         // Number (decimal)
+    ADT_INTEGER,        // Integer and PositiveInteger
+    ADT_DATE,           // Date ("YYYYMMDD")
+    ADT_TIME,           // Time ("HHMM" or "HHMMSS")
+    ADT_BOOLEAN,        // Boolean ("Y" or "N")
+    ADT_ENUMERATION,    // Enumeration (compared case-insensitively)
+    ADT_GRIDSQUARE,     // GridSquare (compared case-insensitively)
+    ADT_LOCATION        // Location (e.g., "N040 42.000")
+}
+
+static ADIF_FIELD_TYPES : &[(&str, AdifDataType)] = &[
+    ("a_index", AdifDataType::ADT_NUMBER),
+    ("age", AdifDataType::ADT_NUMBER),
+    ("ant_az", AdifDataType::ADT_NUMBER),
+    ("ant_el", AdifDataType::ADT_NUMBER),
+    ("ant_path", AdifDataType::ADT_ENUMERATION),
+    ("arrl_sect", AdifDataType::ADT_ENUMERATION),
+    ("band", AdifDataType::ADT_ENUMERATION),
+    ("band_rx", AdifDataType::ADT_ENUMERATION),
+    ("cont", AdifDataType::ADT_ENUMERATION),
+    ("cqz", AdifDataType::ADT_INTEGER),
+    ("distance", AdifDataType::ADT_NUMBER),
+    ("dxcc", AdifDataType::ADT_ENUMERATION),
+    ("eqsl_qslrdate", AdifDataType::ADT_DATE),
+    ("eqsl_qslsdate", AdifDataType::ADT_DATE),
+    ("eqsl_qsl_rcvd", AdifDataType::ADT_ENUMERATION),
+    ("eqsl_qsl_sent", AdifDataType::ADT_ENUMERATION),
+    ("force_init", AdifDataType::ADT_BOOLEAN),
+    ("freq", AdifDataType::ADT_NUMBER),
+    ("freq_rx", AdifDataType::ADT_NUMBER),
+    ("gridsquare", AdifDataType::ADT_GRIDSQUARE),
+    ("ituz", AdifDataType::ADT_INTEGER),
+    ("k_index", AdifDataType::ADT_INTEGER),
+    ("lat", AdifDataType::ADT_LOCATION),
+    ("lon", AdifDataType::ADT_LOCATION),
+    ("lotw_qslrdate", AdifDataType::ADT_DATE),
+    ("lotw_qslsdate", AdifDataType::ADT_DATE),
+    ("lotw_qsl_rcvd", AdifDataType::ADT_ENUMERATION),
+    ("lotw_qsl_sent", AdifDataType::ADT_ENUMERATION),
+    ("max_bursts", AdifDataType::ADT_NUMBER),
+    ("mode", AdifDataType::ADT_ENUMERATION),
+    ("ms_shower", AdifDataType::ADT_STRING),
+    ("my_cq_zone", AdifDataType::ADT_INTEGER),
+    ("my_dxcc", AdifDataType::ADT_ENUMERATION),
+    ("my_gridsquare", AdifDataType::ADT_GRIDSQUARE),
+    ("my_itu_zone", AdifDataType::ADT_INTEGER),
+    ("my_lat", AdifDataType::ADT_LOCATION),
+    ("my_lon", AdifDataType::ADT_LOCATION),
+    ("nr_bursts", AdifDataType::ADT_INTEGER),
+    ("nr_pings", AdifDataType::ADT_INTEGER),
+    ("prop_mode", AdifDataType::ADT_ENUMERATION),
+    ("qslrdate", AdifDataType::ADT_DATE),
+    ("qslsdate", AdifDataType::ADT_DATE),
+    ("qsl_rcvd", AdifDataType::ADT_ENUMERATION),
+    ("qsl_rcvd_via", AdifDataType::ADT_ENUMERATION),
+    ("qsl_sent", AdifDataType::ADT_ENUMERATION),
+    ("qsl_sent_via", AdifDataType::ADT_ENUMERATION),
+    ("qso_complete", AdifDataType::ADT_ENUMERATION),
+    ("qso_date", AdifDataType::ADT_DATE),
+    ("qso_date_off", AdifDataType::ADT_DATE),
+    ("qso_random", AdifDataType::ADT_BOOLEAN),
+    ("rx_pwr", AdifDataType::ADT_NUMBER),
+    ("sfi", AdifDataType::ADT_INTEGER),
+    ("srx", AdifDataType::ADT_INTEGER),
+    ("stx", AdifDataType::ADT_INTEGER),
+    ("submode", AdifDataType::ADT_ENUMERATION),
+    ("swl", AdifDataType::ADT_BOOLEAN),
+    ("ten_ten", AdifDataType::ADT_INTEGER),
+    ("time_off", AdifDataType::ADT_TIME),
+    ("time_on", AdifDataType::ADT_TIME),
+    ("tx_pwr", AdifDataType::ADT_NUMBER),
+    ("uksmg", AdifDataType::ADT_INTEGER),
+];
+
+//
+// The Band enumeration: each band's name and its lower and upper frequency
+// limits, in MHz.
+//
+static ADIF_BANDS : &[(&str, f64, f64)] = &[
+    ("2190M", 0.1357, 0.1378),
+    ("630M", 0.472, 0.479),
+    ("560M", 0.501, 0.504),
+    ("160M", 1.8, 2.0),
+    ("80M", 3.5, 4.0),
+    ("60M", 5.06, 5.45),
+    ("40M", 7.0, 7.3),
+    ("30M", 10.1, 10.15),
+    ("20M", 14.0, 14.35),
+    ("17M", 18.068, 18.168),
+    ("15M", 21.0, 21.45),
+    ("12M", 24.890, 24.99),
+    ("10M", 28.0, 29.7),
+    ("8M", 40.0, 45.0),
+    ("6M", 50.0, 54.0),
+    ("5M", 54.000001, 69.9),
+    ("4M", 70.0, 71.0),
+    ("2M", 144.0, 148.0),
+    ("1.25M", 222.0, 225.0),
+    ("70CM", 420.0, 450.0),
+    ("33CM", 902.0, 928.0),
+    ("23CM", 1240.0, 1300.0),
+    ("13CM", 2300.0, 2450.0),
+    ("9CM", 3300.0, 3500.0),
+    ("6CM", 5650.0, 5925.0),
+    ("3CM", 10000.0, 10500.0),
+    ("1.25CM", 24000.0, 24250.0),
+    ("6MM", 47000.0, 47200.0),
+    ("4MM", 75500.0, 81000.0),
+    ("2.5MM", 119980.0, 123000.0),
+    ("2MM", 134000.0, 149000.0),
+    ("1MM", 241000.0, 250000.0),
+    ("SUBMM", 300000.0, 7500000.0),
+];
+
+//
+// Returns the frequency limits (in MHz) of the named band, or None if it's not
+// a band that ADIF defines.  Band names are case-insensitive.
+//
+pub fn adif_band_range(band: &str) -> Option<(f64, f64)>
+{
+    let band = band.trim().to_uppercase();
+    ADIF_BANDS.iter().find(|(b, _, _)| *b == band).map(|&(_, lo, hi)| (lo, hi))
+}
+
+//
+// Returns the (upper-case) name of the band containing the given frequency (in
+// MHz), if any.
+//
+pub fn adif_freq_band(freq: f64) -> Option<&'static str>
+{
+    ADIF_BANDS.iter().find(|&&(_, lo, hi)| freq >= lo && freq <= hi)
+        .map(|&(b, _, _)| b)
+}
+
+//
+// Returns the data type of the named (canonicalized) field.
+//
+pub fn adif_field_type(name: &str) -> AdifDataType
+{
+    for &(field, datatype) in ADIF_FIELD_TYPES {
+        if field == name {
+            return datatype;
+        }
+    }
+
+    AdifDataType::ADT_STRING
+}
+
+//
+// Compare two values of the given data type.  Values that can't be interpreted
+// as the given type are compared as strings, and they sort after values that
+// can be interpreted.
+//
+pub fn adif_typed_compare(datatype: AdifDataType, v1: &str, v2: &str) ->
+    Ordering
+{
+    match datatype {
+        AdifDataType::ADT_NUMBER | AdifDataType::ADT_INTEGER => {
+            adif_compare_parsed(adif_parse_number(v1),
+                adif_parse_number(v2), v1, v2)
+        },
+        AdifDataType::ADT_DATE => {
+            adif_compare_parsed(adif_parse_date(v1), adif_parse_date(v2),
+                v1, v2)
+        },
+        AdifDataType::ADT_TIME => {
+            adif_compare_parsed(adif_parse_time(v1), adif_parse_time(v2),
+                v1, v2)
+        },
+        AdifDataType::ADT_BOOLEAN | AdifDataType::ADT_ENUMERATION |
+        AdifDataType::ADT_GRIDSQUARE | AdifDataType::ADT_LOCATION => {
+            v1.trim().to_uppercase().cmp(&v2.trim().to_uppercase())
+        },
+        AdifDataType::ADT_STRING => v1.cmp(v2)
+    }
+}
+
+//
+// Parse a value of type Number.  The specification only allows an optional
+// minus sign followed by digits with an optional decimal point, so we don't
+// accept everything that Rust does (e.g., "inf", "NaN", or "1e5").  Values too
+// large to represent are treated as invalid too.  This guarantees that any two
+// parsed values can be ordered.
+//
+fn adif_parse_number(text: &str) -> Option<f64>
+{
+    let text = text.trim();
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let mut parts = digits.splitn(2, '.');
+    let whole = parts.next().unwrap_or("");
+    let fraction = parts.next().unwrap_or("");
+    if whole.len() + fraction.len() == 0 ||
+        !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    text.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+//
+// Compare two values that have been parsed (if possible).  "T" must be totally
+// ordered for the values that its parser accepts so that this is a total order.
+//
+fn adif_compare_parsed<T: PartialOrd>(p1: Option<T>, p2: Option<T>,
+    v1: &str, v2: &str) -> Ordering
+{
+    match (p1, p2) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or_else(|| v1.cmp(v2)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => v1.cmp(v2)
+    }
+}
+
+//
+// Returns true if the two values of the named field are equivalent according to
+// the field's data type.
+//
+pub fn adif_typed_equal(name: &str, v1: &str, v2: &str) -> bool
+{
+    adif_typed_compare(adif_field_type(name), v1, v2) == Ordering::Equal
+}
+
+//
+// Compare band names by wavelength (longest first), so that, e.g., "160M"
+// comes before "20M" and "2M" comes before "70CM".  Names that aren't a
+// wavelength sort after those that are, as strings.
+//
+pub fn adif_band_compare(b1: &str, b2: &str) -> Ordering
+{
+    //
+    // We negate the wavelength so that longer ones sort first.
+    //
+    let meters = |b: &str| {
+        let b = b.trim().to_uppercase();
+        let (number, scale) = if let Some(n) = b.strip_suffix("MM") {
+            (n.to_string(), 0.001)
+        } else if let Some(n) = b.strip_suffix("CM") {
+            (n.to_string(), 0.01)
+        } else if let Some(n) = b.strip_suffix('M') {
+            (n.to_string(), 1.0)
+        } else {
+            return None;
+        };
+        adif_parse_number(&number).map(|n| -n * scale)
+    };
+
+    adif_compare_parsed(meters(b1), meters(b2), b1, b2)
+}
+
+#[cfg(test)]
+mod test {
+    use super::adif_band_range;
+    use super::adif_freq_band;
+    use super::adif_typed_equal;
+
+    #[test]
+    fn typed_equality() {
+        assert!(adif_typed_equal("freq", "14.074", "14.07400"));
+        assert!(!adif_typed_equal("freq", "14.074", "14.075"));
+        assert!(adif_typed_equal("time_on", "1200", "120000"));
+        assert!(adif_typed_equal("band", "20M", "20m"));
+        assert!(adif_typed_equal("gridsquare", "fn31", "FN31"));
+        assert!(!adif_typed_equal("name", "Dave", "dave"));
+        assert!(adif_typed_equal("freq", "bogus", "bogus"));
+        assert!(!adif_typed_equal("freq", "14", "bogus"));
+        assert!(adif_typed_equal("freq", "-.5", "-0.50"));
+        assert!(!adif_typed_equal("freq", "NaN", "nan"));
+        assert!(!adif_typed_equal("freq", "1e5", "100000"));
+    }
+
+    #[test]
+    fn typed_order() {
+        //
+        // Values that aren't valid Numbers sort after all valid ones, as
+        // strings, so the order is total.
+        //
+        let mut values = vec![ "NaN", "10", "inf", "1e5", "-2", "9.5", ".",
+            "-", "bogus" ];
+        values.sort_by(|a, b| super::adif_typed_compare(
+            super::AdifDataType::ADT_NUMBER, a, b));
+        assert_eq!(values, vec![ "-2", "9.5", "10", "-", ".", "1e5", "NaN",
+            "bogus", "inf" ]);
+
+        let mut bands = vec![ "70cm", "20M", "bogus", "160m", "2m", "1.25m",
+            "2190M", "M" ];
+        bands.sort_by(|a, b| super::adif_band_compare(a, b));
+        assert_eq!(bands, vec![ "2190M", "160m", "20M", "2m", "1.25m", "70cm",
+            "M", "bogus" ]);
+    }
+
+    #[test]
+    fn bands() {
+        assert_eq!(adif_band_range("20m"), Some((14.0, 14.35)));
+        assert_eq!(adif_band_range("70cm"), Some((420.0, 450.0)));
+        assert_eq!(adif_band_range("21m"), None);
+        assert_eq!(adif_freq_band(14.074), Some("20M"));
+        assert_eq!(adif_freq_band(144.174), Some("2M"));
+        assert_eq!(adif_freq_band(15.0), None);
+    }
+}
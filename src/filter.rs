@@ -0,0 +1,607 @@
+//
+// src/filter.rs: filter expressions for selecting records
+//
+// Several tools let the user select which records to operate on with an
+// expression like:
+//
+//     band in (20m, 40m) and qso_date >= 20240101 and call ~ '^K' and
+//         not exists(qsl_rcvd)
+//
+// The grammar is:
+//
+//     expr       := term ("or" term)*
+//     term       := factor ("and" factor)*
+//     factor     := ("not" | "!") factor | "(" expr ")" | predicate
+//     predicate  := "exists" "(" FIELD ")"
+//                 | FIELD
+//                 | FIELD OP VALUE
+//                 | FIELD OP "$" FIELD
+//                 | FIELD "in" "(" VALUE ("," VALUE)* ")"
+//
+// where OP is one of "=" (or "=="), "!=", "<", "<=", ">", ">=", "~" (matches a
+// pattern; see src/regex.rs), and "!~" (doesn't match a pattern).  A FIELD by
+// itself is the same as "exists(FIELD)".  Keywords and field names are
+// case-insensitive.  A VALUE is either a bare word (letters, digits, and any of
+// "._-+/:*#@") or a string in single or double quotes.  For compatibility with
+// older versions, "FIELD=" at the end of an expression (or before ")") means
+// "FIELD = ''".  "$FIELD" in place of a VALUE stands for the value of another
+// field of the same record, as in "dxcc != $my_dxcc".
+//
+// Values are compared according to the field's ADIF data type (see
+// src/fields.rs), so "freq > 14" compares numerically and "band = 20M" matches
+// "20m".  Any comparison against a field that's absent from the record is
+// false (even "!="), as is a comparison against "$FIELD" when that field is
+// absent; use "exists" to test whether a field is present.  Besides
+// the record's own fields, expressions can use the fields derived from the QSO
+// date and time that reports use ("year", "month", "day", and "hour").
+//
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use adif::AdifRecord;
+use fields::adif_field_type;
+use fields::adif_typed_compare;
+use regex::AdifRegex;
+use regex::adif_regex_compile;
+use regex::adif_regex_matches;
+use report::adif_report_value;
+use super::AdifParseError;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdifFilterOp {
+    AFO_EQ,
+    AFO_NE,
+    AFO_LT,
+    AFO_LE,
+    AFO_GT,
+    AFO_GE
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdifFilter {
+    AFL_AND(Box<AdifFilter>, Box<AdifFilter>),
+    AFL_OR(Box<AdifFilter>, Box<AdifFilter>),
+    AFL_NOT(Box<AdifFilter>),
+    AFL_EXISTS(String),
+    AFL_COMPARE(String, AdifFilterOp, String),
+    AFL_COMPARE_FIELD(String, AdifFilterOp, String),
+    AFL_IN(String, Vec<String>),
+    AFL_MATCH(String, AdifRegex)
+}
+
+fn filter_quote(value: &str) -> String
+{
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn filter_op_name(op: AdifFilterOp) -> &'static str
+{
+    match op {
+        AdifFilterOp::AFO_EQ => "=",
+        AdifFilterOp::AFO_NE => "!=",
+        AdifFilterOp::AFO_LT => "<",
+        AdifFilterOp::AFO_LE => "<=",
+        AdifFilterOp::AFO_GT => ">",
+        AdifFilterOp::AFO_GE => ">="
+    }
+}
+
+//
+// Filters are displayed fully parenthesized, which is mainly useful for
+// checking how an expression was parsed.
+//
+impl fmt::Display for AdifFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdifFilter::AFL_AND(a, b) => write!(f, "({} and {})", a, b),
+            AdifFilter::AFL_OR(a, b) => write!(f, "({} or {})", a, b),
+            AdifFilter::AFL_NOT(a) => write!(f, "(not {})", a),
+            AdifFilter::AFL_EXISTS(field) => write!(f, "exists({})", field),
+            AdifFilter::AFL_COMPARE(field, op, value) => {
+                write!(f, "({} {} {})", field, filter_op_name(*op),
+                    filter_quote(value))
+            },
+            AdifFilter::AFL_COMPARE_FIELD(field, op, other) => {
+                write!(f, "({} {} ${})", field, filter_op_name(*op), other)
+            },
+            AdifFilter::AFL_IN(field, values) => {
+                let quoted : Vec<String> = values.iter().map(
+                    |v| filter_quote(v)).collect();
+                write!(f, "({} in ({}))", field, quoted.join(", "))
+            },
+            AdifFilter::AFL_MATCH(field, regex) => {
+                write!(f, "({} ~ {})", field,
+                    filter_quote(&regex.to_string()))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum FilterToken {
+    Word(String),               // bare word (field name, keyword, or value)
+    Quoted(String),             // quoted string
+    FieldRef(String),           // "$" followed by a field name
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    End
+}
+
+fn filter_error(text: &str, pos: usize, message: &str) -> AdifParseError
+{
+    AdifParseError::ADIF_EBADINPUT(format!("filter \"{}\" (near character \
+        {}): {}", text, pos + 1, message))
+}
+
+fn filter_word_char(c: char) -> bool
+{
+    c.is_alphanumeric() || "._-+/:*#@".contains(c)
+}
+
+//
+// Split the expression into tokens, each with its starting (character)
+// position for error messages.
+//
+fn filter_tokenize(text: &str) -> Result<Vec<(FilterToken, usize)>,
+    AdifParseError>
+{
+    let chars : Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let next = chars.get(i + 1).cloned();
+        let token = match c {
+            '(' => FilterToken::LParen,
+            ')' => FilterToken::RParen,
+            ',' => FilterToken::Comma,
+            '~' => FilterToken::Op("~"),
+            '=' if next == Some('=') => { i += 1; FilterToken::Op("=") },
+            '=' => FilterToken::Op("="),
+            '!' if next == Some('=') => { i += 1; FilterToken::Op("!=") },
+            '!' if next == Some('~') => { i += 1; FilterToken::Op("!~") },
+            '!' => FilterToken::Op("!"),
+            '<' if next == Some('=') => { i += 1; FilterToken::Op("<=") },
+            '<' => FilterToken::Op("<"),
+            '>' if next == Some('=') => { i += 1; FilterToken::Op(">=") },
+            '>' => FilterToken::Op(">"),
+            '\'' | '"' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(filter_error(text, start,
+                            "unterminated string")),
+                        Some(&q) if q == c => {
+                            //
+                            // A doubled quote stands for the quote itself.
+                            //
+                            if chars.get(i + 1) == Some(&c) {
+                                value.push(c);
+                                i += 2;
+                                continue;
+                            }
+                            break;
+                        },
+                        Some(&ch) => {
+                            value.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                FilterToken::Quoted(value)
+            },
+            '$' if next.is_some_and(filter_word_char) => {
+                let mut word = String::new();
+                i += 1;
+                while i < chars.len() && filter_word_char(chars[i]) {
+                    word.push(chars[i]);
+                    i += 1;
+                }
+                tokens.push((FilterToken::FieldRef(word.to_lowercase()),
+                    start));
+                continue;
+            },
+            c if filter_word_char(c) => {
+                let mut word = String::new();
+                while i < chars.len() && filter_word_char(chars[i]) {
+                    word.push(chars[i]);
+                    i += 1;
+                }
+                tokens.push((FilterToken::Word(word), start));
+                continue;
+            },
+            _ => return Err(filter_error(text, start,
+                &format!("unexpected character \"{}\"", c)))
+        };
+
+        tokens.push((token, start));
+        i += 1;
+    }
+
+    tokens.push((FilterToken::End, chars.len()));
+    Ok(tokens)
+}
+
+struct FilterParser<'a> {
+    fp_text : &'a str,
+    fp_tokens : Vec<(FilterToken, usize)>,
+    fp_pos : usize
+}
+
+impl<'a> FilterParser<'a> {
+    fn peek(&self) -> &FilterToken
+    {
+        &self.fp_tokens[self.fp_pos].0
+    }
+
+    fn next(&mut self) -> FilterToken
+    {
+        let token = self.fp_tokens[self.fp_pos].0.clone();
+        if token != FilterToken::End {
+            self.fp_pos += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: &str) -> AdifParseError
+    {
+        filter_error(self.fp_text, self.fp_tokens[self.fp_pos].1, message)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool
+    {
+        match self.peek() {
+            FilterToken::Word(w) => w.eq_ignore_ascii_case(keyword),
+            _ => false
+        }
+    }
+
+    fn expect(&mut self, token: FilterToken, what: &str) ->
+        Result<(), AdifParseError>
+    {
+        if *self.peek() != token {
+            return Err(self.error(&format!("expected {}", what)));
+        }
+        self.next();
+        Ok(())
+    }
+
+    fn parse_expr(&mut self) -> Result<AdifFilter, AdifParseError>
+    {
+        let mut left = self.parse_term()?;
+        while self.peek_keyword("or") {
+            self.next();
+            let right = self.parse_term()?;
+            left = AdifFilter::AFL_OR(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_term(&mut self) -> Result<AdifFilter, AdifParseError>
+    {
+        let mut left = self.parse_factor()?;
+        while self.peek_keyword("and") {
+            self.next();
+            let right = self.parse_factor()?;
+            left = AdifFilter::AFL_AND(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_factor(&mut self) -> Result<AdifFilter, AdifParseError>
+    {
+        if self.peek_keyword("not") || *self.peek() == FilterToken::Op("!") {
+            self.next();
+            let operand = self.parse_factor()?;
+            return Ok(AdifFilter::AFL_NOT(Box::new(operand)));
+        }
+
+        if *self.peek() == FilterToken::LParen {
+            self.next();
+            let expr = self.parse_expr()?;
+            self.expect(FilterToken::RParen, "\")\"")?;
+            return Ok(expr);
+        }
+
+        self.parse_predicate()
+    }
+
+    fn parse_field(&mut self) -> Result<String, AdifParseError>
+    {
+        match self.peek().clone() {
+            FilterToken::Word(w) => {
+                self.next();
+                Ok(w.to_lowercase())
+            },
+            _ => Err(self.error("expected field name"))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<String, AdifParseError>
+    {
+        match self.peek().clone() {
+            FilterToken::Word(w) | FilterToken::Quoted(w) => {
+                self.next();
+                Ok(w)
+            },
+            _ => Err(self.error("expected value"))
+        }
+    }
+
+    fn parse_predicate(&mut self) -> Result<AdifFilter, AdifParseError>
+    {
+        if self.peek_keyword("exists") &&
+            self.fp_tokens[self.fp_pos + 1].0 == FilterToken::LParen {
+            self.next();
+            self.next();
+            let field = self.parse_field()?;
+            self.expect(FilterToken::RParen, "\")\"")?;
+            return Ok(AdifFilter::AFL_EXISTS(field));
+        }
+
+        let field = self.parse_field()?;
+
+        if self.peek_keyword("in") {
+            self.next();
+            self.expect(FilterToken::LParen, "\"(\"")?;
+            let mut values = vec![ self.parse_value()? ];
+            while *self.peek() == FilterToken::Comma {
+                self.next();
+                values.push(self.parse_value()?);
+            }
+            self.expect(FilterToken::RParen, "\")\" or \",\"")?;
+            return Ok(AdifFilter::AFL_IN(field, values));
+        }
+
+        let op = match self.peek() {
+            FilterToken::Op(op) if *op != "!" => *op,
+            _ => return Ok(AdifFilter::AFL_EXISTS(field))
+        };
+        self.next();
+
+        if op == "=" && (*self.peek() == FilterToken::End ||
+            *self.peek() == FilterToken::RParen) {
+            return Ok(AdifFilter::AFL_COMPARE(field, AdifFilterOp::AFO_EQ,
+                String::new()));
+        }
+
+        let cmp = match op {
+            "=" => Some(AdifFilterOp::AFO_EQ),
+            "!=" => Some(AdifFilterOp::AFO_NE),
+            "<" => Some(AdifFilterOp::AFO_LT),
+            "<=" => Some(AdifFilterOp::AFO_LE),
+            ">" => Some(AdifFilterOp::AFO_GT),
+            ">=" => Some(AdifFilterOp::AFO_GE),
+            _ => None
+        };
+
+        if let FilterToken::FieldRef(other) = self.peek().clone() {
+            return match cmp {
+                Some(cmp) => {
+                    self.next();
+                    Ok(AdifFilter::AFL_COMPARE_FIELD(field, cmp, other))
+                },
+                None => Err(self.error("expected pattern"))
+            };
+        }
+
+        let value = self.parse_value()?;
+        let op = match cmp {
+            Some(cmp) => cmp,
+            None => {
+                let regex = adif_regex_compile(&value).map_err(
+                    AdifParseError::ADIF_EBADINPUT)?;
+                let matcher = AdifFilter::AFL_MATCH(field, regex);
+                return Ok(if op == "!~" {
+                    AdifFilter::AFL_NOT(Box::new(matcher))
+                } else {
+                    matcher
+                });
+            }
+        };
+
+        Ok(AdifFilter::AFL_COMPARE(field, op, value))
+    }
+}
+
+//
+// Parse a filter expression.  See the comment at the top of this file for the
+// syntax.
+//
+pub fn adif_parse_filter(text: &str) -> Result<AdifFilter, AdifParseError>
+{
+    let mut parser = FilterParser {
+        fp_text: text,
+        fp_tokens: filter_tokenize(text)?,
+        fp_pos: 0
+    };
+
+    if *parser.peek() == FilterToken::End {
+        return Err(parser.error("empty expression"));
+    }
+
+    let filter = parser.parse_expr()?;
+    if *parser.peek() != FilterToken::End {
+        return Err(parser.error("expected \"and\", \"or\", or end of \
+            expression"));
+    }
+
+    Ok(filter)
+}
+
+//
+// Returns the value of "field" in "rec" for the purpose of filtering.
+//
+fn adif_filter_value(rec: &AdifRecord, field: &str) -> Option<String>
+{
+    if let Some(value) = rec.adir_field_values.get(field) {
+        return Some(value.clone());
+    }
+
+    match field {
+        "year" | "month" | "day" | "hour" => {
+            let value = adif_report_value(rec, field);
+            if value.is_empty() { None } else { Some(value) }
+        },
+        _ => None
+    }
+}
+
+//
+// Returns true if "actual" (a value of "field") compares to "value" as "op"
+// says it should.
+//
+fn adif_filter_compare(field: &str, op: AdifFilterOp, actual: &str,
+    value: &str) -> bool
+{
+    let ord = adif_typed_compare(adif_field_type(field), actual, value);
+    match op {
+        AdifFilterOp::AFO_EQ => ord == Ordering::Equal,
+        AdifFilterOp::AFO_NE => ord != Ordering::Equal,
+        AdifFilterOp::AFO_LT => ord == Ordering::Less,
+        AdifFilterOp::AFO_LE => ord != Ordering::Greater,
+        AdifFilterOp::AFO_GT => ord == Ordering::Greater,
+        AdifFilterOp::AFO_GE => ord != Ordering::Less
+    }
+}
+
+//
+// Returns true if "rec" satisfies "filter".
+//
+pub fn adif_filter_matches(filter: &AdifFilter, rec: &AdifRecord) -> bool
+{
+    match filter {
+        AdifFilter::AFL_AND(a, b) =>
+            adif_filter_matches(a, rec) && adif_filter_matches(b, rec),
+        AdifFilter::AFL_OR(a, b) =>
+            adif_filter_matches(a, rec) || adif_filter_matches(b, rec),
+        AdifFilter::AFL_NOT(a) => !adif_filter_matches(a, rec),
+        AdifFilter::AFL_EXISTS(field) =>
+            adif_filter_value(rec, field).is_some(),
+        AdifFilter::AFL_COMPARE(field, op, value) => {
+            match adif_filter_value(rec, field) {
+                Some(actual) => adif_filter_compare(field, *op, &actual,
+                    value),
+                None => false
+            }
+        },
+        AdifFilter::AFL_COMPARE_FIELD(field, op, other) => {
+            match (adif_filter_value(rec, field),
+                adif_filter_value(rec, other)) {
+                (Some(actual), Some(value)) => adif_filter_compare(field, *op,
+                    &actual, &value),
+                _ => false
+            }
+        },
+        AdifFilter::AFL_IN(field, values) => {
+            match adif_filter_value(rec, field) {
+                Some(actual) => {
+                    let datatype = adif_field_type(field);
+                    values.iter().any(|v| adif_typed_compare(datatype,
+                        &actual, v) == Ordering::Equal)
+                },
+                None => false
+            }
+        },
+        AdifFilter::AFL_MATCH(field, regex) => {
+            match adif_filter_value(rec, field) {
+                Some(actual) => adif_regex_matches(regex, &actual),
+                None => false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifRecord;
+    use super::*;
+
+    fn qso(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    fn check(expr: &str, rec: &AdifRecord) -> bool {
+        adif_filter_matches(&adif_parse_filter(expr).unwrap(), rec)
+    }
+
+    #[test]
+    fn parse() {
+        let filter = adif_parse_filter("band in (20m,40m) and \
+            QSO_DATE >= 20240101 and call ~ '^K' and not exists(qsl_rcvd) \
+            or !comment").unwrap();
+        assert_eq!(filter.to_string(), "(((((band in ('20m', '40m')) and \
+            (qso_date >= '20240101')) and (call ~ '^K')) and \
+            (not exists(qsl_rcvd))) or (not exists(comment)))");
+
+        let filter = adif_parse_filter("a = 'it''s' or (b != \"x y\" and \
+            c=)").unwrap();
+        assert_eq!(filter.to_string(), "((a = 'it''s') or ((b != 'x y') and \
+            (c = '')))");
+        assert_eq!(adif_parse_filter("call !~ '/P$'").unwrap().to_string(),
+            "(not (call ~ '/P$'))");
+
+        for bad in &[ "", "band <", "band in 20m", "band in (20m", "(band",
+            "band = 20m extra", "call ~ '('", "a = 'b", "a & b", "not" ] {
+            assert!(adif_parse_filter(bad).is_err(), "filter {}", bad);
+        }
+    }
+
+    #[test]
+    fn evaluate() {
+        let rec = qso(&[ ("call", "KK6ZBI"), ("band", "20m"),
+            ("freq", "14.074"), ("qso_date", "20240315"),
+            ("time_on", "1830"), ("comment", "") ]);
+
+        assert!(check("band = 20M", &rec));
+        assert!(check("band in (40m, 20M)", &rec));
+        assert!(!check("band in (40m, 80m)", &rec));
+        assert!(check("freq > 14", &rec));
+        assert!(check("freq < 14.1", &rec));
+        assert!(check("freq = 14.07400", &rec));
+        assert!(check("qso_date >= 20240101 and year = 2024", &rec));
+        assert!(check("hour = 18 and month = 202403", &rec));
+        assert!(check("call ~ '^K' and not exists(qsl_rcvd)", &rec));
+        assert!(check("call ~ 6z", &rec));
+        assert!(check("call !~ '/P$'", &rec));
+        assert!(check("comment and comment = ''", &rec));
+        assert!(check("comment=", &rec));
+        assert!(!check("qsl_rcvd != Y", &rec));
+        assert!(check("qsl_rcvd = Y or band = 20m", &rec));
+        assert!(!check("not (band = 20m)", &rec));
+    }
+
+    #[test]
+    fn field_references() {
+        let rec = qso(&[ ("call", "KK6ZBI"), ("dxcc", "291"),
+            ("my_dxcc", "291"), ("cqz", "3"), ("my_cq_zone", "05") ]);
+
+        assert_eq!(adif_parse_filter("dxcc != $MY_DXCC").unwrap().to_string(),
+            "(dxcc != $my_dxcc)");
+        assert!(check("dxcc = $my_dxcc", &rec));
+        assert!(check("cqz < $my_cq_zone", &rec));
+        assert!(!check("cqz = $my_cq_zone", &rec));
+        assert!(!check("dxcc = $state", &rec));
+        assert!(!check("dxcc != $state", &rec));
+        assert!(check("call = '$my_dxcc' or call != '$my_dxcc'", &rec));
+        assert!(adif_parse_filter("call ~ $my_call").is_err());
+        assert!(adif_parse_filter("call in ($my_call)").is_err());
+    }
+}
@@ -0,0 +1,557 @@
+//
+// src/geo.rs: locations of QSOs and maps of them
+//
+// ADIF records where each station was in two ways: as a Maidenhead grid square
+// ("gridsquare" and "my_gridsquare", like "CM87" or "FN42aa") and as a
+// latitude and longitude ("lat", "lon", "my_lat", and "my_lon", each written
+// like "N037 46.500").  We use the latitude and longitude when both are
+// present and valid, and otherwise the center of the grid square.
+//
+// adif_map_write() draws records on a map as GeoJSON (RFC 7946) or KML.  Each
+// QSO with a known location becomes a point, and (if the station's own
+// location is known too) a line along the great-circle path from the station
+// to it.  Each station location is drawn as a point as well.  The features
+// have properties taken from selected fields of the record, plus the distance
+// (in km) and bearing (in degrees) of the path.  In GeoJSON, paths that cross
+// the antimeridian are split there (as RFC 7946 recommends).  Records without
+// a known location are skipped, and a diagnostic describes each one.
+//
+
+use std::io;
+
+use adif::AdifDiagnostic;
+use adif::AdifRecord;
+use adx::adx_escape;
+use filter::AdifFilter;
+use filter::adif_filter_matches;
+use json::JsonValue;
+use json::json_number;
+use json::json_object;
+use json::json_string;
+
+const ADIF_EARTH_RADIUS_KM : f64 = 6371.0;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AdifMapFormat {
+    #[default]
+    AMF_GEOJSON,
+    AMF_KML
+}
+
+#[derive(Clone, Debug)]
+pub struct AdifMapOptions {
+    pub amap_format : AdifMapFormat,
+    pub amap_fields : Vec<String>,          // fields to include as properties
+    pub amap_filter : Option<AdifFilter>,   // records to draw
+    pub amap_paths : bool,                  // draw great-circle paths
+    pub amap_station : Option<(f64, f64)>   // overrides the record's location
+}
+
+impl Default for AdifMapOptions {
+    fn default() -> AdifMapOptions {
+        AdifMapOptions {
+            amap_format: AdifMapFormat::AMF_GEOJSON,
+            amap_fields: [ "call", "qso_date", "time_on", "band", "mode",
+                "gridsquare" ].iter().map(|f| f.to_string()).collect(),
+            amap_filter: None,
+            amap_paths: true,
+            amap_station: None
+        }
+    }
+}
+
+pub fn adif_parse_map_format(name: &str) -> Option<AdifMapFormat>
+{
+    match name.to_lowercase().as_str() {
+        "geojson" => Some(AdifMapFormat::AMF_GEOJSON),
+        "kml" => Some(AdifMapFormat::AMF_KML),
+        _ => None
+    }
+}
+
+//
+// Returns the latitude and longitude (in degrees) of the center of a
+// Maidenhead grid square of 2, 4, 6, or 8 characters, or None if it's not
+// valid.
+//
+pub fn adif_parse_gridsquare(grid: &str) -> Option<(f64, f64)>
+{
+    let bytes = grid.to_ascii_uppercase().into_bytes();
+    if bytes.is_empty() || bytes.len() > 8 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+
+    //
+    // Each pair of characters divides the previous square into a grid of
+    // smaller squares: 18 by 18 fields, 10 by 10 squares, 24 by 24
+    // subsquares, and 10 by 10 extended squares.
+    //
+    let (mut lon, mut lat) = (-180.0, -90.0);
+    let (mut width, mut height) = (360.0, 180.0);
+    for (i, pair) in bytes.chunks(2).enumerate() {
+        let (base, count) = match i {
+            0 => (b'A', 18),
+            2 => (b'A', 24),
+            _ => (b'0', 10)
+        };
+        let (x, y) = (pair[0].wrapping_sub(base), pair[1].wrapping_sub(base));
+        if x >= count || y >= count {
+            return None;
+        }
+        width /= count as f64;
+        height /= count as f64;
+        lon += x as f64 * width;
+        lat += y as f64 * height;
+    }
+
+    Some((lat + height / 2.0, lon + width / 2.0))
+}
+
+//
+// Returns the value (in degrees, negative for south and west) of an ADIF
+// Location, which is "XDDD MM.MMM", where X is N, S, E, or W, DDD is degrees
+// (0-180), and MM.MMM is minutes (0-59.999).
+//
+pub fn adif_parse_location(value: &str) -> Option<f64>
+{
+    let bytes = value.as_bytes();
+    if !value.is_ascii() || bytes.len() != 11 || bytes[4] != b' ' ||
+        bytes[7] != b'.' || !value[1..4].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let hemisphere = bytes[0].to_ascii_uppercase();
+    let degrees = value[1..4].parse::<u32>().ok()?;
+    let minutes = value[5..11].parse::<f64>().ok()?;
+    let limit = if hemisphere == b'N' || hemisphere == b'S' { 90 } else { 180 };
+    if !b"NSEW".contains(&hemisphere) || degrees > limit || minutes >= 60.0 {
+        return None;
+    }
+
+    let value = degrees as f64 + minutes / 60.0;
+    Some(if hemisphere == b'S' || hemisphere == b'W' { -value } else { value })
+}
+
+//
+// Returns the location (latitude and longitude, in degrees) of the station
+// worked in "rec", or with "mine", of the station making the QSO.
+//
+pub fn adif_record_location(rec: &AdifRecord, mine: bool) -> Option<(f64, f64)>
+{
+    let prefix = if mine { "my_" } else { "" };
+    let get = |name: &str| rec.adir_field_values.get(
+        &format!("{}{}", prefix, name)).map(|v| v.trim());
+    let lat = get("lat").and_then(adif_parse_location);
+    let lon = get("lon").and_then(adif_parse_location);
+    match (lat, lon) {
+        (Some(lat), Some(lon)) if lat.abs() <= 90.0 => Some((lat, lon)),
+        _ => get("gridsquare").and_then(adif_parse_gridsquare)
+    }
+}
+
+fn adif_to_vector(point: (f64, f64)) -> [f64; 3]
+{
+    let (lat, lon) = (point.0.to_radians(), point.1.to_radians());
+    [ lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin() ]
+}
+
+//
+// Returns the angle (in radians) between two points on the Earth's surface.
+//
+fn adif_angle(from: (f64, f64), to: (f64, f64)) -> f64
+{
+    let (a, b) = (adif_to_vector(from), adif_to_vector(to));
+    let dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let cross = [ a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0] ];
+    (cross[0].powi(2) + cross[1].powi(2) + cross[2].powi(2)).sqrt().atan2(dot)
+}
+
+//
+// Returns the great-circle distance (in km) between two points.
+//
+pub fn adif_distance_km(from: (f64, f64), to: (f64, f64)) -> f64
+{
+    adif_angle(from, to) * ADIF_EARTH_RADIUS_KM
+}
+
+//
+// Returns the initial bearing (in degrees clockwise from true north) of the
+// great-circle path from one point to another.
+//
+pub fn adif_bearing(from: (f64, f64), to: (f64, f64)) -> f64
+{
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let dlon = (to.1 - from.1).to_radians();
+    let bearing = (dlon.sin() * lat2.cos()).atan2(lat1.cos() * lat2.sin() -
+        lat1.sin() * lat2.cos() * dlon.cos()).to_degrees();
+    (bearing + 360.0) % 360.0
+}
+
+//
+// Returns points along the great-circle path from one point to another,
+// including both ends, spaced at most about 100 km apart.
+//
+pub fn adif_great_circle(from: (f64, f64), to: (f64, f64)) -> Vec<(f64, f64)>
+{
+    let angle = adif_angle(from, to);
+    let nsegments = (angle * ADIF_EARTH_RADIUS_KM / 100.0).ceil().max(1.0);
+    if angle.sin().abs() < 1e-9 {
+        return vec![ from, to ];
+    }
+
+    let (a, b) = (adif_to_vector(from), adif_to_vector(to));
+    let mut points = vec![ from ];
+    for i in 1..(nsegments as usize) {
+        let f = i as f64 / nsegments;
+        let wa = ((1.0 - f) * angle).sin() / angle.sin();
+        let wb = (f * angle).sin() / angle.sin();
+        let v : Vec<f64> = (0..3).map(|k| wa * a[k] + wb * b[k]).collect();
+        points.push((v[2].atan2(v[0].hypot(v[1])).to_degrees(),
+            v[1].atan2(v[0]).to_degrees()));
+    }
+    points.push(to);
+    points
+}
+
+//
+// Splits a path into pieces that don't cross the antimeridian, adding a point
+// on the antimeridian at the end of one piece and the start of the next.
+//
+fn adif_split_path(points: &[(f64, f64)]) -> Vec<Vec<(f64, f64)>>
+{
+    let mut pieces = vec![ vec![ points[0] ] ];
+    for pair in points.windows(2) {
+        let ((lat1, lon1), (lat2, lon2)) = (pair[0], pair[1]);
+        if (lon2 - lon1).abs() > 180.0 {
+            let edge = if lon1 > 0.0 { 180.0 } else { -180.0 };
+            let lon2 = if lon1 > 0.0 { lon2 + 360.0 } else { lon2 - 360.0 };
+            let lat = lat1 + (lat2 - lat1) * (edge - lon1) / (lon2 - lon1);
+            pieces.last_mut().unwrap().push((lat, edge));
+            pieces.push(vec![ (lat, -edge) ]);
+        }
+        pieces.last_mut().unwrap().push(pair[1]);
+    }
+    pieces
+}
+
+//
+// Rounds a coordinate to about a meter, which is far more precise than any
+// grid square.
+//
+fn adif_round(value: f64) -> f64
+{
+    (value * 1e5).round() / 1e5
+}
+
+struct AdifMapQso<'a> {
+    amq_record : &'a AdifRecord,
+    amq_location : (f64, f64),
+    amq_station : Option<(f64, f64)>
+}
+
+fn adif_geojson_point(point: (f64, f64)) -> JsonValue
+{
+    JsonValue::JSV_ARRAY(vec![ json_number(adif_round(point.1)),
+        json_number(adif_round(point.0)) ])
+}
+
+fn adif_geojson_feature(geometry: JsonValue,
+    properties: Vec<(String, JsonValue)>) -> JsonValue
+{
+    json_object(vec![
+        ("type", json_string("Feature")),
+        ("geometry", geometry),
+        ("properties", JsonValue::JSV_OBJECT(properties))
+    ])
+}
+
+//
+// Returns the properties of a feature for "qso", as (name, value) pairs.
+// Field values are strings, and the distance and bearing are numbers.
+//
+fn adif_map_properties(qso: &AdifMapQso, options: &AdifMapOptions) ->
+    Vec<(String, JsonValue)>
+{
+    let mut properties : Vec<(String, JsonValue)> = options.amap_fields.iter()
+        .filter_map(|f| qso.amq_record.adir_field_values.get(f).map(
+        |v| (f.clone(), json_string(v)))).collect();
+    if let Some(station) = qso.amq_station {
+        properties.push((String::from("distance_km"), json_number(
+            adif_distance_km(station, qso.amq_location).round())));
+        properties.push((String::from("bearing"), json_number(
+            adif_bearing(station, qso.amq_location).round())));
+    }
+    properties
+}
+
+fn adif_write_geojson(qsos: &[AdifMapQso], stations: &[(f64, f64)],
+    options: &AdifMapOptions, out: &mut dyn io::Write) -> io::Result<()>
+{
+    let mut features = Vec::new();
+    for station in stations {
+        features.push(adif_geojson_feature(json_object(vec![
+            ("type", json_string("Point")),
+            ("coordinates", adif_geojson_point(*station))
+        ]), vec![ (String::from("station"), JsonValue::JSV_BOOL(true)) ]));
+    }
+
+    for qso in qsos {
+        let properties = adif_map_properties(qso, options);
+
+        features.push(adif_geojson_feature(json_object(vec![
+            ("type", json_string("Point")),
+            ("coordinates", adif_geojson_point(qso.amq_location))
+        ]), properties.clone()));
+
+        if let (true, Some(station)) = (options.amap_paths, qso.amq_station) {
+            let pieces : Vec<JsonValue> = adif_split_path(&adif_great_circle(
+                station, qso.amq_location)).iter().map(
+                |piece| JsonValue::JSV_ARRAY(piece.iter().map(
+                |p| adif_geojson_point(*p)).collect())).collect();
+            let geometry = if pieces.len() == 1 {
+                json_object(vec![
+                    ("type", json_string("LineString")),
+                    ("coordinates", pieces.into_iter().next().unwrap())
+                ])
+            } else {
+                json_object(vec![
+                    ("type", json_string("MultiLineString")),
+                    ("coordinates", JsonValue::JSV_ARRAY(pieces))
+                ])
+            };
+            features.push(adif_geojson_feature(geometry, properties));
+        }
+    }
+
+    writeln!(out, "{}", json_object(vec![
+        ("type", json_string("FeatureCollection")),
+        ("features", JsonValue::JSV_ARRAY(features))
+    ]))
+}
+
+fn adif_kml_coordinates(points: &[(f64, f64)]) -> String
+{
+    let coordinates : Vec<String> = points.iter().map(|p| format!("{},{}",
+        adif_round(p.1), adif_round(p.0))).collect();
+    coordinates.join(" ")
+}
+
+fn adif_write_kml(qsos: &[AdifMapQso], stations: &[(f64, f64)],
+    options: &AdifMapOptions, out: &mut dyn io::Write) -> io::Result<()>
+{
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<kml xmlns=\"http://www.opengis.net/kml/2.2\">")?;
+    writeln!(out, "  <Document>")?;
+    for station in stations {
+        writeln!(out, "    <Placemark>")?;
+        writeln!(out, "      <name>station</name>")?;
+        writeln!(out, "      <Point><coordinates>{}</coordinates></Point>",
+            adif_kml_coordinates(&[ *station ]))?;
+        writeln!(out, "    </Placemark>")?;
+    }
+
+    for qso in qsos {
+        let name = qso.amq_record.adir_field_values.get("call").map(
+            |c| c.as_str()).unwrap_or("");
+        writeln!(out, "    <Placemark>")?;
+        writeln!(out, "      <name>{}</name>", adx_escape(name))?;
+        writeln!(out, "      <ExtendedData>")?;
+        for (name, value) in adif_map_properties(qso, options) {
+            let value = match value {
+                JsonValue::JSV_STRING(s) => s,
+                v => v.to_string()
+            };
+            writeln!(out, "        <Data name=\"{}\"><value>{}</value></Data>",
+                adx_escape(&name), adx_escape(&value))?;
+        }
+        writeln!(out, "      </ExtendedData>")?;
+
+        let point = format!("<Point><coordinates>{}</coordinates></Point>",
+            adif_kml_coordinates(&[ qso.amq_location ]));
+        match (options.amap_paths, qso.amq_station) {
+            (true, Some(station)) => {
+                writeln!(out, "      <MultiGeometry>")?;
+                writeln!(out, "        {}", point)?;
+                writeln!(out, "        <LineString><tessellate>1</tessellate>\
+                    <coordinates>{}</coordinates></LineString>",
+                    adif_kml_coordinates(&adif_great_circle(station,
+                    qso.amq_location)))?;
+                writeln!(out, "      </MultiGeometry>")?;
+            },
+            _ => writeln!(out, "      {}", point)?
+        }
+        writeln!(out, "    </Placemark>")?;
+    }
+
+    writeln!(out, "  </Document>")?;
+    writeln!(out, "</kml>")
+}
+
+//
+// Write a map of "records" in the format given by "options".  The returned
+// diagnostics identify records by their position in "records".
+//
+pub fn adif_map_write(records: &[AdifRecord], options: &AdifMapOptions,
+    out: &mut dyn io::Write) -> io::Result<Vec<AdifDiagnostic>>
+{
+    let mut diagnostics = Vec::new();
+    let mut qsos = Vec::new();
+    let mut stations : Vec<(f64, f64)> = Vec::new();
+    for (i, rec) in records.iter().enumerate() {
+        if options.amap_filter.as_ref().is_some_and(
+            |f| !adif_filter_matches(f, rec)) {
+            continue;
+        }
+
+        let location = match adif_record_location(rec, false) {
+            Some(location) => location,
+            None => {
+                diagnostics.push(AdifDiagnostic {
+                    adid_record: Some(i + 1),
+                    adid_line: None,
+                    adid_message: String::from("skipped: no valid location \
+                        (\"lat\" and \"lon\" or \"gridsquare\")")
+                });
+                continue;
+            }
+        };
+
+        let station = options.amap_station.or_else(
+            || adif_record_location(rec, true));
+        if let Some(station) = station {
+            if !stations.contains(&station) {
+                stations.push(station);
+            }
+        }
+        qsos.push(AdifMapQso {
+            amq_record: rec,
+            amq_location: location,
+            amq_station: station
+        });
+    }
+
+    match options.amap_format {
+        AdifMapFormat::AMF_GEOJSON =>
+            adif_write_geojson(&qsos, &stations, options, out)?,
+        AdifMapFormat::AMF_KML =>
+            adif_write_kml(&qsos, &stations, options, out)?
+    }
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifRecord;
+    use filter::adif_parse_filter;
+    use json::json_parse;
+    use super::*;
+
+    fn qso(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(adif_parse_gridsquare("JJ"), Some((5.0, 10.0)));
+        assert_eq!(adif_parse_gridsquare("JJ00"), Some((0.5, 1.0)));
+        let (lat, lon) = adif_parse_gridsquare("cm87wt").unwrap();
+        assert!(close(lat, 37.8125, 1e-9) && close(lon, -122.125, 1e-9));
+        let (lat, lon) = adif_parse_gridsquare("FN42aa00").unwrap();
+        assert!(close(lat, 42.0 + 1.0 / 480.0, 1e-9));
+        assert!(close(lon, -72.0 + 1.0 / 240.0, 1e-9));
+        for bad in &[ "", "C", "CM8", "SA00", "CM8A", "CM87YA", "CM87aa0A",
+            "CM87aa00aa" ] {
+            assert_eq!(adif_parse_gridsquare(bad), None, "grid {}", bad);
+        }
+
+        assert_eq!(adif_parse_location("N037 46.500"), Some(37.775));
+        assert_eq!(adif_parse_location("w122 25.200"), Some(-122.42));
+        for bad in &[ "N091 00.000", "E181 00.000", "N037 60.000",
+            "X037 46.500", "N37 46.500", "N037 46.5", "é12 34.567" ] {
+            assert_eq!(adif_parse_location(bad), None, "location {}", bad);
+        }
+
+        let rec = qso(&[ ("gridsquare", "CM87"), ("lat", "N037 46.500"),
+            ("lon", "W122 25.200"), ("my_gridsquare", "FN42") ]);
+        assert_eq!(adif_record_location(&rec, false), Some((37.775, -122.42)));
+        assert_eq!(adif_record_location(&rec, true), Some((42.5, -71.0)));
+        let rec = qso(&[ ("gridsquare", "CM87"), ("lat", "N037 46.500") ]);
+        assert_eq!(adif_record_location(&rec, false), Some((37.5, -123.0)));
+    }
+
+    #[test]
+    fn paths() {
+        let boston = (42.36, -71.06);
+        let london = (51.51, -0.13);
+        assert!(close(adif_distance_km(boston, london), 5265.0, 5.0));
+        assert!(close(adif_bearing(boston, london), 54.0, 1.0));
+        assert!(close(adif_bearing(london, boston), 288.0, 1.0));
+        assert_eq!(adif_bearing((0.0, 0.0), (0.0, 10.0)), 90.0);
+
+        let path = adif_great_circle(boston, london);
+        assert_eq!(path.len(), 54);
+        assert_eq!(path[0], boston);
+        assert_eq!(path[53], london);
+        assert!(path.iter().all(|p| p.0 >= 42.0 && p.0 <= 54.0));
+
+        let pieces = adif_split_path(&adif_great_circle((21.3, -157.9),
+            (35.7, 139.7)));
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].last().unwrap().1, -180.0);
+        assert_eq!(pieces[1][0].1, 180.0);
+        assert_eq!(pieces[0].last().unwrap().0, pieces[1][0].0);
+    }
+
+    #[test]
+    fn maps() {
+        let records = vec![
+            qso(&[ ("call", "G4ABC"), ("gridsquare", "IO91"),
+                ("my_gridsquare", "FN42"), ("band", "20m") ]),
+            qso(&[ ("call", "JA1XX"), ("gridsquare", "PM95"),
+                ("my_gridsquare", "FN42"), ("band", "40m") ]),
+            qso(&[ ("call", "K1ABC"), ("band", "20m") ])
+        ];
+
+        let mut options = AdifMapOptions::default();
+        let mut out = Vec::new();
+        let diagnostics = adif_map_write(&records, &options, &mut out)
+            .unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].adid_record, Some(3));
+        let text = String::from_utf8(out).unwrap();
+        let value = json_parse(&text).unwrap().to_string();
+        assert!(value.starts_with("{\"type\":\"FeatureCollection\",\
+            \"features\":[{\"type\":\"Feature\",\"geometry\":{\"type\":\
+            \"Point\",\"coordinates\":[-71,42.5]},\"properties\":\
+            {\"station\":true}},{\"type\":\"Feature\",\"geometry\":\
+            {\"type\":\"Point\",\"coordinates\":[-1,51.5]},\"properties\":\
+            {\"call\":\"G4ABC\",\"band\":\"20m\",\"gridsquare\":\"IO91\",\
+            \"distance_km\":5194,\"bearing\":53}}"), "{}", value);
+        assert_eq!(value.matches("\"LineString\"").count(), 1);
+        assert_eq!(value.matches("\"MultiLineString\"").count(), 1);
+
+        options.amap_format = AdifMapFormat::AMF_KML;
+        options.amap_paths = false;
+        options.amap_filter = Some(adif_parse_filter("band = 20m")
+            .unwrap());
+        let mut out = Vec::new();
+        adif_map_write(&records, &options, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("<name>G4ABC</name>"));
+        assert!(text.contains("<Data name=\"distance_km\"><value>5194</value>\
+            </Data>"));
+        assert!(text.contains("<coordinates>-1,51.5</coordinates>"));
+        assert!(!text.contains("JA1XX"));
+        assert!(!text.contains("LineString"));
+    }
+}
@@ -0,0 +1,450 @@
+//
+// src/json.rs: minimal JSON support
+//
+// Several tools emit machine-readable output as JSON, and "adif convert" can
+// read records from JSON.  Rather than take on a dependency, this file
+// provides a small value type, a serializer, and a parser.
+//
+
+use std::fmt;
+
+use adif::AdifRecord;
+use super::AdifParseError;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    JSV_NULL,
+    JSV_BOOL(bool),
+    JSV_NUMBER(String),                     // text, as it was written
+    JSV_STRING(String),
+    JSV_ARRAY(Vec<JsonValue>),
+    JSV_OBJECT(Vec<(String, JsonValue)>)    // preserves property order
+}
+
+//
+// Convenience constructors
+//
+pub fn json_string(s: &str) -> JsonValue
+{
+    JsonValue::JSV_STRING(s.to_string())
+}
+
+pub fn json_optional_string(s: &Option<String>) -> JsonValue
+{
+    match s {
+        Some(s) => JsonValue::JSV_STRING(s.clone()),
+        None => JsonValue::JSV_NULL
+    }
+}
+
+//
+// JSON has no representation for NaN or infinity, so those become null.
+// Integral values are written without a fractional part.
+//
+pub fn json_number<T: Into<f64>>(n: T) -> JsonValue
+{
+    let n = n.into();
+    if !n.is_finite() {
+        JsonValue::JSV_NULL
+    } else if n.fract() == 0.0 && n.abs() < 1e15 {
+        JsonValue::JSV_NUMBER((n as i64).to_string())
+    } else {
+        JsonValue::JSV_NUMBER(n.to_string())
+    }
+}
+
+pub fn json_object(properties: Vec<(&str, JsonValue)>) -> JsonValue
+{
+    JsonValue::JSV_OBJECT(properties.into_iter().map(
+        |(k, v)| (k.to_string(), v)).collect())
+}
+
+//
+// Represent a record as an object whose properties are the record's fields.  A
+// field with multiple values (see AdifDuplicatePolicy) is represented as an
+// array of those values.
+//
+pub fn json_record(rec: &AdifRecord) -> JsonValue
+{
+    JsonValue::JSV_OBJECT(rec.adir_field_values.iter().map(|(k, v)| {
+        let value = match rec.adir_extra_values.get(k) {
+            None => json_string(v),
+            Some(extras) => JsonValue::JSV_ARRAY(
+                std::iter::once(v).chain(extras.iter())
+                .map(|e| json_string(e)).collect())
+        };
+        (k.clone(), value)
+    }).collect())
+}
+
+//
+// Write "s" as a quoted JSON string.
+//
+pub fn json_write_string(f: &mut dyn fmt::Write, s: &str) -> fmt::Result
+{
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?
+        }
+    }
+    f.write_char('"')
+}
+
+//
+// Values are serialized compactly (with no whitespace), which makes them
+// suitable for one-value-per-line output.
+//
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonValue::JSV_NULL => write!(f, "null"),
+            JsonValue::JSV_BOOL(b) => write!(f, "{}", b),
+            JsonValue::JSV_NUMBER(n) => write!(f, "{}", n),
+            JsonValue::JSV_STRING(s) => json_write_string(f, s),
+            JsonValue::JSV_ARRAY(values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            },
+            JsonValue::JSV_OBJECT(properties) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in properties.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    json_write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+//
+// Parse a complete JSON document.  On failure, the error describes the problem
+// and the 1-based line on which it was found.
+//
+pub fn json_parse(text: &str) -> Result<JsonValue, AdifParseError>
+{
+    let mut parser = JsonParser { jp_text: text.as_bytes(), jp_pos: 0 };
+    let value = parser.value(0).map_err(AdifParseError::ADIF_EBADINPUT)?;
+    parser.skip_whitespace();
+    if parser.jp_pos < parser.jp_text.len() {
+        return Err(AdifParseError::ADIF_EBADINPUT(parser.error(
+            "unexpected text after value")));
+    }
+    Ok(value)
+}
+
+//
+// Arrays and objects nested deeper than this are rejected rather than risk
+// overflowing the stack.
+//
+const JSON_MAX_DEPTH : usize = 64;
+
+struct JsonParser<'a> {
+    jp_text : &'a [u8],
+    jp_pos : usize              // byte offset of the next character
+}
+
+impl<'a> JsonParser<'a> {
+    fn error(&self, message: &str) -> String
+    {
+        let end = self.jp_pos.min(self.jp_text.len());
+        let line = self.jp_text[..end].iter().filter(|&&b| b == b'\n')
+            .count() + 1;
+        format!("line {}: {}", line, message)
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        while self.jp_pos < self.jp_text.len() &&
+            b" \t\r\n".contains(&self.jp_text[self.jp_pos]) {
+            self.jp_pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8>
+    {
+        self.skip_whitespace();
+        self.jp_text.get(self.jp_pos).cloned()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String>
+    {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c as char)));
+        }
+        self.jp_pos += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word: &str, value: JsonValue) ->
+        Result<JsonValue, String>
+    {
+        if !self.jp_text[self.jp_pos..].starts_with(word.as_bytes()) {
+            return Err(self.error("invalid value"));
+        }
+        self.jp_pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<JsonValue, String>
+    {
+        if depth > JSON_MAX_DEPTH {
+            return Err(self.error("values nested too deeply"));
+        }
+
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some(b'n') => self.keyword("null", JsonValue::JSV_NULL),
+            Some(b't') => self.keyword("true", JsonValue::JSV_BOOL(true)),
+            Some(b'f') => self.keyword("false", JsonValue::JSV_BOOL(false)),
+            Some(b'"') => Ok(JsonValue::JSV_STRING(self.string()?)),
+            Some(b'[') => {
+                self.jp_pos += 1;
+                let mut values = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.jp_pos += 1;
+                    return Ok(JsonValue::JSV_ARRAY(values));
+                }
+                loop {
+                    values.push(self.value(depth + 1)?);
+                    match self.peek() {
+                        Some(b',') => self.jp_pos += 1,
+                        Some(b']') => {
+                            self.jp_pos += 1;
+                            return Ok(JsonValue::JSV_ARRAY(values));
+                        },
+                        _ => return Err(self.error("expected ',' or ']'"))
+                    }
+                }
+            },
+            Some(b'{') => {
+                self.jp_pos += 1;
+                let mut properties = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.jp_pos += 1;
+                    return Ok(JsonValue::JSV_OBJECT(properties));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected property name"));
+                    }
+                    let name = self.string()?;
+                    self.expect(b':')?;
+                    properties.push((name, self.value(depth + 1)?));
+                    match self.peek() {
+                        Some(b',') => self.jp_pos += 1,
+                        Some(b'}') => {
+                            self.jp_pos += 1;
+                            return Ok(JsonValue::JSV_OBJECT(properties));
+                        },
+                        _ => return Err(self.error("expected ',' or '}'"))
+                    }
+                }
+            },
+            Some(_) => self.number()
+        }
+    }
+
+    //
+    // Parse a number.  We keep the text as it was written (rather than
+    // converting it to a floating-point value) so that, e.g., "14.0740" and
+    // large integers are preserved exactly.  The syntax is:
+    //
+    //     -?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?
+    //
+    fn number(&mut self) -> Result<JsonValue, String>
+    {
+        let start = self.jp_pos;
+        let digits = |p: &mut JsonParser| -> usize {
+            let begin = p.jp_pos;
+            while p.jp_text.get(p.jp_pos).is_some_and(|c| c.is_ascii_digit()) {
+                p.jp_pos += 1;
+            }
+            p.jp_pos - begin
+        };
+        let skip = |p: &mut JsonParser, chars: &[u8]| -> bool {
+            match p.jp_text.get(p.jp_pos) {
+                Some(c) if chars.contains(c) => {
+                    p.jp_pos += 1;
+                    true
+                },
+                _ => false
+            }
+        };
+
+        skip(self, b"-");
+        let leading_zero = self.jp_text.get(self.jp_pos) == Some(&b'0');
+        let nwhole = digits(self);
+        let mut valid = nwhole == 1 || (nwhole > 1 && !leading_zero);
+        if valid && skip(self, b".") {
+            valid = digits(self) > 0;
+        }
+        if valid && skip(self, b"eE") {
+            skip(self, b"+-");
+            valid = digits(self) > 0;
+        }
+
+        if !valid {
+            self.jp_pos = start;
+            return Err(self.error("invalid value"));
+        }
+
+        Ok(JsonValue::JSV_NUMBER(String::from_utf8_lossy(
+            &self.jp_text[start..self.jp_pos]).into_owned()))
+    }
+
+    //
+    // Parse a string, starting at the opening quote.
+    //
+    fn string(&mut self) -> Result<String, String>
+    {
+        self.jp_pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let c = match self.jp_text.get(self.jp_pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(&c) => c
+            };
+            self.jp_pos += 1;
+
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.jp_text.get(self.jp_pos).cloned();
+                    self.jp_pos += 1;
+                    let decoded = match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape"))
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend(decoded.encode_utf8(&mut buf).as_bytes());
+                },
+                c if c < 0x20 => {
+                    return Err(self.error("control character in string"));
+                },
+                c => bytes.push(c)
+            }
+        }
+
+        String::from_utf8(bytes).map_err(
+            |_| self.error("invalid UTF-8 in string"))
+    }
+
+    //
+    // Decode the hex digits of a "\u" escape (which may be the first half of a
+    // UTF-16 surrogate pair).
+    //
+    fn unicode_escape(&mut self) -> Result<char, String>
+    {
+        let hex4 = |p: &mut JsonParser| -> Option<u32> {
+            let digits = p.jp_text.get(p.jp_pos..p.jp_pos + 4)?;
+            let value = u32::from_str_radix(
+                std::str::from_utf8(digits).ok()?, 16).ok()?;
+            p.jp_pos += 4;
+            Some(value)
+        };
+
+        let high = match hex4(self) {
+            Some(v) => v,
+            None => return Err(self.error("invalid \\u escape"))
+        };
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.jp_text[self.jp_pos..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate in \\u escape"));
+            }
+            self.jp_pos += 2;
+            match hex4(self) {
+                Some(low) if (0xdc00..0xe000).contains(&low) => {
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                },
+                _ => return Err(self.error("unpaired surrogate in \\u escape"))
+            }
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(
+            || self.error("invalid character in \\u escape"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serialize() {
+        let value = json_object(vec![
+            ("call", json_string("KK6ZBI")),
+            ("comment", json_string("said \"hi\"\n")),
+            ("freq", json_number(14.074)),
+            ("count", json_number(3)),
+            ("missing", json_number(f64::NAN)),
+            ("list", JsonValue::JSV_ARRAY(vec![ JsonValue::JSV_BOOL(true) ]))
+        ]);
+        assert_eq!(value.to_string(), "{\"call\":\"KK6ZBI\",\
+            \"comment\":\"said \\\"hi\\\"\\n\",\"freq\":14.074,\"count\":3,\
+            \"missing\":null,\"list\":[true]}");
+    }
+
+    #[test]
+    fn parse() {
+        let text = "{\"call\": \"KK6ZBI\", \"freq\": 14.074,\n\
+            \"tags\": [true, false, null],\
+            \"note\": \"\\u00e9\\ud83d\\ude00\\n\", \"empty\": {}}";
+        let value = json_parse(text).unwrap();
+        assert_eq!(value, JsonValue::JSV_OBJECT(vec![
+            (String::from("call"), json_string("KK6ZBI")),
+            (String::from("freq"), json_number(14.074)),
+            (String::from("tags"), JsonValue::JSV_ARRAY(vec![
+                JsonValue::JSV_BOOL(true), JsonValue::JSV_BOOL(false),
+                JsonValue::JSV_NULL ])),
+            (String::from("note"), json_string("\u{e9}\u{1f600}\n")),
+            (String::from("empty"), JsonValue::JSV_OBJECT(vec![]))
+        ]));
+        assert_eq!(json_parse(&value.to_string()).unwrap(), value);
+
+        assert_eq!(json_parse("[1, 2").unwrap_err().to_string(),
+            "input error: line 1: expected ',' or ']'");
+        assert_eq!(json_parse("{\n\"a\": tru}").unwrap_err().to_string(),
+            "input error: line 2: invalid value");
+        assert!(json_parse("\"\\ud800\"").is_err());
+        assert!(json_parse("1 2").is_err());
+
+        //
+        // Numbers keep their original text.
+        //
+        for text in &[ "14.0740", "1e5", "-0.5E-3", "12345678901234567890" ] {
+            assert_eq!(json_parse(text).unwrap(),
+                JsonValue::JSV_NUMBER(text.to_string()));
+        }
+        for text in &[ "01", "+1", "1.", ".5", "1e", "-", "1.5e+" ] {
+            assert!(json_parse(text).is_err(), "{}", text);
+        }
+    }
+}
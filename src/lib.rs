@@ -11,8 +11,8 @@
 // currently two physical file formats: ADI (a somewhat baroque format described
 // originally in version 1, which dates back to 1996) and ADX (a more modern
 // XML-based format).  ADI appears to be more widely used, while ADX is marked
-// optional in the standard.  For that reason, this crate currently only seeks
-// to implement ADI.
+// optional in the standard.  This crate reads and writes both (see src/adx.rs
+// for ADX), and it can also read and write CSV and JSON (see src/convert.rs).
 //
 // Section II.A ("Upward Compatibility") guarantees that "an ADIF file compliant
 // with ADIF version N will comply with any future ADIF version M where M>N."
@@ -22,16 +22,249 @@
 use std::io;
 use std::fmt;
 
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+
 mod adi;
 mod adif;
+mod adiftypes;
 mod adifutil;
+mod adx;
+mod awards;
+mod cabrillo;
+mod callsign;
+mod contest;
+mod convert;
+mod csv;
+mod csvimport;
+mod dedupe;
+mod diff;
+mod dump;
+mod fields;
+mod filter;
+mod geo;
+mod json;
+mod merge;
+mod patch;
+mod qsl;
+mod reconcile;
+mod regex;
+mod report;
+mod sort;
+mod split;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod validate;
 
 //
 // TODO decide whether there's a cleaner way to structure this.
 //
-pub use adif::AdifDumpWhichRecords;
+pub use adif::AdifDiagnostic;
+pub use adif::AdifDuplicatePolicy;
+pub use adif::AdifFieldState;
+pub use adif::AdifFile;
+pub use adif::AdifHeader;
+pub use adif::AdifHeaderField;
+pub use adif::AdifParseOptions;
 pub use adif::AdifRecord;
-pub use adif::adif_dump;
+pub use adif::AdifUserdef;
+pub use adif::adif_header_created_timestamp;
+pub use adif::adif_header_program_id;
+pub use adif::adif_header_program_version;
+pub use adif::adif_header_set;
+pub use adif::adif_header_unknown_fields;
+pub use adif::adif_header_userdefs;
+pub use adif::adif_header_value;
+pub use adif::adif_header_version;
+pub use adif::adif_parse_duplicate_policy;
+pub use adif::adif_record_field;
+pub use adx::adx_escape;
+pub use adx::adx_parse;
+pub use awards::ADIF_AWARDS;
+pub use awards::ADIF_WAS_STATES;
+pub use awards::AdifAward;
+pub use awards::AdifAwardOptions;
+pub use awards::AdifAwardProgress;
+pub use awards::AdifAwardStatus;
+pub use awards::adif_award_entities;
+pub use awards::adif_award_name;
+pub use awards::adif_award_progress;
+pub use awards::adif_mode_group;
+pub use awards::adif_parse_award;
+pub use cabrillo::AdifCabrilloExchange;
+pub use cabrillo::AdifCabrilloImport;
+pub use cabrillo::AdifCabrilloOptions;
+pub use cabrillo::adif_cabrillo_exchange;
+pub use cabrillo::adif_cabrillo_mode;
+pub use cabrillo::adif_cabrillo_parse;
+pub use cabrillo::adif_cabrillo_write;
+pub use cabrillo::adif_parse_cabrillo_exchange;
+pub use callsign::AdifCallsign;
+pub use callsign::AdifCtyDatabase;
+pub use callsign::AdifCtyEntity;
+pub use callsign::AdifCtyLookup;
+pub use callsign::adif_cty_fill;
+pub use callsign::adif_cty_lookup;
+pub use callsign::adif_cty_parse_csv;
+pub use callsign::adif_cty_parse_dat;
+pub use callsign::adif_parse_callsign;
+pub use callsign::adif_wpx_prefix;
+pub use contest::ADIF_CONTEST_RULES;
+pub use contest::AdifContestMultiplier;
+pub use contest::AdifContestQso;
+pub use contest::AdifContestRules;
+pub use contest::AdifContestScore;
+pub use contest::AdifContestStatus;
+pub use contest::AdifContestTotals;
+pub use contest::adif_contest_rules;
+pub use contest::adif_contest_score;
+pub use contest::adif_parse_contest_rule;
+pub use contest::adif_parse_contest_rules;
+pub use convert::AdifReadOptions;
+pub use convert::adif_conversion_losses;
+pub use convert::adif_format_for_filename;
+pub use convert::adif_read;
+pub use csv::csv_line;
+pub use csv::csv_parse;
+pub use csv::csv_quote;
+pub use csvimport::AdifCsvBadRow;
+pub use csvimport::AdifCsvColumn;
+pub use csvimport::AdifCsvConversion;
+pub use csvimport::AdifCsvImport;
+pub use csvimport::AdifCsvMapping;
+pub use csvimport::adif_csv_import;
+pub use csvimport::adif_parse_csv_mapping;
+pub use csvimport::adif_parse_csv_mapping_line;
+pub use dedupe::AdifDedupeCluster;
+pub use dedupe::AdifDedupeOptions;
+pub use dedupe::AdifDedupePrecedence;
+pub use dedupe::AdifDedupeResult;
+pub use dedupe::adif_dedupe;
+pub use dedupe::adif_parse_dedupe_precedence;
+pub use dedupe::adif_record_confirmations;
+pub use diff::AdifCompareMode;
+pub use diff::AdifDiffOptions;
+pub use diff::AdifFieldDiff;
+pub use diff::AdifMatchOptions;
+pub use diff::AdifMatchResult;
+pub use diff::adif_diff_records;
+pub use diff::adif_match_records;
+pub use diff::adif_parse_compare_mode;
+pub use diff::adif_qso_summary;
+pub use diff::adif_record_timestamp;
+pub use diff::adif_values_equal;
+pub use dump::AdifDumpFormat;
+pub use dump::AdifDumpOptions;
+pub use dump::AdifDumpWhichRecords;
+pub use dump::adif_dump;
+pub use dump::adif_parse_dump_format;
+pub use fields::AdifDataType;
+pub use fields::adif_band_compare;
+pub use fields::adif_band_range;
+pub use fields::adif_field_type;
+pub use fields::adif_freq_band;
+pub use fields::adif_typed_compare;
+pub use fields::adif_typed_equal;
+pub use filter::AdifFilter;
+pub use filter::AdifFilterOp;
+pub use filter::adif_filter_matches;
+pub use filter::adif_parse_filter;
+pub use geo::AdifMapFormat;
+pub use geo::AdifMapOptions;
+pub use geo::adif_bearing;
+pub use geo::adif_distance_km;
+pub use geo::adif_great_circle;
+pub use geo::adif_map_write;
+pub use geo::adif_parse_gridsquare;
+pub use geo::adif_parse_location;
+pub use geo::adif_parse_map_format;
+pub use geo::adif_record_location;
+pub use json::JsonValue;
+pub use json::json_number;
+pub use json::json_object;
+pub use json::json_optional_string;
+pub use json::json_parse;
+pub use json::json_record;
+pub use json::json_string;
+pub use merge::AdifConflictKind;
+pub use merge::AdifConflictStrategy;
+pub use merge::AdifMergeConflict;
+pub use merge::AdifMergeOptions;
+pub use merge::AdifMergeResult;
+pub use merge::adif_merge;
+pub use merge::adif_parse_conflict_strategy;
+pub use patch::AdifPatchResult;
+pub use patch::adif_patch_apply;
+pub use patch::adif_patch_create;
+pub use qsl::ADIF_QSL_SERVICES;
+pub use qsl::ADIF_QSL_STATES;
+pub use qsl::AdifQslDirection;
+pub use qsl::AdifQslService;
+pub use qsl::AdifQslServiceReport;
+pub use qsl::AdifQslState;
+pub use qsl::AdifQslUpdate;
+pub use qsl::AdifQslUpdateSummary;
+pub use qsl::adif_parse_qsl_service;
+pub use qsl::adif_parse_qsl_state;
+pub use qsl::adif_parse_qsl_update;
+pub use qsl::adif_qsl_report;
+pub use qsl::adif_qsl_service_name;
+pub use qsl::adif_qsl_state;
+pub use qsl::adif_qsl_state_name;
+pub use qsl::adif_qsl_update;
+pub use qsl::adif_qsl_update_records;
+pub use reconcile::AdifReconcileOptions;
+pub use reconcile::AdifReconcileResult;
+pub use reconcile::adif_reconcile;
+pub use reconcile::adif_reconcile_service;
+pub use report::AdifReport;
+pub use report::AdifReportCounts;
+pub use report::AdifReportMatrix;
+pub use report::AdifReportRow;
+pub use report::adif_report;
+pub use report::adif_report_matrix;
+pub use report::adif_report_value;
+pub use sort::AdifRecordGroup;
+pub use sort::AdifSortKey;
+pub use sort::adif_group_records;
+pub use sort::adif_parse_sort_keys;
+pub use sort::adif_record_compare;
+pub use sort::adif_sort_compare;
+pub use sort::adif_sort_records;
+pub use split::AdifSplitOptions;
+pub use split::AdifSplitPart;
+pub use split::adif_split;
+pub use split::adif_split_names;
+#[cfg(feature = "sqlite")]
+pub use sqlite::ADIF_STORE_COLUMNS;
+#[cfg(feature = "sqlite")]
+pub use sqlite::AdifStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::AdifStoreOptions;
+#[cfg(feature = "sqlite")]
+pub use sqlite::AdifStoreResult;
+#[cfg(feature = "sqlite")]
+pub use sqlite::adif_store_export;
+#[cfg(feature = "sqlite")]
+pub use sqlite::adif_store_open;
+#[cfg(feature = "sqlite")]
+pub use sqlite::adif_store_upsert;
+pub use validate::adif_validate;
+pub use validate::adif_validate_record;
+pub use validate::adif_validate_value;
+pub use adiftypes::AdifDate;
+pub use adiftypes::AdifTime;
+pub use adiftypes::AdifTimestamp;
+pub use adiftypes::AdifVersion;
+pub use adiftypes::adif_date_from_days;
+pub use adiftypes::adif_date_to_days;
+pub use adiftypes::adif_parse_date;
+pub use adiftypes::adif_parse_time;
+pub use adiftypes::adif_parse_timestamp;
+pub use adiftypes::adif_parse_version;
+pub use adiftypes::adif_timestamp_from_seconds;
+pub use adiftypes::adif_timestamp_now;
+pub use adiftypes::adif_timestamp_to_seconds;
 
 //
 // AdifParseError is used to represent any sort of operational error we may
@@ -56,7 +289,7 @@ impl fmt::Display for AdifParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             AdifParseError::ADIF_EIO(ioerror) => {
-                write!(f, "{}", ioerror.to_string())
+                write!(f, "{}", ioerror)
             },
             AdifParseError::ADIF_EBADINPUT(message) => {
                 write!(f, "input error: {}", message)
@@ -68,9 +301,33 @@ impl fmt::Display for AdifParseError {
     }
 }
 
-pub fn adif_parse(label: &str, source: &mut io::Read) ->
+pub fn adif_parse(label: &str, source: &mut dyn io::Read) ->
     Result<adif::AdifFile, AdifParseError>
+{
+    adif_parse_options(label, source, &AdifParseOptions::default())
+}
+
+pub fn adif_parse_options(label: &str, source: &mut dyn io::Read,
+    options: &AdifParseOptions) -> Result<adif::AdifFile, AdifParseError>
 {
     let adi = adi::adi_parse(source)?;
-    adif::adif_parse_adi(label, &adi)
+    adif::adif_parse_adi(label, &adi, options)
+}
+
+//
+// Write the given file in ADI format.
+//
+pub fn adif_write(adif: &adif::AdifFile, out: &mut dyn io::Write) ->
+    Result<(), AdifParseError>
+{
+    adi::adi_write(&adif::adif_to_adi(adif), out)
+}
+
+//
+// Write the given file in ADX format.
+//
+pub fn adif_write_adx(adif: &adif::AdifFile, out: &mut dyn io::Write) ->
+    Result<(), AdifParseError>
+{
+    adx::adx_write(adif, out)
 }
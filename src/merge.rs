@@ -0,0 +1,514 @@
+//
+// src/merge.rs: three-way merge of ADIF files
+//
+// Given a "base" log and two logs derived from it ("left" and "right"), we
+// produce a merged log containing the changes made in both.  Records are
+// matched up using the same facility as "adif diff" (see src/diff.rs): base
+// records are matched against each of the derived logs, and records that are
+// new in both derived logs are matched against each other.
+//
+// For each field of a record, if only one side changed the value (relative to
+// the base), we take that side's value.  If both sides made the same change,
+// we take it.  Otherwise, the field is in conflict.  Records can also conflict:
+// one side may have deleted a record that the other side modified.  Conflicts
+// are resolved according to an AdifConflictStrategy, which can be specified
+// for all fields and overridden for individual fields.  Conflicts that remain
+// unresolved keep the left value, and the merged record is marked with an
+// "APP_ADIFMERGE_CONFLICT" field listing the conflicting fields so that they
+// can be found and fixed by hand.
+//
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use adif::AdifFile;
+use adif::AdifRecord;
+use adiftypes::AdifDate;
+use adiftypes::adif_parse_date;
+use diff::AdifDiffOptions;
+use diff::AdifMatchOptions;
+use diff::adif_diff_records;
+use diff::adif_match_records;
+use diff::adif_qso_summary;
+use diff::adif_values_equal;
+
+const ADIF_MERGE_CONFLICT : &str = "app_adifmerge_conflict";
+
+//
+// Fields that record when a QSL was received.  These are used to implement
+// ACS_PREFER_NEWEST_QSL.
+//
+const ADIF_MERGE_QSL_DATES : &[&str] = &[
+    "qslrdate", "lotw_qslrdate", "eqsl_qslrdate"
+];
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AdifConflictStrategy {
+    #[default]
+    ACS_NONE,                   // leave the conflict unresolved
+    ACS_PREFER_LEFT,            // take the left value
+    ACS_PREFER_RIGHT,           // take the right value
+    ACS_PREFER_NEWEST_QSL       // take the value from the record whose most
+                                // recent QSL was received most recently
+}
+
+//
+// Parse the name of a conflict strategy, as a user would specify it on the
+// command line.
+//
+pub fn adif_parse_conflict_strategy(name: &str) -> Option<AdifConflictStrategy>
+{
+    match name {
+        "none" => Some(AdifConflictStrategy::ACS_NONE),
+        "left" | "prefer-left" => Some(AdifConflictStrategy::ACS_PREFER_LEFT),
+        "right" | "prefer-right" =>
+            Some(AdifConflictStrategy::ACS_PREFER_RIGHT),
+        "newest-qsl" | "prefer-newest-qsl" =>
+            Some(AdifConflictStrategy::ACS_PREFER_NEWEST_QSL),
+        _ => None
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifMergeOptions {
+    pub amgo_match : AdifMatchOptions,      // how to match up records
+    pub amgo_diff : AdifDiffOptions,        // how to compare field values
+    pub amgo_strategy : AdifConflictStrategy,   // default strategy
+    pub amgo_field_strategies : BTreeMap<String, AdifConflictStrategy>
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdifConflictKind {
+    ACK_FIELD,          // both sides changed a field differently
+    ACK_DELETE_LEFT,    // left deleted a record that right modified
+    ACK_DELETE_RIGHT    // right deleted a record that left modified
+}
+
+//
+// AdifMergeConflict: describes one conflict.  For record-level conflicts
+// (ACK_DELETE_*), "amc_field" is empty and the values are not used.
+//
+#[derive(Clone, Debug)]
+pub struct AdifMergeConflict {
+    pub amc_kind : AdifConflictKind,
+    pub amc_summary : String,           // summary of the QSO (for reporting)
+    pub amc_record : Option<usize>,     // index of merged record, if any
+    pub amc_field : String,             // field in conflict
+    pub amc_base : Option<String>,      // base value (None if absent)
+    pub amc_left : Option<String>,      // left value (None if absent)
+    pub amc_right : Option<String>,     // right value (None if absent)
+    pub amc_resolution : Option<AdifConflictStrategy> // None if unresolved
+}
+
+#[derive(Debug)]
+pub struct AdifMergeResult {
+    pub amgr_file : AdifFile,           // merged log
+    pub amgr_conflicts : Vec<AdifMergeConflict>,
+    pub amgr_added : usize,             // records added by either side
+    pub amgr_deleted : usize,           // records deleted by either side
+    pub amgr_changed : usize            // base records changed by either side
+}
+
+//
+// Returns the date of the most recent QSL received for this QSO, if any.
+//
+fn adif_merge_newest_qsl(rec: &AdifRecord) -> Option<AdifDate>
+{
+    ADIF_MERGE_QSL_DATES.iter().filter_map(|f| {
+        adif_parse_date(rec.adir_field_values.get(*f)?)
+    }).max()
+}
+
+fn adif_merge_equal(field: &str, v1: Option<&String>, v2: Option<&String>,
+    options: &AdifMergeOptions) -> bool
+{
+    let diff = &options.amgo_diff;
+    let mode = diff.ado_field_modes.get(field).unwrap_or(&diff.ado_mode);
+    match (v1, v2) {
+        (None, None) => true,
+        (Some(a), Some(b)) => adif_values_equal(field, a, b, mode),
+        _ => false
+    }
+}
+
+//
+// Decide which side wins a conflict.  Returns Some(true) for left, Some(false)
+// for right, and None if the conflict remains unresolved.
+//
+fn adif_merge_resolve(strategy: AdifConflictStrategy, left: &AdifRecord,
+    right: &AdifRecord) -> Option<bool>
+{
+    match strategy {
+        AdifConflictStrategy::ACS_NONE => None,
+        AdifConflictStrategy::ACS_PREFER_LEFT => Some(true),
+        AdifConflictStrategy::ACS_PREFER_RIGHT => Some(false),
+        AdifConflictStrategy::ACS_PREFER_NEWEST_QSL => {
+            let lq = adif_merge_newest_qsl(left);
+            let rq = adif_merge_newest_qsl(right);
+            if lq > rq {
+                Some(true)
+            } else if rq > lq {
+                Some(false)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+//
+// Merge the fields of two records that both derive from "base" (which is None
+// for records that are new on both sides).  Conflicts are appended to
+// "conflicts" (with the merged record index filled in).
+//
+fn adif_merge_records(base: Option<&AdifRecord>, left: &AdifRecord,
+    right: &AdifRecord, options: &AdifMergeOptions, which: usize,
+    conflicts: &mut Vec<AdifMergeConflict>) -> AdifRecord
+{
+    let empty = AdifRecord::default();
+    let base = base.unwrap_or(&empty);
+    let mut merged = AdifRecord::default();
+    let mut unresolved = Vec::new();
+
+    let names : BTreeSet<&String> = base.adir_field_values.keys()
+        .chain(left.adir_field_values.keys())
+        .chain(right.adir_field_values.keys())
+        .filter(|n| n.as_str() != ADIF_MERGE_CONFLICT).collect();
+
+    for name in names {
+        let vb = base.adir_field_values.get(name);
+        let vl = left.adir_field_values.get(name);
+        let vr = right.adir_field_values.get(name);
+
+        let (value, from_left) = if adif_merge_equal(name, vl, vr, options) {
+            (vl, true)
+        } else if adif_merge_equal(name, vb, vl, options) {
+            (vr, false)
+        } else if adif_merge_equal(name, vb, vr, options) {
+            (vl, true)
+        } else {
+            let strategy = *options.amgo_field_strategies.get(name)
+                .unwrap_or(&options.amgo_strategy);
+            let resolution = adif_merge_resolve(strategy, left, right);
+            conflicts.push(AdifMergeConflict {
+                amc_kind: AdifConflictKind::ACK_FIELD,
+                amc_summary: String::new(),
+                amc_record: Some(which),
+                amc_field: name.clone(),
+                amc_base: vb.cloned(),
+                amc_left: vl.cloned(),
+                amc_right: vr.cloned(),
+                amc_resolution: resolution.map(|_| strategy)
+            });
+            match resolution {
+                Some(true) => (vl, true),
+                Some(false) => (vr, false),
+                None => {
+                    unresolved.push(name.clone());
+                    (vl, true)
+                }
+            }
+        };
+
+        if let Some(v) = value {
+            merged.adir_field_values.insert(name.clone(), v.clone());
+            let side = if from_left { left } else { right };
+            if let Some(extras) = side.adir_extra_values.get(name) {
+                merged.adir_extra_values.insert(name.clone(), extras.clone());
+            }
+        }
+    }
+
+    if !unresolved.is_empty() {
+        merged.adir_field_values.insert(ADIF_MERGE_CONFLICT.to_string(),
+            unresolved.join(","));
+    }
+
+    merged
+}
+
+//
+// Handle a base record that one side deleted.  "kept" is the other side's
+// version of the record.  Returns the record to keep, if any.
+//
+fn adif_merge_deleted(base: &AdifRecord, kept: &AdifRecord, kept_left: bool,
+    options: &AdifMergeOptions, which: usize,
+    conflicts: &mut Vec<AdifMergeConflict>) -> Option<AdifRecord>
+{
+    if adif_diff_records(base, kept, &options.amgo_diff).is_empty() {
+        return None;
+    }
+
+    //
+    // The other side modified the record, so this is a conflict.  Only the
+    // default strategy applies here, since there's no single field involved.
+    //
+    let keep = match options.amgo_strategy {
+        AdifConflictStrategy::ACS_PREFER_LEFT => Some(kept_left),
+        AdifConflictStrategy::ACS_PREFER_RIGHT => Some(!kept_left),
+        _ => None
+    };
+
+    let mut rec = kept.clone();
+    conflicts.push(AdifMergeConflict {
+        amc_kind: if kept_left {
+            AdifConflictKind::ACK_DELETE_RIGHT
+        } else {
+            AdifConflictKind::ACK_DELETE_LEFT
+        },
+        amc_summary: String::new(),
+        amc_record: if keep == Some(false) { None } else { Some(which) },
+        amc_field: String::new(),
+        amc_base: None,
+        amc_left: None,
+        amc_right: None,
+        amc_resolution: keep.map(|_| options.amgo_strategy)
+    });
+
+    match keep {
+        Some(false) => None,
+        Some(true) => Some(rec),
+        None => {
+            rec.adir_field_values.insert(ADIF_MERGE_CONFLICT.to_string(),
+                String::from("*"));
+            Some(rec)
+        }
+    }
+}
+
+//
+// Merge "left" and "right", which both derive from "base".  See the comment at
+// the top of this file for details.
+//
+pub fn adif_merge(base: &AdifFile, left: &AdifFile, right: &AdifFile,
+    options: &AdifMergeOptions) -> AdifMergeResult
+{
+    let mopts = &options.amgo_match;
+    let mleft = adif_match_records(&base.adif_records, &left.adif_records,
+        mopts);
+    let mright = adif_match_records(&base.adif_records, &right.adif_records,
+        mopts);
+
+    let mut left_of = vec![ None; base.adif_records.len() ];
+    let mut right_of = vec![ None; base.adif_records.len() ];
+    for &(b, l) in &mleft.amr_matched {
+        left_of[b] = Some(l);
+    }
+    for &(b, r) in &mright.amr_matched {
+        right_of[b] = Some(r);
+    }
+
+    let mut records = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut summaries = Vec::new();
+    let mut added = 0;
+    let mut deleted = 0;
+    let mut changed = 0;
+
+    for (b, brec) in base.adif_records.iter().enumerate() {
+        let nconflicts = conflicts.len();
+        let which = records.len();
+        let merged = match (left_of[b], right_of[b]) {
+            (Some(l), Some(r)) => Some(adif_merge_records(Some(brec),
+                &left.adif_records[l], &right.adif_records[r], options,
+                which, &mut conflicts)),
+            (Some(l), None) => adif_merge_deleted(brec, &left.adif_records[l],
+                true, options, which, &mut conflicts),
+            (None, Some(r)) => adif_merge_deleted(brec,
+                &right.adif_records[r], false, options, which, &mut conflicts),
+            (None, None) => None
+        };
+
+        for _ in nconflicts..conflicts.len() {
+            summaries.push(adif_qso_summary(brec));
+        }
+
+        match merged {
+            None => deleted += 1,
+            Some(rec) => {
+                if adif_diff_records(brec, &rec, &options.amgo_diff)
+                    .iter().any(|d| d.afd_field != ADIF_MERGE_CONFLICT) {
+                    changed += 1;
+                }
+                records.push(rec);
+            }
+        }
+    }
+
+    //
+    // Records added on both sides may describe the same QSO, so match those up
+    // too.  Matched pairs are merged with no base.
+    //
+    let new_left : Vec<AdifRecord> = mleft.amr_right_only.iter().map(
+        |&l| left.adif_records[l].clone()).collect();
+    let new_right : Vec<AdifRecord> = mright.amr_right_only.iter().map(
+        |&r| right.adif_records[r].clone()).collect();
+    let mnew = adif_match_records(&new_left, &new_right, mopts);
+    let mut new_right_of = vec![ None; new_left.len() ];
+    for &(l, r) in &mnew.amr_matched {
+        new_right_of[l] = Some(r);
+    }
+
+    for (l, lrec) in new_left.iter().enumerate() {
+        let nconflicts = conflicts.len();
+        let rec = match new_right_of[l] {
+            Some(r) => adif_merge_records(None, lrec, &new_right[r], options,
+                records.len(), &mut conflicts),
+            None => lrec.clone()
+        };
+        for _ in nconflicts..conflicts.len() {
+            summaries.push(adif_qso_summary(lrec));
+        }
+        records.push(rec);
+        added += 1;
+    }
+
+    for &r in &mnew.amr_right_only {
+        records.push(new_right[r].clone());
+        added += 1;
+    }
+
+    for (conflict, summary) in conflicts.iter_mut().zip(summaries) {
+        conflict.amc_summary = summary;
+    }
+
+    AdifMergeResult {
+        amgr_file: AdifFile {
+            adif_header: base.adif_header.clone(),
+            adif_label: format!("merge of {} and {}", left.adif_label,
+                right.adif_label),
+            adif_diagnostics: Vec::new(),
+            adif_records: records
+        },
+        amgr_conflicts: conflicts,
+        amgr_added: added,
+        amgr_deleted: deleted,
+        amgr_changed: changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifFile;
+    use adif::AdifHeader;
+    use adif::AdifRecord;
+
+    fn make_file(records: Vec<Vec<(&str, &str)>>) -> AdifFile {
+        AdifFile {
+            adif_header: AdifHeader::default(),
+            adif_label: String::from("test"),
+            adif_diagnostics: vec![],
+            adif_records: records.iter().map(|fields| {
+                let mut rec = AdifRecord::default();
+                for (name, value) in fields {
+                    rec.adir_field_values.insert(name.to_string(),
+                        value.to_string());
+                }
+                rec
+            }).collect()
+        }
+    }
+
+    fn qso<'a>(call: &'a str, time: &'a str, extra: &[(&'a str, &'a str)]) ->
+        Vec<(&'a str, &'a str)>
+    {
+        let mut fields = vec![ ("call", call), ("qso_date", "20180623"),
+            ("time_on", time), ("band", "20m"), ("mode", "SSB") ];
+        fields.extend_from_slice(extra);
+        fields
+    }
+
+    #[test]
+    fn merge_three_way() {
+        let base = make_file(vec![
+            qso("W1AW", "1800", &[ ("name", "Hiram"), ("qth", "CT") ]),
+            qso("K1ABC", "1810", &[]),
+            qso("N0CALL", "1820", &[ ("comment", "x") ]),
+        ]);
+        let left = make_file(vec![
+            qso("W1AW", "1800", &[ ("name", "Hiram P"), ("qth", "CT") ]),
+            qso("K1ABC", "1810", &[ ("gridsquare", "FN42") ]),
+            qso("N0CALL", "1820", &[ ("comment", "x") ]),
+            qso("VE3XYZ", "1900", &[ ("comment", "left") ]),
+        ]);
+        let right = make_file(vec![
+            qso("W1AW", "1800", &[ ("name", "Hiram") ]),
+            qso("K1ABC", "1811", &[ ("gridsquare", "FN43") ]),
+            qso("VE3XYZ", "1901", &[ ("comment", "right") ]),
+            qso("G4ABC", "1930", &[]),
+        ]);
+
+        let options = super::AdifMergeOptions::default();
+        let result = super::adif_merge(&base, &left, &right, &options);
+        let records = &result.amgr_file.adif_records;
+        assert_eq!(records.len(), 4);
+        assert_eq!(result.amgr_deleted, 1);
+        assert_eq!(result.amgr_added, 2);
+
+        // Non-conflicting changes from both sides are merged.
+        assert_eq!(records[0].adir_field_values["name"], "Hiram P");
+        assert!(!records[0].adir_field_values.contains_key("qth"));
+
+        // Conflicting changes are reported and marked.
+        let conflicts = &result.amgr_conflicts;
+        let fields : Vec<&str> = conflicts.iter().map(
+            |c| c.amc_field.as_str()).collect();
+        assert_eq!(fields, vec![ "gridsquare", "comment", "time_on" ]);
+        assert!(conflicts.iter().all(|c| c.amc_resolution.is_none()));
+        assert_eq!(records[1].adir_field_values["gridsquare"], "FN42");
+        assert_eq!(records[1].adir_field_values["time_on"], "1811");
+        assert_eq!(records[1].adir_field_values["app_adifmerge_conflict"],
+            "gridsquare");
+
+        let options = super::AdifMergeOptions {
+            amgo_strategy: super::AdifConflictStrategy::ACS_PREFER_RIGHT,
+            ..Default::default()
+        };
+        let result = super::adif_merge(&base, &left, &right, &options);
+        let records = &result.amgr_file.adif_records;
+        assert_eq!(records[1].adir_field_values["gridsquare"], "FN43");
+        assert!(!records[1].adir_field_values.contains_key(
+            "app_adifmerge_conflict"));
+        assert_eq!(records[2].adir_field_values["comment"], "right");
+    }
+
+    #[test]
+    fn merge_newest_qsl() {
+        let base = make_file(vec![ qso("W1AW", "1800", &[]) ]);
+        let left = make_file(vec![ qso("W1AW", "1800", &[
+            ("qsl_rcvd", "Y"), ("qslrdate", "20180801") ]) ]);
+        let right = make_file(vec![ qso("W1AW", "1800", &[
+            ("qsl_rcvd", "V"), ("lotw_qslrdate", "20180901") ]) ]);
+
+        let options = super::AdifMergeOptions {
+            amgo_strategy: super::AdifConflictStrategy::ACS_PREFER_NEWEST_QSL,
+            ..Default::default()
+        };
+        let result = super::adif_merge(&base, &left, &right, &options);
+        let rec = &result.amgr_file.adif_records[0];
+        assert_eq!(rec.adir_field_values["qsl_rcvd"], "V");
+        assert_eq!(result.amgr_conflicts.len(), 1);
+        assert!(result.amgr_conflicts[0].amc_resolution.is_some());
+    }
+
+    #[test]
+    fn merge_delete_conflict() {
+        let base = make_file(vec![ qso("W1AW", "1800", &[]) ]);
+        let left = make_file(vec![]);
+        let right = make_file(vec![ qso("W1AW", "1800", &[
+            ("comment", "edited") ]) ]);
+
+        let options = super::AdifMergeOptions::default();
+        let result = super::adif_merge(&base, &left, &right, &options);
+        assert_eq!(result.amgr_file.adif_records.len(), 1);
+        assert_eq!(result.amgr_conflicts[0].amc_kind,
+            super::AdifConflictKind::ACK_DELETE_LEFT);
+
+        let right = make_file(vec![ qso("W1AW", "1800", &[]) ]);
+        let result = super::adif_merge(&base, &left, &right, &options);
+        assert_eq!(result.amgr_file.adif_records.len(), 0);
+        assert!(result.amgr_conflicts.is_empty());
+    }
+}
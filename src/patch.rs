@@ -0,0 +1,442 @@
+//
+// src/patch.rs: ADIF patch files
+//
+// A patch describes how to change one log (the "target") so that it agrees with
+// another.  Patches are generated from the results of matching and comparing
+// two files (see src/diff.rs), and they're themselves ADIF files, so they can
+// be inspected (or edited) with the usual tools.
+//
+// Each record in a patch has an "APP_ADIFDIFF_OP" field describing what to do:
+//
+//     ADD         The record is added to the target as-is (without the
+//                 APP_ADIFDIFF_* fields).
+//
+//     DELETE      The matching record is removed from the target.  The patch
+//                 record contains only the fields needed to find the record.
+//
+//     MODIFY      The matching record is updated: each changed field in the
+//                 patch record is set to the value in the patch, and each
+//                 field listed (comma-separated) in "APP_ADIFDIFF_REMOVE" is
+//                 removed.
+//
+// Records are found in the target using the same matching rules that were used
+// to generate the patch.  Those are recorded in the patch header as
+// "APP_ADIFDIFF_KEYS" (the key fields) and "APP_ADIFDIFF_TOLERANCE" (the time
+// tolerance, in seconds, or "none" if times were not used).  The fields used
+// to find a record are the key fields plus "qso_date" and "time_on" (if times
+// are used).  When one of those fields is itself being changed, the patch
+// record contains the new value and "APP_ADIFDIFF_OLD_<FIELD>" contains the
+// value used to find the record.  Locator fields without such an entry are
+// used only to find the record and are never applied to it.
+//
+
+use std::collections::BTreeSet;
+
+use adif::AdifFile;
+use adif::AdifHeader;
+use adif::AdifHeaderField;
+use adif::AdifRecord;
+use diff::AdifDiffOptions;
+use diff::AdifMatchOptions;
+use diff::AdifMatchResult;
+use diff::adif_diff_records;
+use diff::adif_match_records;
+use super::AdifParseError;
+
+const ADIF_PATCH_PREFIX : &str = "app_adifdiff_";
+const ADIF_PATCH_OP : &str = "app_adifdiff_op";
+const ADIF_PATCH_REMOVE : &str = "app_adifdiff_remove";
+const ADIF_PATCH_OLD_PREFIX : &str = "app_adifdiff_old_";
+const ADIF_PATCH_HEADER_KEYS : &str = "APP_ADIFDIFF_KEYS";
+const ADIF_PATCH_HEADER_TOLERANCE : &str = "APP_ADIFDIFF_TOLERANCE";
+
+const ADIF_PATCH_OP_ADD : &str = "ADD";
+const ADIF_PATCH_OP_DELETE : &str = "DELETE";
+const ADIF_PATCH_OP_MODIFY : &str = "MODIFY";
+
+//
+// AdifPatchResult: describes the outcome of applying a patch.  Patch records
+// are identified by their index in the patch file.
+//
+#[derive(Clone, Debug, Default)]
+pub struct AdifPatchResult {
+    pub apr_added : usize,              // records added
+    pub apr_deleted : usize,            // records deleted
+    pub apr_modified : usize,           // records modified
+    pub apr_failed : Vec<usize>         // patch records with no match
+}
+
+//
+// Returns the names of the fields used to find a record in the target.
+//
+fn adif_patch_locator_fields(options: &AdifMatchOptions) -> Vec<String>
+{
+    let mut fields = options.amo_key_fields.clone();
+    if options.amo_use_time {
+        for f in &[ "qso_date", "time_on" ] {
+            if !fields.iter().any(|k| k == f) {
+                fields.push(f.to_string());
+            }
+        }
+    }
+    fields
+}
+
+//
+// Returns a copy of "rec" containing only the fields used to find it.
+//
+fn adif_patch_locator(rec: &AdifRecord, locators: &[String]) -> AdifRecord
+{
+    let mut locator = AdifRecord::default();
+    for f in locators {
+        if let Some(v) = rec.adir_field_values.get(f) {
+            locator.adir_field_values.insert(f.clone(), v.clone());
+        }
+    }
+    locator
+}
+
+//
+// Generate a patch that would change "left" so that it agrees with "right",
+// given the result of matching the two files' records.
+//
+pub fn adif_patch_create(left: &AdifFile, right: &AdifFile,
+    result: &AdifMatchResult, match_options: &AdifMatchOptions,
+    diff_options: &AdifDiffOptions) -> AdifFile
+{
+    let locators = adif_patch_locator_fields(match_options);
+    let mut header = AdifHeader {
+        adh_preamble: Some(format!("Patch to make {} agree with {}\n",
+            left.adif_label, right.adif_label)),
+        ..AdifHeader::default()
+    };
+    header.adh_fields.push(AdifHeaderField {
+        adhf_name: String::from(ADIF_PATCH_HEADER_KEYS),
+        adhf_type: None,
+        adhf_value: match_options.amo_key_fields.join(",")
+    });
+    header.adh_fields.push(AdifHeaderField {
+        adhf_name: String::from(ADIF_PATCH_HEADER_TOLERANCE),
+        adhf_type: None,
+        adhf_value: if match_options.amo_use_time {
+            match_options.amo_time_tolerance.to_string()
+        } else {
+            String::from("none")
+        }
+    });
+
+    let mut records = Vec::new();
+
+    for &i in &result.amr_left_only {
+        let mut patchrec = adif_patch_locator(&left.adif_records[i],
+            &locators);
+        patchrec.adir_field_values.insert(ADIF_PATCH_OP.to_string(),
+            ADIF_PATCH_OP_DELETE.to_string());
+        records.push(patchrec);
+    }
+
+    for &(i, j) in &result.amr_matched {
+        let lrec = &left.adif_records[i];
+        let diffs = adif_diff_records(lrec, &right.adif_records[j],
+            diff_options);
+        if diffs.is_empty() {
+            continue;
+        }
+
+        let mut patchrec = adif_patch_locator(lrec, &locators);
+        let mut removed = Vec::new();
+        for d in diffs {
+            match d.afd_right {
+                Some(v) => {
+                    if locators.contains(&d.afd_field) {
+                        patchrec.adir_field_values.insert(format!("{}{}",
+                            ADIF_PATCH_OLD_PREFIX, d.afd_field),
+                            d.afd_left.unwrap_or_default());
+                    }
+                    patchrec.adir_field_values.insert(d.afd_field, v);
+                },
+                None => removed.push(d.afd_field)
+            }
+        }
+
+        patchrec.adir_field_values.insert(ADIF_PATCH_OP.to_string(),
+            ADIF_PATCH_OP_MODIFY.to_string());
+        if !removed.is_empty() {
+            patchrec.adir_field_values.insert(ADIF_PATCH_REMOVE.to_string(),
+                removed.join(","));
+        }
+        records.push(patchrec);
+    }
+
+    for &j in &result.amr_right_only {
+        let mut patchrec = right.adif_records[j].clone();
+        patchrec.adir_field_values.insert(ADIF_PATCH_OP.to_string(),
+            ADIF_PATCH_OP_ADD.to_string());
+        records.push(patchrec);
+    }
+
+    AdifFile {
+        adif_header: header,
+        adif_label: format!("patch from {} to {}", left.adif_label,
+            right.adif_label),
+        adif_diagnostics: Vec::new(),
+        adif_records: records
+    }
+}
+
+//
+// Reconstruct the matching options from a patch's header.
+//
+fn adif_patch_match_options(header: &AdifHeader) ->
+    Result<AdifMatchOptions, AdifParseError>
+{
+    let mut options = AdifMatchOptions::default();
+    for field in &header.adh_fields {
+        let name = field.adhf_name.to_uppercase();
+        if name == ADIF_PATCH_HEADER_KEYS {
+            options.amo_key_fields = field.adhf_value.split(',')
+                .map(|f| f.trim().to_lowercase())
+                .filter(|f| !f.is_empty()).collect();
+        } else if name == ADIF_PATCH_HEADER_TOLERANCE {
+            if field.adhf_value.trim() == "none" {
+                options.amo_use_time = false;
+            } else {
+                match field.adhf_value.trim().parse::<i64>() {
+                    Ok(t) => options.amo_time_tolerance = t,
+                    Err(_) => {
+                        return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                            "patch header: invalid time tolerance \"{}\"",
+                            field.adhf_value)));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+//
+// Apply the patch "patch" to "target".  Patch records that don't match any
+// record in the target are reported in the result rather than treated as
+// errors so that callers can decide what to do about them.
+//
+pub fn adif_patch_apply(target: &mut AdifFile, patch: &AdifFile) ->
+    Result<AdifPatchResult, AdifParseError>
+{
+    let options = adif_patch_match_options(&patch.adif_header)?;
+    let locators = adif_patch_locator_fields(&options);
+    let mut result = AdifPatchResult::default();
+
+    //
+    // Build a list of locator records (for DELETE and MODIFY operations) and
+    // match them against the target.
+    //
+    let mut which : Vec<usize> = Vec::new();
+    let mut finders : Vec<AdifRecord> = Vec::new();
+    for (p, patchrec) in patch.adif_records.iter().enumerate() {
+        let op = match patchrec.adir_field_values.get(ADIF_PATCH_OP) {
+            Some(op) => op.to_uppercase(),
+            None => {
+                return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                    "patch record {}: missing \"{}\"", p + 1, ADIF_PATCH_OP)));
+            }
+        };
+
+        if op == ADIF_PATCH_OP_ADD {
+            continue;
+        }
+
+        if op != ADIF_PATCH_OP_DELETE && op != ADIF_PATCH_OP_MODIFY {
+            return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                "patch record {}: unsupported operation \"{}\"", p + 1, op)));
+        }
+
+        let mut finder = adif_patch_locator(patchrec, &locators);
+        for f in &locators {
+            let old = format!("{}{}", ADIF_PATCH_OLD_PREFIX, f);
+            if let Some(v) = patchrec.adir_field_values.get(&old) {
+                finder.adir_field_values.insert(f.clone(), v.clone());
+            }
+        }
+        which.push(p);
+        finders.push(finder);
+    }
+
+    let matches = adif_match_records(&finders, &target.adif_records, &options);
+    for &f in &matches.amr_left_only {
+        result.apr_failed.push(which[f]);
+    }
+
+    let mut deleted = BTreeSet::new();
+    for &(f, t) in &matches.amr_matched {
+        let patchrec = &patch.adif_records[which[f]];
+        if patchrec.adir_field_values[ADIF_PATCH_OP].to_uppercase() ==
+            ADIF_PATCH_OP_DELETE {
+            deleted.insert(t);
+            result.apr_deleted += 1;
+            continue;
+        }
+
+        //
+        // Locator fields are only there to find the record, and the target's
+        // values may legitimately differ from them (e.g., times within the
+        // tolerance).  We only set those that the patch says have changed.
+        //
+        let trec = &mut target.adif_records[t];
+        for (name, value) in &patchrec.adir_field_values {
+            if name.starts_with(ADIF_PATCH_PREFIX) {
+                continue;
+            }
+            if locators.contains(name) && !patchrec.adir_field_values
+                .contains_key(&format!("{}{}", ADIF_PATCH_OLD_PREFIX, name)) {
+                continue;
+            }
+            trec.adir_field_values.insert(name.clone(), value.clone());
+            trec.adir_extra_values.remove(name);
+        }
+        if let Some(removed) = patchrec.adir_field_values.get(
+            ADIF_PATCH_REMOVE) {
+            for name in removed.split(',') {
+                let name = name.trim().to_lowercase();
+                trec.adir_field_values.remove(&name);
+                trec.adir_extra_values.remove(&name);
+            }
+        }
+        result.apr_modified += 1;
+    }
+
+    if !deleted.is_empty() {
+        let records = std::mem::take(&mut target.adif_records);
+        target.adif_records = records.into_iter().enumerate()
+            .filter(|(t, _)| !deleted.contains(t))
+            .map(|(_, rec)| rec).collect();
+    }
+
+    for patchrec in &patch.adif_records {
+        if patchrec.adir_field_values[ADIF_PATCH_OP].to_uppercase() !=
+            ADIF_PATCH_OP_ADD {
+            continue;
+        }
+
+        let mut rec = patchrec.clone();
+        rec.adir_field_values.retain(|k, _| !k.starts_with(ADIF_PATCH_PREFIX));
+        target.adif_records.push(rec);
+        result.apr_added += 1;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifFile;
+    use adif::AdifHeader;
+    use adif::AdifRecord;
+    use diff::AdifDiffOptions;
+    use diff::AdifMatchOptions;
+    use diff::adif_diff_records;
+    use diff::adif_match_records;
+
+    fn make_file(records: Vec<Vec<(&str, &str)>>) -> AdifFile {
+        AdifFile {
+            adif_header: AdifHeader::default(),
+            adif_label: String::from("test"),
+            adif_diagnostics: vec![],
+            adif_records: records.iter().map(|fields| {
+                let mut rec = AdifRecord::default();
+                for (name, value) in fields {
+                    rec.adir_field_values.insert(name.to_string(),
+                        value.to_string());
+                }
+                rec
+            }).collect()
+        }
+    }
+
+    #[test]
+    fn create_and_apply() {
+        let left = make_file(vec![
+            vec![ ("call", "W1AW"), ("qso_date", "20181130"),
+                ("time_on", "0100"), ("band", "20m"), ("mode", "CW"),
+                ("comment", "old"), ("name", "Hiram") ],
+            vec![ ("call", "K1ABC"), ("qso_date", "20181130"),
+                ("time_on", "0200"), ("band", "40m"), ("mode", "CW") ],
+        ]);
+        let right = make_file(vec![
+            vec![ ("call", "W1AW"), ("qso_date", "20181130"),
+                ("time_on", "0102"), ("band", "20m"), ("mode", "CW"),
+                ("comment", "new") ],
+            vec![ ("call", "N0CALL"), ("qso_date", "20181130"),
+                ("time_on", "0300"), ("band", "40m"), ("mode", "CW") ],
+        ]);
+
+        let match_options = AdifMatchOptions::default();
+        let diff_options = AdifDiffOptions::default();
+        let result = adif_match_records(&left.adif_records,
+            &right.adif_records, &match_options);
+        let patch = super::adif_patch_create(&left, &right, &result,
+            &match_options, &diff_options);
+        assert_eq!(patch.adif_records.len(), 3);
+
+        let mut target = make_file(vec![]);
+        target.adif_records = left.adif_records.clone();
+        let applied = super::adif_patch_apply(&mut target, &patch).unwrap();
+        assert_eq!(applied.apr_added, 1);
+        assert_eq!(applied.apr_deleted, 1);
+        assert_eq!(applied.apr_modified, 1);
+        assert!(applied.apr_failed.is_empty());
+
+        let result = adif_match_records(&target.adif_records,
+            &right.adif_records, &match_options);
+        assert_eq!(result.amr_matched.len(), 2);
+        for (i, j) in result.amr_matched {
+            assert!(adif_diff_records(&target.adif_records[i],
+                &right.adif_records[j], &diff_options).is_empty());
+        }
+
+        //
+        // Applying the same patch again should fail to find the deleted
+        // record.
+        //
+        let applied = super::adif_patch_apply(&mut target, &patch).unwrap();
+        assert_eq!(applied.apr_failed, vec![ 0 ]);
+    }
+
+    #[test]
+    fn locators_not_applied() {
+        let left = make_file(vec![
+            vec![ ("call", "W1AW"), ("qso_date", "20181130"),
+                ("time_on", "0100"), ("band", "20m"), ("mode", "CW"),
+                ("comment", "old") ],
+        ]);
+        let right = make_file(vec![
+            vec![ ("call", "W1AW"), ("qso_date", "20181130"),
+                ("time_on", "0100"), ("band", "20m"), ("mode", "CW"),
+                ("comment", "new") ],
+        ]);
+
+        let match_options = AdifMatchOptions::default();
+        let result = adif_match_records(&left.adif_records,
+            &right.adif_records, &match_options);
+        let patch = super::adif_patch_create(&left, &right, &result,
+            &match_options, &AdifDiffOptions::default());
+
+        //
+        // The target's time differs from the patch's (but is within the
+        // tolerance), and only the comment should change.
+        //
+        let mut target = make_file(vec![
+            vec![ ("call", "w1aw"), ("qso_date", "20181130"),
+                ("time_on", "010130"), ("band", "20M"), ("mode", "CW"),
+                ("comment", "old") ],
+        ]);
+        let applied = super::adif_patch_apply(&mut target, &patch).unwrap();
+        assert_eq!(applied.apr_modified, 1);
+        let values = &target.adif_records[0].adir_field_values;
+        assert_eq!(values["time_on"], "010130");
+        assert_eq!(values["call"], "w1aw");
+        assert_eq!(values["band"], "20M");
+        assert_eq!(values["comment"], "new");
+    }
+}
@@ -0,0 +1,474 @@
+//
+// src/qsl.rs: tracking and updating QSL status
+//
+// A QSO can be confirmed in three ways, each with its own fields:
+//
+//   service  sent / received               dates                  route
+//   card     qsl_sent / qsl_rcvd           qslsdate / qslrdate    qsl_*_via
+//   lotw     lotw_qsl_sent / lotw_qsl_rcvd lotw_qsl[sr]date       -
+//   eqsl     eqsl_qsl_sent / eqsl_qsl_rcvd eqsl_qsl[sr]date       -
+//
+// The "sent" fields take the values Y (sent), N (not sent), R (requested by
+// the other station), Q (queued), and I (ignore); the "received" fields take
+// Y (received), N, R (requested), I, and V (verified).  From these we derive a
+// single AdifQslState for each service, which is what the QSL manager cares
+// about: whether a QSL still needs to be sent, has been sent and is awaiting a
+// reply, or has been confirmed.
+//
+// Updates set a new "sent" or "received" status for one service, along with
+// the date (for Y and V) and, for cards, the route.  New values are checked
+// against the ADIF enumerations, and updates that would undo progress (like
+// changing qsl_sent from Y back to Q, or changing a status of I at all) are
+// refused unless forced.
+//
+
+use std::collections::BTreeMap;
+
+use adif::AdifDiagnostic;
+use adif::AdifRecord;
+use adiftypes::AdifDate;
+use filter::AdifFilter;
+use filter::adif_filter_matches;
+use validate::ADIF_QSL_RCVD;
+use validate::ADIF_QSL_SENT;
+use validate::ADIF_QSL_VIA;
+use super::AdifParseError;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AdifQslService {
+    AQS_CARD,       // paper QSL card
+    AQS_LOTW,       // ARRL Logbook of The World
+    AQS_EQSL        // eQSL.cc
+}
+
+pub static ADIF_QSL_SERVICES : &[AdifQslService] = &[
+    AdifQslService::AQS_CARD,
+    AdifQslService::AQS_LOTW,
+    AdifQslService::AQS_EQSL
+];
+
+pub fn adif_qsl_service_name(service: AdifQslService) -> &'static str
+{
+    match service {
+        AdifQslService::AQS_CARD => "card",
+        AdifQslService::AQS_LOTW => "lotw",
+        AdifQslService::AQS_EQSL => "eqsl"
+    }
+}
+
+pub fn adif_parse_qsl_service(name: &str) -> Option<AdifQslService>
+{
+    ADIF_QSL_SERVICES.iter().copied().find(
+        |s| adif_qsl_service_name(*s).eq_ignore_ascii_case(name.trim()))
+}
+
+//
+// The fields used by each service: sent, received, date sent, date received,
+// and (for cards only) route sent and route received.
+//
+type AdifQslFields = (&'static str, &'static str, &'static str, &'static str,
+    Option<(&'static str, &'static str)>);
+
+fn adif_qsl_fields(service: AdifQslService) -> AdifQslFields
+{
+    match service {
+        AdifQslService::AQS_CARD => ("qsl_sent", "qsl_rcvd", "qslsdate",
+            "qslrdate", Some(("qsl_sent_via", "qsl_rcvd_via"))),
+        AdifQslService::AQS_LOTW => ("lotw_qsl_sent", "lotw_qsl_rcvd",
+            "lotw_qslsdate", "lotw_qslrdate", None),
+        AdifQslService::AQS_EQSL => ("eqsl_qsl_sent", "eqsl_qsl_rcvd",
+            "eqsl_qslsdate", "eqsl_qslrdate", None)
+    }
+}
+
+//
+// AdifQslState: where a QSO stands with one service.  A received QSL counts as
+// a confirmation whether or not we sent one.
+//
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AdifQslState {
+    AQST_NOT_SENT,      // nothing sent, requested, or received
+    AQST_TO_SEND,       // requested or queued, but not yet sent
+    AQST_SENT,          // sent and awaiting confirmation
+    AQST_CONFIRMED,     // received (or verified)
+    AQST_IGNORED        // marked "I" (ignore)
+}
+
+pub static ADIF_QSL_STATES : &[AdifQslState] = &[
+    AdifQslState::AQST_NOT_SENT,
+    AdifQslState::AQST_TO_SEND,
+    AdifQslState::AQST_SENT,
+    AdifQslState::AQST_CONFIRMED,
+    AdifQslState::AQST_IGNORED
+];
+
+pub fn adif_qsl_state_name(state: AdifQslState) -> &'static str
+{
+    match state {
+        AdifQslState::AQST_NOT_SENT => "not-sent",
+        AdifQslState::AQST_TO_SEND => "to-send",
+        AdifQslState::AQST_SENT => "sent",
+        AdifQslState::AQST_CONFIRMED => "confirmed",
+        AdifQslState::AQST_IGNORED => "ignored"
+    }
+}
+
+pub fn adif_parse_qsl_state(name: &str) -> Option<AdifQslState>
+{
+    ADIF_QSL_STATES.iter().copied().find(
+        |s| adif_qsl_state_name(*s).eq_ignore_ascii_case(name.trim()))
+}
+
+fn adif_qsl_value(rec: &AdifRecord, field: &str) -> String
+{
+    rec.adir_field_values.get(field).map(|v| v.trim().to_uppercase())
+        .unwrap_or_default()
+}
+
+pub fn adif_qsl_state(rec: &AdifRecord, service: AdifQslService) ->
+    AdifQslState
+{
+    let (sent_field, rcvd_field, _, _, _) = adif_qsl_fields(service);
+    let sent = adif_qsl_value(rec, sent_field);
+    let rcvd = adif_qsl_value(rec, rcvd_field);
+    match (sent.as_str(), rcvd.as_str()) {
+        (_, "Y") | (_, "V") => AdifQslState::AQST_CONFIRMED,
+        ("I", _) | (_, "I") => AdifQslState::AQST_IGNORED,
+        ("Y", _) => AdifQslState::AQST_SENT,
+        ("R", _) | ("Q", _) => AdifQslState::AQST_TO_SEND,
+        _ => AdifQslState::AQST_NOT_SENT
+    }
+}
+
+//
+// AdifQslServiceReport: how many QSOs are in each state for one service, plus
+// (for cards) how many were sent by each route.
+//
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifQslServiceReport {
+    pub aqsr_service : AdifQslService,
+    pub aqsr_states : BTreeMap<AdifQslState, usize>,
+    pub aqsr_via : BTreeMap<String, usize>
+}
+
+pub fn adif_qsl_report(records: &[AdifRecord], filter: Option<&AdifFilter>) ->
+    Vec<AdifQslServiceReport>
+{
+    ADIF_QSL_SERVICES.iter().map(|&service| {
+        let mut report = AdifQslServiceReport {
+            aqsr_service: service,
+            aqsr_states: ADIF_QSL_STATES.iter().map(|&s| (s, 0)).collect(),
+            aqsr_via: BTreeMap::new()
+        };
+        let via_field = adif_qsl_fields(service).4.map(|(sent, _)| sent);
+        for rec in records {
+            if filter.is_some_and(|f| !adif_filter_matches(f, rec)) {
+                continue;
+            }
+            *report.aqsr_states.entry(adif_qsl_state(rec, service))
+                .or_insert(0) += 1;
+            let via = via_field.map(|f| adif_qsl_value(rec, f))
+                .unwrap_or_default();
+            if !via.is_empty() {
+                *report.aqsr_via.entry(via).or_insert(0) += 1;
+            }
+        }
+        report
+    }).collect()
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdifQslDirection {
+    AQD_SENT,
+    AQD_RCVD
+}
+
+//
+// AdifQslUpdate: a new status for one direction of one service.
+//
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifQslUpdate {
+    pub aqu_service : AdifQslService,
+    pub aqu_direction : AdifQslDirection,
+    pub aqu_status : String,            // new status, like "Y" or "Q"
+    pub aqu_date : Option<AdifDate>,    // date sent or received (Y or V only)
+    pub aqu_via : Option<String>,       // route (cards only)
+    pub aqu_force : bool                // allow updates that undo progress
+}
+
+//
+// Parse an update like "card-sent", "lotw-rcvd", or "card-sent=Q".  The status
+// defaults to "Y".  The date, route, and force flag can be set afterwards.
+//
+pub fn adif_parse_qsl_update(spec: &str) ->
+    Result<AdifQslUpdate, AdifParseError>
+{
+    let bad = |message: String| AdifParseError::ADIF_EBADINPUT(message);
+    let (which, status) = match spec.split_once('=') {
+        Some((w, s)) => (w.trim(), s.trim().to_uppercase()),
+        None => (spec.trim(), String::from("Y"))
+    };
+    let (service, direction) = which.split_once('-').ok_or_else(
+        || bad(format!("expected SERVICE-sent or SERVICE-rcvd: \"{}\"",
+        which)))?;
+    let service = adif_parse_qsl_service(service).ok_or_else(
+        || bad(format!("unknown QSL service (expected card, lotw, or eqsl): \
+        \"{}\"", service)))?;
+    let (direction, allowed) = match direction.to_lowercase().as_str() {
+        "sent" => (AdifQslDirection::AQD_SENT, ADIF_QSL_SENT),
+        "rcvd" => (AdifQslDirection::AQD_RCVD, ADIF_QSL_RCVD),
+        _ => return Err(bad(format!("expected \"sent\" or \"rcvd\": \"{}\"",
+            direction)))
+    };
+    if !allowed.contains(&status.as_str()) {
+        return Err(bad(format!("invalid status \"{}\" (expected one of {})",
+            status, allowed.join(", "))));
+    }
+
+    Ok(AdifQslUpdate {
+        aqu_service: service,
+        aqu_direction: direction,
+        aqu_status: status,
+        aqu_date: None,
+        aqu_via: None,
+        aqu_force: false
+    })
+}
+
+//
+// How far along a status is, so that we can tell whether an update undoes
+// progress.  "I" isn't ranked: it can always be set, but never changed without
+// forcing.
+//
+fn adif_qsl_rank(direction: AdifQslDirection, status: &str) -> Option<u32>
+{
+    match (direction, status) {
+        (_, "") | (_, "N") => Some(0),
+        (_, "R") => Some(1),
+        (AdifQslDirection::AQD_SENT, "Q") => Some(2),
+        (AdifQslDirection::AQD_SENT, "Y") => Some(3),
+        (AdifQslDirection::AQD_RCVD, "Y") => Some(2),
+        (AdifQslDirection::AQD_RCVD, "V") => Some(3),
+        _ => None
+    }
+}
+
+//
+// Apply an update to one record.  Returns whether the record changed, or a
+// description of why the update was refused.  If the status is unchanged, the
+// date and route are only filled in where missing.
+//
+pub fn adif_qsl_update(rec: &mut AdifRecord, update: &AdifQslUpdate) ->
+    Result<bool, AdifParseError>
+{
+    adif_qsl_apply(rec, update).map_err(AdifParseError::ADIF_EBADINPUT)
+}
+
+//
+// Implements adif_qsl_update(), returning the reason for refusing an update as
+// a plain message so that callers (like src/reconcile.rs) can report it
+// without unpacking an AdifParseError.
+//
+pub fn adif_qsl_apply(rec: &mut AdifRecord, update: &AdifQslUpdate) ->
+    Result<bool, String>
+{
+    let (sent_field, rcvd_field, sdate_field, rdate_field, via_fields) =
+        adif_qsl_fields(update.aqu_service);
+    let (status_field, date_field, via_field) = match update.aqu_direction {
+        AdifQslDirection::AQD_SENT =>
+            (sent_field, sdate_field, via_fields.map(|(s, _)| s)),
+        AdifQslDirection::AQD_RCVD =>
+            (rcvd_field, rdate_field, via_fields.map(|(_, r)| r))
+    };
+
+    let new = update.aqu_status.to_uppercase();
+    let allowed = match update.aqu_direction {
+        AdifQslDirection::AQD_SENT => ADIF_QSL_SENT,
+        AdifQslDirection::AQD_RCVD => ADIF_QSL_RCVD
+    };
+    if !allowed.contains(&new.as_str()) {
+        return Err(format!("invalid {} status: \"{}\"", status_field, new));
+    }
+    let via = match (&update.aqu_via, via_field) {
+        (None, _) => None,
+        (Some(v), Some(f)) => {
+            let v = v.trim().to_uppercase();
+            if !ADIF_QSL_VIA.contains(&v.as_str()) {
+                return Err(format!("invalid {}: \"{}\"", f, v));
+            }
+            Some((f, v))
+        },
+        (Some(_), None) => return Err(format!("{} QSLs have no route",
+            adif_qsl_service_name(update.aqu_service)))
+    };
+
+    let old = adif_qsl_value(rec, status_field);
+    if old != new && !update.aqu_force {
+        let refused = match (adif_qsl_rank(update.aqu_direction, &old),
+            adif_qsl_rank(update.aqu_direction, &new)) {
+            _ if new == "I" => false,
+            (Some(o), Some(n)) => n < o,
+            _ => true
+        };
+        if refused {
+            return Err(format!("won't change {} from \"{}\" to \"{}\"",
+                status_field, old, new));
+        }
+    }
+
+    let mut changed = false;
+    let mut set = |name: &str, value: String, replace: bool| {
+        let fields = &mut rec.adir_field_values;
+        let current = fields.get(name).map(|v| v.trim()).unwrap_or("");
+        if current != value && (replace || current.is_empty()) {
+            fields.insert(name.to_string(), value);
+            changed = true;
+        }
+    };
+    let replace = old != new;
+    set(status_field, new.clone(), true);
+    if new == "Y" || new == "V" {
+        if let Some(date) = &update.aqu_date {
+            set(date_field, date.to_string(), replace);
+        }
+    }
+    if let Some((field, value)) = via {
+        set(field, value, replace);
+    }
+    Ok(changed)
+}
+
+//
+// AdifQslUpdateSummary: the result of applying updates to a list of records.
+// Refused updates are described by diagnostics (with 1-based record numbers).
+//
+#[derive(Clone, Debug, Default)]
+pub struct AdifQslUpdateSummary {
+    pub aqus_matched : usize,           // records matching the filter
+    pub aqus_changed : usize,           // records changed
+    pub aqus_diagnostics : Vec<AdifDiagnostic>
+}
+
+//
+// Apply each update, in order, to each record that matches the filter.  A
+// refused update doesn't prevent the others from being applied.
+//
+pub fn adif_qsl_update_records(records: &mut [AdifRecord],
+    filter: Option<&AdifFilter>, updates: &[AdifQslUpdate]) ->
+    AdifQslUpdateSummary
+{
+    let mut summary = AdifQslUpdateSummary::default();
+    for (i, rec) in records.iter_mut().enumerate() {
+        if filter.is_some_and(|f| !adif_filter_matches(f, rec)) {
+            continue;
+        }
+
+        summary.aqus_matched += 1;
+        let mut changed = false;
+        for update in updates {
+            match adif_qsl_apply(rec, update) {
+                Ok(c) => changed |= c,
+                Err(message) => summary.aqus_diagnostics.push(AdifDiagnostic {
+                    adid_record: Some(i + 1),
+                    adid_line: None,
+                    adid_message: message
+                })
+            }
+        }
+        if changed {
+            summary.aqus_changed += 1;
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod test {
+    use adiftypes::adif_parse_date;
+    use filter::adif_parse_filter;
+    use super::*;
+
+    fn qso(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    #[test]
+    fn states() {
+        let rec = qso(&[("qsl_sent", "Y"), ("lotw_qsl_sent", "y"),
+            ("lotw_qsl_rcvd", "V"), ("eqsl_qsl_sent", "Q")]);
+        assert_eq!(adif_qsl_state(&rec, AdifQslService::AQS_CARD),
+            AdifQslState::AQST_SENT);
+        assert_eq!(adif_qsl_state(&rec, AdifQslService::AQS_LOTW),
+            AdifQslState::AQST_CONFIRMED);
+        assert_eq!(adif_qsl_state(&rec, AdifQslService::AQS_EQSL),
+            AdifQslState::AQST_TO_SEND);
+        assert_eq!(adif_qsl_state(&qso(&[("qsl_rcvd", "I")]),
+            AdifQslService::AQS_CARD), AdifQslState::AQST_IGNORED);
+        assert_eq!(adif_qsl_state(&qso(&[]), AdifQslService::AQS_CARD),
+            AdifQslState::AQST_NOT_SENT);
+
+        let records = vec![rec, qso(&[("qsl_sent", "Y"),
+            ("qsl_sent_via", "b"), ("band", "40m")])];
+        let report = adif_qsl_report(&records, None);
+        assert_eq!(report.len(), 3);
+        assert_eq!(report[0].aqsr_states[&AdifQslState::AQST_SENT], 2);
+        assert_eq!(report[0].aqsr_states[&AdifQslState::AQST_CONFIRMED], 0);
+        assert_eq!(report[0].aqsr_via["B"], 1);
+        let filter = adif_parse_filter("band = 40m").unwrap();
+        let report = adif_qsl_report(&records, Some(&filter));
+        assert_eq!(report[1].aqsr_states[&AdifQslState::AQST_NOT_SENT], 1);
+    }
+
+    #[test]
+    fn updates() {
+        assert!(adif_parse_qsl_update("card").is_err());
+        assert!(adif_parse_qsl_update("fax-sent").is_err());
+        assert!(adif_parse_qsl_update("card-rcvd=Q").is_err());
+        let mut update = adif_parse_qsl_update("card-sent").unwrap();
+        assert_eq!(update.aqu_service, AdifQslService::AQS_CARD);
+        assert_eq!(update.aqu_status, "Y");
+        update.aqu_date = adif_parse_date("20240105");
+        update.aqu_via = Some(String::from("b"));
+
+        let mut rec = qso(&[("call", "W1AW"), ("qsl_sent", "R")]);
+        assert!(adif_qsl_update(&mut rec, &update).unwrap());
+        assert_eq!(rec.adir_field_values["qsl_sent"], "Y");
+        assert_eq!(rec.adir_field_values["qslsdate"], "20240105");
+        assert_eq!(rec.adir_field_values["qsl_sent_via"], "B");
+        assert!(!adif_qsl_update(&mut rec, &update).unwrap());
+
+        let mut queue = adif_parse_qsl_update("card-sent=q").unwrap();
+        assert_eq!(adif_qsl_update(&mut rec, &queue).unwrap_err().to_string(),
+            "input error: won't change qsl_sent from \"Y\" to \"Q\"");
+        queue.aqu_force = true;
+        assert!(adif_qsl_update(&mut rec, &queue).unwrap());
+
+        let mut lotw = adif_parse_qsl_update("lotw-rcvd").unwrap();
+        let mut ignored = qso(&[("lotw_qsl_rcvd", "I")]);
+        assert!(adif_qsl_update(&mut ignored, &lotw).is_err());
+        lotw.aqu_via = Some(String::from("E"));
+        assert_eq!(adif_qsl_update(&mut rec, &lotw).unwrap_err().to_string(),
+            "input error: lotw QSLs have no route");
+
+        let mut records = vec![
+            qso(&[("call", "W1AW")]),
+            qso(&[("call", "K2XX"), ("qsl_sent", "I")]),
+            qso(&[("call", "N0CALL")])
+        ];
+        let filter = adif_parse_filter("call in (W1AW, K2XX)").unwrap();
+        let summary = adif_qsl_update_records(&mut records, Some(&filter),
+            &[update]);
+        assert_eq!(summary.aqus_matched, 2);
+        assert_eq!(summary.aqus_changed, 1);
+        assert_eq!(summary.aqus_diagnostics.len(), 1);
+        assert_eq!(summary.aqus_diagnostics[0].adid_record, Some(2));
+        assert!(!records[2].adir_field_values.contains_key("qsl_sent"));
+    }
+}
@@ -0,0 +1,425 @@
+//
+// src/reconcile.rs: applying LoTW and eQSL confirmations to a log
+//
+// Both LoTW and eQSL let users download their confirmations as ADIF files.  We
+// match each confirmation against the log and mark the matching QSO as
+// confirmed by that service ("lotw_qsl_rcvd" or "eqsl_qsl_rcvd", along with
+// the date received).  Award credit listed in the confirmation's
+// "credit_granted" is added to the QSO's.
+//
+// The services don't record QSOs exactly as the log does, so matching is
+// looser than for "adif diff": the callsigns must match (case-insensitively),
+// the bands must match (using the band for "freq" if "band" is missing), and
+// the modes must be in the same family (CW, phone, or digital; see
+// adif_mode_group()), which is how LoTW itself matches QSOs.  When both the
+// QSO and the confirmation have a "station_callsign", those must match too, so
+// that a log with QSOs from several of the user's callsigns gets each
+// confirmation applied to the right one.  Among QSOs that agree on these, times
+// must be within a tolerance (default 30 minutes) and the closest pairs are
+// matched first.  Each confirmation matches at most one QSO.
+//
+// LoTW downloads use "qsl_rcvd" and "qslrdate" to describe the confirmation
+// itself, so records in the download with "qsl_rcvd" other than Y or V (e.g.,
+// QSOs that were uploaded but not confirmed) are not treated as confirmations.
+// The service is recognized from the file's header (LoTW's "ARRL Logbook of the
+// World Status Report" and eQSL's "eQSL.cc" program ID), or can be given.
+//
+
+use adif::AdifDiagnostic;
+use adif::AdifFile;
+use adif::AdifRecord;
+use adif::adif_header_program_id;
+use adiftypes::AdifDate;
+use adiftypes::adif_parse_date;
+use awards::adif_mode_group;
+use diff::AdifMatchOptions;
+use diff::adif_match_records;
+use fields::adif_freq_band;
+use qsl::AdifQslDirection;
+use qsl::AdifQslService;
+use qsl::AdifQslState;
+use qsl::AdifQslUpdate;
+use qsl::adif_qsl_apply;
+use qsl::adif_qsl_service_name;
+use qsl::adif_qsl_state;
+use super::AdifParseError;
+
+#[derive(Clone, Debug)]
+pub struct AdifReconcileOptions {
+    pub arco_service : Option<AdifQslService>,  // None: recognize from header
+    pub arco_time_tolerance : i64,              // seconds
+    pub arco_date : Option<AdifDate>            // default date received
+}
+
+impl Default for AdifReconcileOptions {
+    fn default() -> AdifReconcileOptions {
+        AdifReconcileOptions {
+            arco_service: None,
+            arco_time_tolerance: 30 * 60,
+            arco_date: None
+        }
+    }
+}
+
+//
+// AdifReconcileResult: describes what was matched and updated.  Records are
+// identified by their index in the log or the confirmation file.  Updates that
+// were refused (e.g., because the QSO's status is "I") are described by
+// diagnostics with 1-based record numbers in the log.
+//
+#[derive(Clone, Debug)]
+pub struct AdifReconcileResult {
+    pub arcr_service : AdifQslService,
+    pub arcr_matched : Vec<(usize, usize)>,     // (log, confirmation)
+    pub arcr_updated : Vec<usize>,              // log records changed
+    pub arcr_unmatched : Vec<usize>,            // confirmations with no QSO
+    pub arcr_unconfirmed : Vec<usize>,          // records not confirmed
+    pub arcr_diagnostics : Vec<AdifDiagnostic>
+}
+
+//
+// Recognize the service that produced a confirmation file from its header.
+//
+pub fn adif_reconcile_service(adf: &AdifFile) -> Option<AdifQslService>
+{
+    let header = &adf.adif_header;
+    let text = format!("{} {}", header.adh_preamble.as_deref()
+        .unwrap_or(""), adif_header_program_id(header).unwrap_or(""))
+        .to_lowercase();
+    if text.contains("lotw") || text.contains("logbook of the world") {
+        Some(AdifQslService::AQS_LOTW)
+    } else if text.contains("eqsl") {
+        Some(AdifQslService::AQS_EQSL)
+    } else {
+        None
+    }
+}
+
+//
+// Build the record used for matching: the callsign, band, mode family,
+// station callsign, and time of the QSO.
+//
+fn adif_reconcile_key(rec: &AdifRecord) -> AdifRecord
+{
+    let get = |name: &str| rec.adir_field_values.get(name).map(
+        |v| v.trim().to_uppercase()).filter(|v| !v.is_empty());
+    let band = get("band").or_else(|| get("freq").and_then(
+        |f| f.parse::<f64>().ok()).and_then(adif_freq_band).map(
+        |b| b.to_uppercase()));
+    let group = get("mode").map(|m| adif_mode_group(&m).to_string());
+
+    let mut key = AdifRecord::default();
+    for (name, value) in [("call", get("call")), ("band", band),
+        ("mode_group", group),
+        ("station_callsign", get("station_callsign")),
+        ("qso_date", get("qso_date")), ("time_on", get("time_on"))] {
+        if let Some(v) = value {
+            key.adir_field_values.insert(name.to_string(), v);
+        }
+    }
+    key
+}
+
+//
+// Match the log records "left" with the confirmations "right" (both given as
+// indexes into "log_keys" and "keys"), removing the records matched from both
+// lists and returning the matched pairs.
+//
+fn adif_reconcile_match(log_keys: &[AdifRecord], keys: &[AdifRecord],
+    left: &mut Vec<usize>, right: &mut Vec<usize>,
+    options: &AdifMatchOptions) -> Vec<(usize, usize)>
+{
+    let lrecs : Vec<AdifRecord> = left.iter().map(|&i| log_keys[i].clone())
+        .collect();
+    let rrecs : Vec<AdifRecord> = right.iter().map(|&j| keys[j].clone())
+        .collect();
+    let matches = adif_match_records(&lrecs, &rrecs, options);
+    let pairs : Vec<(usize, usize)> = matches.amr_matched.iter().map(
+        |&(i, j)| (left[i], right[j])).collect();
+    left.retain(|i| !pairs.iter().any(|(l, _)| l == i));
+    right.retain(|j| !pairs.iter().any(|(_, r)| r == j));
+    pairs
+}
+
+fn adif_is_confirmation(rec: &AdifRecord) -> bool
+{
+    rec.adir_field_values.get("qsl_rcvd").is_none_or(|v| {
+        let v = v.trim();
+        v.is_empty() || v.eq_ignore_ascii_case("Y") ||
+            v.eq_ignore_ascii_case("V")
+    })
+}
+
+//
+// Add the items of a comma-separated list (like "credit_granted") that aren't
+// already present.  Returns whether anything was added.
+//
+fn adif_merge_list(rec: &mut AdifRecord, field: &str, items: &str) -> bool
+{
+    let mut list : Vec<String> = rec.adir_field_values.get(field).map(
+        |v| v.split(',').map(|i| i.trim().to_string())
+        .filter(|i| !i.is_empty()).collect()).unwrap_or_default();
+    let mut added = false;
+    for item in items.split(',').map(|i| i.trim()).filter(|i| !i.is_empty()) {
+        if !list.iter().any(|i| i.eq_ignore_ascii_case(item)) {
+            list.push(item.to_string());
+            added = true;
+        }
+    }
+    if added {
+        rec.adir_field_values.insert(field.to_string(), list.join(","));
+    }
+    added
+}
+
+//
+// Apply the confirmations in "confirmations" to the records in "log".
+//
+pub fn adif_reconcile(log: &mut [AdifRecord], confirmations: &AdifFile,
+    options: &AdifReconcileOptions) ->
+    Result<AdifReconcileResult, AdifParseError>
+{
+    let service = match options.arco_service.or_else(
+        || adif_reconcile_service(confirmations)) {
+        Some(AdifQslService::AQS_CARD) => return Err(
+            AdifParseError::ADIF_EBADINPUT(String::from("confirmations must \
+            come from LoTW or eQSL"))),
+        Some(s) => s,
+        None => return Err(AdifParseError::ADIF_EBADINPUT(String::from(
+            "can't tell whether confirmations are from LoTW or eQSL")))
+    };
+
+    let mut result = AdifReconcileResult {
+        arcr_service: service,
+        arcr_matched: Vec::new(),
+        arcr_updated: Vec::new(),
+        arcr_unmatched: Vec::new(),
+        arcr_unconfirmed: Vec::new(),
+        arcr_diagnostics: Vec::new()
+    };
+
+    let mut which = Vec::new();
+    let mut keys = Vec::new();
+    for (i, rec) in confirmations.adif_records.iter().enumerate() {
+        if adif_is_confirmation(rec) {
+            which.push(i);
+            keys.push(adif_reconcile_key(rec));
+        } else {
+            result.arcr_unconfirmed.push(i);
+        }
+    }
+    let log_keys : Vec<AdifRecord> = log.iter().map(adif_reconcile_key)
+        .collect();
+
+    //
+    // First match the records that both have a station callsign, requiring
+    // those to be equal.  Then match the rest, as long as one side of each
+    // pair lacks a station callsign.
+    //
+    let mut match_options = AdifMatchOptions {
+        amo_key_fields: vec![ String::from("call"), String::from("band"),
+            String::from("mode_group"), String::from("station_callsign") ],
+        amo_use_time: true,
+        amo_time_tolerance: options.arco_time_tolerance
+    };
+    let station = |k: &AdifRecord| k.adir_field_values.contains_key(
+        "station_callsign");
+    let mut lrest : Vec<usize> = (0..log_keys.len()).collect();
+    let mut rrest : Vec<usize> = (0..keys.len()).collect();
+    let mut left : Vec<usize> = lrest.iter().cloned().filter(
+        |&i| station(&log_keys[i])).collect();
+    let mut right : Vec<usize> = rrest.iter().cloned().filter(
+        |&j| station(&keys[j])).collect();
+    let mut matched = adif_reconcile_match(&log_keys, &keys, &mut left,
+        &mut right, &match_options);
+    lrest.retain(|i| !matched.iter().any(|(l, _)| l == i));
+    rrest.retain(|j| !matched.iter().any(|(_, r)| r == j));
+
+    match_options.amo_key_fields.pop();
+    let mut right : Vec<usize> = rrest.iter().cloned().filter(
+        |&j| !station(&keys[j])).collect();
+    matched.extend(adif_reconcile_match(&log_keys, &keys, &mut lrest,
+        &mut right, &match_options));
+    rrest.retain(|j| !matched.iter().any(|(_, r)| r == j));
+    let mut left : Vec<usize> = lrest.iter().cloned().filter(
+        |&i| !station(&log_keys[i])).collect();
+    matched.extend(adif_reconcile_match(&log_keys, &keys, &mut left,
+        &mut rrest, &match_options));
+    result.arcr_unmatched = rrest.iter().map(|&j| which[j]).collect();
+
+    for (i, j) in matched {
+        let conf = &confirmations.adif_records[which[j]];
+        let update = AdifQslUpdate {
+            aqu_service: service,
+            aqu_direction: AdifQslDirection::AQD_RCVD,
+            aqu_status: String::from("Y"),
+            aqu_date: conf.adir_field_values.get("qslrdate").and_then(
+                |d| adif_parse_date(d)).or(options.arco_date),
+            aqu_via: None,
+            aqu_force: false
+        };
+
+        //
+        // Don't touch QSOs that are already confirmed (which might be marked
+        // "V", for verified).
+        //
+        let confirmed = adif_qsl_state(&log[i], service) ==
+            AdifQslState::AQST_CONFIRMED;
+        let updated = if confirmed {
+            Ok(false)
+        } else {
+            adif_qsl_apply(&mut log[i], &update)
+        };
+        let mut changed = match updated {
+            Ok(c) => c,
+            Err(message) => {
+                result.arcr_diagnostics.push(AdifDiagnostic {
+                    adid_record: Some(i + 1),
+                    adid_line: None,
+                    adid_message: format!("{} confirmation not applied: {}",
+                        adif_qsl_service_name(service), message)
+                });
+                false
+            }
+        };
+        if let Some(credit) = conf.adir_field_values.get("credit_granted") {
+            changed |= adif_merge_list(&mut log[i], "credit_granted", credit);
+        }
+
+        result.arcr_matched.push((i, which[j]));
+        if changed {
+            result.arcr_updated.push(i);
+        }
+    }
+
+    result.arcr_matched.sort();
+    result.arcr_updated.sort();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use adif_parse;
+    use super::*;
+
+    fn qso(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    #[test]
+    fn lotw() {
+        let input = "ARRL Logbook of the World Status Report\n<EOH>\n\
+            <CALL:4>W1AW<BAND:3>20M<MODE:3>FT8<QSO_DATE:8>20240101\
+            <TIME_ON:6>120300<QSL_RCVD:1>Y<QSLRDATE:8>20240110\
+            <CREDIT_GRANTED:9>DXCC:LOTW<EOR>\n\
+            <CALL:4>K2XX<FREQ:5>7.030<MODE:2>CW<QSO_DATE:8>20240101\
+            <TIME_ON:4>1300<QSL_RCVD:1>Y<EOR>\n\
+            <CALL:6>N0CALL<BAND:3>40M<MODE:2>CW<QSO_DATE:8>20240101\
+            <TIME_ON:4>1400<QSL_RCVD:1>N<EOR>\n\
+            <CALL:5>VE3XX<BAND:3>40M<MODE:2>CW<QSO_DATE:8>20240101\
+            <TIME_ON:4>1500<QSL_RCVD:1>Y<EOR>\n";
+        let confirmations = adif_parse("lotw.adi", &mut input.as_bytes())
+            .unwrap();
+        assert_eq!(adif_reconcile_service(&confirmations),
+            Some(AdifQslService::AQS_LOTW));
+
+        let mut log = vec![
+            qso(&[("call", "w1aw"), ("band", "20m"), ("mode", "MFSK"),
+                ("submode", "FT4"), ("qso_date", "20240101"),
+                ("time_on", "1155"), ("credit_granted", "DXCC:CARD")]),
+            qso(&[("call", "K2XX"), ("band", "40m"), ("mode", "CW"),
+                ("qso_date", "20240101"), ("time_on", "1320"),
+                ("lotw_qsl_rcvd", "I")]),
+            qso(&[("call", "N0CALL"), ("band", "40m"), ("mode", "CW"),
+                ("qso_date", "20240101"), ("time_on", "1400")]),
+            qso(&[("call", "VE3XX"), ("band", "40m"), ("mode", "SSB"),
+                ("qso_date", "20240101"), ("time_on", "1500")])
+        ];
+        let options = AdifReconcileOptions {
+            arco_date: adif_parse_date("20240201"),
+            ..Default::default()
+        };
+        let result = adif_reconcile(&mut log, &confirmations, &options)
+            .unwrap();
+        assert_eq!(result.arcr_matched, vec![ (0, 0), (1, 1) ]);
+        assert_eq!(result.arcr_updated, vec![ 0 ]);
+        assert_eq!(result.arcr_unmatched, vec![ 3 ]);
+        assert_eq!(result.arcr_unconfirmed, vec![ 2 ]);
+        assert_eq!(result.arcr_diagnostics.len(), 1);
+        assert_eq!(result.arcr_diagnostics[0].adid_record, Some(2));
+
+        let fields = &log[0].adir_field_values;
+        assert_eq!(fields["lotw_qsl_rcvd"], "Y");
+        assert_eq!(fields["lotw_qslrdate"], "20240110");
+        assert_eq!(fields["credit_granted"], "DXCC:CARD,DXCC:LOTW");
+        assert!(!log[2].adir_field_values.contains_key("lotw_qsl_rcvd"));
+    }
+
+    #[test]
+    fn station_callsign() {
+        let input = "ARRL Logbook of the World Status Report\n<EOH>\n\
+            <CALL:4>W1AW<BAND:3>20M<MODE:2>CW<QSO_DATE:8>20240101\
+            <TIME_ON:4>1200<STATION_CALLSIGN:4>K1BB<QSL_RCVD:1>Y<EOR>\n\
+            <CALL:4>W1AW<BAND:3>20M<MODE:2>CW<QSO_DATE:8>20240101\
+            <TIME_ON:4>1200<STATION_CALLSIGN:4>K1CC<QSL_RCVD:1>Y<EOR>\n\
+            <CALL:4>W1AW<BAND:3>40M<MODE:2>CW<QSO_DATE:8>20240101\
+            <TIME_ON:4>1300<STATION_CALLSIGN:4>K1AA<QSL_RCVD:1>Y<EOR>\n";
+        let confirmations = adif_parse("lotw.adi", &mut input.as_bytes())
+            .unwrap();
+        let mut log = vec![
+            qso(&[("call", "W1AW"), ("band", "20m"), ("mode", "CW"),
+                ("qso_date", "20240101"), ("time_on", "1200"),
+                ("station_callsign", "K1AA")]),
+            qso(&[("call", "W1AW"), ("band", "20m"), ("mode", "CW"),
+                ("qso_date", "20240101"), ("time_on", "1205"),
+                ("station_callsign", "k1bb")]),
+            qso(&[("call", "W1AW"), ("band", "40m"), ("mode", "CW"),
+                ("qso_date", "20240101"), ("time_on", "1300")])
+        ];
+        let result = adif_reconcile(&mut log, &confirmations,
+            &AdifReconcileOptions::default()).unwrap();
+        assert_eq!(result.arcr_matched, vec![ (1, 0), (2, 2) ]);
+        assert_eq!(result.arcr_unmatched, vec![ 1 ]);
+        assert!(!log[0].adir_field_values.contains_key("lotw_qsl_rcvd"));
+    }
+
+    #[test]
+    fn eqsl() {
+        let input = "ADIF 3 Export from eQSL.cc\n\
+            <PROGRAMID:21>eQSL.cc DownloadInBox<EOH>\n\
+            <CALL:4>W1AW<BAND:3>20M<MODE:3>SSB<QSO_DATE:8>20240101\
+            <TIME_ON:4>1200<QSL_SENT:1>Y<EOR>\n";
+        let confirmations = adif_parse("inbox.adi", &mut input.as_bytes())
+            .unwrap();
+        let mut log = vec![ qso(&[("call", "W1AW"), ("band", "20m"),
+            ("mode", "SSB"), ("qso_date", "20240101"), ("time_on", "1200")]) ];
+        let options = AdifReconcileOptions {
+            arco_date: adif_parse_date("20240201"),
+            ..Default::default()
+        };
+        let result = adif_reconcile(&mut log, &confirmations, &options)
+            .unwrap();
+        assert_eq!(result.arcr_service, AdifQslService::AQS_EQSL);
+        assert_eq!(result.arcr_updated, vec![ 0 ]);
+        assert_eq!(log[0].adir_field_values["eqsl_qsl_rcvd"], "Y");
+        assert_eq!(log[0].adir_field_values["eqsl_qslrdate"], "20240201");
+
+        log[0].adir_field_values.insert(String::from("eqsl_qsl_rcvd"),
+            String::from("V"));
+        let result = adif_reconcile(&mut log, &confirmations, &options)
+            .unwrap();
+        assert_eq!(result.arcr_matched, vec![ (0, 0) ]);
+        assert!(result.arcr_updated.is_empty());
+        assert!(result.arcr_diagnostics.is_empty());
+
+        let plain = adif_parse("plain.adi", &mut "x<EOH>".as_bytes())
+            .unwrap();
+        assert!(matches!(adif_reconcile(&mut log, &plain,
+            &AdifReconcileOptions::default()),
+            Err(AdifParseError::ADIF_EBADINPUT(_))));
+    }
+}
@@ -0,0 +1,505 @@
+//
+// src/regex.rs: minimal regular expressions
+//
+// Filters (see src/filter.rs) can match field values against patterns.  We
+// only need a small subset of the usual regular expression syntax, so rather
+// than take on a dependency, this file implements a simple matcher.  Supported
+// syntax:
+//
+//     c           a literal character
+//     .           any character
+//     [abc]       any of the listed characters (ranges like "a-z" are allowed,
+//                 and "[^...]" matches any character not listed)
+//     \d \w \s    a digit, a word character, or whitespace
+//     \c          the character c (e.g., "\." for a literal period)
+//     ^ $         the start or end of the value
+//     (...)       a group
+//     a|b         either a or b
+//     * + ?       zero or more, one or more, or zero or one of the preceding
+//                 item
+//
+// Matching is case-insensitive, and a pattern matches if it matches any part
+// of the value (so use "^" and "$" to match the whole value).
+//
+// Patterns are compiled into a small program for a nondeterministic automaton
+// (Thompson's construction), which is simulated by tracking the set of
+// instructions that could be executing at each position in the text.  Unlike a
+// backtracking matcher, this takes time proportional to the length of the text
+// times the length of the program, regardless of the pattern, and it never
+// recurses on the text.
+//
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+enum RegexAtom {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),     // ranges and whether negated
+    Group(Vec<Vec<RegexPiece>>),        // alternatives
+    Start,
+    End
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct RegexPiece {
+    rp_atom : RegexAtom,
+    rp_min : usize,
+    rp_max : Option<usize>              // None means unlimited
+}
+
+//
+// RegexInst: an instruction in a compiled pattern.  "Split" continues at both
+// targets, and "Jump" continues at its target.  The others either consume one
+// character of text (if it matches), check the position, or report a match.
+//
+#[derive(Clone, Debug, PartialEq)]
+enum RegexInst {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Split(usize, usize),
+    Jump(usize),
+    Match
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifRegex {
+    arx_pattern : String,
+    arx_program : Vec<RegexInst>
+}
+
+impl fmt::Display for AdifRegex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.arx_pattern)
+    }
+}
+
+struct RegexParser<'a> {
+    rxp_chars : Vec<char>,
+    rxp_pos : usize,
+    rxp_pattern : &'a str
+}
+
+impl<'a> RegexParser<'a> {
+    fn error(&self, message: &str) -> String
+    {
+        format!("pattern \"{}\" (near character {}): {}", self.rxp_pattern,
+            self.rxp_pos + 1, message)
+    }
+
+    fn peek(&self) -> Option<char>
+    {
+        self.rxp_chars.get(self.rxp_pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char>
+    {
+        let c = self.peek();
+        if c.is_some() {
+            self.rxp_pos += 1;
+        }
+        c
+    }
+
+    fn parse_alternatives(&mut self) -> Result<Vec<Vec<RegexPiece>>, String>
+    {
+        let mut alternatives = vec![ self.parse_sequence()? ];
+        while self.peek() == Some('|') {
+            self.next();
+            alternatives.push(self.parse_sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_sequence(&mut self) -> Result<Vec<RegexPiece>, String>
+    {
+        let mut pieces = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            let atom = self.parse_atom()?;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                _ => (1, Some(1))
+            };
+            if max != Some(1) || min != 1 {
+                self.next();
+                if atom == RegexAtom::Start || atom == RegexAtom::End {
+                    return Err(self.error("nothing to repeat"));
+                }
+            }
+            pieces.push(RegexPiece { rp_atom: atom, rp_min: min,
+                rp_max: max });
+        }
+        Ok(pieces)
+    }
+
+    fn parse_escape(&mut self) -> Result<RegexAtom, String>
+    {
+        let class = |ranges: &[(char, char)]| {
+            Ok(RegexAtom::Class(ranges.to_vec(), false))
+        };
+        match self.next() {
+            None => Err(self.error("trailing backslash")),
+            Some('d') => class(&[ ('0', '9') ]),
+            Some('w') => class(&[ ('a', 'z'), ('A', 'Z'), ('0', '9'),
+                ('_', '_') ]),
+            Some('s') => class(&[ (' ', ' '), ('\t', '\t'), ('\n', '\n'),
+                ('\r', '\r') ]),
+            Some(c) => Ok(RegexAtom::Char(c))
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<RegexAtom, String>
+    {
+        match self.next() {
+            None => Err(self.error("unexpected end of pattern")),
+            Some('.') => Ok(RegexAtom::Any),
+            Some('^') => Ok(RegexAtom::Start),
+            Some('$') => Ok(RegexAtom::End),
+            Some('\\') => self.parse_escape(),
+            Some('(') => {
+                let alternatives = self.parse_alternatives()?;
+                if self.next() != Some(')') {
+                    return Err(self.error("missing \")\""));
+                }
+                Ok(RegexAtom::Group(alternatives))
+            },
+            Some('[') => self.parse_class(),
+            Some(c) if c == '*' || c == '+' || c == '?' => {
+                Err(self.error("nothing to repeat"))
+            },
+            Some(c) => Ok(RegexAtom::Char(c))
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<RegexAtom, String>
+    {
+        let negated = if self.peek() == Some('^') {
+            self.next();
+            true
+        } else {
+            false
+        };
+
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                None => return Err(self.error("missing \"]\"")),
+                Some(']') if !first => break,
+                Some('\\') => match self.parse_escape()? {
+                    RegexAtom::Char(c) => c,
+                    RegexAtom::Class(r, _) => {
+                        ranges.extend(r);
+                        first = false;
+                        continue;
+                    },
+                    _ => unreachable!()
+                },
+                Some(c) => c
+            };
+            first = false;
+
+            let next = self.rxp_chars.get(self.rxp_pos + 1);
+            if self.peek() == Some('-') && next.is_some_and(|&n| n != ']') {
+                self.next();
+                let end = self.next().unwrap();
+                if end < c {
+                    return Err(self.error("invalid range"));
+                }
+                ranges.push((c, end));
+            } else {
+                ranges.push((c, c));
+            }
+        }
+
+        Ok(RegexAtom::Class(ranges, negated))
+    }
+}
+
+//
+// Compile a pattern.  On failure, returns a message describing the problem.
+//
+pub fn adif_regex_compile(pattern: &str) -> Result<AdifRegex, String>
+{
+    let mut parser = RegexParser {
+        rxp_chars: pattern.chars().collect(),
+        rxp_pos: 0,
+        rxp_pattern: pattern
+    };
+    let alternatives = parser.parse_alternatives()?;
+    if parser.rxp_pos < parser.rxp_chars.len() {
+        return Err(parser.error("unexpected \")\""));
+    }
+
+    let mut program = Vec::new();
+    regex_compile_alternatives(&alternatives, &mut program);
+    program.push(RegexInst::Match);
+
+    Ok(AdifRegex {
+        arx_pattern: pattern.to_string(),
+        arx_program: program
+    })
+}
+
+//
+// Append instructions for "alternatives" to "program".  Each alternative but
+// the last is preceded by a "Split" to the next one and followed by a "Jump"
+// past the rest.  Targets that aren't known yet are filled in afterwards.
+//
+fn regex_compile_alternatives(alternatives: &[Vec<RegexPiece>],
+    program: &mut Vec<RegexInst>)
+{
+    let mut jumps = Vec::new();
+    for (i, seq) in alternatives.iter().enumerate() {
+        if i + 1 == alternatives.len() {
+            regex_compile_sequence(seq, program);
+            break;
+        }
+
+        let split = program.len();
+        program.push(RegexInst::Split(split + 1, 0));
+        regex_compile_sequence(seq, program);
+        jumps.push(program.len());
+        program.push(RegexInst::Jump(0));
+        program[split] = RegexInst::Split(split + 1, program.len());
+    }
+
+    let end = program.len();
+    for j in jumps {
+        program[j] = RegexInst::Jump(end);
+    }
+}
+
+fn regex_compile_sequence(seq: &[RegexPiece], program: &mut Vec<RegexInst>)
+{
+    for piece in seq {
+        regex_compile_piece(piece, program);
+    }
+}
+
+//
+// The parser only produces pieces that match exactly once or that have one of
+// the repetition operators, so those are the only cases we handle.
+//
+fn regex_compile_piece(piece: &RegexPiece, program: &mut Vec<RegexInst>)
+{
+    let start = program.len();
+    match (piece.rp_min, piece.rp_max) {
+        (1, Some(1)) => regex_compile_atom(&piece.rp_atom, program),
+        (0, Some(1)) => {
+            program.push(RegexInst::Split(start + 1, 0));
+            regex_compile_atom(&piece.rp_atom, program);
+            program[start] = RegexInst::Split(start + 1, program.len());
+        },
+        (0, None) => {
+            program.push(RegexInst::Split(start + 1, 0));
+            regex_compile_atom(&piece.rp_atom, program);
+            program.push(RegexInst::Jump(start));
+            program[start] = RegexInst::Split(start + 1, program.len());
+        },
+        (1, None) => {
+            regex_compile_atom(&piece.rp_atom, program);
+            program.push(RegexInst::Split(start, program.len() + 1));
+        },
+        _ => unreachable!()
+    }
+}
+
+fn regex_compile_atom(atom: &RegexAtom, program: &mut Vec<RegexInst>)
+{
+    let inst = match atom {
+        RegexAtom::Group(alternatives) => {
+            regex_compile_alternatives(alternatives, program);
+            return;
+        },
+        RegexAtom::Char(c) => RegexInst::Char(*c),
+        RegexAtom::Any => RegexInst::Any,
+        RegexAtom::Class(ranges, negated) =>
+            RegexInst::Class(ranges.clone(), *negated),
+        RegexAtom::Start => RegexInst::Start,
+        RegexAtom::End => RegexInst::End
+    };
+    program.push(inst);
+}
+
+fn regex_char_eq(c1: char, c2: char) -> bool
+{
+    c1 == c2 || c1.to_lowercase().eq(c2.to_lowercase())
+}
+
+fn regex_class_matches(ranges: &[(char, char)], c: char) -> bool
+{
+    let lower = c.to_lowercase().next().unwrap_or(c);
+    let upper = c.to_uppercase().next().unwrap_or(c);
+    ranges.iter().any(|&(lo, hi)| {
+        (lo <= c && c <= hi) || (lo <= lower && lower <= hi) ||
+            (lo <= upper && upper <= hi)
+    })
+}
+
+//
+// RegexThreads: the set of instructions that could be executing at a given
+// position in the text, in the order they were added.  "rxt_seen[pc]" is one
+// more than the last position at which instruction "pc" was visited, so that
+// we visit each instruction at most once per position.
+//
+struct RegexThreads {
+    rxt_list : Vec<usize>,
+    rxt_seen : Vec<usize>
+}
+
+impl RegexThreads {
+    fn new(size: usize) -> RegexThreads
+    {
+        RegexThreads { rxt_list: Vec::new(), rxt_seen: vec![ 0; size ] }
+    }
+}
+
+//
+// Add instruction "pc" to "threads", following any jumps, splits, and
+// assertions (which don't consume text) at position "pos".  Only instructions
+// that consume a character are added.  Returns true if the program can reach
+// its "Match" instruction.
+//
+fn regex_add_thread(program: &[RegexInst], text: &[char], pos: usize,
+    threads: &mut RegexThreads, pc: usize) -> bool
+{
+    let mut stack = vec![ pc ];
+    while let Some(pc) = stack.pop() {
+        if threads.rxt_seen[pc] == pos + 1 {
+            continue;
+        }
+        threads.rxt_seen[pc] = pos + 1;
+
+        match program[pc] {
+            RegexInst::Match => return true,
+            RegexInst::Jump(target) => stack.push(target),
+            RegexInst::Split(first, second) => {
+                stack.push(second);
+                stack.push(first);
+            },
+            RegexInst::Start => if pos == 0 {
+                stack.push(pc + 1);
+            },
+            RegexInst::End => if pos == text.len() {
+                stack.push(pc + 1);
+            },
+            _ => threads.rxt_list.push(pc)
+        }
+    }
+
+    false
+}
+
+//
+// Returns true if the pattern matches any part of "text".
+//
+pub fn adif_regex_matches(regex: &AdifRegex, text: &str) -> bool
+{
+    let program = &regex.arx_program;
+    let chars : Vec<char> = text.chars().collect();
+    let mut current = RegexThreads::new(program.len());
+    let mut next = RegexThreads::new(program.len());
+
+    for pos in 0..=chars.len() {
+        //
+        // A match may start at any position, so we start a new thread at the
+        // beginning of the program at each one.
+        //
+        if regex_add_thread(program, &chars, pos, &mut current, 0) {
+            return true;
+        }
+
+        if pos == chars.len() {
+            break;
+        }
+
+        let c = chars[pos];
+        for &pc in &current.rxt_list {
+            let matched = match &program[pc] {
+                RegexInst::Char(p) => regex_char_eq(*p, c),
+                RegexInst::Any => true,
+                RegexInst::Class(ranges, negated) =>
+                    regex_class_matches(ranges, c) != *negated,
+                _ => unreachable!()
+            };
+            if matched && regex_add_thread(program, &chars, pos + 1,
+                &mut next, pc + 1) {
+                return true;
+            }
+        }
+
+        current.rxt_list.clear();
+        std::mem::swap(&mut current, &mut next);
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        adif_regex_matches(&adif_regex_compile(pattern).unwrap(), text)
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches("^K", "KK6ZBI"));
+        assert!(matches("^k", "KK6ZBI"));
+        assert!(!matches("^K$", "KK6ZBI"));
+        assert!(matches("6Z", "KK6ZBI"));
+        assert!(matches("^[A-Z]+[0-9][A-Z]+$", "KK6ZBI"));
+        assert!(!matches("^[A-Z]+[0-9][A-Z]+$", "KK6ZBI/P"));
+        assert!(matches("/(P|M)$", "KK6ZBI/M"));
+        assert!(matches("^\\w+\\d\\w*$", "W1AW"));
+        assert!(matches("^14\\.0", "14.074"));
+        assert!(!matches("^14\\.0", "1400"));
+        assert!(matches("^a?b*c+$", "bbc"));
+        assert!(matches("^(ab)*$", ""));
+        assert!(matches("^(a*)*$", "aaa"));
+        assert!(matches("[^0-9]", "12a"));
+        assert!(!matches("[^0-9]", "123"));
+        assert!(matches("^[-a]+$", "a-a"));
+        assert!(matches("^(a|b)?c$", "c"));
+        assert!(matches("^(a|bc|)+d$", "abcad"));
+        assert!(!matches("^(a|bc)+d$", "abd"));
+        assert!(matches("x$|^y", "ya"));
+        assert!(!matches("x$|^y", "ay"));
+        assert!(!matches("^$", "a"));
+        assert!(matches("^$", ""));
+
+        assert!(adif_regex_compile("(abc").is_err());
+        assert!(adif_regex_compile("abc)").is_err());
+        assert!(adif_regex_compile("*a").is_err());
+        assert!(adif_regex_compile("[abc").is_err());
+        assert!(adif_regex_compile("[z-a]").is_err());
+        assert!(adif_regex_compile("a\\").is_err());
+    }
+
+    #[test]
+    fn long_inputs() {
+        //
+        // These would take a backtracking matcher a very long time (or
+        // overflow its stack).
+        //
+        let text = "a".repeat(100000);
+        assert!(!matches("a*b", &text));
+        assert!(!matches(".*.*.*b", &text));
+        assert!(matches("^(a*)*$", &text));
+        assert!(!matches("(a*)*b", &text));
+        assert!(!matches("^(a|aa)+$", &format!("{}b", text)));
+        assert!(matches("^(a+)+$", &text));
+        assert!(matches("a$", &text));
+    }
+}
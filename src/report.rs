@@ -0,0 +1,265 @@
+//
+// src/report.rs: summarizing QSOs
+//
+// A report counts QSOs grouped by the values of one or more fields (e.g., by
+// "dxcc", or by "band" and "mode").  For each group, we count the QSOs worked
+// and the QSOs confirmed (by paper QSL, LoTW, or eQSL).
+//
+// In addition to the record's own fields, reports can group by a few values
+// derived from the QSO's date and time: "year" (YYYY), "month" (YYYYMM), "day"
+// (YYYYMMDD), and "hour" (HH, UTC).  Values of enumerated fields (like "band"
+// and "mode") are uppercased so that, e.g., "20m" and "20M" are counted
+// together.  Records that lack a field are grouped under an empty value.
+//
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use adif::AdifRecord;
+use adiftypes::adif_parse_date;
+use adiftypes::adif_parse_time;
+use dedupe::adif_record_confirmations;
+use fields::AdifDataType;
+use fields::adif_field_type;
+use sort::adif_sort_compare;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AdifReportCounts {
+    pub arc_worked : usize,             // number of QSOs
+    pub arc_confirmed : usize           // number of confirmed QSOs
+}
+
+impl AdifReportCounts {
+    fn add(&mut self, other: &AdifReportCounts)
+    {
+        self.arc_worked += other.arc_worked;
+        self.arc_confirmed += other.arc_confirmed;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AdifReportRow {
+    pub arpr_key : Vec<String>,         // one value for each report field
+    pub arpr_counts : AdifReportCounts
+}
+
+#[derive(Clone, Debug)]
+pub struct AdifReport {
+    pub arp_fields : Vec<String>,       // fields used for grouping
+    pub arp_rows : Vec<AdifReportRow>,  // sorted by key
+    pub arp_total : AdifReportCounts
+}
+
+//
+// AdifReportMatrix: a two-field report laid out as a table (e.g., band by
+// mode).  "arm_cells[i][j]" holds the counts for row value "arm_rows[i]" and
+// column value "arm_columns[j]".
+//
+#[derive(Clone, Debug)]
+pub struct AdifReportMatrix {
+    pub arm_row_field : String,
+    pub arm_column_field : String,
+    pub arm_rows : Vec<String>,
+    pub arm_columns : Vec<String>,
+    pub arm_cells : Vec<Vec<AdifReportCounts>>,
+    pub arm_row_totals : Vec<AdifReportCounts>,
+    pub arm_column_totals : Vec<AdifReportCounts>,
+    pub arm_total : AdifReportCounts
+}
+
+//
+// Returns the value of "field" used to group this record.  See the comment at
+// the top of this file.
+//
+pub fn adif_report_value(rec: &AdifRecord, field: &str) -> String
+{
+    let get = |name: &str| rec.adir_field_values.get(name);
+    match field {
+        "year" | "month" | "day" => {
+            match get("qso_date").and_then(|d| adif_parse_date(d)) {
+                Some(date) => {
+                    let s = date.to_string();
+                    let len = match field {
+                        "year" => 4,
+                        "month" => 6,
+                        _ => 8
+                    };
+                    s[0..len].to_string()
+                },
+                None => String::new()
+            }
+        },
+        "hour" => {
+            match get("time_on").and_then(|t| adif_parse_time(t)) {
+                Some(time) => format!("{:02}", time.adt_hour),
+                None => String::new()
+            }
+        },
+        _ => {
+            let value = get(field).map(|v| v.trim()).unwrap_or("");
+            match adif_field_type(field) {
+                AdifDataType::ADT_BOOLEAN | AdifDataType::ADT_ENUMERATION |
+                AdifDataType::ADT_GRIDSQUARE => value.to_uppercase(),
+                _ => value.to_string()
+            }
+        }
+    }
+}
+
+fn adif_report_count(rec: &AdifRecord) -> AdifReportCounts
+{
+    AdifReportCounts {
+        arc_worked: 1,
+        arc_confirmed: if adif_record_confirmations(rec) > 0 { 1 } else { 0 }
+    }
+}
+
+//
+// Count the given records grouped by the values of "fields".
+//
+pub fn adif_report<'a, I>(records: I, fields: &[String]) -> AdifReport
+    where I: IntoIterator<Item = &'a AdifRecord>
+{
+    let mut groups : BTreeMap<Vec<String>, AdifReportCounts> = BTreeMap::new();
+    let mut total = AdifReportCounts::default();
+
+    for rec in records {
+        let key = fields.iter().map(|f| adif_report_value(rec, f)).collect();
+        let count = adif_report_count(rec);
+        groups.entry(key).or_default().add(&count);
+        total.add(&count);
+    }
+
+    let mut rows : Vec<AdifReportRow> = groups.into_iter().map(
+        |(key, counts)| AdifReportRow { arpr_key: key, arpr_counts: counts })
+        .collect();
+    rows.sort_by(|r1, r2| {
+        fields.iter().enumerate().fold(Ordering::Equal, |ord, (i, f)| {
+            ord.then_with(|| adif_sort_compare(f, &r1.arpr_key[i],
+                &r2.arpr_key[i]))
+        })
+    });
+
+    AdifReport {
+        arp_fields: fields.to_vec(),
+        arp_rows: rows,
+        arp_total: total
+    }
+}
+
+//
+// Count the given records in a table whose rows are the values of "row_field"
+// and whose columns are the values of "column_field".
+//
+pub fn adif_report_matrix<'a, I>(records: I, row_field: &str,
+    column_field: &str) -> AdifReportMatrix
+    where I: IntoIterator<Item = &'a AdifRecord>
+{
+    let fields = vec![ row_field.to_string(), column_field.to_string() ];
+    let report = adif_report(records, &fields);
+
+    let mut rows : Vec<String> = Vec::new();
+    let mut columns : Vec<String> = Vec::new();
+    for row in &report.arp_rows {
+        if !rows.contains(&row.arpr_key[0]) {
+            rows.push(row.arpr_key[0].clone());
+        }
+        if !columns.contains(&row.arpr_key[1]) {
+            columns.push(row.arpr_key[1].clone());
+        }
+    }
+    columns.sort_by(|c1, c2| adif_sort_compare(column_field, c1, c2));
+
+    let mut cells = vec![ vec![ AdifReportCounts::default(); columns.len() ];
+        rows.len() ];
+    let mut row_totals = vec![ AdifReportCounts::default(); rows.len() ];
+    let mut column_totals = vec![ AdifReportCounts::default(); columns.len() ];
+    for row in &report.arp_rows {
+        let i = rows.iter().position(|r| *r == row.arpr_key[0]).unwrap();
+        let j = columns.iter().position(|c| *c == row.arpr_key[1]).unwrap();
+        cells[i][j] = row.arpr_counts;
+        row_totals[i].add(&row.arpr_counts);
+        column_totals[j].add(&row.arpr_counts);
+    }
+
+    AdifReportMatrix {
+        arm_row_field: row_field.to_string(),
+        arm_column_field: column_field.to_string(),
+        arm_rows: rows,
+        arm_columns: columns,
+        arm_cells: cells,
+        arm_row_totals: row_totals,
+        arm_column_totals: column_totals,
+        arm_total: report.arp_total
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifRecord;
+
+    fn qso(fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    fn records() -> Vec<AdifRecord> {
+        vec![
+            qso(&[ ("band", "20m"), ("mode", "SSB"), ("cqz", "5"),
+                ("qso_date", "20180623"), ("time_on", "1800"),
+                ("lotw_qsl_rcvd", "Y") ]),
+            qso(&[ ("band", "20M"), ("mode", "FT8"), ("cqz", "14"),
+                ("qso_date", "20180623"), ("time_on", "1930") ]),
+            qso(&[ ("band", "40m"), ("mode", "SSB"), ("cqz", "5"),
+                ("qso_date", "20180701"), ("time_on", "0200"),
+                ("qsl_rcvd", "V") ]),
+            qso(&[ ("band", "160m"), ("mode", "CW") ]),
+        ]
+    }
+
+    #[test]
+    fn report_by_fields() {
+        let records = records();
+        let fields = vec![ String::from("cqz") ];
+        let report = super::adif_report(&records, &fields);
+        let keys : Vec<&str> = report.arp_rows.iter().map(
+            |r| r.arpr_key[0].as_str()).collect();
+        assert_eq!(keys, vec![ "5", "14", "" ]);
+        assert_eq!(report.arp_rows[0].arpr_counts.arc_worked, 2);
+        assert_eq!(report.arp_rows[0].arpr_counts.arc_confirmed, 2);
+        assert_eq!(report.arp_total.arc_worked, 4);
+        assert_eq!(report.arp_total.arc_confirmed, 2);
+
+        let fields = vec![ String::from("month"), String::from("hour") ];
+        let report = super::adif_report(&records, &fields);
+        let keys : Vec<String> = report.arp_rows.iter().map(
+            |r| r.arpr_key.join("/")).collect();
+        assert_eq!(keys, vec![ "/", "201806/18", "201806/19", "201807/02" ]);
+
+        //
+        // Bands are ordered by wavelength, not alphabetically.
+        //
+        let fields = vec![ String::from("band") ];
+        let report = super::adif_report(&records, &fields);
+        let keys : Vec<&str> = report.arp_rows.iter().map(
+            |r| r.arpr_key[0].as_str()).collect();
+        assert_eq!(keys, vec![ "160M", "40M", "20M" ]);
+    }
+
+    #[test]
+    fn report_matrix() {
+        let records = records();
+        let matrix = super::adif_report_matrix(&records, "band", "mode");
+        assert_eq!(matrix.arm_rows, vec![ "160M", "40M", "20M" ]);
+        assert_eq!(matrix.arm_columns, vec![ "CW", "FT8", "SSB" ]);
+        assert_eq!(matrix.arm_cells[2][2].arc_worked, 1);
+        assert_eq!(matrix.arm_cells[2][2].arc_confirmed, 1);
+        assert_eq!(matrix.arm_cells[0][1].arc_worked, 0);
+        assert_eq!(matrix.arm_row_totals[2].arc_worked, 2);
+        assert_eq!(matrix.arm_column_totals[2].arc_worked, 2);
+        assert_eq!(matrix.arm_total.arc_worked, 4);
+    }
+}
@@ -0,0 +1,278 @@
+//
+// src/sort.rs: sorting and grouping records
+//
+// Records can be sorted by any list of fields.  Values are compared according
+// to the field's ADIF data type (see src/fields.rs), so dates and times sort
+// chronologically and frequencies numerically.  Bands sort by wavelength
+// (longest first) rather than alphabetically.  Besides the record's own fields,
+// sort keys can use the derived fields from src/report.rs ("year", "month",
+// "day", and "hour") and "time", which is the QSO's start time (the combination
+// of "qso_date" and "time_on").  Records that lack a field sort after records
+// that have it, whichever direction the key is sorted in.
+//
+// Records can also be grouped by the values of one or more fields, as reports
+// do (see src/report.rs).
+//
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use adif::AdifRecord;
+use diff::adif_record_timestamp;
+use fields::adif_band_compare;
+use fields::adif_field_type;
+use fields::adif_typed_compare;
+use report::adif_report_value;
+use super::AdifParseError;
+
+//
+// Name of the sort key for a QSO's start time
+//
+const ADIF_SORT_TIME : &str = "time";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdifSortKey {
+    pub asrt_field : String,            // field name (lowercase)
+    pub asrt_descending : bool          // sort largest values first
+}
+
+//
+// Parse a comma-separated list of sort keys.  Each key is a field name,
+// optionally preceded by "-" to sort in descending order (e.g.,
+// "band,-time").
+//
+pub fn adif_parse_sort_keys(spec: &str) ->
+    Result<Vec<AdifSortKey>, AdifParseError>
+{
+    let mut keys = Vec::new();
+    for part in spec.split(',') {
+        let part = part.trim();
+        let (field, descending) = match part.strip_prefix('-') {
+            Some(f) => (f, true),
+            None => (part, false)
+        };
+
+        if field.is_empty() ||
+            !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(AdifParseError::ADIF_EBADINPUT(format!(
+                "invalid sort key \"{}\" in \"{}\"", part, spec)));
+        }
+
+        keys.push(AdifSortKey {
+            asrt_field: field.to_lowercase(),
+            asrt_descending: descending
+        });
+    }
+
+    Ok(keys)
+}
+
+//
+// Compare two values of "field" for sorting.
+//
+pub fn adif_sort_compare(field: &str, v1: &str, v2: &str) -> Ordering
+{
+    match field {
+        "band" | "band_rx" => adif_band_compare(v1, v2),
+        _ => adif_typed_compare(adif_field_type(field), v1, v2)
+    }
+}
+
+//
+// Returns the value of "field" used for sorting, if the record has one.
+//
+fn adif_sort_value(rec: &AdifRecord, field: &str) -> Option<String>
+{
+    match field {
+        "year" | "month" | "day" | "hour" => {
+            let value = adif_report_value(rec, field);
+            if value.is_empty() { None } else { Some(value) }
+        },
+        _ => rec.adir_field_values.get(field).cloned()
+    }
+}
+
+//
+// Put "None" after "Some", regardless of sort direction.
+//
+fn adif_sort_present<T, F>(v1: Option<T>, v2: Option<T>, descending: bool,
+    compare: F) -> Ordering
+    where F: Fn(&T, &T) -> Ordering
+{
+    match (v1, v2) {
+        (Some(a), Some(b)) => {
+            let ord = compare(&a, &b);
+            if descending { ord.reverse() } else { ord }
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal
+    }
+}
+
+//
+// Compare two records according to "keys".
+//
+pub fn adif_record_compare(r1: &AdifRecord, r2: &AdifRecord,
+    keys: &[AdifSortKey]) -> Ordering
+{
+    keys.iter().fold(Ordering::Equal, |ord, key| {
+        ord.then_with(|| {
+            let field = key.asrt_field.as_str();
+            if field == ADIF_SORT_TIME {
+                adif_sort_present(adif_record_timestamp(r1),
+                    adif_record_timestamp(r2), key.asrt_descending,
+                    |t1, t2| t1.cmp(t2))
+            } else {
+                adif_sort_present(adif_sort_value(r1, field),
+                    adif_sort_value(r2, field), key.asrt_descending,
+                    |v1, v2| adif_sort_compare(field, v1, v2))
+            }
+        })
+    })
+}
+
+//
+// Sort records according to "keys".  The sort is stable, so records that
+// compare equal stay in their original order.
+//
+pub fn adif_sort_records(records: &mut [AdifRecord], keys: &[AdifSortKey])
+{
+    records.sort_by(|r1, r2| adif_record_compare(r1, r2, keys));
+}
+
+#[derive(Clone, Debug)]
+pub struct AdifRecordGroup<'a> {
+    pub argp_key : Vec<String>,         // one value for each grouping field
+    pub argp_records : Vec<&'a AdifRecord>  // in their original order
+}
+
+//
+// Group records by the values of "fields".  Groups are sorted by their values
+// (with records that lack a field last), and records within each group are in
+// the order given.
+//
+pub fn adif_group_records<'a, I>(records: I, fields: &[String]) ->
+    Vec<AdifRecordGroup<'a>>
+    where I: IntoIterator<Item = &'a AdifRecord>
+{
+    let mut groups : Vec<AdifRecordGroup<'a>> = Vec::new();
+    let mut indexes : HashMap<Vec<String>, usize> = HashMap::new();
+    for rec in records {
+        let key : Vec<String> = fields.iter().map(
+            |f| adif_report_value(rec, f)).collect();
+        match indexes.get(&key) {
+            Some(&i) => groups[i].argp_records.push(rec),
+            None => {
+                indexes.insert(key.clone(), groups.len());
+                groups.push(AdifRecordGroup {
+                    argp_key: key,
+                    argp_records: vec![ rec ]
+                });
+            }
+        }
+    }
+
+    let nonempty = |v: &String| if v.is_empty() { None } else {
+        Some(v.clone())
+    };
+    groups.sort_by(|g1, g2| {
+        fields.iter().enumerate().fold(Ordering::Equal, |ord, (i, f)| {
+            ord.then_with(|| adif_sort_present(nonempty(&g1.argp_key[i]),
+                nonempty(&g2.argp_key[i]), false,
+                |v1, v2| adif_sort_compare(f, v1, v2)))
+        })
+    });
+    groups
+}
+
+#[cfg(test)]
+mod test {
+    use adif::AdifRecord;
+    use super::*;
+
+    fn qso(call: &str, fields: &[(&str, &str)]) -> AdifRecord {
+        let mut rec = AdifRecord::default();
+        rec.adir_field_values.insert("call".to_string(), call.to_string());
+        for (name, value) in fields {
+            rec.adir_field_values.insert(name.to_string(), value.to_string());
+        }
+        rec
+    }
+
+    fn calls<'a, I: IntoIterator<Item = &'a AdifRecord>>(records: I)
+        -> Vec<String>
+    {
+        records.into_iter().map(
+            |r| r.adir_field_values["call"].clone()).collect()
+    }
+
+    fn records() -> Vec<AdifRecord> {
+        vec![
+            qso("A", &[ ("band", "20m"), ("freq", "14.2"),
+                ("qso_date", "20240301"), ("time_on", "1200") ]),
+            qso("B", &[ ("band", "160m"), ("freq", "1.84"),
+                ("qso_date", "20240301"), ("time_on", "0930") ]),
+            qso("C", &[ ("band", "20M"), ("freq", "14.074"),
+                ("qso_date", "20231231"), ("time_on", "235959") ]),
+            qso("D", &[ ("freq", "7.1") ]),
+            qso("E", &[ ("band", "2m"), ("freq", "144.2"),
+                ("qso_date", "20240301"), ("time_on", "1200") ])
+        ]
+    }
+
+    #[test]
+    fn sort() {
+        let mut recs = records();
+        let keys = adif_parse_sort_keys("time").unwrap();
+        adif_sort_records(&mut recs, &keys);
+        assert_eq!(calls(&recs), [ "C", "B", "A", "E", "D" ]);
+
+        let keys = adif_parse_sort_keys("-time").unwrap();
+        adif_sort_records(&mut recs, &keys);
+        assert_eq!(calls(&recs), [ "A", "E", "B", "C", "D" ]);
+
+        let keys = adif_parse_sort_keys("freq").unwrap();
+        adif_sort_records(&mut recs, &keys);
+        assert_eq!(calls(&recs), [ "B", "D", "C", "A", "E" ]);
+
+        let keys = adif_parse_sort_keys("BAND, -qso_date").unwrap();
+        adif_sort_records(&mut recs, &keys);
+        assert_eq!(calls(&recs), [ "B", "A", "C", "E", "D" ]);
+
+        assert!(adif_parse_sort_keys("band,").is_err());
+        assert!(adif_parse_sort_keys("-").is_err());
+        assert!(adif_parse_sort_keys("band;time").is_err());
+    }
+
+    #[test]
+    fn group() {
+        let recs = records();
+        let groups = adif_group_records(&recs, &[ "band".to_string() ]);
+        let summary : Vec<(String, Vec<String>)> = groups.iter().map(
+            |g| (g.argp_key[0].clone(), calls(g.argp_records.iter().cloned())))
+            .collect();
+        assert_eq!(summary, vec![
+            ("160M".to_string(), vec![ "B".to_string() ]),
+            ("20M".to_string(), vec![ "A".to_string(), "C".to_string() ]),
+            ("2M".to_string(), vec![ "E".to_string() ]),
+            ("".to_string(), vec![ "D".to_string() ])
+        ]);
+    }
+
+    #[test]
+    fn group_many() {
+        let recs : Vec<AdifRecord> = (0..20000).map(
+            |i| qso(&format!("W{}", i), &[ ("gridsquare",
+            &format!("FN{:05}", i % 5000)) ])).collect();
+        let groups = adif_group_records(&recs, &[ "gridsquare".to_string() ]);
+        assert_eq!(groups.len(), 5000);
+        for (i, g) in groups.iter().enumerate() {
+            assert_eq!(g.argp_key, vec![ format!("FN{:05}", i) ]);
+            assert_eq!(calls(g.argp_records.iter().cloned()), vec![
+                format!("W{}", i), format!("W{}", i + 5000),
+                format!("W{}", i + 10000), format!("W{}", i + 15000)
+            ]);
+        }
+    }
+}
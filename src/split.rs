@@ -0,0 +1,160 @@
+//
+// src/split.rs: splitting a file into several files
+//
+// A big log can be partitioned by the values of any fields (e.g., by
+// "station_callsign" or "operator" for a multi-operator log, or by
+// "my_gridsquare" for a rover), including the derived fields "year", "month",
+// "day", and "hour" (see src/report.rs).  Partitions are ordered by their
+// values, as in reports, and records within each partition stay in their
+// original order.  Each partition can also be split into chunks of at most a
+// given number of records (e.g., to stay under an upload limit).
+//
+// Each part is a complete AdifFile with a copy of the original header, so it
+// can be written out as a valid file by itself.  Callers usually set a new
+// "created_timestamp" for the parts, since they're newly created files.
+//
+
+use std::collections::BTreeSet;
+
+use adif::AdifFile;
+use adif::adif_header_set;
+use adiftypes::AdifTimestamp;
+use sort::adif_group_records;
+
+#[derive(Clone, Debug, Default)]
+pub struct AdifSplitOptions {
+    pub aspo_fields : Vec<String>,          // fields to partition by
+    pub aspo_max_records : Option<usize>,   // max records in each part
+    pub aspo_timestamp : Option<AdifTimestamp>  // new created_timestamp
+}
+
+//
+// AdifSplitPart: one part of a split file.  Chunks are numbered from 1 within
+// each partition.
+//
+pub struct AdifSplitPart {
+    pub aspp_key : Vec<String>,     // one value for each partitioning field
+    pub aspp_chunk : usize,         // chunk number within the partition
+    pub aspp_nchunks : usize,       // number of chunks in the partition
+    pub aspp_file : AdifFile
+}
+
+pub fn adif_split(adf: &AdifFile, options: &AdifSplitOptions) ->
+    Vec<AdifSplitPart>
+{
+    let mut header = adf.adif_header.clone();
+    if let Some(timestamp) = options.aspo_timestamp {
+        adif_header_set(&mut header, "CREATED_TIMESTAMP",
+            &timestamp.to_string());
+    }
+
+    let mut parts = Vec::new();
+    let groups = adif_group_records(&adf.adif_records, &options.aspo_fields);
+    for group in groups {
+        let size = options.aspo_max_records.unwrap_or(
+            group.argp_records.len()).max(1);
+        let chunks : Vec<_> = group.argp_records.chunks(size).collect();
+        let nchunks = chunks.len();
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            parts.push(AdifSplitPart {
+                aspp_key: group.argp_key.clone(),
+                aspp_chunk: i + 1,
+                aspp_nchunks: nchunks,
+                aspp_file: AdifFile {
+                    adif_header: header.clone(),
+                    adif_label: adf.adif_label.clone(),
+                    adif_diagnostics: Vec::new(),
+                    adif_records: chunk.iter().map(|r| (*r).clone()).collect()
+                }
+            });
+        }
+    }
+    parts
+}
+
+//
+// Returns a name for each part that's suitable for use in a filename, like
+// "W1AW-2024" or "W1AW-2024-002".  Values are made safe by replacing any
+// character other than a letter, digit, "-", "_", or "." with "_", and missing
+// values become "none".  Chunk numbers are included only when the file was
+// split into chunks.  Names are made unique by adding a suffix if needed.
+//
+pub fn adif_split_names(parts: &[AdifSplitPart], options: &AdifSplitOptions) ->
+    Vec<String>
+{
+    let width = parts.iter().map(|p| p.aspp_nchunks).max().unwrap_or(1)
+        .to_string().len().max(3);
+    let mut used = BTreeSet::new();
+    parts.iter().map(|part| {
+        let mut words : Vec<String> = part.aspp_key.iter().map(|v| {
+            if v.is_empty() {
+                String::from("none")
+            } else {
+                v.chars().map(|c| if c.is_ascii_alphanumeric() ||
+                    c == '-' || c == '_' || c == '.' { c } else { '_' })
+                    .collect()
+            }
+        }).collect();
+        if options.aspo_max_records.is_some() {
+            words.push(format!("{:0width$}", part.aspp_chunk, width = width));
+        }
+
+        let base = words.join("-");
+        let mut name = base.clone();
+        let mut n = 1;
+        while !used.insert(name.to_lowercase()) {
+            n += 1;
+            name = format!("{}_{}", base, n);
+        }
+        name
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use adif::adif_header_created_timestamp;
+    use adif_parse;
+    use adiftypes::adif_parse_timestamp;
+    use super::*;
+
+    #[test]
+    fn split() {
+        let input = "log\n<CREATED_TIMESTAMP:15>20200101 000000<EOH>\n\
+            <CALL:4>W1AW<STATION_CALLSIGN:4>K1AA<QSO_DATE:8>20230101<EOR>\n\
+            <CALL:4>W2AW<STATION_CALLSIGN:4>K1AA<QSO_DATE:8>20240101<EOR>\n\
+            <CALL:4>W3AW<STATION_CALLSIGN:6>K1AA/P<QSO_DATE:8>20240102<EOR>\n\
+            <CALL:4>W4AW<STATION_CALLSIGN:4>K1AA<QSO_DATE:8>20240103<EOR>\n\
+            <CALL:4>W5AW<QSO_DATE:8>20240104<EOR>\n\
+            <CALL:4>W6AW<STATION_CALLSIGN:6>K1AA_P<QSO_DATE:8>20240105<EOR>\n";
+        let adf = adif_parse("log.adi", &mut input.as_bytes()).unwrap();
+        let calls = |part: &AdifSplitPart| -> Vec<String> {
+            part.aspp_file.adif_records.iter().map(
+                |r| r.adir_field_values["call"].clone()).collect()
+        };
+
+        let mut options = AdifSplitOptions {
+            aspo_fields: vec![ String::from("station_callsign"),
+                String::from("year") ],
+            ..Default::default()
+        };
+        let parts = adif_split(&adf, &options);
+        assert_eq!(parts.len(), 5);
+        assert_eq!(parts[1].aspp_key, vec![ "K1AA", "2024" ]);
+        assert_eq!(calls(&parts[1]), vec![ "W2AW", "W4AW" ]);
+        assert_eq!(adif_split_names(&parts, &options), vec![ "K1AA-2023",
+            "K1AA-2024", "K1AA_P-2024", "K1AA_P-2024_2", "none-2024" ]);
+        assert_eq!(adif_header_created_timestamp(&parts[0].aspp_file
+            .adif_header).unwrap().to_string(), "20200101 000000");
+
+        options.aspo_fields.clear();
+        options.aspo_max_records = Some(4);
+        options.aspo_timestamp = adif_parse_timestamp("20240201 120000");
+        let parts = adif_split(&adf, &options);
+        assert_eq!(parts.len(), 2);
+        assert_eq!((parts[1].aspp_chunk, parts[1].aspp_nchunks), (2, 2));
+        assert_eq!(calls(&parts[1]), vec![ "W5AW", "W6AW" ]);
+        assert_eq!(adif_split_names(&parts, &options), vec![ "001", "002" ]);
+        assert_eq!(adif_header_created_timestamp(&parts[1].aspp_file
+            .adif_header).unwrap().to_string(), "20240201 120000");
+    }
+}
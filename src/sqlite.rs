@@ -0,0 +1,604 @@
+//
+// src/sqlite.rs: storing logs in an SQLite database
+//
+// This module is only built with the "sqlite" feature.  A store is an SQLite
+// database holding one log, which is built up incrementally by importing ADIF
+// files into it and can be exported back to ADIF at any time.  The schema is:
+//
+//     log_info (name, value)
+//         information about the log as a whole: the header "preamble" (the
+//         free text before the first header field) and the "key_fields" used
+//         to identify QSOs
+//
+//     header (position, name, type, value)
+//         the header fields (including USERDEFn and APP_* fields), in order
+//
+//     qsos (id, identity, call, qso_date, time_on, ...)
+//         one row per QSO, with a typed column for each of the most commonly
+//         used fields (see ADIF_STORE_COLUMNS).  Dates and times are stored as
+//         in ADIF (YYYYMMDD and HHMM[SS]).
+//
+//     qso_fields (qso_id, name, seq, value)
+//         all other fields of each QSO (including application-defined and
+//         user-defined fields).  "seq" is 0 for a field's value and counts up
+//         from 1 for any additional values of the same field (see
+//         ADP_KEEP_ALL in src/adif.rs).
+//
+// Exporting is lossless: importing a file into a new store and exporting it
+// gives back the same header and the same records in the same order.  After
+// several imports, the export has the most recent header and every stored QSO
+// (in the order they were first imported).  To make that work, when the text
+// of a value can't be reproduced exactly from a typed column (e.g., a
+// frequency written "14.0740", or a DXCC entity that isn't a number), the text
+// is also stored in "qso_fields" and takes precedence on export.
+//
+// Each QSO's "identity" is built from its key fields (by default "call",
+// "band", "mode", and "station_callsign", compared case-insensitively, as for
+// matching records in src/diff.rs) plus its start date and time (to the
+// second, with "HHMM" taken as "HHMM00").  A key field that's missing counts
+// as empty, but records missing the date or time have no identity and are
+// always added.  Importing a record with the same identity as a stored QSO
+// replaces that QSO, so re-importing an updated export of a log updates the
+// QSOs already stored.  Records from the same file never replace each other:
+// if a file has several records with one identity, they replace that many
+// stored QSOs (in order) and the rest are added.  The key fields are recorded
+// when a store is first imported into, and importing with different key
+// fields is an error, since the stored identities would no longer match.  The
+// header is replaced by that of the most recently imported file.
+//
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io;
+
+use rusqlite;
+use rusqlite::types::Value;
+
+use adi::AdiFile;
+use adi::AdiHeader;
+use adi::AdiRecord;
+use adif::AdifDuplicatePolicy;
+use adif::AdifFile;
+use adif::AdifParseOptions;
+use adif::AdifRecord;
+use adif::adif_data_specifier;
+use adif::adif_parse_adi;
+use adif::adif_to_adi;
+use super::AdifParseError;
+
+//
+// The fields stored in typed columns of the "qsos" table, with the type of
+// each column.
+//
+pub const ADIF_STORE_COLUMNS : &[(&str, &str)] = &[
+    ("call", "TEXT"),
+    ("qso_date", "TEXT"),
+    ("time_on", "TEXT"),
+    ("qso_date_off", "TEXT"),
+    ("time_off", "TEXT"),
+    ("band", "TEXT"),
+    ("freq", "REAL"),
+    ("mode", "TEXT"),
+    ("submode", "TEXT"),
+    ("station_callsign", "TEXT"),
+    ("operator", "TEXT"),
+    ("rst_sent", "TEXT"),
+    ("rst_rcvd", "TEXT"),
+    ("tx_pwr", "REAL"),
+    ("gridsquare", "TEXT"),
+    ("my_gridsquare", "TEXT"),
+    ("dxcc", "INTEGER"),
+    ("my_dxcc", "INTEGER"),
+    ("country", "TEXT"),
+    ("cont", "TEXT"),
+    ("cqz", "INTEGER"),
+    ("ituz", "INTEGER"),
+    ("state", "TEXT"),
+    ("name", "TEXT"),
+    ("contest_id", "TEXT"),
+    ("srx", "INTEGER"),
+    ("stx", "INTEGER"),
+    ("qsl_sent", "TEXT"),
+    ("qsl_rcvd", "TEXT"),
+    ("lotw_qsl_sent", "TEXT"),
+    ("lotw_qsl_rcvd", "TEXT"),
+    ("eqsl_qsl_sent", "TEXT"),
+    ("eqsl_qsl_rcvd", "TEXT"),
+    ("comment", "TEXT")
+];
+
+pub struct AdifStore {
+    pub ast_conn : rusqlite::Connection
+}
+
+#[derive(Clone, Debug)]
+pub struct AdifStoreOptions {
+    pub aso_key_fields : Vec<String>    // fields that identify a QSO
+}
+
+impl Default for AdifStoreOptions {
+    fn default() -> AdifStoreOptions {
+        AdifStoreOptions {
+            aso_key_fields: vec![ String::from("call"), String::from("band"),
+                String::from("mode"), String::from("station_callsign") ]
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AdifStoreResult {
+    pub asr_inserted : usize,       // new QSOs (including "unkeyed" ones)
+    pub asr_updated : usize,        // QSOs that replaced stored ones
+    pub asr_unkeyed : usize         // new QSOs that have no identity
+}
+
+impl From<rusqlite::Error> for AdifParseError {
+    fn from(error: rusqlite::Error) -> Self {
+        AdifParseError::ADIF_EIO(io::Error::other(error))
+    }
+}
+
+//
+// Open the store in the database file "path", creating the file and schema if
+// needed.  The path ":memory:" refers to a new, temporary in-memory database.
+//
+pub fn adif_store_open(path: &str) -> Result<AdifStore, AdifParseError>
+{
+    let conn = rusqlite::Connection::open(path)?;
+    let columns : Vec<String> = ADIF_STORE_COLUMNS.iter().map(
+        |(name, sqltype)| format!("\"{}\" {}", name, sqltype)).collect();
+    conn.execute_batch(&format!("
+        PRAGMA foreign_keys = ON;
+        CREATE TABLE IF NOT EXISTS log_info (
+            name TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS header (
+            position INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            type TEXT,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS qsos (
+            id INTEGER PRIMARY KEY,
+            identity TEXT,
+            {}
+        );
+        CREATE TABLE IF NOT EXISTS qso_fields (
+            qso_id INTEGER NOT NULL REFERENCES qsos(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            seq INTEGER NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (qso_id, name, seq)
+        );
+        CREATE INDEX IF NOT EXISTS qsos_identity ON qsos(identity);
+        CREATE INDEX IF NOT EXISTS qsos_call ON qsos(call);
+        CREATE INDEX IF NOT EXISTS qsos_qso_date ON qsos(qso_date, time_on);
+        CREATE INDEX IF NOT EXISTS qso_fields_name ON qso_fields(name, value);
+        ", columns.join(",\n            ")))?;
+    Ok(AdifStore { ast_conn: conn })
+}
+
+//
+// Returns the identity of "rec" (see the comment at the top of this file), if
+// it has one.
+//
+fn adif_store_identity(rec: &AdifRecord, options: &AdifStoreOptions) ->
+    Option<String>
+{
+    let get = |name: &str| rec.adir_field_values.get(name).map(
+        |v| v.trim().to_uppercase()).filter(|v| !v.is_empty());
+    let date = get("qso_date")?;
+    let mut time = get("time_on")?;
+    if time.len() == 4 {
+        time.push_str("00");
+    }
+    let mut parts : Vec<String> = options.aso_key_fields.iter().map(
+        |field| get(field).unwrap_or_default()).collect();
+    parts.push(date);
+    parts.push(time);
+    Some(parts.join("|"))
+}
+
+//
+// Returns the value to store in the typed column for "text", and whether the
+// text can be reproduced exactly from it.
+//
+fn adif_store_typed(sqltype: &str, text: &str) -> (Value, bool)
+{
+    match sqltype {
+        "INTEGER" => match text.parse::<i64>() {
+            Ok(n) => (Value::Integer(n), n.to_string() == text),
+            Err(_) => (Value::Null, false)
+        },
+        "REAL" => match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => (Value::Real(n), n.to_string() == text),
+            _ => (Value::Null, false)
+        },
+        _ => (Value::Text(text.to_string()), true)
+    }
+}
+
+//
+// Returns the text of a value read from a typed column, if there is one.
+//
+fn adif_store_text(value: Value) -> Option<String>
+{
+    match value {
+        Value::Null | Value::Blob(_) => None,
+        Value::Integer(n) => Some(n.to_string()),
+        Value::Real(n) => Some(n.to_string()),
+        Value::Text(s) => Some(s)
+    }
+}
+
+//
+// Import the header and records of "adf" into "store".  Records are added or
+// replace stored QSOs as described at the top of this file.
+//
+pub fn adif_store_upsert(store: &mut AdifStore, adf: &AdifFile,
+    options: &AdifStoreOptions) -> Result<AdifStoreResult, AdifParseError>
+{
+    let mut result = AdifStoreResult::default();
+    let tx = store.ast_conn.transaction()?;
+
+    let key_fields = options.aso_key_fields.join(",");
+    let stored : Option<String> = tx.query_row("SELECT value FROM log_info \
+        WHERE name = 'key_fields'", [], |row| row.get(0)).map(Some)
+        .or_else(|error| match error {
+            rusqlite::Error::QueryReturnedNoRows => Ok(None),
+            _ => Err(error)
+        })?;
+    match stored {
+        Some(stored) if stored != key_fields => {
+            return Err(AdifParseError::ADIF_EBADINPUT(format!("store \
+                identifies QSOs by \"{}\", not \"{}\"", stored, key_fields)));
+        },
+        Some(_) => (),
+        None => {
+            tx.execute("INSERT INTO log_info (name, value) \
+                VALUES ('key_fields', ?1)", [ &key_fields ])?;
+        }
+    }
+
+    //
+    // Store the header in the physical form we'd write to an ADI file, which
+    // is what we'll parse back when exporting.
+    //
+    let header = adif_to_adi(&AdifFile {
+        adif_header: adf.adif_header.clone(),
+        adif_label: adf.adif_label.clone(),
+        adif_diagnostics: Vec::new(),
+        adif_records: Vec::new()
+    }).adi_header.expect("header");
+    tx.execute("INSERT OR REPLACE INTO log_info (name, value) \
+        VALUES ('preamble', ?1)",
+        [ String::from_utf8_lossy(&header.adih_content) ])?;
+    tx.execute("DELETE FROM header", [])?;
+    for (i, field) in header.adih_fields.iter().enumerate() {
+        tx.execute("INSERT INTO header (position, name, type, value) \
+            VALUES (?1, ?2, ?3, ?4)", rusqlite::params![ i as i64,
+            field.adif_name, field.adif_type,
+            String::from_utf8_lossy(&field.adif_bytes) ])?;
+    }
+
+    let names : Vec<String> = ADIF_STORE_COLUMNS.iter().map(
+        |(name, _)| format!("\"{}\"", name)).collect();
+    let placeholders : Vec<String> = (0..names.len() + 1).map(
+        |i| format!("?{}", i + 1)).collect();
+    let insert = format!("INSERT INTO qsos (identity, {}) VALUES ({})",
+        names.join(", "), placeholders.join(", "));
+    let assignments : Vec<String> = names.iter().enumerate().map(
+        |(i, name)| format!("{} = ?{}", name, i + 2)).collect();
+    let update = format!("UPDATE qsos SET {} WHERE id = ?1",
+        assignments.join(", "));
+
+    //
+    // The stored QSOs that records in this file have added or replaced, which
+    // later records in the file must not replace.
+    //
+    let mut seen : BTreeSet<i64> = BTreeSet::new();
+    for rec in &adf.adif_records {
+        let identity = adif_store_identity(rec, options);
+        let mut values = Vec::new();
+        let mut other : Vec<(&String, usize, &String)> = Vec::new();
+        for (name, sqltype) in ADIF_STORE_COLUMNS {
+            let (value, exact) = match rec.adir_field_values.get(*name) {
+                Some(text) => adif_store_typed(sqltype, text),
+                None => (Value::Null, true)
+            };
+            if !exact {
+                let (name, text) = rec.adir_field_values.get_key_value(*name)
+                    .unwrap();
+                other.push((name, 0, text));
+            }
+            values.push(value);
+        }
+        for (name, text) in &rec.adir_field_values {
+            if !ADIF_STORE_COLUMNS.iter().any(|(c, _)| c == name) {
+                other.push((name, 0, text));
+            }
+        }
+        for (name, extras) in &rec.adir_extra_values {
+            for (i, text) in extras.iter().enumerate() {
+                other.push((name, i + 1, text));
+            }
+        }
+
+        let existing : Option<i64> = match identity {
+            None => None,
+            Some(ref identity) => {
+                let mut stmt = tx.prepare_cached("SELECT id FROM qsos \
+                    WHERE identity = ?1 ORDER BY id")?;
+                let ids = stmt.query_map([ identity ], |row| row.get(0))?
+                    .collect::<Result<Vec<i64>, _>>()?;
+                ids.into_iter().find(|id| !seen.contains(id))
+            }
+        };
+
+        let id = match existing {
+            Some(id) => {
+                values.insert(0, Value::Integer(id));
+                tx.execute(&update, rusqlite::params_from_iter(&values))?;
+                tx.execute("DELETE FROM qso_fields WHERE qso_id = ?1",
+                    [ id ])?;
+                result.asr_updated += 1;
+                id
+            },
+            None => {
+                if identity.is_none() {
+                    result.asr_unkeyed += 1;
+                }
+                values.insert(0, match identity {
+                    Some(identity) => Value::Text(identity),
+                    None => Value::Null
+                });
+                tx.execute(&insert, rusqlite::params_from_iter(&values))?;
+                result.asr_inserted += 1;
+                tx.last_insert_rowid()
+            }
+        };
+        seen.insert(id);
+
+        for (name, seq, text) in other {
+            tx.execute("INSERT INTO qso_fields (qso_id, name, seq, value) \
+                VALUES (?1, ?2, ?3, ?4)", rusqlite::params![ id, name,
+                seq as i64, text ])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(result)
+}
+
+//
+// Export the log in "store" as an ADIF file.  Additional values for the same
+// field in a record are kept (as with ADP_KEEP_ALL).
+//
+pub fn adif_store_export(store: &AdifStore, label: &str) ->
+    Result<AdifFile, AdifParseError>
+{
+    let conn = &store.ast_conn;
+    let preamble : String = conn.query_row("SELECT value FROM log_info \
+        WHERE name = 'preamble'", [], |row| row.get(0)).or_else(
+        |error| match error {
+            rusqlite::Error::QueryReturnedNoRows => Ok(String::new()),
+            _ => Err(error)
+        })?;
+
+    let mut stmt = conn.prepare("SELECT name, type, value FROM header \
+        ORDER BY position")?;
+    let header_fields = stmt.query_map([], |row| {
+        let (name, typestr, value) : (String, Option<String>, String) =
+            (row.get(0)?, row.get(1)?, row.get(2)?);
+        Ok(adif_data_specifier(&name, &typestr, &value))
+    })?.collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare("SELECT qso_id, name, seq, value FROM \
+        qso_fields ORDER BY qso_id, name, seq")?;
+    let mut other : BTreeMap<i64, Vec<(String, i64, String)>> =
+        BTreeMap::new();
+    for field in stmt.query_map([], |row| {
+        let field : (i64, String, i64, String) =
+            (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+        Ok(field)
+    })? {
+        let (id, name, seq, text) = field?;
+        other.entry(id).or_default().push((name, seq, text));
+    }
+
+    let names : Vec<String> = ADIF_STORE_COLUMNS.iter().map(
+        |(name, _)| format!("\"{}\"", name)).collect();
+    let mut stmt = conn.prepare(&format!("SELECT id, {} FROM qsos \
+        ORDER BY id", names.join(", ")))?;
+    let mut qsos = stmt.query([])?;
+    let mut records = Vec::new();
+    while let Some(row) = qsos.next()? {
+        let id : i64 = row.get(0)?;
+        let mut values : Vec<(String, Vec<String>)> = Vec::new();
+        for (i, (name, _)) in ADIF_STORE_COLUMNS.iter().enumerate() {
+            if let Some(text) = adif_store_text(row.get(i + 1)?) {
+                values.push((name.to_string(), vec![ text ]));
+            }
+        }
+
+        for (name, seq, text) in other.remove(&id).unwrap_or_default() {
+            match values.iter().position(|(n, _)| *n == name) {
+                Some(i) if seq == 0 => values[i].1[0] = text,
+                Some(i) => values[i].1.push(text),
+                None => values.push((name, vec![ text ]))
+            }
+        }
+
+        values.sort();
+        let mut adir_fields = Vec::new();
+        for (name, texts) in &values {
+            for text in texts {
+                adir_fields.push(adif_data_specifier(&name.to_uppercase(),
+                    &None, text));
+            }
+        }
+        records.push(AdiRecord { adir_fields });
+    }
+
+    let adi = AdiFile {
+        adi_header: Some(AdiHeader {
+            adih_content: preamble.into_bytes(),
+            adih_fields: header_fields
+        }),
+        adi_records: records
+    };
+    let mut adf = adif_parse_adi(label, &adi, &AdifParseOptions {
+        apo_duplicates: AdifDuplicatePolicy::ADP_KEEP_ALL
+    })?;
+    adf.adif_diagnostics.clear();
+    Ok(adf)
+}
+
+#[cfg(test)]
+mod test {
+    use adi::adi_parse_string;
+    use adi::adi_write;
+    use adif::AdifDuplicatePolicy;
+    use adif::AdifParseOptions;
+    use adif::adif_header_program_id;
+    use adif::adif_parse_adi;
+    use adif::adif_to_adi;
+    use super::*;
+
+    fn parse(text: &str) -> AdifFile {
+        adif_parse_adi("test", &adi_parse_string(text).unwrap(),
+            &AdifParseOptions {
+            apo_duplicates: AdifDuplicatePolicy::ADP_KEEP_ALL
+        }).unwrap()
+    }
+
+    fn write(adf: &AdifFile) -> String {
+        let mut out = Vec::new();
+        adi_write(&adif_to_adi(adf), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let adf = parse("Exported from a test\n\
+            <ADIF_VER:5>3.1.4\n\
+            <PROGRAMID:4>test\n\
+            <USERDEF1:19:E>SweaterSize,{S,M,L}\n\
+            <APP_TEST_FOO:3>bar\n\
+            <EOH>\n\
+            <call:6>KK6ZBI <qso_date:8>20240315 <time_on:4>1830 \
+            <band:3>20m <mode:3>SSB <freq:7>14.2500 <dxcc:3>291 \
+            <cqz:2>03 <tx_pwr:3>100 <comment:0> <SweaterSize:1>M \
+            <app_test_x:1>y <note:1>a <note:1>b <eor>\n\
+            <call:4>W1AW <dxcc:3>abc <freq:6>7.0125 <eor>\n\
+            <call:4>W1AW <eor>\n");
+        let mut store = adif_store_open(":memory:").unwrap();
+        let result = adif_store_upsert(&mut store, &adf,
+            &AdifStoreOptions::default()).unwrap();
+        assert_eq!(result, AdifStoreResult {
+            asr_inserted: 3, asr_updated: 0, asr_unkeyed: 2
+        });
+
+        let exported = adif_store_export(&store, "store").unwrap();
+        assert_eq!(write(&exported), write(&adf));
+        for (r1, r2) in exported.adif_records.iter().zip(&adf.adif_records) {
+            assert_eq!(r1.adir_field_values, r2.adir_field_values);
+            assert_eq!(r1.adir_extra_values, r2.adir_extra_values);
+        }
+
+        //
+        // The typed columns can be queried directly.
+        //
+        let (freq, cqz) : (f64, i64) = store.ast_conn.query_row(
+            "SELECT freq, cqz FROM qsos WHERE call = 'KK6ZBI'", [],
+            |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!((freq, cqz), (14.25, 3));
+        let n : i64 = store.ast_conn.query_row("SELECT COUNT(*) FROM \
+            qso_fields WHERE name = 'sweatersize'", [],
+            |row| row.get(0)).unwrap();
+        assert_eq!(n, 1);
+    }
+
+    #[test]
+    fn upsert() {
+        let mut store = adif_store_open(":memory:").unwrap();
+        let options = AdifStoreOptions::default();
+        let first = parse("log <EOH>\n\
+            <call:4>W1AW <qso_date:8>20240315 <time_on:6>183000 \
+            <band:3>20m <mode:2>CW <eor>\n\
+            <call:5>K1ABC <qso_date:8>20240315 <time_on:4>1831 \
+            <band:3>20m <mode:2>CW <eor>\n");
+        adif_store_upsert(&mut store, &first, &options).unwrap();
+
+        let second = parse("log <PROGRAMID:3>new <EOH>\n\
+            <call:4>w1aw <qso_date:8>20240315 <time_on:4>1830 \
+            <band:3>20M <mode:2>cw <qsl_rcvd:1>Y <eor>\n\
+            <call:6>N0CALL <qso_date:8>20240316 <time_on:4>0000 \
+            <band:3>40m <mode:2>CW <eor>\n");
+        let result = adif_store_upsert(&mut store, &second, &options)
+            .unwrap();
+        assert_eq!(result, AdifStoreResult {
+            asr_inserted: 1, asr_updated: 1, asr_unkeyed: 0
+        });
+
+        let exported = adif_store_export(&store, "store").unwrap();
+        assert_eq!(adif_header_program_id(&exported.adif_header),
+            Some("new"));
+        let calls : Vec<&str> = exported.adif_records.iter().map(
+            |r| r.adir_field_values["call"].as_str()).collect();
+        assert_eq!(calls, vec![ "w1aw", "K1ABC", "N0CALL" ]);
+        assert_eq!(exported.adif_records[0].adir_field_values["qsl_rcvd"],
+            "Y");
+    }
+
+    #[test]
+    fn identity() {
+        //
+        // The same QSO logged by two stations, or at two different seconds,
+        // is two QSOs.  Identical records in one file are kept, and importing
+        // the file again replaces them rather than adding more.
+        //
+        let adf = parse("log <EOH>\n\
+            <call:4>W1AW <qso_date:8>20240315 <time_on:4>1200 <band:3>20m \
+            <mode:2>CW <station_callsign:4>K1AA <eor>\n\
+            <call:4>W1AW <qso_date:8>20240315 <time_on:6>120030 <band:3>20m \
+            <mode:2>CW <station_callsign:4>K1BB <eor>\n\
+            <call:4>W1AW <qso_date:8>20240315 <time_on:4>1200 <band:3>20m \
+            <mode:2>CW <station_callsign:4>K1BB <eor>\n\
+            <call:4>W1AW <qso_date:8>20240315 <time_on:6>120000 <band:3>20m \
+            <mode:2>CW <station_callsign:4>K1BB <eor>\n");
+        let mut store = adif_store_open(":memory:").unwrap();
+        let options = AdifStoreOptions::default();
+        let result = adif_store_upsert(&mut store, &adf, &options).unwrap();
+        assert_eq!(result, AdifStoreResult {
+            asr_inserted: 4, asr_updated: 0, asr_unkeyed: 0
+        });
+        let result = adif_store_upsert(&mut store, &adf, &options).unwrap();
+        assert_eq!(result, AdifStoreResult {
+            asr_inserted: 0, asr_updated: 4, asr_unkeyed: 0
+        });
+        let exported = adif_store_export(&store, "store").unwrap();
+        assert_eq!(write(&exported), write(&adf));
+
+        let adf = parse("log <EOH>\n\
+            <call:4>W1AW <qso_date:8>20240315 <time_on:4>1200 <band:3>20m \
+            <mode:2>CW <station_callsign:4>K1AA <eor>\n\
+            <call:4>W1AW <qso_date:8>20240315 <time_on:6>120030 <band:3>20m \
+            <mode:2>CW <station_callsign:4>K1BB <eor>\n");
+        let mut store = adif_store_open(":memory:").unwrap();
+        adif_store_upsert(&mut store, &adf, &options).unwrap();
+        let exported = adif_store_export(&store, "store").unwrap();
+        assert_eq!(exported.adif_records.len(), 2);
+
+        //
+        // A store keeps the key fields it was first used with.
+        //
+        let other = AdifStoreOptions {
+            aso_key_fields: vec![ String::from("call") ]
+        };
+        assert_eq!(adif_store_upsert(&mut store, &adf, &other).unwrap_err()
+            .to_string(), "input error: store identifies QSOs by \
+            \"call,band,mode,station_callsign\", not \"call\"");
+    }
+}
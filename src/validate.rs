@@ -0,0 +1,280 @@
+//
+// src/validate.rs: checking records against the ADIF specification
+//
+// The parser is deliberately lenient about field values: it stores whatever
+// text it finds so that we can process old or sloppy exports.  This module
+// reports the values that don't conform to the specification:
+//
+//   - values of well-known fields that can't be interpreted as the field's data
+//     type (see src/fields.rs), including dates before 1930
+//   - values of a few enumerated fields (bands, continents, QSL statuses and
+//     routes) that aren't in the enumeration
+//   - frequencies that lie outside the band given in the same record
+//   - values of user-defined fields that violate the header's USERDEF
+//     enumeration or range
+//   - records that lack the fields needed to describe a QSO: "call",
+//     "qso_date", "time_on", "mode", and either "band" or "freq"
+//
+// Problems are reported as AdifDiagnostics, just like problems the parser was
+// able to work around.
+//
+
+use adif::AdifDiagnostic;
+use adif::AdifFile;
+use adif::AdifRecord;
+use adif::AdifUserdef;
+use adif::adif_header_userdefs;
+use adiftypes::adif_parse_date;
+use adiftypes::adif_parse_time;
+use fields::AdifDataType;
+use fields::adif_band_range;
+use fields::adif_field_type;
+use geo::adif_parse_gridsquare;
+use geo::adif_parse_location;
+
+//
+// Fields that every QSO record must have.  (Either "band" or "freq" is also
+// required; that's checked separately.)
+//
+static ADIF_REQUIRED_FIELDS : &[&str] = &[ "call", "qso_date", "time_on",
+    "mode" ];
+
+//
+// Allowed values of enumerated fields that we check.  Band is checked against
+// the band table in src/fields.rs.  The QSL enumerations are also used when
+// updating QSL statuses (see src/qsl.rs).
+//
+pub static ADIF_QSL_RCVD : &[&str] = &[ "Y", "N", "R", "I", "V" ];
+pub static ADIF_QSL_SENT : &[&str] = &[ "Y", "N", "R", "Q", "I" ];
+pub static ADIF_QSL_VIA : &[&str] = &[ "B", "D", "E", "M" ];
+static ADIF_ENUMERATIONS : &[(&str, &[&str])] = &[
+    ("ant_path", &[ "G", "O", "S", "L" ]),
+    ("cont", &[ "NA", "SA", "EU", "AF", "OC", "AS", "AN" ]),
+    ("eqsl_qsl_rcvd", ADIF_QSL_RCVD),
+    ("eqsl_qsl_sent", ADIF_QSL_SENT),
+    ("lotw_qsl_rcvd", ADIF_QSL_RCVD),
+    ("lotw_qsl_sent", ADIF_QSL_SENT),
+    ("qsl_rcvd", ADIF_QSL_RCVD),
+    ("qsl_rcvd_via", ADIF_QSL_VIA),
+    ("qsl_sent", ADIF_QSL_SENT),
+    ("qsl_sent_via", ADIF_QSL_VIA),
+    ("qso_complete", &[ "Y", "N", "NIL", "?" ])
+];
+
+//
+// Check every record in the file.  Diagnostics are returned in record order.
+//
+pub fn adif_validate(adf: &AdifFile) -> Vec<AdifDiagnostic>
+{
+    let userdefs = adif_header_userdefs(&adf.adif_header);
+    let mut diagnostics = Vec::new();
+    for (i, rec) in adf.adif_records.iter().enumerate() {
+        diagnostics.extend(adif_validate_record(rec, &userdefs).into_iter()
+            .map(|message| AdifDiagnostic {
+                adid_record: Some(i + 1),
+                adid_line: None,
+                adid_message: message
+            }));
+    }
+    diagnostics
+}
+
+//
+// Check one record, returning a description of each problem.
+//
+pub fn adif_validate_record(rec: &AdifRecord, userdefs: &[AdifUserdef]) ->
+    Vec<String>
+{
+    let mut problems = Vec::new();
+    let present = |name: &str| rec.adir_field_values.get(name).is_some_and(
+        |v| !v.trim().is_empty());
+
+    for name in ADIF_REQUIRED_FIELDS {
+        if !present(name) {
+            problems.push(format!("missing required field \"{}\"", name));
+        }
+    }
+    if !present("band") && !present("freq") {
+        problems.push(String::from("missing both \"band\" and \"freq\""));
+    }
+
+    for (name, value) in &rec.adir_field_values {
+        if value.trim().is_empty() {
+            continue;
+        }
+
+        let userdef = userdefs.iter().find(
+            |u| u.adud_name.eq_ignore_ascii_case(name));
+        let problem = match userdef {
+            Some(u) => adif_validate_userdef(u, value),
+            None => adif_validate_value(name, value)
+        };
+        if let Some(problem) = problem {
+            problems.push(format!("field \"{}\": {}", name, problem));
+        }
+    }
+
+    let freq = rec.adir_field_values.get("freq").and_then(
+        |f| f.trim().parse::<f64>().ok());
+    let range = rec.adir_field_values.get("band").and_then(
+        |b| adif_band_range(b));
+    if let (Some(f), Some((lo, hi))) = (freq, range) {
+        if f < lo || f > hi {
+            problems.push(format!("frequency {} MHz is outside band \"{}\"",
+                f, rec.adir_field_values["band"]));
+        }
+    }
+
+    problems
+}
+
+//
+// Check the value of a well-known field.  Returns a description of the problem,
+// if any.
+//
+pub fn adif_validate_value(name: &str, value: &str) -> Option<String>
+{
+    let value = value.trim();
+    let valid = match adif_field_type(name) {
+        AdifDataType::ADT_STRING => true,
+        AdifDataType::ADT_NUMBER => adif_valid_number(value),
+        AdifDataType::ADT_INTEGER => {
+            let digits = value.strip_prefix('-').unwrap_or(value);
+            !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+        },
+        AdifDataType::ADT_DATE => {
+            if adif_parse_date(value).is_some_and(|d| d.add_year < 1930) {
+                return Some(format!("date before 1930: \"{}\"", value));
+            }
+            adif_parse_date(value).is_some()
+        },
+        AdifDataType::ADT_TIME => adif_parse_time(value).is_some(),
+        AdifDataType::ADT_BOOLEAN => {
+            value.eq_ignore_ascii_case("Y") || value.eq_ignore_ascii_case("N")
+        },
+        AdifDataType::ADT_ENUMERATION => {
+            let upper = value.to_uppercase();
+            if name == "band" || name == "band_rx" {
+                adif_band_range(value).is_some()
+            } else {
+                ADIF_ENUMERATIONS.iter().find(|(f, _)| *f == name).is_none_or(
+                    |(_, allowed)| allowed.contains(&upper.as_str()))
+            }
+        },
+        AdifDataType::ADT_GRIDSQUARE => adif_parse_gridsquare(value).is_some(),
+        AdifDataType::ADT_LOCATION => adif_parse_location(value).is_some()
+    };
+
+    if valid {
+        None
+    } else {
+        Some(format!("invalid {}: \"{}\"", adif_type_name(name), value))
+    }
+}
+
+fn adif_type_name(name: &str) -> &'static str
+{
+    match adif_field_type(name) {
+        AdifDataType::ADT_STRING => "string",
+        AdifDataType::ADT_NUMBER => "number",
+        AdifDataType::ADT_INTEGER => "integer",
+        AdifDataType::ADT_DATE => "date",
+        AdifDataType::ADT_TIME => "time",
+        AdifDataType::ADT_BOOLEAN => "boolean",
+        AdifDataType::ADT_ENUMERATION => "enumeration value",
+        AdifDataType::ADT_GRIDSQUARE => "grid square",
+        AdifDataType::ADT_LOCATION => "location"
+    }
+}
+
+//
+// Check the value of a user-defined field against its definition.
+//
+fn adif_validate_userdef(userdef: &AdifUserdef, value: &str) -> Option<String>
+{
+    let value = value.trim();
+    if let Some(allowed) = &userdef.adud_enum {
+        if !allowed.iter().any(|a| a.eq_ignore_ascii_case(value)) {
+            return Some(format!("\"{}\" is not one of {{{}}}", value,
+                allowed.join(",")));
+        }
+    }
+
+    if let Some((lo, hi)) = userdef.adud_range {
+        match value.parse::<f64>() {
+            Ok(n) if adif_valid_number(value) && n >= lo && n <= hi => (),
+            _ => return Some(format!("\"{}\" is not in the range {}:{}",
+                value, lo, hi))
+        }
+    }
+
+    None
+}
+
+//
+// An ADIF Number is an optional minus sign followed by digits with an optional
+// decimal point.  (Rust's parser also accepts things like "1e5" and "inf".)
+//
+fn adif_valid_number(value: &str) -> bool
+{
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    digits.bytes().any(|b| b.is_ascii_digit()) &&
+        digits.bytes().all(|b| b.is_ascii_digit() || b == b'.') &&
+        digits.bytes().filter(|&b| b == b'.').count() <= 1
+}
+
+#[cfg(test)]
+mod test {
+    use adif_parse;
+    use super::*;
+
+    #[test]
+    fn values() {
+        assert_eq!(adif_validate_value("freq", "14.074"), None);
+        assert_eq!(adif_validate_value("freq", "1e5"),
+            Some(String::from("invalid number: \"1e5\"")));
+        assert_eq!(adif_validate_value("cqz", "5"), None);
+        assert!(adif_validate_value("cqz", "5.5").is_some());
+        assert_eq!(adif_validate_value("cqz", "-5"), None);
+        assert!(adif_validate_value("cqz", "-").is_some());
+        assert!(adif_validate_value("cqz", "--5").is_some());
+        assert_eq!(adif_validate_value("qso_date", "19291231"),
+            Some(String::from("date before 1930: \"19291231\"")));
+        assert!(adif_validate_value("qso_date", "20230230").is_some());
+        assert!(adif_validate_value("time_on", "2460").is_some());
+        assert_eq!(adif_validate_value("swl", "n"), None);
+        assert!(adif_validate_value("swl", "yes").is_some());
+        assert_eq!(adif_validate_value("band", "20m"), None);
+        assert_eq!(adif_validate_value("band", "21m"),
+            Some(String::from("invalid enumeration value: \"21m\"")));
+        assert!(adif_validate_value("qsl_rcvd", "Q").is_some());
+        assert_eq!(adif_validate_value("qsl_sent", "q"), None);
+        assert_eq!(adif_validate_value("qsl_sent_via", "B"), None);
+        assert!(adif_validate_value("qsl_rcvd_via", "X").is_some());
+        assert_eq!(adif_validate_value("mode", "WHATEVER"), None);
+        assert_eq!(adif_validate_value("gridsquare", "FN31pr"), None);
+        assert!(adif_validate_value("gridsquare", "ZZ31").is_some());
+        assert_eq!(adif_validate_value("lat", "N040 42.000"), None);
+        assert!(adif_validate_value("lat", "N091 00.000").is_some());
+        assert!(adif_validate_value("lon", "40.7").is_some());
+        assert_eq!(adif_validate_value("name", "anything"), None);
+    }
+
+    #[test]
+    fn file() {
+        let input = "test\n<USERDEF1:10:E>SIZE,{S,M}<EOH>\n\
+            <CALL:4>W1AW<QSO_DATE:8>20230102<TIME_ON:4>1200<MODE:2>CW\
+            <BAND:3>20M<FREQ:5>7.030<SIZE:1>L<EOR>\
+            <CALL:6>KK6ZBI<QSO_DATE:8>20230102<MODE:3>FT8<CQZ:1>X<EOR>";
+        let adf = adif_parse("test", &mut input.as_bytes()).unwrap();
+        let messages : Vec<String> = adif_validate(&adf).iter().map(
+            |d| d.to_string()).collect();
+        assert_eq!(messages, vec![
+            "record 1: field \"size\": \"L\" is not one of {S,M}",
+            "record 1: frequency 7.03 MHz is outside band \"20M\"",
+            "record 2: missing required field \"time_on\"",
+            "record 2: missing both \"band\" and \"freq\"",
+            "record 2: field \"cqz\": invalid integer: \"X\""
+        ]);
+    }
+}